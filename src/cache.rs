@@ -0,0 +1,194 @@
+// the output of commands that only show things (list, today, week, overdue, agenda, next and
+// cal) is saved in <list file>.cache/ when cache_output = true is set in the config file, so
+// running the same command again on an unchanged file doesn't have to parse and render it all
+// again. handy for things like shell prompts that run todo all the time
+//
+// each saved output is keyed on everything that can change it: the list file's modification
+// time and size, the config file's, the arguments, today's date, the terminal width and
+// whether colours are on. anything that edits the list file changes its modification time (and
+// usually its size), so an edit can't be hidden by an old entry. output isn't saved for a file
+// that changed in the last couple of seconds, in case it changes again within the same tick of
+// a coarse filesystem clock without its size changing. the cache is only ever a
+// shortcut, so anything going wrong with it just means the command runs as normal
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+const COMMANDS: [&str; 13] = [
+    "list", "l", "today", "t", "week", "w", "overdue", "od", "agenda", "ag", "next", "nx", "cal",
+];
+
+pub struct Cache {
+    path: PathBuf,
+    key: String,
+    // the list file was changed too recently to trust its modification time
+    racy: bool,
+}
+
+impl Cache {
+    // None if the command's output can't be cached. `context` is anything else the output
+    // depends on that isn't in the files or arguments
+    pub fn new(
+        list_file: &Path,
+        config_file: &Path,
+        args: &[String],
+        context: &str,
+    ) -> Option<Self> {
+        if !COMMANDS.contains(&args.first()?.as_str()) {
+            return None;
+        }
+        let command = format!("{context}\u{1e}{}", args.join("\u{1f}"));
+        let (modified, size) = stamp(list_file)?;
+        let key = format!(
+            "{modified:?}:{size}\u{1e}{:?}\u{1e}{command}",
+            stamp(config_file)
+        );
+        // the key is the first line of the cache file
+        if key.contains('\n') {
+            return None;
+        }
+        // the file is named after just the command, so each one only ever has one entry,
+        // which gets replaced once the list file changes
+        let mut hasher = DefaultHasher::new();
+        command.hash(&mut hasher);
+        let mut dir = list_file.as_os_str().to_owned();
+        dir.push(".cache");
+        Some(Self {
+            path: PathBuf::from(dir).join(format!("{:016x}", hasher.finish())),
+            key,
+            racy: modified
+                .elapsed()
+                .map_or(true, |age| age < Duration::from_secs(2)),
+        })
+    }
+
+    // the saved output and exit code, if this exact command has been run on this exact file
+    pub fn get(&self) -> Option<(String, i32)> {
+        let contents = std::fs::read_to_string(&self.path).ok()?;
+        let (key, rest) = contents.split_once('\n')?;
+        // an older version of the file, or a different command that hashes the same
+        if key != self.key {
+            return None;
+        }
+        let (code, output) = rest.split_once('\n')?;
+        Some((output.to_owned(), code.parse().ok()?))
+    }
+
+    pub fn put(&self, output: &str, code: i32) {
+        let Some(dir) = self.path.parent() else {
+            return;
+        };
+        if self.racy || std::fs::create_dir_all(dir).is_err() {
+            return;
+        }
+        // written to a temporary file and renamed, so another todo never reads half of it
+        let tmp = self
+            .path
+            .with_extension(format!("tmp{}", std::process::id()));
+        if std::fs::write(&tmp, format!("{}\n{code}\n{output}", self.key)).is_ok() {
+            let _ = std::fs::rename(&tmp, &self.path);
+        }
+    }
+}
+
+// deletes everything saved for `list_file`, for when what was in it has to be forgotten
+pub fn clear(list_file: &Path) {
+    let mut dir = list_file.as_os_str().to_owned();
+    dir.push(".cache");
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+// a file's modification time and size, or None if it isn't there
+fn stamp(path: &Path) -> Option<(SystemTime, u64)> {
+    let metadata = std::fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a list file that was last changed an hour ago, so it's old enough to cache
+    fn settled_file(name: &str, contents: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("todo-cache-{name}-{}", std::process::id()));
+        std::fs::write(&path, contents).unwrap();
+        let an_hour_ago = SystemTime::now() - Duration::from_hours(1);
+        std::fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(an_hour_ago)
+            .unwrap();
+        path
+    }
+
+    fn cleanup(list_file: &Path) {
+        let mut dir = list_file.as_os_str().to_owned();
+        dir.push(".cache");
+        let _ = std::fs::remove_dir_all(PathBuf::from(dir));
+        std::fs::remove_file(list_file).unwrap();
+    }
+
+    #[test]
+    fn only_commands_that_just_show_things() {
+        let file = settled_file("commands", "work:\n");
+        let no_config = file.with_extension("no-config");
+        let args = |s: &str| s.split(' ').map(String::from).collect::<Vec<String>>();
+        for command in ["list work", "today", "od", "cal 2024"] {
+            assert!(Cache::new(&file, &no_config, &args(command), "").is_some());
+        }
+        for command in ["add work thing", "done work thing", "lists"] {
+            assert!(Cache::new(&file, &no_config, &args(command), "").is_none());
+        }
+        assert!(Cache::new(&file, &no_config, &[], "").is_none());
+        cleanup(&file);
+    }
+
+    #[test]
+    fn an_edit_is_never_hidden() {
+        let file = settled_file("edits", "work:\n\t- one\n");
+        let config = file.with_extension("config");
+        let args = vec!["list".to_owned(), "work".to_owned()];
+
+        let cache = Cache::new(&file, &config, &args, "80").unwrap();
+        assert_eq!(cache.get(), None);
+        cache.put("work:\n    one\n", 0);
+        assert_eq!(cache.get(), Some(("work:\n    one\n".to_owned(), 0)));
+        // the same command again finds it
+        let again = Cache::new(&file, &config, &args, "80").unwrap();
+        assert_eq!(again.get().unwrap().0, "work:\n    one\n");
+        // but not with a different terminal width, or with other arguments
+        assert_eq!(
+            Cache::new(&file, &config, &args, "120").unwrap().get(),
+            None
+        );
+        let other = vec!["list".to_owned(), "home".to_owned()];
+        assert_eq!(
+            Cache::new(&file, &config, &other, "80").unwrap().get(),
+            None
+        );
+
+        // just edited, so the modification time can't be trusted yet, and nothing is saved
+        std::fs::write(&file, "work:\n\t- two\n").unwrap();
+        let edited = Cache::new(&file, &config, &args, "80").unwrap();
+        assert_eq!(edited.get(), None);
+        edited.put("work:\n    two\n", 0);
+        assert_eq!(edited.get(), None);
+
+        // a config file appearing counts as a change too
+        let file = settled_file("edits", "work:\n\t- one\n");
+        Cache::new(&file, &config, &args, "80")
+            .unwrap()
+            .put("old", 3);
+        std::fs::write(&config, "ascii = true\n").unwrap();
+        assert_eq!(Cache::new(&file, &config, &args, "80").unwrap().get(), None);
+        std::fs::remove_file(&config).unwrap();
+        assert_eq!(
+            Cache::new(&file, &config, &args, "80").unwrap().get(),
+            Some(("old".to_owned(), 3))
+        );
+        cleanup(&file);
+    }
+}
@@ -0,0 +1,127 @@
+use chrono::{Datelike, NaiveDate, Weekday};
+use std::collections::HashMap;
+use std::fmt::Write;
+
+// each day is "[dd]+N " or " dd +N ", so 7 columns per day
+const CELL_WIDTH: usize = 7;
+
+// how many days into its week `date` is, for weeks that start on `first_day`
+fn days_into_week(date: NaiveDate, first_day: Weekday) -> u32 {
+    (7 + date.weekday().num_days_from_monday() - first_day.num_days_from_monday()) % 7
+}
+
+// the first day of the week `date` is in, for weeks that start on `first_day`
+pub fn week_start(date: NaiveDate, first_day: Weekday) -> NaiveDate {
+    date - chrono::Duration::days(i64::from(days_into_week(date, first_day)))
+}
+
+pub fn parse_month(s: &str, today: NaiveDate) -> Option<(i32, u32)> {
+    let (month, year) = match s.split_once('/') {
+        Some((month, year)) => (month.parse().ok()?, year.parse().ok()?),
+        None => (s.parse().ok()?, today.year()),
+    };
+    NaiveDate::from_ymd_opt(year, month, 1).map(|_| (year, month))
+}
+
+pub fn render_month(
+    year: i32,
+    month: u32,
+    today: NaiveDate,
+    first_day: Weekday,
+    counts: &HashMap<NaiveDate, usize>,
+) -> String {
+    let first = NaiveDate::from_ymd_opt(year, month, 1).unwrap();
+    let mut acc = String::new();
+    let title = format!(
+        "{:^width$}",
+        first.format("%B %Y").to_string(),
+        width = CELL_WIDTH * 7
+    );
+    writeln!(acc, "{}", title.trim_end()).unwrap();
+
+    let mut line = String::new();
+    let mut day = first_day;
+    for _ in 0..7 {
+        let name = day.to_string();
+        write!(line, " {:<width$}", &name[..2], width = CELL_WIDTH - 1).unwrap();
+        day = day.succ();
+    }
+    writeln!(acc, "{}", line.trim_end()).unwrap();
+
+    // blank cells for the days of the previous month
+    line = " ".repeat(days_into_week(first, first_day) as usize * CELL_WIDTH);
+    let mut date = first;
+    while date.month() == month {
+        let (open, close) = if date == today {
+            ("[", "]")
+        } else {
+            (" ", " ")
+        };
+        let count = counts
+            .get(&date)
+            .map_or_else(String::new, |n| format!("+{}", n.min(&99)));
+        write!(line, "{open}{:>2}{close}{count:<3}", date.day()).unwrap();
+        if date.weekday() == first_day.pred() {
+            writeln!(acc, "{}", line.trim_end()).unwrap();
+            line.clear();
+        }
+        date = date.succ_opt().unwrap();
+    }
+    if !line.is_empty() {
+        writeln!(acc, "{}", line.trim_end()).unwrap();
+    }
+    acc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn day(d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 2, d).unwrap()
+    }
+
+    #[test]
+    fn february_2024() {
+        let counts = HashMap::from([(day(1), 2), (day(14), 1), (day(29), 250)]);
+        let out = render_month(2024, 2, day(14), Weekday::Mon, &counts);
+        let lines = out.lines().collect::<Vec<&str>>();
+        assert_eq!(lines[0].trim(), "February 2024");
+        assert_eq!(lines[1], " Mo     Tu     We     Th     Fr     Sa     Su");
+        // the 1st is a Thursday
+        assert_eq!(lines[2], "                       1 +2   2      3      4");
+        assert_eq!(lines[4], " 12     13    [14]+1  15     16     17     18");
+        // more than 99 doesn't fit in the cell
+        assert_eq!(lines[6], " 26     27     28     29 +99");
+        assert_eq!(lines.len(), 7);
+    }
+
+    #[test]
+    fn weeks_starting_on_sunday() {
+        let out = render_month(2024, 2, day(14), Weekday::Sun, &HashMap::new());
+        let lines = out.lines().collect::<Vec<&str>>();
+        assert_eq!(lines[1], " Su     Mo     Tu     We     Th     Fr     Sa");
+        assert_eq!(lines[2], "                              1      2      3");
+        assert_eq!(lines[6], " 25     26     27     28     29");
+
+        // the 14th is a Wednesday
+        assert_eq!(week_start(day(14), Weekday::Mon), day(12));
+        assert_eq!(week_start(day(14), Weekday::Sun), day(11));
+        assert_eq!(week_start(day(11), Weekday::Sun), day(11));
+        // back into January
+        assert_eq!(
+            week_start(day(3), Weekday::Mon),
+            NaiveDate::from_ymd_opt(2024, 1, 29).unwrap()
+        );
+    }
+
+    #[test]
+    fn months() {
+        let today = day(14);
+        assert_eq!(parse_month("3", today), Some((2024, 3)));
+        assert_eq!(parse_month("12/2025", today), Some((2025, 12)));
+        assert_eq!(parse_month("13", today), None);
+        assert_eq!(parse_month("march", today), None);
+        assert_eq!(parse_month("1/", today), None);
+    }
+}
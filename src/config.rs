@@ -0,0 +1,629 @@
+// settings from <config dir>/todo/config. one `key = value` per line, blank lines and lines
+// starting with # are ignored
+//
+//   ascii = true                   only use ASCII in the output, like passing --ascii
+//   reject_duplicate_items = true  don't add items that are already in the list, like
+//                                  passing --unique to add
+//   collapse_single = true         show sublists with one item as "sublist > item", like
+//                                  passing --collapse to list
+//   on_done_hook = <command>       run <command> with the shell each time an item is
+//                                  marked as done. TODO_ITEM, TODO_LIST and TODO_DATE (the
+//                                  deadline, if it has one) are set in its environment
+//   hooks_dir = <directory>        after a command changes the lists and they've been saved,
+//                                  run post-<command> in <directory> if there is one, like
+//                                  post-add or post-done, with the arguments the command got.
+//                                  TODO_COMMAND and TODO_FILE (the list file) are set in its
+//                                  environment
+//   timezone = Europe/London       the time zone used to work out what day it is, instead of
+//                                  the system's. TODO_TZ overrides it
+//   indent_width = 2               how many columns each level of sublist is indented by
+//                                  (default 4)
+//   indent_guides = true           draw a line down the side of each level of sublist
+//   soon_days = 3                  deadlines from 1 up to and including this many days away
+//                                  are coloured cyan, as coming up soon (default 3, 0 turns
+//                                  it off). overdue ones are red and today's yellow
+//   append_only = true             refuse to run the commands that delete things (rmlist,
+//                                  remove, autorm, moveall, move --where and dedup, and removing
+//                                  things in the ui and through serve), unless --force is
+//                                  passed
+//   use_trash = true               put what remove, rmlist and autorm take out in a hidden
+//                                  trash list instead of deleting it, so `todo trash restore`
+//                                  can put it back. only `todo trash empty` deletes it for good
+//   cache_output = true            save what list, today etc. print next to the list file, and
+//                                  show that again if nothing has changed. see src/cache.rs
+//   done_marker = [x]              what goes in front of done items (default ✓), undone items
+//   undone_marker = [ ]            (default a space) and lists with everything in them done
+//   list_done_marker = [x]         (default the same as done_marker). they're all padded to the
+//                                  widest one, and can be put in quotes to keep spaces in them.
+//                                  --ascii still uses [x] and [ ]
+//   stable_ids = true              give every list and item a random ID that doesn't change, like
+//                                  passing --stable-ids
+//   audit_log = true               add a line to history.log in the config directory for each
+//                                  command that changes the lists, with when, who ($USER) and
+//                                  what it did. `todo history` shows it
+//   verify_saves = false           don't read the list file back after saving it to check it
+//                                  loads the same (default true). it's only a little faster
+//   first_day_of_week = sunday     the day weeks start on in cal, list --timeline and
+//                                  week --calendar-week (default monday)
+//   priority_style = symbols       show priorities as !, !! and !!! (then !4, !5...) instead of
+//                                  [p1], [p2] etc. (badge, the default), like --priority-symbols
+//   priority_colours = blue, yellow, red
+//                                  the colours the names of items with priority 1, 2, 3...
+//                                  are shown in (the default). higher priorities get the last
+//                                  one, none leaves a priority uncoloured, and leaving it empty
+//                                  turns it off. the deadline is still coloured by how soon it is
+//
+// after an [aliases] line, each line is a shortcut of your own instead, like
+//
+//   [aliases]
+//   mondays = add chores "$1" --every monday
+//
+// so `todo mondays water the plants` runs `todo add chores "water the plants" --every monday`.
+// $1, $2 etc. are the arguments after the shortcut, and any that aren't used go on the end
+//
+// the hooks run with all the same permissions as todo itself, so only put commands you'd
+// be happy to run by hand in there, and don't let anyone else write to this file. note that
+// `todo serve` runs it too, for items done through the API. item and list names only ever
+// go in environment variables, never into the command, so quote them ("$TODO_ITEM") when
+// using them in the command so the shell doesn't interpret them
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct Config {
+    pub ascii: bool,
+    pub reject_duplicate_items: bool,
+    pub collapse_single: bool,
+    pub on_done_hook: Option<String>,
+    pub hooks_dir: Option<PathBuf>,
+    pub timezone: Option<chrono_tz::Tz>,
+    pub indent_width: usize,
+    pub indent_guides: bool,
+    pub soon_days: i64,
+    pub cache_output: bool,
+    pub append_only: bool,
+    pub use_trash: bool,
+    pub stable_ids: bool,
+    pub verify_saves: bool,
+    pub audit_log: bool,
+    // priority_style = symbols rather than badge
+    pub priority_symbols: bool,
+    // first_day_of_week
+    pub week_start: chrono::Weekday,
+    pub done_marker: String,
+    pub undone_marker: String,
+    // done_marker if it's not set
+    pub list_done_marker: Option<String>,
+    // ANSI colour codes, for priority 1 onwards
+    pub priority_colours: Vec<Option<u8>>,
+    // from the [aliases] section, split up into arguments like batch does
+    pub aliases: HashMap<String, Vec<String>>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            ascii: false,
+            reject_duplicate_items: false,
+            collapse_single: false,
+            on_done_hook: None,
+            hooks_dir: None,
+            timezone: None,
+            indent_width: 4,
+            indent_guides: false,
+            soon_days: 3,
+            cache_output: false,
+            append_only: false,
+            use_trash: false,
+            stable_ids: false,
+            verify_saves: true,
+            audit_log: false,
+            priority_symbols: false,
+            week_start: chrono::Weekday::Mon,
+            done_marker: "✓".to_string(),
+            undone_marker: " ".to_string(),
+            list_done_marker: None,
+            priority_colours: vec![Some(34), Some(33), Some(31)],
+            aliases: HashMap::new(),
+        }
+    }
+}
+
+impl Config {
+    // a missing config file just means everything is left as the default
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let mut config = match std::fs::read_to_string(path) {
+            Ok(contents) => {
+                Self::parse(&contents).map_err(|e| format!("Error in {}: {e}", path.display()))?
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Self::default(),
+            Err(e) => return Err(format!("Unable to read {}: {e}", path.display())),
+        };
+        // TODO_TZ overrides the timezone setting
+        if let Some(name) = std::env::var("TODO_TZ")
+            .ok()
+            .filter(|name| !name.is_empty())
+        {
+            config.timezone = Some(
+                name.parse()
+                    .map_err(|_| format!("Unknown time zone '{name}' in TODO_TZ"))?,
+            );
+        }
+        Ok(config)
+    }
+
+    // every setting and its value, written the way it would be in the file, for `todo config`
+    pub fn settings(&self) -> Vec<(&'static str, String)> {
+        let bool = |value: bool| value.to_string();
+        vec![
+            ("ascii", bool(self.ascii)),
+            ("reject_duplicate_items", bool(self.reject_duplicate_items)),
+            ("collapse_single", bool(self.collapse_single)),
+            (
+                "on_done_hook",
+                self.on_done_hook.clone().unwrap_or_default(),
+            ),
+            (
+                "hooks_dir",
+                self.hooks_dir
+                    .as_ref()
+                    .map_or_else(String::new, |dir| dir.display().to_string()),
+            ),
+            (
+                "timezone",
+                self.timezone
+                    .map_or_else(String::new, |tz| tz.name().to_string()),
+            ),
+            ("indent_width", self.indent_width.to_string()),
+            ("indent_guides", bool(self.indent_guides)),
+            ("soon_days", self.soon_days.to_string()),
+            ("cache_output", bool(self.cache_output)),
+            ("append_only", bool(self.append_only)),
+            ("use_trash", bool(self.use_trash)),
+            ("stable_ids", bool(self.stable_ids)),
+            ("verify_saves", bool(self.verify_saves)),
+            ("audit_log", bool(self.audit_log)),
+            (
+                "first_day_of_week",
+                self.week_start.to_string().to_lowercase(),
+            ),
+            (
+                "priority_style",
+                if self.priority_symbols {
+                    "symbols"
+                } else {
+                    "badge"
+                }
+                .to_string(),
+            ),
+            ("done_marker", quote(&self.done_marker)),
+            ("undone_marker", quote(&self.undone_marker)),
+            (
+                "list_done_marker",
+                quote(self.list_done_marker.as_ref().unwrap_or(&self.done_marker)),
+            ),
+            (
+                "priority_colours",
+                self.priority_colours
+                    .iter()
+                    .map(|code| code.map_or("none", crate::label_name))
+                    .collect::<Vec<&str>>()
+                    .join(", "),
+            ),
+        ]
+    }
+
+    // `contents` (of the config file) with `key` set to `value`, replacing the line it's on if
+    // there is one. the comments and everything else are left as they are
+    pub fn set(contents: &str, key: &str, value: &str) -> Result<String, String> {
+        if !Self::default()
+            .settings()
+            .iter()
+            .any(|(name, _)| *name == key)
+        {
+            return Err(format!("Unknown setting '{key}'"));
+        }
+        // the same checks as when it's loaded
+        Self::parse(&format!("{key} = {value}"))
+            .map_err(|e| e.trim_end_matches(" (line 1)").to_string())?;
+        let new_line = format!("{key} = {value}");
+        let mut lines = contents.lines().map(str::to_owned).collect::<Vec<String>>();
+        // settings have to go before the [aliases] section
+        let end = lines
+            .iter()
+            .position(|line| line.trim().starts_with('['))
+            .unwrap_or(lines.len());
+        let existing = lines[..end].iter().position(|line| {
+            !line.trim().starts_with('#')
+                && line
+                    .split_once('=')
+                    .is_some_and(|(name, _)| name.trim() == key)
+        });
+        if let Some(idx) = existing {
+            lines[idx] = new_line;
+        } else {
+            // after the last setting rather than after the blank line before [aliases]
+            let at = lines[..end]
+                .iter()
+                .rposition(|line| !line.trim().is_empty())
+                .map_or(0, |idx| idx + 1);
+            lines.insert(at, new_line);
+        }
+        let mut contents = lines.join("\n");
+        contents.push('\n');
+        Self::parse(&contents)?;
+        Ok(contents)
+    }
+
+    fn parse(s: &str) -> Result<Self, String> {
+        let mut config = Self::default();
+        let mut in_aliases = false;
+        for (line_num, line) in s.lines().enumerate() {
+            let line_num = line_num + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(section) = line.strip_prefix('[').and_then(|s| s.strip_suffix(']')) {
+                if section.trim() != "aliases" {
+                    return Err(format!("Unknown section '{section}' (line {line_num})"));
+                }
+                in_aliases = true;
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| format!("Expected 'key = value' (line {line_num})"))?;
+            let (key, value) = (key.trim(), value.trim());
+            if in_aliases {
+                if key.is_empty() || key.contains(char::is_whitespace) {
+                    return Err(format!("Invalid alias name '{key}' (line {line_num})"));
+                }
+                let command = crate::split_command_line(value)
+                    .map_err(|e| format!("{e} (line {line_num})"))?;
+                if command.is_empty() {
+                    return Err(format!("Alias '{key}' has no command (line {line_num})"));
+                }
+                config.aliases.insert(key.to_owned(), command);
+                continue;
+            }
+            match key {
+                "ascii" => config.ascii = parse_bool(key, value, line_num)?,
+                "reject_duplicate_items" => {
+                    config.reject_duplicate_items = parse_bool(key, value, line_num)?;
+                }
+                "collapse_single" => config.collapse_single = parse_bool(key, value, line_num)?,
+                "on_done_hook" => {
+                    config.on_done_hook = (!value.is_empty()).then(|| value.to_owned());
+                }
+                "hooks_dir" => config.hooks_dir = (!value.is_empty()).then(|| value.into()),
+                "timezone" => {
+                    config.timezone =
+                        Some(value.parse().map_err(|_| {
+                            format!("Unknown time zone '{value}' (line {line_num})")
+                        })?);
+                }
+                "indent_width" => {
+                    config.indent_width = value.parse().ok().filter(|w| *w > 0).ok_or_else(|| {
+                        format!("Expected a number above 0 for '{key}', not '{value}' (line {line_num})")
+                    })?;
+                }
+                "indent_guides" => config.indent_guides = parse_bool(key, value, line_num)?,
+                "soon_days" => {
+                    config.soon_days = value.parse().ok().filter(|d| *d >= 0).ok_or_else(|| {
+                        format!("Expected a number of days for '{key}', not '{value}' (line {line_num})")
+                    })?;
+                }
+                "cache_output" => config.cache_output = parse_bool(key, value, line_num)?,
+                "append_only" => config.append_only = parse_bool(key, value, line_num)?,
+                "use_trash" => config.use_trash = parse_bool(key, value, line_num)?,
+                "stable_ids" => config.stable_ids = parse_bool(key, value, line_num)?,
+                "verify_saves" => config.verify_saves = parse_bool(key, value, line_num)?,
+                "audit_log" => config.audit_log = parse_bool(key, value, line_num)?,
+                "first_day_of_week" => {
+                    config.week_start = value.parse().map_err(|_| {
+                        format!("Expected a day of the week for '{key}', not '{value}' (line {line_num})")
+                    })?;
+                }
+                "priority_style" => {
+                    config.priority_symbols = match value {
+                        "badge" => false,
+                        "symbols" => true,
+                        _ => {
+                            return Err(format!(
+                                "Expected badge or symbols for '{key}', not '{value}' (line {line_num})"
+                            ))
+                        }
+                    };
+                }
+                "done_marker" => unquote(value).clone_into(&mut config.done_marker),
+                "undone_marker" => unquote(value).clone_into(&mut config.undone_marker),
+                "list_done_marker" => config.list_done_marker = Some(unquote(value).to_owned()),
+                "priority_colours" => {
+                    config.priority_colours = value
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|colour| !colour.is_empty())
+                        .map(|colour| match colour {
+                            "none" => Ok(None),
+                            _ => crate::label_colour(colour).map(Some),
+                        })
+                        .collect::<Result<_, _>>()
+                        .map_err(|e| format!("{e} (line {line_num})"))?;
+                }
+                _ => return Err(format!("Unknown setting '{key}' (line {line_num})")),
+            }
+        }
+        Ok(config)
+    }
+}
+
+// the other way round, for markers that need it
+fn quote(value: &str) -> String {
+    if value.trim() == value && !value.is_empty() {
+        value.to_owned()
+    } else {
+        format!("\"{value}\"")
+    }
+}
+
+// "like this", to keep the spaces at the ends
+fn unquote(value: &str) -> &str {
+    value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+        .unwrap_or(value)
+}
+
+fn parse_bool(key: &str, value: &str, line_num: usize) -> Result<bool, String> {
+    match value {
+        "true" | "yes" | "on" => Ok(true),
+        "false" | "no" | "off" => Ok(false),
+        _ => Err(format!(
+            "Expected true or false for '{key}', not '{value}' (line {line_num})"
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        assert!(!Config::parse("").unwrap().ascii);
+        let config = Config::parse("# plain output for the logs\n\n  ascii =  yes  \n").unwrap();
+        assert!(config.ascii);
+        assert!(!Config::parse("ascii=off").unwrap().ascii);
+
+        assert_eq!(
+            Config::parse("ascii = true\ncolour = blue\n").unwrap_err(),
+            "Unknown setting 'colour' (line 2)"
+        );
+        assert_eq!(
+            Config::parse("ascii = maybe").unwrap_err(),
+            "Expected true or false for 'ascii', not 'maybe' (line 1)"
+        );
+        assert_eq!(
+            Config::parse("\nascii\n").unwrap_err(),
+            "Expected 'key = value' (line 2)"
+        );
+    }
+
+    #[test]
+    fn a_missing_file_is_the_defaults() {
+        let path = std::env::temp_dir().join("todo-tests-no-such-config");
+        assert!(!Config::load(&path).unwrap().ascii);
+    }
+
+    #[test]
+    fn reject_duplicate_items() {
+        let config = Config::parse("ascii = no\nreject_duplicate_items = true\n").unwrap();
+        assert!(config.reject_duplicate_items && !config.ascii);
+        assert!(!Config::default().reject_duplicate_items);
+    }
+
+    #[test]
+    fn on_done_hook() {
+        let config = Config::parse("on_done_hook = notify-send \"done: $TODO_ITEM\"\n").unwrap();
+        assert_eq!(
+            config.on_done_hook.as_deref(),
+            Some("notify-send \"done: $TODO_ITEM\"")
+        );
+        assert_eq!(Config::parse("on_done_hook =").unwrap().on_done_hook, None);
+        assert_eq!(Config::default().on_done_hook, None);
+    }
+
+    #[test]
+    fn timezone() {
+        let config = Config::parse("timezone = Europe/London\n").unwrap();
+        assert_eq!(config.timezone, Some(chrono_tz::Europe::London));
+        assert_eq!(Config::default().timezone, None);
+        assert_eq!(
+            Config::parse("ascii = true\ntimezone = Mars/Olympus_Mons\n").unwrap_err(),
+            "Unknown time zone 'Mars/Olympus_Mons' (line 2)"
+        );
+    }
+
+    #[test]
+    fn soon_days() {
+        assert_eq!(Config::default().soon_days, 3);
+        assert_eq!(Config::parse("soon_days = 0\n").unwrap().soon_days, 0);
+        assert_eq!(Config::parse("soon_days=14").unwrap().soon_days, 14);
+        assert_eq!(
+            Config::parse("soon_days = -2").unwrap_err(),
+            "Expected a number of days for 'soon_days', not '-2' (line 1)"
+        );
+        assert!(Config::parse("soon_days = a week").is_err());
+    }
+
+    #[test]
+    fn append_only() {
+        assert!(!Config::default().append_only);
+        assert!(Config::parse("append_only = yes").unwrap().append_only);
+        assert_eq!(
+            Config::parse("cache_output = on\nappend_only = always\n").unwrap_err(),
+            "Expected true or false for 'append_only', not 'always' (line 2)"
+        );
+    }
+
+    #[test]
+    fn priority_colours() {
+        assert_eq!(
+            Config::default().priority_colours,
+            [Some(34), Some(33), Some(31)]
+        );
+        let config = Config::parse("priority_colours = none, green ,magenta\n").unwrap();
+        assert_eq!(config.priority_colours, [None, Some(32), Some(35)]);
+        assert!(Config::parse("priority_colours =")
+            .unwrap()
+            .priority_colours
+            .is_empty());
+        assert_eq!(
+            Config::parse("soon_days = 2\npriority_colours = red, pink").unwrap_err(),
+            "Unknown colour 'pink', expected one of red, green, yellow, blue, magenta, cyan, white (line 2)"
+        );
+    }
+
+    #[test]
+    fn aliases_section() {
+        let config = Config::parse(
+            "ascii = true\n[aliases]\ntw = list work --collapse\nshop = add \"shopping list\" $1\n",
+        )
+        .unwrap();
+        assert!(config.ascii);
+        assert_eq!(config.aliases["tw"], ["list", "work", "--collapse"]);
+        assert_eq!(config.aliases["shop"], ["add", "shopping list", "$1"]);
+        // settings don't go after the aliases, they'd be read as one
+        assert!(Config::parse("[aliases]\nascii = true\n")
+            .unwrap()
+            .aliases
+            .contains_key("ascii"));
+        assert_eq!(
+            Config::parse("[alias]\n").unwrap_err(),
+            "Unknown section 'alias' (line 1)"
+        );
+        assert_eq!(
+            Config::parse("\n[aliases]\nmy alias = list\n").unwrap_err(),
+            "Invalid alias name 'my alias' (line 3)"
+        );
+        assert_eq!(
+            Config::parse("[aliases]\nnothing =\n").unwrap_err(),
+            "Alias 'nothing' has no command (line 2)"
+        );
+    }
+
+    #[test]
+    fn hooks_dir() {
+        assert_eq!(Config::default().hooks_dir, None);
+        assert_eq!(
+            Config::parse("hooks_dir = /home/me/todo hooks\n")
+                .unwrap()
+                .hooks_dir,
+            Some(PathBuf::from("/home/me/todo hooks"))
+        );
+        assert_eq!(Config::parse("hooks_dir =\n").unwrap().hooks_dir, None);
+    }
+
+    #[test]
+    fn markers() {
+        let config = Config::default();
+        assert_eq!(
+            (config.done_marker.as_str(), config.undone_marker.as_str()),
+            ("✓", " ")
+        );
+        assert_eq!(config.list_done_marker, None);
+        let config = Config::parse(
+            "done_marker = [x]\nundone_marker = \"[ ] \"\nlist_done_marker = \" \"\"\n",
+        )
+        .unwrap();
+        assert_eq!(config.done_marker, "[x]");
+        assert_eq!(config.undone_marker, "[ ] ");
+        // only a pair of quotes comes off
+        assert_eq!(config.list_done_marker.as_deref(), Some(" \""));
+    }
+
+    #[test]
+    fn set_keeps_the_rest_of_the_file() {
+        let file = "# mine\nascii = false\n\n[aliases]\ntw = list work\n";
+        assert_eq!(
+            Config::set(file, "ascii", "true").unwrap(),
+            "# mine\nascii = true\n\n[aliases]\ntw = list work\n"
+        );
+        // a new one goes with the others, not in with the aliases
+        assert_eq!(
+            Config::set(file, "soon_days", "5").unwrap(),
+            "# mine\nascii = false\nsoon_days = 5\n\n[aliases]\ntw = list work\n"
+        );
+        // a commented out line isn't the setting
+        assert_eq!(
+            Config::set("#ascii = true\n", "ascii", "yes").unwrap(),
+            "#ascii = true\nascii = yes\n"
+        );
+        assert_eq!(
+            Config::set("", "use_trash", "on").unwrap(),
+            "use_trash = on\n"
+        );
+
+        assert_eq!(
+            Config::set(file, "tw", "list home").unwrap_err(),
+            "Unknown setting 'tw'"
+        );
+        assert_eq!(
+            Config::set(file, "soon_days", "soon").unwrap_err(),
+            "Expected a number of days for 'soon_days', not 'soon'"
+        );
+    }
+
+    #[test]
+    fn settings_read_back_the_same() {
+        let config =
+            Config::parse("done_marker = \" x \"\npriority_colours = none, red\nsoon_days = 9\n")
+                .unwrap();
+        let file: String = config
+            .settings()
+            .iter()
+            .filter(|(_, value)| !value.is_empty())
+            .map(|(key, value)| format!("{key} = {value}"))
+            .collect::<Vec<String>>()
+            .join("\n");
+        let again = Config::parse(&file).unwrap();
+        assert_eq!(again.settings(), config.settings());
+        assert_eq!(again.done_marker, " x ");
+    }
+
+    #[test]
+    fn priority_style() {
+        assert!(!Config::default().priority_symbols);
+        assert!(
+            Config::parse("priority_style = symbols\n")
+                .unwrap()
+                .priority_symbols
+        );
+        assert!(
+            !Config::parse("priority_style = badge\n")
+                .unwrap()
+                .priority_symbols
+        );
+        assert_eq!(
+            Config::parse("\npriority_style = bangs\n").unwrap_err(),
+            "Expected badge or symbols for 'priority_style', not 'bangs' (line 2)"
+        );
+    }
+
+    #[test]
+    fn first_day_of_week() {
+        assert_eq!(Config::default().week_start, chrono::Weekday::Mon);
+        let config = Config::parse("first_day_of_week = sunday\n").unwrap();
+        assert_eq!(config.week_start, chrono::Weekday::Sun);
+        assert_eq!(
+            Config::parse("first_day_of_week = Sat\n")
+                .unwrap()
+                .week_start,
+            chrono::Weekday::Sat
+        );
+        assert!(Config::parse("first_day_of_week = someday\n").is_err());
+    }
+}
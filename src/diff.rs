@@ -0,0 +1,235 @@
+// `todo diff <old file> <new file>`, what changed between two versions of a list file, e.g.
+// before and after a git pull. lists are matched up by name, and so are the items in them.
+// if a list has more than one item with the same name, they're matched up in order
+
+use crate::parser::json_string;
+use crate::{ListEntry, ListItem, TodoList};
+use chrono::NaiveDate;
+use std::fmt::Write;
+
+pub enum Change {
+    // with how many entries are in it
+    ListAdded(String, usize),
+    ListRemoved(String, usize),
+    // the list, and the item or sublist
+    ItemAdded(String, String),
+    ItemRemoved(String, String),
+    SublistAdded(String, String),
+    SublistRemoved(String, String),
+    Done(String, String),
+    Undone(String, String),
+    DateChanged(String, String, Option<NaiveDate>, Option<NaiveDate>),
+}
+
+pub fn diff(old: &[TodoList], new: &[TodoList]) -> Vec<Change> {
+    let mut changes = Vec::new();
+    for list in old {
+        if !new.iter().any(|other| other.name == list.name) {
+            changes.push(Change::ListRemoved(list.name.clone(), list.items.len()));
+        }
+    }
+    for list in new {
+        match old.iter().find(|other| other.name == list.name) {
+            Some(old_list) => diff_list(old_list, list, &mut changes),
+            None => changes.push(Change::ListAdded(list.name.clone(), list.items.len())),
+        }
+    }
+    changes
+}
+
+fn diff_list(old: &TodoList, new: &TodoList, changes: &mut Vec<Change>) {
+    let name = || new.name.clone();
+    // each old entry can only be matched once, so repeated names pair up in order
+    let mut matched = vec![false; old.items.len()];
+    for entry in &new.items {
+        let found = old.items.iter().enumerate().position(|(idx, other)| {
+            !matched[idx] && other.name() == entry.name() && same_kind(other, entry)
+        });
+        let Some(idx) = found else {
+            changes.push(match entry {
+                ListEntry::Item(item) => Change::ItemAdded(name(), item.name.clone()),
+                ListEntry::List(sublist) => Change::SublistAdded(name(), sublist.clone()),
+            });
+            continue;
+        };
+        matched[idx] = true;
+        if let (ListEntry::Item(before), ListEntry::Item(after)) = (&old.items[idx], entry) {
+            diff_item(&new.name, before, after, changes);
+        }
+    }
+    for (entry, _) in old
+        .items
+        .iter()
+        .zip(matched)
+        .filter(|(_, matched)| !matched)
+    {
+        changes.push(match entry {
+            ListEntry::Item(item) => Change::ItemRemoved(name(), item.name.clone()),
+            ListEntry::List(sublist) => Change::SublistRemoved(name(), sublist.clone()),
+        });
+    }
+}
+
+const fn same_kind(a: &ListEntry, b: &ListEntry) -> bool {
+    matches!(
+        (a, b),
+        (ListEntry::Item(_), ListEntry::Item(_)) | (ListEntry::List(_), ListEntry::List(_))
+    )
+}
+
+fn diff_item(list: &str, old: &ListItem, new: &ListItem, changes: &mut Vec<Change>) {
+    let (list, name) = (list.to_owned(), new.name.clone());
+    match (old.done, new.done) {
+        (false, true) => changes.push(Change::Done(list.clone(), name.clone())),
+        (true, false) => changes.push(Change::Undone(list.clone(), name.clone())),
+        _ => (),
+    }
+    if old.date != new.date {
+        changes.push(Change::DateChanged(list, name, old.date, new.date));
+    }
+}
+
+fn format_date(date: Option<NaiveDate>) -> String {
+    date.map_or_else(
+        || "no date".to_string(),
+        |date| date.format("%d/%m/%Y").to_string(),
+    )
+}
+
+fn entries(count: usize) -> String {
+    format!("{count} entr{}", if count == 1 { "y" } else { "ies" })
+}
+
+pub fn render(changes: &[Change]) -> String {
+    if changes.is_empty() {
+        return "No changes\n".to_string();
+    }
+    let mut acc = String::new();
+    for change in changes {
+        match change {
+            Change::ListAdded(list, count) => {
+                writeln!(acc, "+ list '{list}' ({})", entries(*count))
+            }
+            Change::ListRemoved(list, count) => {
+                writeln!(acc, "- list '{list}' ({})", entries(*count))
+            }
+            Change::ItemAdded(list, name) => writeln!(acc, "{list}: + '{name}'"),
+            Change::ItemRemoved(list, name) => writeln!(acc, "{list}: - '{name}'"),
+            Change::SublistAdded(list, name) => writeln!(acc, "{list}: + sublist '{name}'"),
+            Change::SublistRemoved(list, name) => writeln!(acc, "{list}: - sublist '{name}'"),
+            Change::Done(list, name) => writeln!(acc, "{list}: done '{name}'"),
+            Change::Undone(list, name) => writeln!(acc, "{list}: not done '{name}'"),
+            Change::DateChanged(list, name, old, new) => writeln!(
+                acc,
+                "{list}: '{name}' {} -> {}",
+                format_date(*old),
+                format_date(*new)
+            ),
+        }
+        .unwrap();
+    }
+    acc
+}
+
+fn json_date(date: Option<NaiveDate>) -> String {
+    date.map_or_else(
+        || "null".to_string(),
+        |date| json_string(&date.format("%Y-%m-%d").to_string()),
+    )
+}
+
+pub fn to_json(changes: &[Change]) -> String {
+    let objects = changes
+        .iter()
+        .map(|change| {
+            let (kind, list, rest) = match change {
+                Change::ListAdded(list, count) => {
+                    ("list_added", list, format!(",\"entries\":{count}"))
+                }
+                Change::ListRemoved(list, count) => {
+                    ("list_removed", list, format!(",\"entries\":{count}"))
+                }
+                Change::ItemAdded(list, name) => ("item_added", list, name_json(name)),
+                Change::ItemRemoved(list, name) => ("item_removed", list, name_json(name)),
+                Change::SublistAdded(list, name) => ("sublist_added", list, name_json(name)),
+                Change::SublistRemoved(list, name) => ("sublist_removed", list, name_json(name)),
+                Change::Done(list, name) => ("done", list, name_json(name)),
+                Change::Undone(list, name) => ("undone", list, name_json(name)),
+                Change::DateChanged(list, name, old, new) => (
+                    "date_changed",
+                    list,
+                    format!(
+                        "{},\"old_date\":{},\"new_date\":{}",
+                        name_json(name),
+                        json_date(*old),
+                        json_date(*new)
+                    ),
+                ),
+            };
+            format!(
+                "{{\"change\":\"{kind}\",\"list\":{}{rest}}}",
+                json_string(list)
+            )
+        })
+        .collect::<Vec<String>>();
+    format!("[{}]\n", objects.join(","))
+}
+
+fn name_json(name: &str) -> String {
+    format!(",\"name\":{}", json_string(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_str;
+
+    #[test]
+    fn what_changed() {
+        let old = parse_str(concat!(
+            "work:\n\t- @01/06/2024 report\n\t- email\n\t+ standup\n\t- email\n\t= admin\n",
+            "admin:\n\t- expenses\n",
+            "old stuff:\n\t- a\n\t- b\n",
+        ))
+        .unwrap();
+        let new = parse_str(concat!(
+            "work:\n\t- @08/06/2024 report\n\t+ email\n\t- standup\n\t- review\n\t= reading\n",
+            "admin:\n\t- expenses\n",
+            "reading:\n\t- a book\n",
+        ))
+        .unwrap();
+
+        assert_eq!(
+            render(&diff(&old, &new)),
+            concat!(
+                "- list 'old stuff' (2 entries)\n",
+                "work: 'report' 01/06/2024 -> 08/06/2024\n",
+                // the first email is matched with the first one, and the second one is gone
+                "work: done 'email'\n",
+                "work: not done 'standup'\n",
+                "work: + 'review'\n",
+                "work: + sublist 'reading'\n",
+                "work: - 'email'\n",
+                "work: - sublist 'admin'\n",
+                "+ list 'reading' (1 entry)\n",
+            )
+        );
+        assert_eq!(render(&diff(&new, &new)), "No changes\n");
+        assert_eq!(to_json(&[]), "[]\n");
+    }
+
+    #[test]
+    fn json() {
+        let old = parse_str("home:\n\t- @02/03/2024 \"quoted\" thing\n\t= garden\n").unwrap();
+        let new = parse_str("home:\n\t- \"quoted\" thing\ngarden:\n").unwrap();
+        assert_eq!(
+            to_json(&diff(&old, &new)),
+            concat!(
+                "[{\"change\":\"date_changed\",\"list\":\"home\",\"name\":\"\\\"quoted\\\" thing\",",
+                "\"old_date\":\"2024-03-02\",\"new_date\":null},",
+                "{\"change\":\"sublist_removed\",\"list\":\"home\",\"name\":\"garden\"},",
+                "{\"change\":\"list_added\",\"list\":\"garden\",\"entries\":0}]\n",
+            )
+        );
+    }
+}
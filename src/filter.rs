@@ -0,0 +1,311 @@
+// `list --only <filter>`, a little language for picking out items, e.g.
+//   todo list work --only "priority>=2 and not done and due<7d"
+//
+//   done                         the item is done
+//   priority <op> N              compares the priority (0 if it doesn't have one)
+//   progress <op> N              compares the progress (0 if it doesn't have any)
+//   due <op> <date>              compares the deadline with a date (dd/mm/yy) or a number of
+//                                days (3d) or weeks (2w) from today, which can be negative.
+//                                items without a deadline never match
+//   label = <colour>             the item has that label (or != for doesn't). tag works too
+//   not, and, or, ( ... )        not binds tightest, then and, then or
+//
+// where <op> is one of = (or ==), !=, <, <=, > and >=
+
+use crate::{label_colour, parse_date, ListItem};
+use chrono::NaiveDate;
+
+#[derive(Debug, Clone, Copy)]
+pub enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Op {
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "=" | "==" => Some(Self::Eq),
+            "!=" => Some(Self::Ne),
+            "<" => Some(Self::Lt),
+            "<=" => Some(Self::Le),
+            ">" => Some(Self::Gt),
+            ">=" => Some(Self::Ge),
+            _ => None,
+        }
+    }
+
+    fn holds<T: Ord>(self, a: &T, b: &T) -> bool {
+        match self {
+            Self::Eq => a == b,
+            Self::Ne => a != b,
+            Self::Lt => a < b,
+            Self::Le => a <= b,
+            Self::Gt => a > b,
+            Self::Ge => a >= b,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Filter {
+    Done,
+    Priority(Op, i64),
+    Progress(Op, i64),
+    Due(Op, NaiveDate),
+    // true for =, false for !=
+    Label(bool, String),
+    Not(Box<Self>),
+    And(Box<Self>, Box<Self>),
+    Or(Box<Self>, Box<Self>),
+}
+
+impl Filter {
+    // relative deadlines like 7d are counted from `today`
+    pub fn parse(s: &str, today: NaiveDate) -> Result<Self, String> {
+        let mut parser = Parser {
+            tokens: tokenise(s),
+            pos: 0,
+            today,
+        };
+        let filter = parser.or()?;
+        parser.next().map_or(Ok(filter), |token| {
+            Err(format!("Unexpected '{token}' in filter"))
+        })
+    }
+
+    pub fn matches(&self, item: &ListItem) -> bool {
+        match self {
+            Self::Done => item.done,
+            Self::Priority(op, n) => op.holds(&i64::from(item.priority), n),
+            Self::Progress(op, n) => op.holds(&i64::from(item.progress.unwrap_or(0)), n),
+            Self::Due(op, date) => item.date.is_some_and(|due| op.holds(&due, date)),
+            Self::Label(equal, label) => (item.label.as_ref() == Some(label)) == *equal,
+            Self::Not(filter) => !filter.matches(item),
+            Self::And(a, b) => a.matches(item) && b.matches(item),
+            Self::Or(a, b) => a.matches(item) || b.matches(item),
+        }
+    }
+}
+
+// words, brackets and comparison operators. spaces are only needed between words
+fn tokenise(s: &str) -> Vec<String> {
+    let is_op = |c: char| "<>=!".contains(c);
+    let mut tokens = Vec::new();
+    let mut chars = s.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '(' || c == ')' {
+            tokens.push(c.to_string());
+            chars.next();
+        } else {
+            let op = is_op(c);
+            let mut token = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() || c == '(' || c == ')' || is_op(c) != op {
+                    break;
+                }
+                token.push(c);
+                chars.next();
+            }
+            tokens.push(token);
+        }
+    }
+    tokens
+}
+
+struct Parser {
+    tokens: Vec<String>,
+    pos: usize,
+    today: NaiveDate,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<String> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn peek_is(&self, word: &str) -> bool {
+        self.tokens
+            .get(self.pos)
+            .is_some_and(|token| token.eq_ignore_ascii_case(word))
+    }
+
+    fn expect_next(&mut self) -> Result<String, String> {
+        self.next()
+            .ok_or_else(|| "Unexpected end of filter".to_string())
+    }
+
+    fn or(&mut self) -> Result<Filter, String> {
+        let mut filter = self.and()?;
+        while self.peek_is("or") {
+            self.pos += 1;
+            filter = Filter::Or(Box::new(filter), Box::new(self.and()?));
+        }
+        Ok(filter)
+    }
+
+    fn and(&mut self) -> Result<Filter, String> {
+        let mut filter = self.not()?;
+        while self.peek_is("and") {
+            self.pos += 1;
+            filter = Filter::And(Box::new(filter), Box::new(self.not()?));
+        }
+        Ok(filter)
+    }
+
+    fn not(&mut self) -> Result<Filter, String> {
+        if self.peek_is("not") {
+            self.pos += 1;
+            return Ok(Filter::Not(Box::new(self.not()?)));
+        }
+        self.term()
+    }
+
+    fn term(&mut self) -> Result<Filter, String> {
+        let token = self.expect_next()?;
+        match token.to_lowercase().as_str() {
+            "(" => {
+                let filter = self.or()?;
+                match self.expect_next()?.as_str() {
+                    ")" => Ok(filter),
+                    token => Err(format!("Expected ')' in filter, but found '{token}'")),
+                }
+            }
+            "done" => Ok(Filter::Done),
+            field @ ("priority" | "progress" | "due" | "label" | "tag") => {
+                let op_str = self.expect_next()?;
+                let op = Op::parse(&op_str).ok_or_else(|| {
+                    format!("Expected a comparison after '{field}', but found '{op_str}'")
+                })?;
+                let value = self.expect_next()?;
+                match field {
+                    "priority" => Ok(Filter::Priority(op, parse_number(&value)?)),
+                    "progress" => Ok(Filter::Progress(op, parse_number(&value)?)),
+                    "due" => Ok(Filter::Due(op, self.parse_due(&value)?)),
+                    _ => {
+                        label_colour(&value)?;
+                        match op {
+                            Op::Eq => Ok(Filter::Label(true, value)),
+                            Op::Ne => Ok(Filter::Label(false, value)),
+                            _ => Err(format!(
+                                "Labels can only be compared with = or !=, not '{op_str}'"
+                            )),
+                        }
+                    }
+                }
+            }
+            _ => Err(format!("Unexpected '{token}' in filter")),
+        }
+    }
+
+    fn parse_due(&self, s: &str) -> Result<NaiveDate, String> {
+        if let Some(date) = parse_date(s) {
+            return Ok(date);
+        }
+        let (num, days_per_unit) = if let Some(num) = s.strip_suffix('d') {
+            (num, 1)
+        } else if let Some(num) = s.strip_suffix('w') {
+            (num, 7)
+        } else {
+            return Err(format!(
+                "Invalid date '{s}' in filter, expected dd/mm/yy or e.g. 3d, 2w"
+            ));
+        };
+        let num = parse_number(num)?;
+        Ok(self.today + chrono::Duration::days(num * days_per_unit))
+    }
+}
+
+fn parse_number(s: &str) -> Result<i64, String> {
+    s.trim_start_matches('+')
+        .parse()
+        .map_err(|_| format!("Invalid number '{s}' in filter"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn precedence_and_fields() {
+        let today = NaiveDate::from_ymd_opt(2024, 5, 15).unwrap();
+        let item = ListItem {
+            name: "report".to_owned(),
+            priority: 2,
+            progress: Some(40),
+            date: NaiveDate::from_ymd_opt(2024, 5, 18),
+            label: Some("red".to_owned()),
+            ..Default::default()
+        };
+        let cases = [
+            ("priority>=2 and not done and due<7d", true),
+            ("priority > 2", false),
+            ("PRIORITY == 2 AND progress<50", true),
+            ("due = 18/05/24", true),
+            ("due <= 2d", false),
+            ("due >= -1w", true),
+            ("label = red", true),
+            ("tag != red", false),
+            // not binds tighter than and, which binds tighter than or
+            ("done or priority=2 and label=blue", false),
+            ("(done or priority=2) and not label=blue", true),
+            ("not not done", false),
+            ("done or not done", true),
+        ];
+        for (text, expected) in &cases {
+            let filter = Filter::parse(text, today).unwrap();
+            assert_eq!(filter.matches(&item), *expected, "{text}");
+        }
+
+        // nothing is due before or after an item without a deadline
+        let undated = ListItem::default();
+        assert!(!Filter::parse("due < 100w", today)
+            .unwrap()
+            .matches(&undated));
+        assert!(!Filter::parse("due > -100w", today)
+            .unwrap()
+            .matches(&undated));
+    }
+
+    #[test]
+    fn errors_name_the_token() {
+        let today = NaiveDate::from_ymd_opt(2024, 5, 15).unwrap();
+        let cases = [
+            ("priority", "Unexpected end of filter"),
+            (
+                "priority ~ 2",
+                "Expected a comparison after 'priority', but found '~'",
+            ),
+            (
+                "priority done",
+                "Expected a comparison after 'priority', but found 'done'",
+            ),
+            ("priority >= lots", "Invalid number 'lots' in filter"),
+            (
+                "due < soon",
+                "Invalid date 'soon' in filter, expected dd/mm/yy or e.g. 3d, 2w",
+            ),
+            (
+                "label < red",
+                "Labels can only be compared with = or !=, not '<'",
+            ),
+            ("(done or done", "Unexpected end of filter"),
+            ("(done done)", "Expected ')' in filter, but found 'done'"),
+            ("done)", "Unexpected ')' in filter"),
+            ("urgent", "Unexpected 'urgent' in filter"),
+        ];
+        for (text, error) in &cases {
+            assert_eq!(Filter::parse(text, today).unwrap_err(), *error, "{text}");
+        }
+        assert!(Filter::parse("label = mauve", today)
+            .unwrap_err()
+            .starts_with("Unknown colour 'mauve'"));
+    }
+}
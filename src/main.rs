@@ -1,606 +1,9472 @@
 #![warn(clippy::all, clippy::pedantic, clippy::nursery)]
 #![allow(dead_code, clippy::unnecessary_wraps)]
 
+mod cache;
+mod calendar;
+mod config;
+mod diff;
+mod filter;
 mod parser;
+#[cfg(feature = "serve")]
+mod serve;
+#[cfg(feature = "ui")]
+mod ui;
 
 use chrono::Datelike;
-use chrono::{DateTime, Local};
+use chrono::Local;
+use config::Config;
 
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
 use std::io::Read;
 use std::io::Write;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+use std::sync::OnceLock;
+use unicode_normalization::UnicodeNormalization;
+use unicode_width::UnicodeWidthStr;
 
-const TOAD: &'static str = r#"       _     _
+const TOAD: &str = r#"       _     _
       (')-=-(')
     __(   "   )__
    / _/'-----'\_ \
 ___\\ \\     // //___
 >____)/_\---/_\(____<"#;
 
-#[derive(Debug)]
-pub struct ListItem {
-    name: String,
-    date: Option<chrono::NaiveDate>,
-    done: bool,
+// set while the list file is being replaced, so ctrl-c waits until the save is done
+static SAVING: AtomicBool = AtomicBool::new(false);
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+// what todo exits with once the command is done, for commands that report something that way
+static EXIT_CODE: AtomicI32 = AtomicI32::new(0);
+// set by --out, so the output is plain text even when todo is run from a terminal
+static TO_FILE: AtomicBool = AtomicBool::new(false);
+// priority_style = symbols in the config file, or --priority-symbols
+static PRIORITY_SYMBOLS: AtomicBool = AtomicBool::new(false);
+// from verify_saves in the config file
+static VERIFY_SAVES: AtomicBool = AtomicBool::new(true);
+// from TODO_TZ or the config file. the system's time zone is used if it's not set
+static TIMEZONE: OnceLock<chrono_tz::Tz> = OnceLock::new();
+// from priority_colours in the config file
+static PRIORITY_COLOURS: OnceLock<Vec<Option<u8>>> = OnceLock::new();
+// done_marker, undone_marker and list_done_marker from the config file, padded to the same width
+static MARKERS: OnceLock<[String; 3]> = OnceLock::new();
+// TODO_TODAY pretends it's that day instead, so the output for a given file is always the same
+static FIXED_TODAY: OnceLock<chrono::NaiveDate> = OnceLock::new();
+
+// what day it is, in TIMEZONE. everything that compares dates with today should use this, so
+// they all agree on when the day changes
+fn today() -> chrono::NaiveDate {
+    if let Some(today) = FIXED_TODAY.get() {
+        return *today;
+    }
+    let now = chrono::Utc::now();
+    TIMEZONE.get().map_or_else(
+        || now.with_timezone(&Local).date_naive(),
+        |tz| date_in(*tz, now),
+    )
 }
 
-#[derive(Debug)]
-pub enum ListEntry {
-    Item(ListItem),
-    List(String),
+// the date it is in `tz` at the moment `now`, whatever the clocks there are doing
+fn date_in(tz: chrono_tz::Tz, now: chrono::DateTime<chrono::Utc>) -> chrono::NaiveDate {
+    now.with_timezone(&tz).date_naive()
 }
 
-fn serialise_date(date: chrono::NaiveDate) -> i32 {
-    date.num_days_from_ce()
+// the date and time now, in TIMEZONE, for the history log
+fn now() -> String {
+    let format = "%Y-%m-%d %H:%M:%S";
+    TIMEZONE.get().map_or_else(
+        || Local::now().format(format).to_string(),
+        |tz| {
+            chrono::Utc::now()
+                .with_timezone(tz)
+                .format(format)
+                .to_string()
+        },
+    )
 }
 
-fn deserialise_date(date: i32) -> chrono::NaiveDate {
-    chrono::NaiveDate::from_num_days_from_ce_opt(date).unwrap()
+fn set_clock(config: &Config) -> Result<(), String> {
+    if let Some(tz) = config.timezone {
+        TIMEZONE.set(tz).unwrap();
+    }
+    if let Some(date) = std::env::var("TODO_TODAY")
+        .ok()
+        .filter(|date| !date.is_empty())
+    {
+        let today =
+            parse_date(&date).ok_or_else(|| format!("Invalid date '{date}' in TODO_TODAY"))?;
+        FIXED_TODAY.set(today).unwrap();
+    }
+    Ok(())
 }
 
-#[derive(Debug)]
-pub struct TodoList {
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ListItem {
     name: String,
-    items: Vec<ListEntry>,
+    date: Option<chrono::NaiveDate>,
+    done: bool,
+    // how often it repeats, if it does. `date` is the next occurrence
+    repeat: Option<RepeatKind>,
+    // one of LABEL_COLOURS
+    label: Option<String>,
+    // how much of it has been done so far, as a percentage
+    progress: Option<u8>,
+    // when it was marked as done
+    completed: Option<chrono::NaiveDate>,
+    // when it was added. None for items added before this was kept track of
+    created: Option<chrono::NaiveDate>,
+    // 0 for none, the higher the more important
+    priority: u8,
+    // a link to a ticket, document etc. that it's about
+    url: Option<String>,
+    // for things in the trash (see use_trash in the config file), the list they were taken out
+    // of, and whether it was the reference to the list with this name rather than an item
+    trashed_from: Option<String>,
+    trashed_reference: bool,
+    // a checklist of smaller steps, each with whether it's been done
+    subitems: Vec<(String, bool)>,
+    // with stable_ids, a random ID it keeps whatever happens to its name or where it is, for
+    // things outside todo to keep track of it by
+    id: Option<String>,
 }
 
-impl TodoList {
-    fn new(name: String) -> Self {
-        Self {
-            name,
-            items: Vec::new(),
-        }
-    }
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RepeatKind {
+    EveryNDays(i64),
+    // every week on that day
+    Weekly(chrono::Weekday),
+    // on that day of every month, or the last day of the month in shorter months
+    MonthlyOnDay(u32),
+}
 
-    fn num_valid_entries<F: FnMut(&&ListItem) -> bool>(
-        &self,
-        all: &[Self],
-        predicate: &mut F,
-    ) -> usize {
-        self.items
+impl RepeatKind {
+    // 3d, 2w, daily, weekly, monday, 1st, 15th etc.
+    fn parse(s: &str) -> Result<Self, String> {
+        if let Ok(weekday) = s.parse::<chrono::Weekday>() {
+            return Ok(Self::Weekly(weekday));
+        }
+        let err = || {
+            format!("Invalid repeat period '{s}', expected a number of days or weeks (e.g. 3d, 2w), a day of the week or a day of the month (e.g. 1st, 15th)")
+        };
+        if let Some(day) = ["st", "nd", "rd", "th"]
             .iter()
-            .map(|item| match item {
-                ListEntry::Item(item) => usize::from(predicate(&item)),
-                ListEntry::List(name) => get_list_by_name(all, name)
-                    .unwrap()
-                    .num_valid_entries(all, predicate),
-            })
-            .sum()
-    }
-
-    fn print<F: FnMut(&&ListItem) -> bool>(&self, all: &[Self], mut predicate: F) -> String {
-        let mut acc = String::new();
-        let max = self.get_max_size(all, 0, &mut predicate);
-        self.print_inner(all, 0, max, &mut predicate, true, &mut acc);
-        acc
+            .find_map(|suffix| s.strip_suffix(suffix))
+        {
+            return match day.parse() {
+                Ok(day @ 1..=31) => Ok(Self::MonthlyOnDay(day)),
+                _ => Err(err()),
+            };
+        }
+        let (num, days_per_unit) = match s {
+            "daily" => ("1", 1),
+            "weekly" => ("1", 7),
+            _ if s.ends_with('d') => (&s[..s.len() - 1], 1),
+            _ if s.ends_with('w') => (&s[..s.len() - 1], 7),
+            _ => (s, 1),
+        };
+        match num.parse::<i64>() {
+            Ok(num) if num > 0 => Ok(Self::EveryNDays(num * days_per_unit)),
+            _ => Err(err()),
+        }
     }
 
-    fn print_without_date<F: FnMut(&&ListItem) -> bool>(
-        &self,
-        all: &[Self],
-        mut predicate: F,
-    ) -> String {
-        let mut acc = String::new();
-        let max = self.get_max_size(all, 0, &mut predicate);
-        self.print_inner(all, 0, max, &mut predicate, false, &mut acc);
-        acc
+    // the next occurrence after `date`
+    fn next(self, date: chrono::NaiveDate) -> chrono::NaiveDate {
+        match self {
+            Self::EveryNDays(days) => date + chrono::Duration::days(days),
+            Self::Weekly(weekday) => {
+                let tomorrow = date + chrono::Duration::days(1);
+                tomorrow
+                    + chrono::Duration::days(i64::from(days_until(tomorrow.weekday(), weekday)))
+            }
+            Self::MonthlyOnDay(day) => {
+                let (year, month) = if date.month() == 12 {
+                    (date.year() + 1, 1)
+                } else {
+                    (date.year(), date.month() + 1)
+                };
+                day_of_month(year, month, day)
+            }
+        }
     }
 
-    fn print_inner<F: FnMut(&&ListItem) -> bool>(
-        &self,
-        all: &[Self],
-        indent: usize,
-        maxsize: usize,
-        predicate: &mut F,
-        print_date: bool,
-        acc: &mut String,
-    ) {
-        use std::fmt::Write;
-        if self.num_valid_entries(all, predicate) == 0 {
-            return;
+    // how it's described to people, like "every 2 weeks" or "on the 15th of every month"
+    fn describe(self) -> String {
+        match self {
+            Self::EveryNDays(1) => "every day".to_string(),
+            Self::EveryNDays(7) => "every week".to_string(),
+            Self::EveryNDays(days) if days % 7 == 0 => format!("every {} weeks", days / 7),
+            Self::EveryNDays(days) => format!("every {days} days"),
+            Self::Weekly(weekday) => {
+                let name = match weekday {
+                    chrono::Weekday::Mon => "monday",
+                    chrono::Weekday::Tue => "tuesday",
+                    chrono::Weekday::Wed => "wednesday",
+                    chrono::Weekday::Thu => "thursday",
+                    chrono::Weekday::Fri => "friday",
+                    chrono::Weekday::Sat => "saturday",
+                    chrono::Weekday::Sun => "sunday",
+                };
+                format!("every {name}")
+            }
+            Self::MonthlyOnDay(_) => format!("on the {self} of every month"),
         }
-        let entries_to_print = self
-            .items
-            .iter()
-            .filter(|item| match item {
-                ListEntry::Item(item) => predicate(&item),
-                ListEntry::List(_) => true,
-            })
-            .collect::<Vec<&ListEntry>>();
+    }
 
-        let all_done = self.num_valid_entries(all, &mut |item: &&ListItem| !item.done) == 0;
-        writeln!(
-            acc,
-            "{}{}{}:",
-            if all_done { "✓" } else { " " },
-            " ".repeat(indent * 4),
-            self.name
-        )
-        .unwrap();
-        let indent = indent + 1;
-        let indentstr = " ".repeat(indent * 4);
-        for entry in entries_to_print {
-            match entry {
-                ListEntry::List(list_name) => {
-                    get_list_by_name(all, list_name)
-                        .unwrap()
-                        .print_inner(all, indent, maxsize, predicate, print_date, acc);
-                }
-                ListEntry::Item(item) => {
-                    if print_date && item.date.is_some() {
-                        let tabs = " ".repeat(maxsize - indentstr.len() - item.name.len());
-                        let duration =
-                            item.date.unwrap() - chrono::Local::now().naive_local().date();
-                        let time_until = if duration.num_days() == 1 {
-                            "in 1 day".into()
-                        } else if duration.num_days() < 0 {
-                            format!("{} days ago", -duration.num_days())
-                        } else {
-                            format!("in {} days", duration.num_days())
-                        };
-                        writeln!(
-                            acc,
-                            "{}{}{}{}\t{} ({})",
-                            if item.done { "✓" } else { " " },
-                            indentstr,
-                            item.name,
-                            tabs,
-                            item.date.unwrap().format("%d/%m/%Y"),
-                            time_until,
-                            // item.priority
-                        )
-                        .unwrap();
-                    } else {
-                        writeln!(
-                            acc,
-                            "{}{}{}",
-                            if item.done { "✓" } else { " " },
-                            indentstr,
-                            item.name
-                        )
-                        .unwrap();
-                    }
-                }
-            }
+    // the number of days for the old JSON/YAML repeat_every field, 0 if it isn't a fixed number
+    const fn days(self) -> i64 {
+        match self {
+            Self::EveryNDays(days) => days,
+            Self::Weekly(_) => 7,
+            Self::MonthlyOnDay(_) => 0,
         }
     }
-    fn get_max_size<F: FnMut(&&ListItem) -> bool>(
-        &self,
-        all: &[Self],
-        indent: usize,
-        predicate: &mut F,
-    ) -> usize {
-        let mut max = indent * 4 + self.name.len() + 1;
-        let indent = indent + 1;
-        for entry in &self.items {
-            match entry {
-                ListEntry::List(list_name) => {
-                    max = std::cmp::max(
-                        max,
-                        get_list_by_name(all, list_name)
-                            .unwrap()
-                            .get_max_size(all, indent, predicate),
-                    );
-                }
-                ListEntry::Item(item) if predicate(&item) => {
-                    max = std::cmp::max(max, indent * 4 + item.name.len());
-                }
-                ListEntry::Item(_) => (),
+}
+
+// written the same way it's parsed in the list file, as [every=...]
+impl std::fmt::Display for RepeatKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::EveryNDays(days) => write!(f, "{days}"),
+            Self::Weekly(weekday) => write!(f, "{}", weekday.to_string().to_lowercase()),
+            Self::MonthlyOnDay(day) => {
+                let suffix = match (day % 10, day % 100) {
+                    (_, 11..=13) => "th",
+                    (1, _) => "st",
+                    (2, _) => "nd",
+                    (3, _) => "rd",
+                    _ => "th",
+                };
+                write!(f, "{day}{suffix}")
             }
         }
-        max
     }
 }
 
-fn load(fname: &Path) -> std::io::Result<Vec<TodoList>> {
-    let mut file = std::fs::File::open(fname)?;
-    let mut contents = String::new();
-    file.read_to_string(&mut contents)?;
+// how many days on from `from` the next `to` is, 0 if it's the same day
+const fn days_until(from: chrono::Weekday, to: chrono::Weekday) -> u32 {
+    (7 + to.num_days_from_monday() - from.num_days_from_monday()) % 7
+}
 
-    let result = match parser::parse_str(&contents) {
-        Ok(l) => Ok(l),
-        Err(e) => Err(std::io::Error::new(std::io::ErrorKind::InvalidData, e.0)),
-    };
-    result
+// that day of the month, or the last day of the month if it doesn't have that many days
+fn day_of_month(year: i32, month: u32, day: u32) -> chrono::NaiveDate {
+    (1..=day)
+        .rev()
+        .find_map(|day| chrono::NaiveDate::from_ymd_opt(year, month, day))
+        .unwrap()
 }
 
-fn save(fname: &Path, lists: &[TodoList]) -> std::io::Result<()> {
-    let mut file = std::fs::File::create(fname)?;
-    let out = parser::emit_str(lists);
+// the label's dot and the space after it
+const LABEL_WIDTH: usize = 2;
+const LABEL_COLOURS: [(&str, u8); 7] = [
+    ("red", 31),
+    ("green", 32),
+    ("yellow", 33),
+    ("blue", 34),
+    ("magenta", 35),
+    ("cyan", 36),
+    ("white", 37),
+];
 
-    file.write_all(&out.into_bytes())?;
-    Ok(())
+// the ANSI colour for the time until a deadline `days` away: red once it's passed, yellow
+// on the day, and cyan if it's soon, i.e. from 1 up to and including `soon_days` days
+// away. anything further off isn't coloured
+const fn due_colour(days: i64, soon_days: i64) -> Option<u8> {
+    if days < 0 {
+        Some(31)
+    } else if days == 0 {
+        Some(33)
+    } else if days <= soon_days {
+        Some(36)
+    } else {
+        None
+    }
 }
 
-#[rustfmt::skip]
-fn usage() -> String {
-    "Usage:\ttodo <action> ...\n".to_string() +
-    "\tls  lists                        Show all the lists\n" +
-    "\tl   list <list name> [--small]   Show the items in the specified list.\n" +
-    "\tn   new <name>                   Create a new list\n" +
-    "\trl  rmlist <list>                Delete the specified list\n" +
-    "\ta   add <list> <name> [date]     Add a new item to the specified list\n" +
-    "\tal  addlist <dest> <src>         Add a reference of list <src> to list <dest>\n" +
-    "\td   done <list> <item>           Mark the specified item as done\n" +
-    "\tda  doneall <list>               Mark all items in list as done\n" +
-    "\tuda undoneall <list>             Mark all items in list as not done\n" +
-    "\trm  remove <list> <item>         Remove <item> from <list>\n" +
-    "\tmv  move <source> <item> <dest>  Move an <item> from the list <source> to <dest>\n" +
-    "\tmva moveall <source> <dest>      Move every item from <source> into <dest>. Does not move sublist of source into itself\n" +
-    "\trn  rename <list> <old> <new>    Rename an item in <list> from <old> to <new>\n" +
-    "\trl  renamelist <old> <new>       Rename the list <old> to <new>\n" +
-    // println!("\tr   repeat <list> <item> <time>  Set an item to repeat (mark as un-done) every <time>");
-    "\tar  autorm <list>                Remove all items in <list> that are marked as done\n" +
-    "\tt   today <list> [--short]       List all tasks with a deadline of today.\n                                         If --short is passed, return only the number of tasks, do not list them.\n" +
-    "\tw   week <list> [--short]        List all tasks with a deadline of within the next 7 days\n" +
-    "\tod  overdue <list> [--short]     List all non-completed tasks with a deadline in the past\n\n" +
-    "When specifying lists and items, only the first few characters of their names are needed, as long a they\n" +
-    "uniquely identify a single list or item. For example in a list containing both 'orange' and 'organic',\n" +
-    "'or' would not work but 'ora' would be interpreted as 'orange'. In a list containing 'or' and 'orange',\n" + 
-    "'or' would match 'or' because it's an exact match. 'ora' would be necessary to match 'orange'.\n\n" +
-    "The last argument to a command need not be quoted as additional arguments are automatically concatinated\n" +
-    "with a space. For example, `todo add list this item has multiple words` is valid."
+// how long ago something was, roughly, like 3d, 2w or 5mo
+fn format_age(days: i64) -> String {
+    let days = days.max(0);
+    if days < 14 {
+        format!("{days}d")
+    } else if days < 60 {
+        format!("{}w", days / 7)
+    } else if days < 365 {
+        format!("{}mo", days / 30)
+    } else {
+        format!("{}y", days / 365)
+    }
 }
 
-fn get_list_by_name<'a>(lists: &'a [TodoList], name: &str) -> Result<&'a TodoList, String> {
-    let mut item: Result<&'a TodoList, String> = Err(format!("List '{name}' does not exist"));
-    if name == "toad" {
-        item = Err(TOAD.to_string());
+// the ANSI colour an item's name is shown in for its priority, if any. priorities past the
+// end of priority_colours get the last one
+fn priority_colour(priority: u8) -> Option<u8> {
+    let colours = PRIORITY_COLOURS.get()?;
+    let idx = usize::from(priority).checked_sub(1)?;
+    colours
+        .get(idx)
+        .or_else(|| colours.last())
+        .copied()
+        .flatten()
+}
+
+// and the other way round, for showing the settings
+fn label_name(code: u8) -> &'static str {
+    LABEL_COLOURS
+        .iter()
+        .find(|(_, other)| *other == code)
+        .map_or("none", |(name, _)| name)
+}
+
+// the ANSI colour code for a label
+fn label_colour(label: &str) -> Result<u8, String> {
+    LABEL_COLOURS
+        .iter()
+        .find(|(name, _)| *name == label)
+        .map(|(_, code)| *code)
+        .ok_or_else(|| {
+            let names = LABEL_COLOURS.map(|(name, _)| name);
+            format!(
+                "Unknown colour '{label}', expected one of {}",
+                names.join(", ")
+            )
+        })
+}
+
+impl ListItem {
+    // how many columns the item takes up besides its name, after an indent that many columns
+    // wide: the suffix, and the bit of the label's dot that doesn't fit in the indent
+    fn extra_width(&self, indent: usize, opts: PrintOptions) -> usize {
+        let label = if self.label.is_some() && opts.colour {
+            LABEL_WIDTH.saturating_sub(indent)
+        } else {
+            0
+        };
+        self.name_suffix().width() + label
     }
-    for i in lists {
-        if i.name == name {
-            return Ok(i);
-        }
+
+    // shown after the name of items that aren't done yet: the priority and how far along it is
+    fn name_suffix(&self) -> String {
+        self.name_suffix_with(PRIORITY_SYMBOLS.load(Ordering::Relaxed))
     }
-    for i in lists {
-        if i.name.starts_with(name) {
-            if item.is_ok() {
-                return Err(format!(
-                    "List '{name}' is not specific enough to match a single item"
-                ));
+
+    fn name_suffix_with(&self, priority_symbols: bool) -> String {
+        use std::fmt::Write;
+        let mut acc = String::new();
+        if self.done {
+            return acc;
+        }
+        match self.priority {
+            0 => (),
+            // a few exclamation marks, which get hard to count after three
+            priority @ 1..=3 if priority_symbols => {
+                write!(acc, " {}", "!".repeat(priority.into())).unwrap();
             }
-            item = Ok(i);
+            priority if priority_symbols => write!(acc, " !{priority}").unwrap(),
+            priority => write!(acc, " [p{priority}]").unwrap(),
         }
+        if let Some(progress) = self.progress {
+            write!(acc, " [{progress}%]").unwrap();
+        }
+        if !self.subitems.is_empty() {
+            let done = self.subitems.iter().filter(|(_, done)| *done).count();
+            write!(acc, " [{done}/{}]", self.subitems.len()).unwrap();
+        }
+        acc
     }
-    item
-}
 
-fn get_mut_list_by_name<'a>(
-    lists: &'a mut [TodoList],
-    name: &str,
-) -> Result<&'a mut TodoList, String> {
-    let mut item: Result<&'a mut TodoList, String> = Err(format!("List '{name}' does not exist"));
-    for i in lists {
-        if i.name == name {
-            return Ok(i);
-        }
-        if i.name.starts_with(name) {
-            if item.is_ok() {
-                return Err(format!(
-                    "List '{name}' is not specific enough to match a single item"
-                ));
-            }
-            item = Ok(i);
+    // the checklist, one level further in than the item, each with its own done marker
+    fn print_subitems(&self, indentstr: &str, opts: PrintOptions, acc: &mut String) {
+        use std::fmt::Write;
+        let indentstr = format!("{indentstr}{}", opts.indent(1));
+        let number_width = opts.number_width(self.subitems.len());
+        for (idx, (text, done)) in self.subitems.iter().enumerate() {
+            let number = if opts.numbered {
+                format!("{:<number_width$}", format!("[{idx}]"))
+            } else {
+                String::new()
+            };
+            writeln!(
+                acc,
+                "{}{indentstr}{number}{text}",
+                done_marker(*done, opts.ascii)
+            )
+            .unwrap();
         }
     }
-    item
-}
 
-fn get_index_by_name(list: &TodoList, itemname: &str) -> Result<usize, String> {
-    let mut idx = Err(format!("Item '{itemname}' does not exist"));
-    for (item_index, item) in list.items.iter().enumerate() {
-        let this_item_name = match &item {
-            ListEntry::List(l) => l,
-            ListEntry::Item(i) => &i.name,
-        };
-        if this_item_name == itemname {
-            idx = Ok(item_index);
+    fn set_done(&mut self, done: bool) {
+        if done && !self.done {
+            self.completed = Some(today());
+        } else if !done {
+            self.completed = None;
+        }
+        self.done = done;
+        if done && self.progress.is_some() {
+            self.progress = Some(100);
         }
     }
 
-    if idx.is_err() {
-        for (item_index, item) in list.items.iter().enumerate() {
-            let this_item_name = match &item {
-                ListEntry::List(l) => l,
-                ListEntry::Item(i) => &i.name,
+    fn print(&self, indentstr: &str, name_width: usize, opts: PrintOptions, acc: &mut String) {
+        use std::fmt::Write;
+        let suffix = self.name_suffix();
+        let extra_width = self.extra_width(indentstr.width(), opts);
+        let name = if opts.width.is_some() && !opts.dates_below {
+            truncate(
+                &self.name,
+                name_width.saturating_sub(extra_width),
+                opts.ascii,
+            )
+        } else {
+            Cow::from(&self.name)
+        };
+        // lines under the item line up with its name
+        let continuation = " ".repeat(done_marker(false, opts.ascii).width() + indentstr.width());
+        let subitem_indent = indentstr.to_owned();
+        // the label goes in the last bit of the indent, if there's room, so it doesn't shift
+        // anything along. if there isn't, it takes up some of the item's own column
+        let indentstr = match (&self.label, opts.colour) {
+            (Some(label), true) => format!(
+                "{}\x1b[{}m{}\x1b[0m ",
+                indentstr
+                    .chars()
+                    .take(indentstr.chars().count().saturating_sub(LABEL_WIDTH))
+                    .collect::<String>(),
+                label_colour(label).unwrap_or(0),
+                if opts.ascii { "*" } else { "●" }
+            ),
+            _ => indentstr.to_owned(),
+        };
+        // OSC 8, which makes the name a link in terminals that support it
+        let shown_name = match (&self.url, opts.hyperlinks) {
+            (Some(url), true) => Cow::from(format!("\x1b]8;;{url}\x1b\\{name}\x1b]8;;\x1b\\")),
+            _ => Cow::from(&*name),
+        };
+        // the deadline gets its own colour after the name, so the two never clash
+        let shown_name = match (opts.colour && !self.done)
+            .then(|| priority_colour(self.priority))
+            .flatten()
+        {
+            Some(code) => Cow::from(format!("\x1b[{code}m{shown_name}\x1b[0m")),
+            None => shown_name,
+        };
+        write!(
+            acc,
+            "{}{}{}{}",
+            done_marker(self.done, opts.ascii),
+            indentstr,
+            shown_name,
+            suffix
+        )
+        .unwrap();
+        let padding = " ".repeat(name_width.saturating_sub(name.width() + extra_width));
+        // the age column goes between the names and the dates, so both still line up
+        if opts.age {
+            let age = self.created.map_or_else(
+                || if opts.ascii { "-" } else { "—" }.to_string(),
+                |created| format_age((opts.today - created).num_days()),
+            );
+            write!(acc, "{padding}  {age:>4}").unwrap();
+        }
+        if let (true, Some(date)) = (opts.dates, self.date) {
+            let tabs = if opts.age { "" } else { &padding };
+            let duration = date - opts.today;
+            let time_until = if opts.days_column {
+                format!("{:>5}", format!("{:+}", duration.num_days()))
+            } else if duration.num_days() == 1 {
+                "(in 1 day)".into()
+            } else if duration.num_days() < 0 {
+                format!("({} days ago)", -duration.num_days())
+            } else {
+                format!("(in {} days)", duration.num_days())
             };
-            if this_item_name.starts_with(itemname) {
-                if idx.is_err() {
-                    idx = Ok(item_index);
-                } else {
-                    return Err(format!(
-                        "Item '{itemname}' is not specific enough to match a single item"
-                    ));
-                }
+            let time_until = match (opts.colour && !self.done)
+                .then(|| due_colour(duration.num_days(), opts.soon_days))
+                .flatten()
+            {
+                Some(code) => format!("\x1b[{code}m{time_until}\x1b[0m"),
+                None => time_until,
+            };
+            if opts.dates_below {
+                write!(
+                    acc,
+                    "\n{continuation}  {} {time_until}",
+                    date.format("%d/%m/%Y")
+                )
+                .unwrap();
+            } else {
+                write!(acc, "{}\t{} {}", tabs, date.format("%d/%m/%Y"), time_until).unwrap();
             }
         }
+        if let (Some(url), false) = (&self.url, opts.hyperlinks) {
+            write!(acc, " ({url})").unwrap();
+        }
+        acc.push('\n');
+        self.print_subitems(&subitem_indent, opts, acc);
     }
-    idx
 }
 
-fn parse_date(s: &str) -> Option<chrono::NaiveDate> {
-    chrono::NaiveDate::parse_from_str(s, "%d/%m/%y").map_or_else(
-        |_| chrono::NaiveDate::parse_from_str(s, "%d/%m/%Y").ok(),
-        Some,
-    )
+#[derive(Debug, Clone)]
+pub enum ListEntry {
+    Item(ListItem),
+    List(String),
 }
 
-type CmdResult = Result<(String, bool), String>;
-
-fn cmd_list(lists: &[TodoList], name: &str) -> CmdResult {
-    if let Some(name) = name.strip_suffix("--short") {
-        let list = get_list_by_name(lists, name.trim_end())?;
-        let mut item_names: Vec<&str> = Vec::new();
-        for i in &list.items {
-            if let ListEntry::Item(i) = i {
-                if !i.done {
-                    item_names.push(&i.name);
-                }
-            }
+impl ListEntry {
+    fn name(&self) -> &str {
+        match self {
+            Self::List(name) => name,
+            Self::Item(item) => &item.name,
         }
-        Ok((item_names.join(", "), false))
-    } else {
-        let list = get_list_by_name(lists, name)?;
-        Ok((list.print(lists, |_| true), false))
     }
 }
 
-fn cmd_lists(lists: &[TodoList]) -> CmdResult {
-    let mut res = String::new();
-    for i in lists {
-        res.push_str(&i.name);
-        res.push('\n');
-    }
-    Ok((res, false))
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SortKey {
+    Name,
+    Date,
+    // highest first
+    Priority,
+    // newest first, by when items were added or done
+    Created,
+    Completed,
 }
 
-fn cmd_new(lists: &mut Vec<TodoList>, name: String) -> CmdResult {
-    lists.push(TodoList::new(name));
-    Ok((String::new(), true))
+impl SortKey {
+    fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "name" => Ok(Self::Name),
+            "date" | "days" => Ok(Self::Date),
+            "priority" => Ok(Self::Priority),
+            "created" => Ok(Self::Created),
+            "completed" => Ok(Self::Completed),
+            _ => Err(format!(
+                "Can't sort by '{s}', expected one of name, date, days, priority, created or completed"
+            )),
+        }
+    }
 }
 
-fn cmd_rmlist(lists: &mut Vec<TodoList>, name: &str) -> CmdResult {
-    let name = get_list_by_name(lists, name)?.name.clone();
-    lists.retain(|l| l.name != name);
-    Ok((String::new(), true))
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ListSortKey {
+    Name,
+    // most items first
+    Count,
+    // soonest upcoming deadline first, lists without one at the end
+    Due,
 }
 
-fn cmd_add(lists: &mut [TodoList], args: &[String]) -> CmdResult {
-    let list = get_mut_list_by_name(lists, &args[0])?;
-    let last_arg = &args[args.len() - 1];
-
-    let (name, date) = parse_date(last_arg).map_or_else(
-        || (args[1..].join(" "), None),
-        |timestamp| (args[1..(args.len() - 1)].join(" "), Some(timestamp)),
-    );
+impl ListSortKey {
+    fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "name" => Ok(Self::Name),
+            "count" => Ok(Self::Count),
+            "due" => Ok(Self::Due),
+            _ => Err(format!(
+                "Can't sort lists by '{s}', expected one of name, count or due"
+            )),
+        }
+    }
 
-    list.items.push(ListEntry::Item(ListItem {
-        name,
-        date,
-        done: false,
-    }));
-    Ok((String::new(), true))
+    fn sort<'a>(self, all: &'a [TodoList], lists: &mut [&'a TodoList]) {
+        let today = today();
+        match self {
+            Self::Name => lists.sort_by(|a, b| a.name.cmp(&b.name)),
+            Self::Count => {
+                lists.sort_by_cached_key(|list| std::cmp::Reverse(list.walk_items(all).len()));
+            }
+            Self::Due => lists.sort_by_cached_key(|list| {
+                let date = list.next_deadline(all, today).and_then(|item| item.date);
+                (date.is_none(), date)
+            }),
+        }
+    }
 }
 
-fn cmd_addlist(lists: &mut [TodoList], dest_list: &str, src_list: &str) -> CmdResult {
-    let lname = get_list_by_name(lists, src_list)?.name.clone();
-    let list = get_mut_list_by_name(lists, dest_list)?;
-    list.items.push(ListEntry::List(lname));
-    Ok((String::new(), true))
+// which of two items with the same name is kept when lists are merged
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MergePolicy {
+    // done beats not done, then more progress, then the later deadline
+    MoreDone,
+    // the later deadline (any deadline beats none), then the same as MoreDone
+    Later,
 }
 
-fn cmd_done(lists: &mut [TodoList], list_name: &str, item_name: &str) -> CmdResult {
-    let list = get_mut_list_by_name(lists, list_name)?;
-    let idx = get_index_by_name(list, item_name)?;
-    if let ListEntry::Item(i) = &mut list.items[idx] {
-        i.done = !i.done;
-        Ok((String::new(), true))
-    } else {
-        Err("You can't done a list silly (todo add this feature cos its cool)".to_string())
+impl MergePolicy {
+    fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "done" => Ok(Self::MoreDone),
+            "later" => Ok(Self::Later),
+            _ => Err(format!(
+                "Unknown merge policy '{s}', expected done or later"
+            )),
+        }
     }
-}
 
-fn cmd_doneall(lists: &mut [TodoList], list_name: &str, target_state: bool) -> CmdResult {
-    let list = get_mut_list_by_name(lists, list_name)?;
-    for item in &mut list.items {
-        if let ListEntry::Item(item) = item {
-            item.done = target_state;
+    // whether `theirs` should replace `mine`. if they're equal, `mine` stays
+    fn prefers(self, theirs: &ListItem, mine: &ListItem) -> bool {
+        let doneness = |item: &ListItem| (item.done, item.progress.unwrap_or(0));
+        match self {
+            Self::MoreDone => (doneness(theirs), theirs.date) > (doneness(mine), mine.date),
+            Self::Later => (theirs.date, doneness(theirs)) > (mine.date, doneness(mine)),
         }
     }
-    Ok((String::new(), true))
 }
 
-fn cmd_remove(lists: &mut [TodoList], list_name: &str, item_name: &str) -> CmdResult {
-    let list = get_mut_list_by_name(lists, list_name)?;
-    let idx = get_index_by_name(list, item_name)?;
-    list.items.remove(idx);
-    Ok((String::new(), true))
+// what list --resolve-refs does with the sublists in a list
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RefMode {
+    // show everything in them, under their own header
+    Inline,
+    // one line with the name and how many items are in it
+    Link,
+    // leave them out
+    Skip,
 }
 
-fn cmd_rename(lists: &mut [TodoList], list_name: &str, old: &str, new: &str) -> CmdResult {
-    let list = get_mut_list_by_name(lists, list_name)?;
-    let idx = get_index_by_name(list, old)?;
-    if let ListEntry::Item(i) = &mut list.items[idx] {
-        i.name = new.to_owned();
-        Ok((String::new(), true))
-    } else {
-        Err("Renaming a list entry doesn't really make sense".to_string())
+impl RefMode {
+    fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "inline" => Ok(Self::Inline),
+            "link" => Ok(Self::Link),
+            "skip" => Ok(Self::Skip),
+            _ => Err(format!(
+                "Unknown way to show sublists '{s}', expected inline, link or skip"
+            )),
+        }
     }
 }
 
-fn cmd_rnlist(lists: &mut [TodoList], old: &str, new: &str) -> CmdResult {
-    let list = get_mut_list_by_name(lists, old)?;
-    list.name = new.to_owned();
-    Ok((String::new(), true))
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EntryKinds {
+    All,
+    OnlyItems,
+    OnlyLists,
 }
 
-fn cmd_move(
-    lists: &mut [TodoList],
-    src_list_name: &str,
-    dest_list_name: &str,
-    item_name: &str,
-) -> CmdResult {
-    // check that the dest list exists first
-    // otherwise, either the borrow checker will yell at me (lists is borrowed mutable twice in src_list and dest_list)
-    // or a nonexistant dest list will casue the item to be removed and not replaced
-    let _ = get_list_by_name(lists, dest_list_name)?;
-    let src_list = get_mut_list_by_name(lists, src_list_name)?;
-    let item_idx = get_index_by_name(src_list, item_name)?;
-    let item = src_list.items.remove(item_idx);
+impl EntryKinds {
+    fn from_args(args: &mut Vec<String>) -> Result<Self, String> {
+        match (
+            take_flag(args, "--only-items"),
+            take_flag(args, "--only-lists"),
+        ) {
+            (true, true) => Err("Give either --only-items or --only-lists, not both".to_string()),
+            (true, false) => Ok(Self::OnlyItems),
+            (false, true) => Ok(Self::OnlyLists),
+            (false, false) => Ok(Self::All),
+        }
+    }
 
-    let dest_list = get_mut_list_by_name(lists, dest_list_name).unwrap(); // already checked
-    dest_list.items.push(item);
-    Ok((String::new(), true))
+    const fn shows(self, entry: &ListEntry) -> bool {
+        matches!(
+            (self, entry),
+            (Self::All, _)
+                | (Self::OnlyItems, ListEntry::Item(_))
+                | (Self::OnlyLists, ListEntry::List(_))
+        )
+    }
 }
-fn cmd_moveall(lists: &mut [TodoList], src_list_name: &str, dest_list_name: &str) -> CmdResult {
-    // check that the dest list exists first
-    // otherwise, either the borrow checker will yell at me (lists is borrowed mutable twice in src_list and dest_list)
-    // or a nonexistant dest list will casue the item to be removed and not replaced
-    let _ = get_list_by_name(lists, dest_list_name)?;
-    let src_list = get_mut_list_by_name(lists, src_list_name)?;
-    // Don't move a list into itself. Does not check recursively, so caution is still needed.
-    // let mut items = src_list
-    //     .items
-    //     .extract_if(|item| match item {
-    //         ListEntry::List(list) => list != dest_list_name,
-    //         _ => true,
-    //     })
-    //     .collect::<Vec<ListEntry>>();
 
-    // f***ing extract_if is nightly, so I guess I'll just implement it myself...
-    let mut items = Vec::new();
-    let mut i = 0;
-    while i < src_list.items.len() {
-        if matches!(&src_list.items[i], ListEntry::List(list) if list == dest_list_name) {
-            i += 1;
-        } else {
-            let val = src_list.items.remove(i);
-            items.push(val);
+// how many items in each list (and the lists in it) are shown, and how many aren't done.
+// worked out once for each print, rather than going through a list's sublists every time it
+// comes up, which gets slow with deeply nested lists
+struct Counts<'a> {
+    shown: HashMap<&'a str, usize>,
+    not_done: HashMap<&'a str, usize>,
+}
+
+impl<'a> Counts<'a> {
+    // for `root` and everything under it. the rest of the file isn't looked at
+    fn new<F: FnMut(&&ListItem) -> bool>(
+        root: &'a TodoList,
+        all: &'a [TodoList],
+        predicate: &mut F,
+    ) -> Self {
+        let (mut shown, mut not_done) = (HashMap::new(), HashMap::new());
+        for list in root.subtree(all) {
+            count_entries(list, all, predicate, &mut shown);
+            count_entries(list, all, &mut |item: &&ListItem| !item.done, &mut not_done);
         }
+        Self { shown, not_done }
     }
 
-    let dest_list = get_mut_list_by_name(lists, dest_list_name).unwrap(); // already checked
-    dest_list.items.append(&mut items);
-    Ok((String::new(), true))
+    fn shown(&self, list: &str) -> usize {
+        self.shown.get(list).copied().unwrap_or(0)
+    }
+
+    fn not_done(&self, list: &str) -> usize {
+        self.not_done.get(list).copied().unwrap_or(0)
+    }
 }
 
-fn cmd_autorm(lists: &mut [TodoList], list_name: &str) -> CmdResult {
-    let list = get_mut_list_by_name(lists, list_name)?;
-    list.items.retain(|item| match item {
-        ListEntry::Item(item) => !item.done,
-        ListEntry::List(_) => true,
+// like num_valid_entries, but remembers the count for each list in `acc`
+fn count_entries<'a, F: FnMut(&&ListItem) -> bool>(
+    list: &'a TodoList,
+    all: &'a [TodoList],
+    predicate: &mut F,
+    acc: &mut HashMap<&'a str, usize>,
+) -> usize {
+    if let Some(&count) = acc.get(list.name.as_str()) {
+        return count;
+    }
+    // so a list that (eventually) references itself doesn't go round forever
+    acc.insert(&list.name, 0);
+    let count = list
+        .items
+        .iter()
+        .map(|entry| match entry {
+            ListEntry::Item(item) => usize::from(predicate(&item)),
+            ListEntry::List(name) => get_list_by_name(all, name)
+                .map_or(0, |sublist| count_entries(sublist, all, predicate, acc)),
+        })
+        .sum();
+    acc.insert(&list.name, count);
+    count
+}
+
+// with list --collapse-done, takes the done items out of `entries`, along with the sublists where
+// everything that would be shown is done, and returns how many items that hides altogether
+fn collapse_done<F: FnMut(&&ListItem) -> bool>(
+    entries: &mut Vec<(usize, &ListEntry)>,
+    all: &[TodoList],
+    predicate: &mut F,
+    counts: &Counts,
+) -> usize {
+    let mut hidden = 0;
+    entries.retain(|(_, entry)| {
+        let (done, shown) = match entry {
+            ListEntry::Item(item) => (usize::from(item.done), 1),
+            ListEntry::List(name) => get_list_by_name(all, name).map_or((0, 0), |list| {
+                let mut done = |item: &&ListItem| item.done && predicate(item);
+                (list.num_valid_entries(all, &mut done), counts.shown(name))
+            }),
+        };
+        if done > 0 && done == shown {
+            hidden += done;
+            false
+        } else {
+            true
+        }
     });
-    Ok((String::new(), true))
+    hidden
 }
 
-fn cmd_timeperiods(lists: &[TodoList], args: &[String], op: &str) -> CmdResult {
-    use chrono::Duration;
-    // find out the minimum and maximum allowed difference between the deadline date and today
-    let (min_diff, max_diff, description) = match op {
-        "today" | "t" => (Duration::days(0), Duration::days(1), "today"),
-        "week" | "w" => (Duration::days(1), Duration::days(7), "this week"),
-        "overdue" | "od" => (
-            Duration::days(-365 * 1000), //1000 years ought to be enough
-            Duration::days(0),
-            "overdue",
-        ),
-        _ => unreachable!(),
-    };
+// with list --expand-done-last, the done items in each list go after the rest, otherwise
+// staying in the same order
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DoneLast {
+    Off,
+    // the sublists stay where they are, and the items move around them
+    KeepLists,
+    // --lists-last puts the sublists after the done items too
+    ListsLast,
+}
 
-    let (list_name, short) = if args[args.len() - 1] == "--short" {
-        (args[..args.len() - 1].join(" "), true)
-    } else {
-        (args.join(" "), false)
-    };
+impl DoneLast {
+    fn from_args(args: &mut Vec<String>) -> Self {
+        let done_last = take_flag(args, "--expand-done-last");
+        if take_flag(args, "--lists-last") {
+            Self::ListsLast
+        } else if done_last {
+            Self::KeepLists
+        } else {
+            Self::Off
+        }
+    }
+}
 
-    let list = get_list_by_name(lists, &list_name)?;
-    let now: DateTime<Local> = Local::now();
-    let today = now.date_naive();
-    let mut filter = |item: &&ListItem| {
-        item.date.is_some()
-            && !item.done
-            && item.date.unwrap() - today < max_diff
-            && item.date.unwrap() - today >= min_diff
-    };
-    if short {
-        let num = list.num_valid_entries(lists, &mut filter);
-        if num == 0 {
-            // don't bother printing if there's none. maybe should make this configurable.
-            return Ok((String::new(), false));
+fn move_done_last(entries: &mut [(usize, &ListEntry)], done_last: DoneLast) {
+    let is_done = |entry: &ListEntry| matches!(entry, ListEntry::Item(item) if item.done);
+    match done_last {
+        DoneLast::Off => (),
+        DoneLast::KeepLists => {
+            let slots = (0..entries.len())
+                .filter(|&idx| matches!(entries[idx].1, ListEntry::Item(_)))
+                .collect::<Vec<usize>>();
+            let mut items = slots.iter().map(|&idx| entries[idx]).collect::<Vec<_>>();
+            items.sort_by_key(|(_, entry)| is_done(entry));
+            for (slot, item) in slots.into_iter().zip(items) {
+                entries[slot] = item;
+            }
         }
-        Ok((
-            format!(
-                "You have {} deadline{} {}\n",
-                num,
-                if num == 1 { "" } else { "s" },
-                description
-            ),
-            false,
-        ))
-    } else {
-        Ok((list.print(lists, filter), false))
+        DoneLast::ListsLast => entries.sort_by_key(|(_, entry)| match entry {
+            ListEntry::Item(_) => u8::from(is_done(entry)),
+            ListEntry::List(_) => 2,
+        }),
     }
 }
 
-fn main() {
-    let args: Vec<String> = std::env::args().collect();
-    if args.len() < 2 {
-        println!("{}", usage());
-        return;
+// the order entries are shown in with list --sort
+fn sort_entries(entries: &mut [(usize, &ListEntry)], key: Option<SortKey>) {
+    match key {
+        Some(SortKey::Name) => entries.sort_by_key(|(_, entry)| entry.name()),
+        // undated items and sublists go at the end
+        Some(SortKey::Date) => entries.sort_by_key(|(_, entry)| match entry {
+            ListEntry::Item(item) => (item.date.is_none(), item.date),
+            ListEntry::List(_) => (true, None),
+        }),
+        Some(SortKey::Priority) => entries.sort_by_key(|(_, entry)| match entry {
+            ListEntry::Item(item) => (false, std::cmp::Reverse(item.priority)),
+            ListEntry::List(_) => (true, std::cmp::Reverse(0)),
+        }),
+        // items without the date, like old ones from before it was kept, go at the end
+        Some(key @ (SortKey::Created | SortKey::Completed)) => {
+            entries.sort_by_key(|(_, entry)| {
+                let date = match entry {
+                    ListEntry::Item(item) if key == SortKey::Created => item.created,
+                    ListEntry::Item(item) => item.completed,
+                    ListEntry::List(_) => None,
+                };
+                (date.is_none(), std::cmp::Reverse(date))
+            });
+        }
+        None => (),
     }
+}
 
-    let mut list_file = Path::new("todo.txt");
-    let mut lists;
-    let mut global_list_file;
-    match load(list_file) {
-        Ok(l) => lists = l,
-        Err(_) => {
-            global_list_file = dirs::config_dir()
-                .expect("Unable to locate config directory. What OS are you on?!");
-            global_list_file.push("todo");
-            std::fs::create_dir_all(&global_list_file).expect(
-                "Unable to create the config directory. Do you have the right permissions?",
-            );
-            global_list_file.push("todo.txt");
-            list_file = global_list_file.as_path();
-            lists = load(list_file).unwrap_or_default();
-        }
-    }
-
-    let nargs = args.len() - 2;
-    #[rustfmt::skip] // ree it looks better all nicely indented
-    let result = match args[1].as_str() {
-        "list"    | "l"       if nargs >= 1 => cmd_list(&lists, &args[2..].join(" ")),
-        "lists"   | "ls"      if nargs == 0 => cmd_lists(&lists),
-        "new"     | "n"       if nargs > 0 => cmd_new(&mut lists, args[2..].join(" ")),
-        "rmlist"  | "rl"      if nargs > 0 => cmd_rmlist(&mut lists, &args[2..].join(" ")),
-        "add"     | "a"       if nargs >= 2 => cmd_add(&mut lists, &args[2..]),
-        "addlist" | "al"      if nargs == 2 => cmd_addlist(&mut lists, &args[2], &args[3]),
-        "done"    | "d"       if nargs >= 2 => cmd_done(&mut lists, &args[2], &args[3..].join(" ")),
-        "autorm"  | "ar"      if nargs >= 1 => cmd_autorm(&mut lists, &args[2..].join(" ")),
-        "rename"  | "rn"      if nargs >= 3 => cmd_rename(&mut lists, &args[2], &args[3], &args[4..].join(" ")),
-        "renamelist" | "rl"   if nargs >= 2 => cmd_rnlist(&mut lists, &args[2], &args[3..].join(" ")),
-        "rm" | "remove" | "r" if nargs >= 2 => cmd_remove(&mut lists, &args[2], &args[3..].join(" ")),
-        "move" | "mv" | "m"   if nargs >= 3 => cmd_move(&mut lists, &args[2], &args[4..].join(" "), &args[3]),
-        "moveall" | "mvall"
-        | "mva" | "ma"        if nargs >= 2 => cmd_moveall(&mut lists, &args[2], &args[3..].join(" ")),
-        "today" | "t"
-        | "week" | "w"
-        | "overdue" | "od"    if nargs >= 1 => cmd_timeperiods(&lists, &args[2..], &args[1]),
-        "doneall" | "da" | "undoneall" | "uda" if nargs >= 1 => cmd_doneall(
-            &mut lists,
-            &args.join(" "),
-            args[1] == "doneall" || args[1] == "da"
-        ),
-        _ => Err(usage()),
-    };
-    match result {
-        Ok((msg, modified)) => {
-            print!("{msg}");
-            if modified {
-                save(list_file, &lists).unwrap();
+#[derive(Debug, Clone, Copy)]
+#[allow(clippy::struct_excessive_bools)]
+struct PrintOptions {
+    dates: bool,
+    kinds: EntryKinds,
+    // show the days until each deadline as a plain +N/-N column instead of "(in N days)"
+    days_column: bool,
+    sort: Option<SortKey>,
+    // merge the header of a list that only has one sublist to show into that sublist's
+    // header, as "parent/child:"
+    prune: bool,
+    // total width to fit the output in. item names are cut short so the dates still fit
+    width: Option<usize>,
+    colour: bool,
+    // no unicode, for terminals and logs that can't show it
+    ascii: bool,
+    // show sublists that only have one item to show as "sublist > item" instead of a header
+    collapse_single: bool,
+    // what the deadlines are counted from
+    today: chrono::NaiveDate,
+    // show the done items in each list as one line with how many there are
+    collapse_done: bool,
+    // how many columns each level of sublist is indented by, and whether to draw a line
+    // down the side of each level
+    indent_width: usize,
+    indent_guides: bool,
+    // put each entry's position in its list in front of it, for --index
+    numbered: bool,
+    // deadlines up to this many days away are coloured as coming up soon
+    soon_days: i64,
+    // make the names of items with a url links, instead of putting the url after them
+    hyperlinks: bool,
+    // if the names and dates don't fit in `width`, put each date on its own line under its
+    // item instead of cutting the names short
+    dates_below: bool,
+    // one "list › sublist:" header for each list with something to show in it, instead of
+    // nesting them
+    breadcrumbs: bool,
+    // how long ago each item was added, in a column after the names
+    age: bool,
+    refs: RefMode,
+    // --no-empty: sublists with nothing to show aren't even shown as a link
+    no_empty: bool,
+    done_last: DoneLast,
+    // the day weeks start on, for where --timeline stops naming days
+    week_start: chrono::Weekday,
+}
+
+impl Default for PrintOptions {
+    fn default() -> Self {
+        Self {
+            dates: true,
+            kinds: EntryKinds::All,
+            days_column: false,
+            sort: None,
+            prune: false,
+            width: None,
+            colour: false,
+            ascii: false,
+            collapse_single: false,
+            today: today(),
+            collapse_done: false,
+            indent_width: 4,
+            indent_guides: false,
+            numbered: false,
+            soon_days: 3,
+            hyperlinks: false,
+            dates_below: false,
+            breadcrumbs: false,
+            age: false,
+            refs: RefMode::Inline,
+            no_empty: false,
+            done_last: DoneLast::Off,
+            week_start: chrono::Weekday::Mon,
+        }
+    }
+}
+
+impl PrintOptions {
+    // the defaults, with the settings from the config file that every command shares
+    fn from_config(config: &Config) -> Self {
+        Self {
+            ascii: config.ascii,
+            collapse_single: config.collapse_single,
+            indent_width: config.indent_width,
+            indent_guides: config.indent_guides,
+            soon_days: config.soon_days,
+            week_start: config.week_start,
+            ..Default::default()
+        }
+    }
+
+    // whether a sublist gets a line (or its own header) in the list it's in
+    fn shows_sublist(self, all: &[TodoList], name: &str, counts: &Counts) -> bool {
+        self.refs != RefMode::Skip
+            && get_list_by_name(all, name).is_ok()
+            && (!self.no_empty || counts.shown(name) > 0)
+    }
+
+    // the widest the names can be (starting from `max`) for everything to fit in the width, or
+    // with dates_below, whether the dates have to go under the items
+    fn fit(mut self, max: usize) -> (Self, usize) {
+        let Some(width) = self.width else {
+            self.dates_below = false;
+            return (self, max);
+        };
+        // leave room for the done marker and everything after the name
+        let reserved = done_marker(false, self.ascii).width()
+            + if self.dates { DATE_COLUMN_WIDTH } else { 0 }
+            + if self.age { AGE_COLUMN_WIDTH } else { 0 };
+        self.dates_below &= self.dates && max + reserved > width;
+        if self.dates_below {
+            (self, max)
+        } else {
+            (self, max.min(width.saturating_sub(reserved)))
+        }
+    }
+
+    // how wide the "[N] " in front of the entries of a list with `len` entries is
+    fn number_width(self, len: usize) -> usize {
+        if self.numbered {
+            format!("[{}] ", len.saturating_sub(1)).len()
+        } else {
+            0
+        }
+    }
+
+    // the indent for something `depth` sublists down. it's always depth * indent_width
+    // columns wide, whatever it's drawn with
+    fn indent(self, depth: usize) -> String {
+        if self.indent_guides {
+            let guide = if self.ascii { "|" } else { "│" };
+            let level = format!("{guide}{}", " ".repeat(self.indent_width.saturating_sub(1)));
+            level.repeat(depth)
+        } else {
+            " ".repeat(depth * self.indent_width)
+        }
+    }
+}
+
+fn serialise_date(date: chrono::NaiveDate) -> i32 {
+    date.num_days_from_ce()
+}
+
+const fn deserialise_date(date: i32) -> chrono::NaiveDate {
+    chrono::NaiveDate::from_num_days_from_ce_opt(date).unwrap()
+}
+
+#[derive(Debug, Clone)]
+pub struct TodoList {
+    name: String,
+    items: Vec<ListEntry>,
+    // items added without a date are due this many days after they're added
+    default_due: Option<i64>,
+    // the priority items added to it get (0 for none)
+    default_priority: u8,
+    // the same as ListItem::id
+    id: Option<String>,
+}
+
+impl TodoList {
+    const fn new(name: String) -> Self {
+        Self {
+            name,
+            items: Vec::new(),
+            default_due: None,
+            default_priority: 0,
+            id: None,
+        }
+    }
+
+    fn num_valid_entries<F: FnMut(&&ListItem) -> bool>(
+        &self,
+        all: &[Self],
+        predicate: &mut F,
+    ) -> usize {
+        self.items
+            .iter()
+            .map(|item| match item {
+                ListEntry::Item(item) => usize::from(predicate(&item)),
+                // a reference to a list that's been removed (rmlist --keep-references)
+                ListEntry::List(name) => get_list_by_name(all, name)
+                    .map_or(0, |list| list.num_valid_entries(all, predicate)),
+            })
+            .sum()
+    }
+
+    // this list and every list it references, directly or through other sublists. each
+    // list is only visited once, so shared (or circular) references don't turn up twice
+    fn subtree<'a>(&'a self, all: &'a [Self]) -> Vec<&'a Self> {
+        let mut visited = HashSet::new();
+        let mut acc = Vec::new();
+        self.subtree_inner(all, &mut visited, &mut acc);
+        acc
+    }
+
+    fn subtree_inner<'a>(
+        &'a self,
+        all: &'a [Self],
+        visited: &mut HashSet<&'a str>,
+        acc: &mut Vec<&'a Self>,
+    ) {
+        if !visited.insert(&self.name) {
+            return;
+        }
+        acc.push(self);
+        for entry in &self.items {
+            if let ListEntry::List(name) = entry {
+                if let Ok(list) = get_list_by_name(all, name) {
+                    list.subtree_inner(all, visited, acc);
+                }
             }
         }
-        Err(e) => eprintln!("{e}"),
+    }
+
+    // every item in this list and in the lists it references
+    fn walk_items<'a>(&'a self, all: &'a [Self]) -> Vec<&'a ListItem> {
+        self.subtree(all)
+            .into_iter()
+            .flat_map(|list| &list.items)
+            .filter_map(|entry| match entry {
+                ListEntry::Item(item) => Some(item),
+                ListEntry::List(_) => None,
+            })
+            .collect()
+    }
+
+    // like subtree, but with the path from this list to each one
+    fn subtree_with_paths<'a>(&'a self, all: &'a [Self]) -> Vec<(String, &'a Self)> {
+        let mut visited = HashSet::new();
+        let mut acc = Vec::new();
+        let mut stack = vec![(self.name.clone(), self)];
+        while let Some((path, list)) = stack.pop() {
+            if !visited.insert(list.name.as_str()) {
+                continue;
+            }
+            // pushed in reverse, so they come off the stack in the order they're in the list
+            for entry in list.items.iter().rev() {
+                if let ListEntry::List(name) = entry {
+                    if let Ok(sublist) = get_list_by_name(all, name) {
+                        stack.push((format!("{path}/{}", sublist.name), sublist));
+                    }
+                }
+            }
+            acc.push((path, list));
+        }
+        acc
+    }
+
+    // like walk_items, but with the path from this list to the one each item is in
+    fn walk_items_with_path<'a>(&'a self, all: &'a [Self]) -> Vec<(String, &'a ListItem)> {
+        let mut acc = Vec::new();
+        self.walk_items_with_path_inner(all, &self.name, &mut HashSet::new(), &mut acc);
+        acc
+    }
+
+    fn walk_items_with_path_inner<'a>(
+        &'a self,
+        all: &'a [Self],
+        path: &str,
+        visited: &mut HashSet<&'a str>,
+        acc: &mut Vec<(String, &'a ListItem)>,
+    ) {
+        if !visited.insert(&self.name) {
+            return;
+        }
+        for entry in &self.items {
+            match entry {
+                ListEntry::Item(item) => acc.push((path.to_owned(), item)),
+                ListEntry::List(name) => {
+                    if let Ok(list) = get_list_by_name(all, name) {
+                        let path = format!("{path}/{}", list.name);
+                        list.walk_items_with_path_inner(all, &path, visited, acc);
+                    }
+                }
+            }
+        }
+    }
+
+    // adds the entries of `other` that this list doesn't have yet, matching them up by name.
+    // when both have an item with the same name, the one `policy` prefers is kept
+    fn merge(&mut self, other: &Self, policy: MergePolicy) {
+        for entry in &other.items {
+            let existing = self.items.iter_mut().find(|mine| {
+                mine.name() == entry.name()
+                    && matches!(
+                        (&**mine, entry),
+                        (ListEntry::Item(_), ListEntry::Item(_))
+                            | (ListEntry::List(_), ListEntry::List(_))
+                    )
+            });
+            match (existing, entry) {
+                (Some(ListEntry::Item(mine)), ListEntry::Item(theirs)) => {
+                    if policy.prefers(theirs, mine) {
+                        mine.clone_from(theirs);
+                    }
+                }
+                (Some(_), _) => (),
+                (None, entry) => self.items.push(entry.clone()),
+            }
+        }
+    }
+
+    // the item that's not done yet with the soonest deadline from today on
+    fn next_deadline<'a>(
+        &'a self,
+        all: &'a [Self],
+        today: chrono::NaiveDate,
+    ) -> Option<&'a ListItem> {
+        self.walk_items(all)
+            .into_iter()
+            .filter(|item| !item.done && item.date.is_some_and(|date| date >= today))
+            .min_by_key(|item| item.date)
+    }
+
+    fn print<F: FnMut(&&ListItem) -> bool>(&self, all: &[Self], predicate: F) -> String {
+        self.print_with(all, predicate, PrintOptions::default())
+    }
+
+    fn print_without_date<F: FnMut(&&ListItem) -> bool>(
+        &self,
+        all: &[Self],
+        predicate: F,
+    ) -> String {
+        self.print_with(
+            all,
+            predicate,
+            PrintOptions {
+                dates: false,
+                ..Default::default()
+            },
+        )
+    }
+
+    fn print_with<F: FnMut(&&ListItem) -> bool>(
+        &self,
+        all: &[Self],
+        mut predicate: F,
+        opts: PrintOptions,
+    ) -> String {
+        if opts.breadcrumbs {
+            return self.print_with_breadcrumbs(all, predicate, opts);
+        }
+        let mut acc = String::new();
+        let counts = Counts::new(self, all, &mut predicate);
+        let max = self.get_max_size(all, 0, &mut predicate, opts, &counts);
+        let (opts, max) = opts.fit(max);
+        self.print_inner(all, 0, max, &mut predicate, opts, &counts, "", &mut acc);
+        acc
+    }
+
+    // the items under a header with the whole path to the list they're in, like
+    // "work › home:", so deep lists don't take a line for every level above them
+    fn print_with_breadcrumbs<F: FnMut(&&ListItem) -> bool>(
+        &self,
+        all: &[Self],
+        mut predicate: F,
+        opts: PrintOptions,
+    ) -> String {
+        use std::fmt::Write;
+        let mut acc = String::new();
+        // each list only gets one header, even if some of its items come after a sublist
+        let mut groups: Vec<(String, Vec<&ListItem>)> = Vec::new();
+        for (path, item) in self.walk_items_with_path(all) {
+            if !predicate(&item) {
+                continue;
+            }
+            match groups.iter_mut().find(|(other, _)| *other == path) {
+                Some((_, items)) => items.push(item),
+                None => groups.push((path, vec![item])),
+            }
+        }
+        let separator = if opts.ascii { " > " } else { " › " };
+        let max = groups
+            .iter()
+            .flat_map(|(_, items)| items)
+            .map(|item| {
+                opts.indent_width + item.name.width() + item.extra_width(opts.indent_width, opts)
+            })
+            .max()
+            .unwrap_or(0);
+        let (opts, max) = opts.fit(max);
+        let indentstr = opts.indent(1);
+        for (path, items) in groups {
+            writeln!(
+                acc,
+                "{}{}{}:",
+                list_marker(items.iter().all(|item| item.done), opts.ascii),
+                opts.indent(0),
+                path.replace('/', separator)
+            )
+            .unwrap();
+            for item in items {
+                item.print(
+                    &indentstr,
+                    max.saturating_sub(opts.indent_width),
+                    opts,
+                    &mut acc,
+                );
+            }
+        }
+        acc
+    }
+
+    // every item on its own line, after the path to the list it's in, instead of under
+    // list headers
+    fn print_with_paths<F: FnMut(&&ListItem) -> bool>(
+        &self,
+        all: &[Self],
+        mut predicate: F,
+        opts: PrintOptions,
+    ) -> String {
+        let mut acc = String::new();
+        let items = self
+            .walk_items_with_path(all)
+            .into_iter()
+            .filter(|(_, item)| predicate(item))
+            .map(|(path, item)| ListItem {
+                name: format!("{path}: {}", item.name),
+                ..item.clone()
+            })
+            .collect::<Vec<ListItem>>();
+        let max = items
+            .iter()
+            .map(|item| {
+                opts.indent_width
+                    + item.name.width()
+                    + item.extra_width(opts.indent_width, opts)
+                    + 1
+            })
+            .max()
+            .unwrap_or(0);
+        let (opts, max) = opts.fit(max);
+        let indentstr = opts.indent(1);
+        for item in &items {
+            item.print(
+                &indentstr,
+                max.saturating_sub(opts.indent_width),
+                opts,
+                &mut acc,
+            );
+        }
+        acc
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn print_inner<F: FnMut(&&ListItem) -> bool>(
+        &self,
+        all: &[Self],
+        indent: usize,
+        maxsize: usize,
+        predicate: &mut F,
+        opts: PrintOptions,
+        counts: &Counts,
+        header_prefix: &str,
+        acc: &mut String,
+    ) {
+        use std::fmt::Write;
+        if counts.shown(&self.name) == 0 {
+            return;
+        }
+        // along with where they are in the list, for --numbered
+        let mut entries_to_print = self
+            .items
+            .iter()
+            .enumerate()
+            .filter(|(_, entry)| opts.kinds.shows(entry))
+            .filter(|(_, item)| match item {
+                ListEntry::Item(item) => predicate(&item),
+                ListEntry::List(name) => opts.shows_sublist(all, name, counts),
+            })
+            .collect::<Vec<(usize, &ListEntry)>>();
+        // the sublists that would have made it worth showing aren't being shown
+        if entries_to_print.is_empty() && opts.refs == RefMode::Skip {
+            return;
+        }
+        let hidden = if opts.collapse_done {
+            collapse_done(&mut entries_to_print, all, predicate, counts)
+        } else {
+            0
+        };
+        sort_entries(&mut entries_to_print, opts.sort);
+        move_done_last(&mut entries_to_print, opts.done_last);
+
+        if let Some(only) = (opts.prune && opts.refs == RefMode::Inline)
+            .then(|| {
+                let entries = entries_to_print.iter().map(|(_, entry)| *entry);
+                only_shown_sublist(all, &entries.collect::<Vec<&ListEntry>>(), counts)
+            })
+            .flatten()
+        {
+            let prefix = format!("{header_prefix}{}/", self.name);
+            only.print_inner(all, indent, maxsize, predicate, opts, counts, &prefix, acc);
+            return;
+        }
+
+        let all_done = counts.not_done(&self.name) == 0;
+        writeln!(
+            acc,
+            "{}{}{}{}:",
+            list_marker(all_done, opts.ascii),
+            opts.indent(indent),
+            header_prefix,
+            self.name
+        )
+        .unwrap();
+        let indent = indent + 1;
+        let indentstr = opts.indent(indent);
+        let name_width = maxsize.saturating_sub(indent * opts.indent_width);
+        let number_width = opts.number_width(self.items.len());
+        for (idx, entry) in entries_to_print {
+            let number = if opts.numbered {
+                format!("{:<number_width$}", format!("[{idx}]"))
+            } else {
+                String::new()
+            };
+            match entry {
+                ListEntry::List(list_name) if opts.refs == RefMode::Link => {
+                    let list = get_list_by_name(all, list_name).unwrap();
+                    list.print_link(all, predicate, opts, &format!("{indentstr}{number}"), acc);
+                }
+                ListEntry::List(list_name) => {
+                    let list = get_list_by_name(all, list_name).unwrap();
+                    match opts
+                        .collapse_single
+                        .then(|| list.single_shown_item(all, predicate, opts, counts))
+                        .flatten()
+                    {
+                        Some(item) => ListItem {
+                            name: format!("{number}{} > {}", list.name, item.name),
+                            ..item.clone()
+                        }
+                        .print(&indentstr, name_width, opts, acc),
+                        None => {
+                            list.print_inner(
+                                all, indent, maxsize, predicate, opts, counts, &number, acc,
+                            );
+                        }
+                    }
+                }
+                ListEntry::Item(item) if opts.numbered => ListItem {
+                    name: format!("{number}{}", item.name),
+                    ..item.clone()
+                }
+                .print(&indentstr, name_width, opts, acc),
+                ListEntry::Item(item) => item.print(&indentstr, name_width, opts, acc),
+            }
+        }
+        if hidden > 0 {
+            writeln!(
+                acc,
+                "{}{indentstr}({hidden} completed item{} hidden)",
+                done_marker(true, opts.ascii),
+                if hidden == 1 { "" } else { "s" }
+            )
+            .unwrap();
+        }
+    }
+
+    // the one line for this list with list --resolve-refs link, after `indent`
+    fn print_link<F: FnMut(&&ListItem) -> bool>(
+        &self,
+        all: &[Self],
+        predicate: &mut F,
+        opts: PrintOptions,
+        indent: &str,
+        acc: &mut String,
+    ) {
+        use std::fmt::Write;
+        let items = self.walk_items(all);
+        let shown = items.iter().filter(|item| predicate(item)).count();
+        writeln!(
+            acc,
+            "{}{indent}{}",
+            list_marker(items.iter().all(|item| item.done), opts.ascii),
+            link_text(self, shown, opts.ascii)
+        )
+        .unwrap();
+    }
+
+    // if the only thing that would be shown of this list is one item, returns that item
+    fn single_shown_item<F: FnMut(&&ListItem) -> bool>(
+        &self,
+        all: &[Self],
+        predicate: &mut F,
+        opts: PrintOptions,
+        counts: &Counts,
+    ) -> Option<&ListItem> {
+        let mut shown = None;
+        for entry in self.items.iter().filter(|entry| opts.kinds.shows(entry)) {
+            match entry {
+                ListEntry::Item(item) if predicate(&item) => {
+                    if shown.is_some() {
+                        return None;
+                    }
+                    shown = Some(item);
+                }
+                ListEntry::Item(_) => (),
+                ListEntry::List(name) => {
+                    if get_list_by_name(all, name).is_ok_and(|list| counts.shown(&list.name) > 0) {
+                        return None;
+                    }
+                }
+            }
+        }
+        shown
+    }
+
+    fn get_max_size<F: FnMut(&&ListItem) -> bool>(
+        &self,
+        all: &[Self],
+        indent: usize,
+        predicate: &mut F,
+        opts: PrintOptions,
+        counts: &Counts,
+    ) -> usize {
+        let mut max = indent * opts.indent_width + self.name.width() + 1;
+        let indent = indent + 1;
+        let number_width = opts.number_width(self.items.len());
+        for entry in &self.items {
+            match entry {
+                ListEntry::List(list_name) => {
+                    let Ok(list) = get_list_by_name(all, list_name) else {
+                        continue;
+                    };
+                    match opts.refs {
+                        RefMode::Skip => continue,
+                        RefMode::Link => {
+                            let shown = list
+                                .walk_items(all)
+                                .into_iter()
+                                .filter(|item| predicate(item))
+                                .count();
+                            max = std::cmp::max(
+                                max,
+                                indent * opts.indent_width
+                                    + number_width
+                                    + link_text(list, shown, opts.ascii).width(),
+                            );
+                            continue;
+                        }
+                        RefMode::Inline => (),
+                    }
+                    let size = opts
+                        .collapse_single
+                        .then(|| list.single_shown_item(all, predicate, opts, counts))
+                        .flatten()
+                        .map_or_else(
+                            // the sublist's own line has a number in front of it, but
+                            // not the items in it
+                            || {
+                                std::cmp::max(
+                                    list.get_max_size(all, indent, predicate, opts, counts),
+                                    indent * opts.indent_width
+                                        + number_width
+                                        + list.name.width()
+                                        + 1,
+                                )
+                            },
+                            // shown as "list > item"
+                            |item| {
+                                indent * opts.indent_width
+                                    + number_width
+                                    + list.name.width()
+                                    + 3
+                                    + item.name.width()
+                                    + item.extra_width(indent * opts.indent_width, opts)
+                            },
+                        );
+                    max = std::cmp::max(max, size);
+                }
+                ListEntry::Item(item) if predicate(&item) => {
+                    max = std::cmp::max(
+                        max,
+                        indent * opts.indent_width
+                            + number_width
+                            + item.name.width()
+                            + item.extra_width(indent * opts.indent_width, opts),
+                    );
+                }
+                ListEntry::Item(_) => (),
+            }
+        }
+        max
+    }
+}
+
+// ~/.config/todo or wherever it is on this OS, created if it doesn't exist yet
+fn config_dir() -> PathBuf {
+    // so the tests don't mess with the real config, history etc.
+    let mut dir = if cfg!(test) {
+        std::env::temp_dir().join("todo-tests")
+    } else {
+        dirs::config_dir().expect("Unable to locate config directory. What OS are you on?!")
+    };
+    dir.push("todo");
+    std::fs::create_dir_all(&dir)
+        .expect("Unable to create the config directory. Do you have the right permissions?");
+    dir
+}
+
+fn load(fname: &Path) -> std::io::Result<Vec<TodoList>> {
+    let mut file = std::fs::File::open(fname)?;
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes)?;
+    let contents = decode_utf8(bytes)?;
+
+    parser::parse_str(&contents)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e.0))
+}
+
+// like String::from_utf8, but if there's something that isn't UTF-8 in there (a stray byte
+// from another program, say), the error says exactly where, so it can be found and fixed
+fn decode_utf8(bytes: Vec<u8>) -> std::io::Result<String> {
+    String::from_utf8(bytes).map_err(|e| {
+        let offset = e.utf8_error().valid_up_to();
+        let before = &e.as_bytes()[..offset];
+        let line = before.split(|&b| b == b'\n').count();
+        let column = before.iter().rev().take_while(|&&b| b != b'\n').count() + 1;
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("invalid UTF-8 at byte {offset} (line {line}, column {column})"),
+        )
+    })
+}
+
+// todo.txt in the current directory if there is one, otherwise the one in the config directory
+fn list_file() -> PathBuf {
+    let local = PathBuf::from("todo.txt");
+    if local.exists() {
+        return local;
+    }
+    let mut global = config_dir();
+    global.push("todo.txt");
+    global
+}
+
+// only a file that isn't there counts as empty: one that can't be read or parsed is an
+// error, so it doesn't get overwritten with nothing on the next save
+// with `strict`, anything in the file that's only allowed for older versions or by accident
+// is an error too
+fn load_lists(path: &Path, strict: bool) -> Result<Vec<TodoList>, String> {
+    let lists = match load(path) {
+        Ok(lists) => lists,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(format!("Unable to load {}: {e}", path.display())),
+    };
+    if strict {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("Unable to load {}: {e}", path.display()))?;
+        let problems = parser::strict_problems(&contents);
+        if !problems.is_empty() {
+            return Err(format!(
+                "{} problem{} in {}:\n{}",
+                problems.len(),
+                if problems.len() == 1 { "" } else { "s" },
+                path.display(),
+                problems.join("\n")
+            ));
+        }
+    }
+    Ok(lists)
+}
+
+// the cache for this command, if it's turned on and the command can use it
+fn output_cache(list_file: &Path, config_file: &Path, args: &[String]) -> Option<cache::Cache> {
+    // --priority-symbols has been taken out of the arguments by now, so it goes in here
+    let context = format!(
+        "{}\u{1f}{:?}\u{1f}{}\u{1f}{}\u{1f}{}",
+        today(),
+        terminal_width(),
+        use_colour(),
+        supports_hyperlinks(),
+        PRIORITY_SYMBOLS.load(Ordering::Relaxed)
+    );
+    cache::Cache::new(list_file, config_file, args, &context)
+}
+
+// reads a whole file, or stdin if the name is "-"
+fn read_input(source: &str) -> Result<String, String> {
+    let mut bytes = Vec::new();
+    let result = if source == "-" {
+        std::io::stdin().read_to_end(&mut bytes)
+    } else {
+        std::fs::File::open(source).and_then(|mut file| file.read_to_end(&mut bytes))
+    };
+    let name = if source == "-" { "stdin" } else { source };
+    result
+        .and_then(|_| decode_utf8(bytes))
+        .map_err(|e| format!("Unable to read {name}: {e}"))
+}
+
+fn save(fname: &Path, lists: &[TodoList]) -> std::io::Result<()> {
+    let out = parser::emit_str(lists);
+    let mut tmp_name = fname.as_os_str().to_owned();
+    tmp_name.push(".tmp");
+    let tmp_name = Path::new(&tmp_name);
+
+    // --- critical section ---
+    // the new contents are written to a temporary file which is then renamed over the old
+    // one, so the list file is always either the old or the new version and never half
+    // written. ctrl-c is held off until the rename is done (see the handler in main)
+    SAVING.store(true, Ordering::SeqCst);
+    let result = write_synced(tmp_name, &out)
+        .and_then(|()| verify_saved(tmp_name, &out))
+        .and_then(|()| std::fs::rename(tmp_name, fname));
+    SAVING.store(false, Ordering::SeqCst);
+    // --- end of critical section ---
+
+    if INTERRUPTED.load(Ordering::SeqCst) {
+        std::process::exit(130);
+    }
+    result
+}
+
+fn write_and_replace(tmp_name: &Path, fname: &Path, contents: &str) -> std::io::Result<()> {
+    write_synced(tmp_name, contents)?;
+    std::fs::rename(tmp_name, fname)
+}
+
+fn write_synced(fname: &Path, contents: &str) -> std::io::Result<()> {
+    let mut file = std::fs::File::create(fname)?;
+    file.write_all(contents.as_bytes())?;
+    file.sync_all()
+}
+
+// reads the new file back before it replaces the old one, and checks it comes out the same.
+// if it doesn't, whatever's wrong with it would lose something the next time it's saved, so
+// it's thrown away and the old file is left as it was
+fn verify_saved(tmp_name: &Path, contents: &str) -> std::io::Result<()> {
+    if !VERIFY_SAVES.load(Ordering::SeqCst) {
+        return Ok(());
+    }
+    let problem = match std::fs::read_to_string(tmp_name) {
+        Err(e) => Some(format!("it couldn't be read back ({e})")),
+        Ok(written) if written != contents => {
+            Some("what was read back isn't what was written".to_string())
+        }
+        Ok(written) => match parser::parse_str(&written) {
+            Err(e) => Some(format!("it doesn't load ({})", e.0)),
+            Ok(lists) if parser::emit_str(&lists) != written => {
+                Some("it doesn't load the same as what was saved".to_string())
+            }
+            Ok(_) => None,
+        },
+    };
+    let Some(problem) = problem else {
+        return Ok(());
+    };
+    let _ = std::fs::remove_file(tmp_name);
+    Err(std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        format!("{problem}, so the list file has been left as it was"),
+    ))
+}
+
+#[rustfmt::skip]
+fn usage() -> String {
+    "Usage:\ttodo <action> ...\n".to_string() +
+    "\tls  lists                        Show all the lists. --only-items/--only-lists only shows the lists\n                                         which have items/sublists in them. --compact shows one line for each\n                                         list that isn't a sublist, with how many items are done and the next\n                                         deadline. --sort name|count|due orders the lists by name, number of\n                                         items (most first) or soonest deadline, and --persist saves that order.\n                                         --no-empty leaves out lists with no items in them or their sublists\n                                         --tree shows how the lists are nested in each other, with the lists that aren't in\n                                         any other at the top. A list that's already been shown is marked with ↻\n" +
+    "\tl   list <list name> [--small]   Show the items in the specified list.\n                                         --days shows the days until each deadline as a number (+N/-N)\n                                         --sort name|date|days|priority|created|completed orders the items in each list (sort\n                                         saves an order). created and completed put the newest first\n                                         --only-items hides sublists, --only-lists shows just the sublists\n                                         --width N cuts long names short to fit N columns (0 for no limit). Without it, if\n                                         the dates don't fit in the terminal (or $COLUMNS), they go under each item\n                                         --prune merges lists that only contain one sublist into a single\n                                         'list/sublist:' header. Also works with today, week and overdue\n                                         --label <colour> only shows items with that label\n                                         --min-priority N and --max-priority N only show items with a priority in\n                                         that range. Also work with today, week and overdue\n                                         --completed-since <date> only shows items done on or after <date>, and\n                                         --created-since <date> items added on or after it\n                                         --only <filter> only shows items matching <filter>, e.g.\n                                         \"priority>=2 and not done and due<7d\". It can use done, priority, progress,\n                                         due (a date, or days/weeks from today like 3d) and label, with = != < <= > >=,\n                                         and, or, not and brackets\n                                         --no-colour turns off colours (as does setting NO_COLOR)\n                                         --ascii only uses ASCII characters, e.g. [x] instead of ✓. Also\n                                         works with lists, today, week and overdue, or set ascii = true in\n                                         <config dir>/todo/config\n                                         --collapse shows sublists with just one item in them on one line, as\n                                         'sublist > item'. Also works with today, week and overdue, or set\n                                         collapse_single = true in the config file\n                                         --collapse-done shows the done items in each list as one line saying how\n                                         many there are. Sublists with everything in them done are folded into it too\n                                         --numbered puts each entry's position in its list in front of it, like [2].\n                                         Each sublist is numbered from 0 again. done, remove and move take --index N\n                                         instead of <item> to pick out an entry by that number\n                                         --ndjson prints the items (with the filters above) as one JSON object per line, with\n                                         the path to the list each one is in\n                                         --show-path shows the items (with the filters above) without the list headers, each\n                                         after the path to the list it's in, like 'work/home: item'\n                                         --breadcrumb shows each list with something to show once, under a 'work › home:'\n                                         header, instead of under a header for every level. Also works with today, week, overdue\n                                         and agenda\n                                         --age shows how long ago each item was added, like 3d, 2w or 5mo, in a column after\n                                         the names (— for items from before that was kept track of)\n                                         --no-dates leaves the deadlines out, for a plain checklist\n                                         --only-leaf-lists shows just the lists with items in them (rather than only sublists) under\n                                         <list>, each with its path, how many items it has and which other lists it's in\n                                         --resolve-refs link shows each sublist as one line with how many items are in it, instead\n                                         of everything in it, and --resolve-refs skip leaves the sublists out (inline is the default)\n                                         --no-empty (or --hide-empty) leaves out sublists with nothing to show, even as links. Lists\n                                         with nothing to show are left out of today, week and overdue anyway, so it's accepted there too\n                                         --timeline shows the items that aren't done under Overdue, Today, Tomorrow, the rest of this week's\n                                         days by name, Next week, Later and No date headings, most important first, each after the path to its\n                                         list. Without <list> it has the items in all the lists\n                                         --expand-done-last puts the done items in each list after the rest, without sorting them\n                                         otherwise. Sublists stay where they are, unless --lists-last puts them at the end too\n" +
+    "\tn   new <name>                   Create a new list. With --from-stdin instead of <name>, creates a list for\n                                         each line of stdin\n" +
+    "\trl  rmlist <list>                Delete the specified list, and take it out of any lists it's in, unless\n                                         --keep-references is passed\n" +
+    "\ta   add <list> <name> [date]     Add a new item to the specified list. If --every <period> is passed,\n                                         the item repeats every <period>, e.g. 3d, 2w, weekly, monday or 15th.\n                                         --unique refuses to add an item that's already in the list (ignoring\n                                         case), or set reject_duplicate_items = true in the config file\n                                         A name in double quotes (e.g. '\"12/12/24\"') is taken as it is, not as a date\n                                         --create-list makes <list> first if there isn't one with that name\n                                         --in <duration> makes it due that long from today, e.g. 3d, 2w, 1m or 1y, instead of\n                                         giving a date\n" +
+    "\tlc  listconfig <list> [--due <offset>] [--priority <n>]\n                                         Show or set the defaults for new items in <list>. --due +3d makes items\n                                         added without a date due 3 days later, --due none turns it off. --priority 2 gives\n                                         new items priority 2, --priority none turns it off\n" +
+    "\ttp  template save <list> <name>  Save a copy of the items in <list> as the template <name>\n" +
+    "\ttp  template apply <name> <list> Add fresh (not done, undated) copies of the items in template <name> to <list>\n" +
+    "\ttp  template list                Show all the saved templates\n" +
+    "\tim  import <file>                Add the lists in <file> (- to read from stdin) to your lists. Lists with\n                                         the same name as an existing list are merged into it, like merge does\n                                         It can be a whole list file or part of one, like from export --txt --list <list>\n" +
+    "\tbk  backup [file] [--yaml]       Write all the lists to <file> (- for stdout, default: a new file in\n                                         <config dir>/todo/backups). --yaml writes them as YAML instead\n" +
+    "\tex  export --txt|--csv|--json|--yaml|--ndjson [file]\n                                         Write all the lists to <file> (default: stdout) in that format. --txt is the same format\n                                         as the list file, which import reads back. --csv has one\n                                         row per item, with the path to the list it's in and ISO dates. --ndjson has one JSON object\n                                         per item, like list --ndjson\n                                         --list <list> only writes <list> and the lists in it (and the lists in those...), so\n                                         you can share one list with someone else and they can import it\n" +
+    "\t    diff <old file> <new file>   Show what changed between two list files, e.g. two versions of one in git:\n                                         lists and items added or removed, items done and deadlines changed. --json prints\n                                         the changes as JSON\n" +
+    "\twh  which <list> [item]          Show which list (and item in it) a name picks out, without doing anything\n                                         to them, or why it doesn't pick out just one. Handy for checking a short name or\n                                         pattern before using it with done or remove\n" +
+    "\t    batch < <file>               Run each line of <file> as a todo command (like 'add work \"fix it\" 12/12'),\n                                         then save once at the end. Lines starting with # are skipped, and if a line fails,\n                                         the error says which and the rest still run. A line that fails is undone, and each line that worked\n                                         goes in the history by itself\n" +
+    "\tal  addlist <dest> <src>         Add a reference of list <src> to list <dest>. Either can be a path through\n                                         sublists, like work/home. --copy adds a new copy of <src> (and its sublists)\n                                         instead, which doesn't change when <src> does\n" +
+    "\td   done <list> <item>           Mark the specified item as done, and run on_done_hook if it's set in the\n                                         config file. --index N instead of <item> picks it by its list --numbered number\n                                         An <item> with * or ? in it, like \"fix *\", marks every item it matches as done.\n                                         remove and move take patterns like that too\n                                         @last as <item> picks the last item added to <list>, here and in remove and rename\n                                         It says which item was done, by its whole name, as do remove, rename and move. --quiet leaves that out\n" +
+    "\tda  doneall <list> [--recursive] Mark all items in list as done. With --recursive, items in sublists too\n" +
+    "\tuda undoneall <list> [--recursive]\n                                         Mark all items in list as not done. With --recursive, items in sublists too\n" +
+    "\trm  remove <list> <item>         Remove <item> from <list>. --index N instead of <item> picks it by its\n                                         list --numbered number\n" +
+    "\tmv  move <source> <item> <dest>  Move an <item> from the list <source> to <dest>. <item> can be a sublist,\n                                         and <source> and <dest> can be paths like work/home. --index N instead of <item>\n                                         picks it by its list --numbered number. --create makes <dest> as a new list first\n                                         (unless it's close enough to an existing list's name that it would be taken for it)\n                                         --where <filter> instead of <item> (move <source> <dest> --where \"done\") moves every item in\n                                         <source> matching <filter>, which works like list --only\n" +
+    "\tso  sort <list> --by <key>       Put the entries in <list> in order in the file, by name, date, priority (highest\n                                         first), created or completed (newest first). Sublists go by the soonest deadline,\n                                         highest priority or newest item in them. --reverse turns the order round, and --lists-first/--lists-last put the sublists together\n" +
+    "\tmg  merge <source> <dest>        Copy the items and sublists in <source> that aren't in <dest> yet into it.\n                                         When both have an item with the same name, the done one (then the one with\n                                         more progress, then the later deadline) is kept. --prefer later keeps the\n                                         one with the later deadline first instead\n" +
+    "\tmva moveall <source> <dest>      Move every item from <source> into <dest>. Does not move sublist of source into itself\n" +
+    "\trn  rename <list> <old> <new>    Rename an item in <list> from <old> to <new>. Fails if another item is\n                                         already called <new>, unless --allow-duplicate is passed\n" +
+    "\tsa  subadd <list> <item> <text>  Add a step to the checklist in an item, shown under it. Items show how many of their\n                                         steps are done, like [2/3]\n" +
+    "\tsd  subdone <list> <item> <n>    Mark step <n> (from 0, as shown by list --numbered) in an item's checklist as done,\n                                         or as not done if it already is\n" +
+    "\trl  renamelist <old> <new>       Rename the list <old> to <new>\n" +
+    "\tlb  label <list> <item> <colour> Label an item with a colour (red, green, yellow, blue, magenta, cyan,\n                                         white), shown as a coloured dot next to it. 'none' removes the label\n" +
+    "\tln  link <list> <item> <url>     Attach a link (like https://...) to an item. list shows its name as a link in\n                                         terminals that can, or the url after it otherwise (and with --no-links). 'none'\n                                         removes it\n\to   open <list> <item>           Open an item's link in your browser\n" +
+    "\tpg  progress <list> <item> <percent>\n                                         Set how much of an item has been done (0-100), shown after its name as [45%].\n                                         100 marks it as done, and marking an item with progress as done sets it to 100\n" +
+    "\tpr  priority <list> <item> <n>   Set the priority of an item, from 0 (none, the default) to 255. It's shown\n                                         after the name as [pN]\n" +
+    "\trp  repeat <list> <item> <period> Set an item to repeat every <period>. Marking it as done moves\n                                         its deadline on to the next occurrence instead\n                                         A day of the month like 31st is the last day in shorter months\n" +
+    "\tdd  dedup [list] [--recursive]   Remove repeated items (by name) and sublists from <list>, or all the lists,\n                                         keeping the first one. If any of the copies was done, the one kept is too.\n                                         With --recursive, <list>'s sublists are done too\n" +
+    "\t    fix [--dry-run]              Repair the list file: trim spaces from the ends of names, fill in or take out sublists\n                                         that aren't the name of a list, take out references that put a list inside itself,\n                                         remove items that are exactly the same as another, and rewrite old date formats.\n                                         Everything it changes is listed. --dry-run only shows what it would change\n" +
+    "\tar  autorm <list>                Remove all items in <list> that are marked as done. With --completed-before\n                                         <date>, only the ones that were done before <date>\n                                         --forget removes them for good, even with use_trash on, along with any done items\n                                         from <list> already in the trash and the saved output of cache_output, so they\n                                         can't be got back at all. Lines in the history about them are taken out too (ones that\n                                         say their whole name about <list>, and the adds that added them), and\n                                         @last forgets them\n" +
+    "\ttr  trash list|restore|empty     With use_trash = true in the config file, remove, rmlist and autorm put things in\n                                         the trash instead of deleting them. trash list shows what's in it, trash restore <name>\n                                         puts something back where it was, and trash empty deletes everything in it for good\n" +
+    "\tt   today <list> [--short]       List all tasks with a deadline of today.\n                                         If --short is passed, return only the number of tasks, do not list them.\n                                         --count prints just the number, and exits with it (up to 125)\n                                         With --all instead of <list>, looks through all the lists. Lists with\n                                         nothing due are left out\n" +
+    "\tw   week <list> [--short]        List all tasks with a deadline of within the next 7 days\n                                         --calendar-week only looks at the rest of this calendar week instead, up to\n                                         first_day_of_week in the config file\n" +
+    "\tod  overdue <list> [--short]     List all non-completed tasks with a deadline in the past\n" +
+    "\tag  agenda [list]                Show what's overdue and what's due today, under separate headers. Without\n                                         <list> (or with --all), looks through all the lists\n" +
+    "\tnx  next [--list <list>]         Show the one task to do next: the one that's been due the longest (the\n                                         highest priority first if there's a tie), or if nothing's due, the highest\n                                         priority one without a deadline. --list only looks in <list> and its sublists\n" +
+    "\trc  recur [--list <list>]         Show every repeating item, soonest first, with when it's next due, how often it repeats\n                                         and the list it's in. --list only looks in <list> and its sublists, and --json prints them as JSON\n" +
+    "\t    serve [--port <port>]        Start an HTTP/JSON API on localhost:<port> (default 8080), if built with\n                                         the 'serve' feature. There is no authentication! POSTs need an X-Todo: 1\n                                         header. See src/serve.rs\n" +
+    "\t    ui [list]                    Browse and edit the lists (or just <list>) full-screen, if built with the\n                                         'ui' feature. Space marks an item as done, a adds one, d removes one and\n                                         q saves and quits\n" +
+    "\tst  stats [list] [--record]      Show how many items there are in <list> (or all the lists), and how many are\n                                         done and overdue. --record also adds them to <config dir>/todo/stats.csv, and\n                                         --history shows what's been recorded for <list> (or all the lists) so far\n" +
+    "\tcal cal [month] [--list <list>]  Show a calendar of this month (or mm, mm/yyyy) with the number of\n                                         tasks due each day. --list only counts tasks in <list> and its sublists\n" +
+    "\t    history [--clear]            With audit_log = true in the config file, show when each command that changed the lists\n                                         was run, by who, and what it did. --clear empties it\n" +
+    "\t    config list|get|set          Show all the settings (list), one of them (get <key>), or change one in the config file\n                                         (set <key> <value>), e.g. `todo config set soon_days 5`. Unknown settings and values\n                                         that aren't right for the setting are refused\n\n" +
+    "--out <file> with any command writes what it would show to <file> instead, e.g. for a daily report from cron.\n" +
+    "--strict with any command stops it if anything in the list file is only there for older versions or looks like\n" +
+    "a mistake (like a sublist that isn't a list, or an unknown label), and shows all of them. Handy for checking a\n" +
+    "file in CI with `todo lists --strict`.\n" +
+    "--priority-symbols with any command shows priorities as !, !! and !!! (and !4 and so on above that) instead of\n" +
+    "[p1], [p2] etc. Set priority_style = symbols in the config file to always show them that way.\n" +
+    "--stable-ids with any command gives every list and item a random ID that stays the same when it's renamed or\n" +
+    "moved (or set stable_ids = true in the config file). #<ID>, or just the start of it, then picks it out.\n" +
+    "Shortcuts of your own, like `mondays = add chores \"$1\" --every monday`, go under [aliases] in the config file.\n\n" +
+    "When specifying lists and items, only the first few characters of their names are needed, as long a they\n" +
+    "uniquely identify a single list or item. For example in a list containing both 'orange' and 'organic',\n" +
+    "'or' would not work but 'ora' would be interpreted as 'orange'. In a list containing 'or' and 'orange',\n" + 
+    "'or' would match 'or' because it's an exact match. 'ora' would be necessary to match 'orange'.\n\n" +
+    "The last argument to a command need not be quoted as additional arguments are automatically concatinated\n" +
+    "with a space. For example, `todo add list this item has multiple words` is valid."
+}
+
+// the error for a name that's the start of more than one list or item, saying which ones
+fn ambiguous<'a>(kind: &str, name: &str, matches: impl Iterator<Item = &'a str>) -> String {
+    use std::fmt::Write;
+    const MAX_SHOWN: usize = 10;
+    let matches = matches.collect::<Vec<&str>>();
+    let mut shown = matches
+        .iter()
+        .take(MAX_SHOWN)
+        .map(|name| format!("'{name}'"))
+        .collect::<Vec<String>>()
+        .join(", ");
+    if matches.len() > MAX_SHOWN {
+        write!(shown, " (and {} more)", matches.len() - MAX_SHOWN).unwrap();
+    }
+    format!("{kind} '{name}' is not specific enough to match a single item, it matches {shown}")
+}
+
+// names are compared in NFC, so an é typed as e + a combining accent matches one stored as a
+// single character, and the other way round. they look the same, so they should be the same
+fn nfc(name: &str) -> String {
+    name.nfc().collect()
+}
+
+// #<start of an ID> picks out the list or item with that ID (see stable_ids in the config file).
+// None if nothing's ID starts with that, so it can still be the start of a name
+fn index_by_id<'a>(
+    kind: &str,
+    ids: impl Iterator<Item = Option<&'a str>>,
+    name: &str,
+) -> Option<Result<usize, String>> {
+    let prefix = name.strip_prefix('#').filter(|prefix| !prefix.is_empty())?;
+    let matches = ids
+        .enumerate()
+        .filter_map(|(idx, id)| Some((idx, id?)))
+        .filter(|(_, id)| id.starts_with(prefix))
+        .collect::<Vec<(usize, &str)>>();
+    match matches.as_slice() {
+        [] => None,
+        [(idx, _)] => Some(Ok(*idx)),
+        _ => Some(Err(ambiguous(
+            kind,
+            name,
+            matches.iter().map(|(_, id)| *id),
+        ))),
+    }
+}
+
+fn get_list_index_by_name(lists: &[TodoList], name: &str) -> Result<usize, String> {
+    // sublists are looked up by the exact name in the file all the time while printing, so
+    // that's tried before normalising every name
+    if let Some(idx) = lists.iter().position(|list| list.name == name) {
+        return Ok(idx);
+    }
+    if let Some(result) = index_by_id("List", lists.iter().map(|list| list.id.as_deref()), name) {
+        return result;
+    }
+    let name = &nfc(name);
+    if let Some(idx) = lists.iter().position(|list| nfc(&list.name) == *name) {
+        return Ok(idx);
+    }
+    let matches = (0..lists.len())
+        .filter(|&idx| nfc(&lists[idx].name).starts_with(name.as_str()))
+        .collect::<Vec<usize>>();
+    match matches.as_slice() {
+        [] if name == "toad" => Err(TOAD.to_string()),
+        [] => Err(format!("List '{name}' does not exist")),
+        [idx] => Ok(*idx),
+        _ => Err(ambiguous(
+            "List",
+            name,
+            matches.iter().map(|&idx| lists[idx].name.as_str()),
+        )),
+    }
+}
+
+fn get_list_by_name<'a>(lists: &'a [TodoList], name: &str) -> Result<&'a TodoList, String> {
+    get_list_index_by_name(lists, name).map(|idx| &lists[idx])
+}
+
+fn get_mut_list_by_name<'a>(
+    lists: &'a mut [TodoList],
+    name: &str,
+) -> Result<&'a mut TodoList, String> {
+    let idx = get_list_index_by_name(lists, name)?;
+    Ok(&mut lists[idx])
+}
+
+// like get_list_index_by_name, but also takes a path through sublists like work/home/garden.
+// each part after the first only has to match one of the sublists of the list before it
+fn get_list_index_by_path(lists: &[TodoList], path: &str) -> Result<usize, String> {
+    if !path.contains('/') || lists.iter().any(|list| list.name == path) {
+        return get_list_index_by_name(lists, path);
+    }
+    let mut parts = path.split('/');
+    let mut idx = get_list_index_by_name(lists, parts.next().unwrap_or_default())?;
+    for part in parts {
+        let list = &lists[idx];
+        let entry = get_index_by_name(list, part)?;
+        match &list.items[entry] {
+            ListEntry::List(name) => idx = get_list_index_by_name(lists, name)?,
+            ListEntry::Item(item) => {
+                return Err(format!(
+                    "'{}' in '{}' is an item, not a list",
+                    item.name, list.name
+                ))
+            }
+        }
+    }
+    Ok(idx)
+}
+
+// the entry at position `index` in the list, as shown by list --numbered, if --index was
+// passed, otherwise the one called `name`
+fn pick_entry(list: &TodoList, index: Option<&str>, name: &str) -> Result<usize, String> {
+    let Some(index) = index else {
+        return get_index_by_name(list, name);
+    };
+    index
+        .parse()
+        .ok()
+        .filter(|&idx| idx < list.items.len())
+        .ok_or_else(|| format!("There is no entry {index} in '{}'", list.name))
+}
+
+// names with a * or ? in them are taken as patterns by done, remove and move, and pick out
+// every entry they match instead of the one with that name (or starting with it)
+fn is_glob(name: &str) -> bool {
+    name.contains(['*', '?'])
+}
+
+fn glob_entries(list: &TodoList, pattern: &str) -> Result<Vec<usize>, String> {
+    let glob = glob::Pattern::new(pattern)
+        .map_err(|e| format!("Invalid pattern '{pattern}': {}", e.msg))?;
+    let matches = (0..list.items.len())
+        .filter(|&idx| glob.matches(list.items[idx].name()))
+        .collect::<Vec<usize>>();
+    if matches.is_empty() {
+        return Err(format!("Nothing in '{}' matches '{pattern}'", list.name));
+    }
+    Ok(matches)
+}
+
+fn get_index_by_name(list: &TodoList, itemname: &str) -> Result<usize, String> {
+    let itemname = &nfc(itemname);
+    if let Some(idx) = list
+        .items
+        .iter()
+        .rposition(|item| nfc(item.name()) == *itemname)
+    {
+        return Ok(idx);
+    }
+    let ids = list.items.iter().map(|entry| match entry {
+        ListEntry::Item(item) => item.id.as_deref(),
+        // the ID of a sublist is on the list itself, and that's not here
+        ListEntry::List(_) => None,
+    });
+    if let Some(result) = index_by_id("Item", ids, itemname) {
+        return result;
+    }
+    if itemname == "@last" {
+        let name = last_added(&list.name)
+            .ok_or_else(|| format!("Nothing has been added to '{}' yet", list.name))?;
+        return list
+            .items
+            .iter()
+            .rposition(|item| nfc(item.name()) == nfc(&name))
+            .ok_or_else(|| {
+                format!(
+                    "'{name}', the last item added to '{}', isn't in it any more",
+                    list.name
+                )
+            });
+    }
+    let matches = (0..list.items.len())
+        .filter(|&idx| nfc(list.items[idx].name()).starts_with(itemname.as_str()))
+        .collect::<Vec<usize>>();
+    match matches.as_slice() {
+        [] => Err(format!("Item '{itemname}' does not exist")),
+        [idx] => Ok(*idx),
+        _ => Err(ambiguous(
+            "Item",
+            itemname,
+            matches.iter().map(|&idx| list.items[idx].name()),
+        )),
+    }
+}
+
+// works out when a repeating item is first due. if a date was given explicitly it has to
+// agree with the period, otherwise it's the next matching day (which can be today) or one
+// period from today
+fn first_occurrence(
+    repeat: RepeatKind,
+    date: Option<chrono::NaiveDate>,
+) -> Result<chrono::NaiveDate, String> {
+    let today = today();
+    match (date, repeat) {
+        (Some(date), RepeatKind::Weekly(weekday)) if date.weekday() != weekday => Err(format!(
+            "{} is a {}, but the item is meant to repeat every {weekday}",
+            date.format("%d/%m/%Y"),
+            date.weekday()
+        )),
+        (Some(date), RepeatKind::MonthlyOnDay(day))
+            if date != day_of_month(date.year(), date.month(), day) =>
+        {
+            Err(format!(
+                "{} isn't on the {repeat}, but the item is meant to repeat on the {repeat} of every month",
+                date.format("%d/%m/%Y"),
+            ))
+        }
+        (Some(date), _) => Ok(date),
+        (None, RepeatKind::Weekly(weekday)) => Ok(today
+            + chrono::Duration::days(i64::from(days_until(today.weekday(), weekday)))),
+        (None, RepeatKind::MonthlyOnDay(day)) => {
+            let this_month = day_of_month(today.year(), today.month(), day);
+            Ok(if this_month >= today {
+                this_month
+            } else {
+                repeat.next(this_month)
+            })
+        }
+        (None, RepeatKind::EveryNDays(days)) => Ok(today + chrono::Duration::days(days)),
+    }
+}
+
+// parses an offset from today like 3, +3d or 2w into a number of days
+fn parse_day_offset(s: &str) -> Option<i64> {
+    let s = s.strip_prefix('+').unwrap_or(s);
+    if let Some(weeks) = s.strip_suffix('w') {
+        return weeks.parse::<i64>().ok().map(|weeks| weeks * 7);
+    }
+    s.strip_suffix('d').unwrap_or(s).parse().ok()
+}
+
+// the day a duration like 3d, 2w, 1m or 1y (or "3 days") from `from` is. a month on from the
+// 31st is the last day of a shorter month
+fn add_duration(from: chrono::NaiveDate, s: &str) -> Option<chrono::NaiveDate> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let count = s[..split].parse::<u32>().ok()?;
+    match s[split..].trim() {
+        "d" | "day" | "days" => from.checked_add_days(chrono::Days::new(count.into())),
+        "w" | "week" | "weeks" => from.checked_add_days(chrono::Days::new(u64::from(count) * 7)),
+        "m" | "month" | "months" => from.checked_add_months(chrono::Months::new(count)),
+        "y" | "year" | "years" => {
+            from.checked_add_months(chrono::Months::new(count.checked_mul(12)?))
+        }
+        _ => None,
+    }
+}
+
+fn parse_date(s: &str) -> Option<chrono::NaiveDate> {
+    chrono::NaiveDate::parse_from_str(s, "%d/%m/%y").map_or_else(
+        |_| chrono::NaiveDate::parse_from_str(s, "%d/%m/%Y").ok(),
+        Some,
+    )
+}
+
+// which heading an item goes under with list --timeline, and where that heading comes. the
+// rest of this week's days after tomorrow are each shown by name, then next week's together
+fn timeline_bucket(
+    date: Option<chrono::NaiveDate>,
+    today: chrono::NaiveDate,
+    week_start: chrono::Weekday,
+) -> (i64, String) {
+    let Some(date) = date else {
+        return (i64::MAX, "No date".to_string());
+    };
+    let next_week = calendar::week_start(today, week_start) + chrono::Duration::days(7);
+    match (date - today).num_days() {
+        days if days < 0 => (-1, "Overdue".to_string()),
+        0 => (0, "Today".to_string()),
+        1 => (1, "Tomorrow".to_string()),
+        days if date < next_week => (days, date.format("%A").to_string()),
+        _ if date < next_week + chrono::Duration::days(7) => (7, "Next week".to_string()),
+        _ => (8, "Later".to_string()),
+    }
+}
+
+// the items that aren't done under `roots`, each after the path to its list, under a heading
+// for when they're due, most important first
+fn print_timeline<F: FnMut(&&ListItem) -> bool>(
+    all: &[TodoList],
+    roots: &[&TodoList],
+    mut predicate: F,
+    opts: PrintOptions,
+) -> String {
+    use std::fmt::Write;
+    // a list can be under more than one of the roots, but its items are only shown once
+    let mut seen = HashSet::new();
+    let mut items = roots
+        .iter()
+        .flat_map(|root| root.walk_items_with_path(all))
+        .filter(|(_, item)| !item.done && predicate(item))
+        .filter(|(_, item)| seen.insert(std::ptr::from_ref(*item)))
+        .map(|(path, item)| {
+            (
+                timeline_bucket(item.date, opts.today, opts.week_start),
+                ListItem {
+                    name: format!("{path}: {}", item.name),
+                    ..item.clone()
+                },
+            )
+        })
+        .collect::<Vec<((i64, String), ListItem)>>();
+    items.sort_by_key(|((order, _), item)| (*order, std::cmp::Reverse(item.priority), item.date));
+    let max = items
+        .iter()
+        .map(|(_, item)| {
+            opts.indent_width + item.name.width() + item.extra_width(opts.indent_width, opts) + 1
+        })
+        .max()
+        .unwrap_or(0);
+    let (opts, max) = opts.fit(max);
+    let indentstr = opts.indent(1);
+    let mut acc = String::new();
+    let mut heading = None;
+    for ((order, name), item) in &items {
+        if heading != Some(order) {
+            writeln!(acc, "{name}:").unwrap();
+            heading = Some(order);
+        }
+        item.print(
+            &indentstr,
+            max.saturating_sub(opts.indent_width),
+            opts,
+            &mut acc,
+        );
+    }
+    acc
+}
+
+// how a sublist is shown with list --resolve-refs link, with how many items in it are shown.
+// each list under it is only counted once, however many times it's referenced
+fn link_text(list: &TodoList, shown: usize, ascii: bool) -> String {
+    format!(
+        "{} {} ({shown} item{})",
+        if ascii { "->" } else { "→" },
+        list.name,
+        if shown == 1 { "" } else { "s" }
+    )
+}
+
+// if the only entries that will be shown are one sublist, returns that sublist
+fn only_shown_sublist<'a>(
+    all: &'a [TodoList],
+    entries: &[&ListEntry],
+    counts: &Counts,
+) -> Option<&'a TodoList> {
+    let mut shown = None;
+    for entry in entries {
+        match entry {
+            ListEntry::Item(_) => return None,
+            ListEntry::List(name) => {
+                let list = get_list_by_name(all, name).unwrap();
+                if counts.shown(&list.name) > 0 {
+                    if shown.is_some() {
+                        return None;
+                    }
+                    shown = Some(list);
+                }
+            }
+        }
+    }
+    shown
+}
+
+// --no-empty (or --hide-empty), which leaves out lists with nothing to show in list, lists and
+// today, week and overdue
+fn take_no_empty(args: &mut Vec<String>) -> bool {
+    take_flag(args, "--no-empty") | take_flag(args, "--hide-empty")
+}
+
+// the range of priorities allowed by --min-priority and --max-priority, all of them by default
+fn take_priority_range(args: &mut Vec<String>) -> Result<std::ops::RangeInclusive<u8>, String> {
+    let mut take = |name, default| -> Result<u8, String> {
+        take_option(args, name)?.map_or(Ok(default), |value| {
+            value
+                .parse()
+                .map_err(|_| format!("Invalid priority '{value}', expected 0 to 255"))
+        })
+    };
+    Ok(take("--min-priority", u8::MIN)?..=take("--max-priority", u8::MAX)?)
+}
+
+// removes `flag` from the arguments, returning whether it was there
+fn take_flag(args: &mut Vec<String>, flag: &str) -> bool {
+    let len = args.len();
+    args.retain(|arg| arg != flag);
+    args.len() != len
+}
+
+// removes `name` and the value following it from the arguments
+fn take_option(args: &mut Vec<String>, name: &str) -> Result<Option<String>, String> {
+    let Some(idx) = args.iter().position(|arg| arg == name) else {
+        return Ok(None);
+    };
+    if idx + 1 >= args.len() {
+        return Err(format!("Expected a value after '{name}'"));
+    }
+    let value = args.remove(idx + 1);
+    args.remove(idx);
+    Ok(Some(value))
+}
+
+// roughly how much space the tab, date and "(in N days)" take up after an item's name
+const DATE_COLUMN_WIDTH: usize = 32;
+// and the ages, with list --age
+const AGE_COLUMN_WIDTH: usize = 6;
+
+// cuts `s` down to at most `width` columns, ending with an ellipsis if anything was cut off
+fn truncate(s: &str, width: usize, ascii: bool) -> Cow<'_, str> {
+    if s.width() <= width {
+        return Cow::from(s);
+    }
+    let ellipsis = if ascii { "..." } else { "…" };
+    if width < ellipsis.width() {
+        return Cow::from(".".repeat(width));
+    }
+    let mut acc = String::new();
+    for c in s.chars() {
+        if acc.width() + c.to_string().width() + ellipsis.width() > width {
+            break;
+        }
+        acc.push(c);
+    }
+    acc.push_str(ellipsis);
+    Cow::from(acc)
+}
+
+// what goes in front of done items, and the space for it in front of the rest
+fn done_marker(done: bool, ascii: bool) -> &'static str {
+    match (done, ascii, MARKERS.get()) {
+        (true, true, _) => "[x]",
+        (false, true, _) => "[ ]",
+        (true, false, Some([marker, _, _])) | (false, false, Some([_, marker, _])) => marker,
+        (true, false, None) => "✓",
+        (false, false, None) => " ",
+    }
+}
+
+// the same for the headers of lists, which are done when everything in them is
+fn list_marker(done: bool, ascii: bool) -> &'static str {
+    match (done, ascii, MARKERS.get()) {
+        (true, false, Some([_, _, marker])) => marker,
+        _ => done_marker(done, ascii),
+    }
+}
+
+fn set_markers(config: &Config) {
+    MARKERS.set(padded_markers(config)).unwrap();
+}
+
+// pad the markers from the config file out to the widest, so the names all line up
+fn padded_markers(config: &Config) -> [String; 3] {
+    let markers = [
+        &config.done_marker,
+        &config.undone_marker,
+        config
+            .list_done_marker
+            .as_ref()
+            .unwrap_or(&config.done_marker),
+    ];
+    let width = markers
+        .iter()
+        .map(|marker| marker.width())
+        .max()
+        .unwrap_or(0);
+    markers.map(|marker| format!("{marker}{}", " ".repeat(width - marker.width())))
+}
+
+// colours are only used when printing straight to a terminal, and can be turned off
+// with the NO_COLOR environment variable
+fn use_colour() -> bool {
+    use std::io::IsTerminal;
+    std::io::stdout().is_terminal()
+        && std::env::var_os("NO_COLOR").is_none()
+        && !TO_FILE.load(Ordering::SeqCst)
+}
+
+// whether item names with a link can be shown as links. only where colours would be, since
+// they're escape codes too. most terminals either support OSC 8 links or quietly ignore them,
+// apart from the linux console, which shows the escape codes
+fn supports_hyperlinks() -> bool {
+    use_colour() && std::env::var("TERM").map_or(true, |term| term != "linux" && term != "dumb")
+}
+
+// the width of the terminal, if stdout is one. $COLUMNS wins if it's set, so the width can be
+// given when the output isn't going to a terminal, e.g. in a pipe or from a script
+fn terminal_width() -> Option<usize> {
+    std::env::var("COLUMNS")
+        .ok()
+        .and_then(|columns| columns.trim().parse().ok())
+        .filter(|&columns| columns > 0)
+        .or_else(|| {
+            terminal_size::terminal_size().map(|(terminal_size::Width(w), _)| usize::from(w))
+        })
+}
+
+// list --width, where 0 means there's no limit
+fn parse_width(width: &str) -> Result<Option<usize>, String> {
+    match width.parse() {
+        Ok(0) => Ok(None),
+        Ok(width) => Ok(Some(width)),
+        Err(_) => Err(format!("Invalid width '{width}'")),
+    }
+}
+
+type CmdResult = Result<(String, bool), String>;
+
+fn cmd_list(lists: &[TodoList], args: &[String], config: &Config) -> CmdResult {
+    let mut args = args.to_vec();
+    let short = take_flag(&mut args, "--short");
+    let ndjson = take_flag(&mut args, "--ndjson");
+    let show_path = take_flag(&mut args, "--show-path");
+    let leaf_lists = take_flag(&mut args, "--only-leaf-lists");
+    let timeline = take_flag(&mut args, "--timeline");
+    let width = take_option(&mut args, "--width")?;
+    let opts = PrintOptions {
+        kinds: EntryKinds::from_args(&mut args)?,
+        days_column: take_flag(&mut args, "--days"),
+        sort: take_option(&mut args, "--sort")?
+            .map(|key| SortKey::parse(&key))
+            .transpose()?,
+        prune: take_flag(&mut args, "--prune"),
+        // an explicit --width cuts names short, but the terminal's width just moves the dates
+        // out of the way
+        dates_below: width.is_none(),
+        width: match width {
+            Some(width) => parse_width(&width)?,
+            None => terminal_width(),
+        },
+        colour: !take_flag(&mut args, "--no-colour") && use_colour(),
+        hyperlinks: !take_flag(&mut args, "--no-links") && supports_hyperlinks(),
+        ascii: take_flag(&mut args, "--ascii") || config.ascii,
+        collapse_single: take_flag(&mut args, "--collapse") || config.collapse_single,
+        collapse_done: take_flag(&mut args, "--collapse-done"),
+        numbered: take_flag(&mut args, "--numbered"),
+        breadcrumbs: take_flag(&mut args, "--breadcrumb"),
+        age: take_flag(&mut args, "--age"),
+        refs: take_option(&mut args, "--resolve-refs")?
+            .map(|mode| RefMode::parse(&mode))
+            .transpose()?
+            .unwrap_or(RefMode::Inline),
+        done_last: DoneLast::from_args(&mut args),
+        no_empty: take_no_empty(&mut args),
+        // like print_without_date, but with all the other options too
+        dates: !(take_flag(&mut args, "--no-dates") | take_flag(&mut args, "--no-date")),
+        ..PrintOptions::from_config(config)
+    };
+    let label = take_option(&mut args, "--label")?;
+    if let Some(label) = &label {
+        label_colour(label)?;
+    }
+    let priorities = take_priority_range(&mut args)?;
+    let only = take_option(&mut args, "--only")?
+        .map(|expr| filter::Filter::parse(&expr, today()))
+        .transpose()?;
+    let mut since = |flag| {
+        take_option(&mut args, flag)?
+            .map(|date| parse_date(&date).ok_or_else(|| format!("Invalid date '{date}'")))
+            .transpose()
+    };
+    let completed_since = since("--completed-since")?;
+    let created_since = since("--created-since")?;
+    // items that don't have the date at all are left out
+    let on_or_after = |date: Option<chrono::NaiveDate>, cutoff: Option<chrono::NaiveDate>| {
+        cutoff.is_none_or(|cutoff| date.is_some_and(|date| date >= cutoff))
+    };
+    // without a list, the timeline has everything in it
+    let list = if timeline && args.is_empty() {
+        None
+    } else {
+        Some(get_list_by_name(lists, &args.join(" "))?)
+    };
+    let filter = |item: &&ListItem| {
+        (label.is_none() || item.label == label)
+            && priorities.contains(&item.priority)
+            && on_or_after(item.completed, completed_since)
+            && on_or_after(item.created, created_since)
+            && only.as_ref().is_none_or(|only| only.matches(item))
+    };
+    if timeline {
+        let roots = list.map_or_else(|| top_level_lists(lists), |list| vec![list]);
+        return Ok((print_timeline(lists, &roots, filter, opts), false));
+    }
+    let list = list.unwrap();
+    if short {
+        let mut item_names: Vec<&str> = Vec::new();
+        for i in &list.items {
+            if let ListEntry::Item(i) = i {
+                if !i.done {
+                    item_names.push(&i.name);
+                }
+            }
+        }
+        return Ok((item_names.join(", "), false));
+    }
+    if leaf_lists {
+        return Ok((print_leaf_lists(lists, list), false));
+    }
+    if show_path {
+        return Ok((list.print_with_paths(lists, filter, opts), false));
+    }
+    if ndjson {
+        let items = list
+            .walk_items_with_path(lists)
+            .into_iter()
+            .filter(|(_, item)| filter(item))
+            .collect::<Vec<(String, &ListItem)>>();
+        return Ok((parser::emit_ndjson(&items), false));
+    }
+    Ok((list.print_with(lists, filter, opts), false))
+}
+
+fn cmd_lists(lists: &mut [TodoList], args: &[String], config: &Config) -> CmdResult {
+    let mut args = args.to_vec();
+    let kinds = EntryKinds::from_args(&mut args)?;
+    let compact = take_flag(&mut args, "--compact");
+    let ascii = take_flag(&mut args, "--ascii") || config.ascii;
+    let sort = take_option(&mut args, "--sort")?
+        .map(|key| ListSortKey::parse(&key))
+        .transpose()?;
+    let persist = take_flag(&mut args, "--persist");
+    let no_empty = take_no_empty(&mut args);
+    let tree = take_flag(&mut args, "--tree");
+    if !args.is_empty() {
+        return Err(usage());
+    }
+    if tree {
+        return Ok((print_list_tree(lists, ascii), false));
+    }
+
+    // --persist puts the lists in the file in that order too, otherwise it's just for show
+    if let (true, Some(sort)) = (persist, sort) {
+        let mut order = lists.iter().collect::<Vec<&TodoList>>();
+        sort.sort(lists, &mut order);
+        let order = order
+            .into_iter()
+            .map(|list| list.name.clone())
+            .collect::<Vec<String>>();
+        lists.sort_by_key(|list| order.iter().position(|name| *name == list.name));
+    } else if persist {
+        return Err("--persist only works with --sort".to_string());
+    }
+
+    let lists = &*lists;
+    let trashed = trashed_lists(lists);
+    // with --only-items/--only-lists, only show lists that have that kind of entry in them
+    let mut shown = lists
+        .iter()
+        .filter(|list| !trashed.contains(list.name.as_str()))
+        .filter(|list| {
+            kinds == EntryKinds::All || list.items.iter().any(|entry| kinds.shows(entry))
+        })
+        // --no-empty leaves out lists without any items, in them or in their sublists
+        .filter(|list| !no_empty || !list.walk_items(lists).is_empty())
+        .collect::<Vec<&TodoList>>();
+    if let Some(sort) = sort {
+        sort.sort(lists, &mut shown);
+    }
+    if compact {
+        return Ok((lists_summary(lists, shown.into_iter(), ascii), persist));
+    }
+    let mut res = String::new();
+    for i in shown {
+        res.push_str(&i.name);
+        res.push('\n');
+    }
+    Ok((res, persist))
+}
+
+// how the lists are nested in each other, just going by the sublists. a list that's already
+// been shown is marked with ↻ instead of being shown again, so shared and circular ones don't
+// go on forever
+fn print_list_tree(lists: &[TodoList], ascii: bool) -> String {
+    let mut visited = HashSet::new();
+    let mut acc = String::new();
+    for root in top_level_lists(lists) {
+        print_tree_node(lists, root, "", "", ascii, &mut visited, &mut acc);
+    }
+    // lists that only reference each other in a circle aren't anywhere under the top level ones
+    for list in lists.iter().filter(|list| list.name != TRASH) {
+        if !visited.contains(list.name.as_str()) {
+            print_tree_node(lists, list, "", "", ascii, &mut visited, &mut acc);
+        }
+    }
+    acc
+}
+
+fn print_tree_node<'a>(
+    lists: &'a [TodoList],
+    list: &'a TodoList,
+    first_prefix: &str,
+    prefix: &str,
+    ascii: bool,
+    visited: &mut HashSet<&'a str>,
+    acc: &mut String,
+) {
+    use std::fmt::Write;
+    if !visited.insert(&list.name) {
+        writeln!(
+            acc,
+            "{first_prefix}{} {}",
+            list.name,
+            if ascii { "(again)" } else { "↻" }
+        )
+        .unwrap();
+        return;
+    }
+    writeln!(acc, "{first_prefix}{}", list.name).unwrap();
+    let sublists = list
+        .items
+        .iter()
+        .filter_map(|entry| match entry {
+            ListEntry::List(name) => get_list_by_name(lists, name).ok(),
+            ListEntry::Item(_) => None,
+        })
+        .collect::<Vec<&TodoList>>();
+    let (branch, last_branch, line) = if ascii {
+        ("|-- ", "`-- ", "|   ")
+    } else {
+        ("├── ", "└── ", "│   ")
+    };
+    for (idx, sublist) in sublists.iter().enumerate() {
+        let is_last = idx + 1 == sublists.len();
+        print_tree_node(
+            lists,
+            sublist,
+            &format!("{prefix}{}", if is_last { last_branch } else { branch }),
+            &format!("{prefix}{}", if is_last { "    " } else { line }),
+            ascii,
+            visited,
+            acc,
+        );
+    }
+}
+
+// the lists under `root` with items of their own in them, rather than just sublists, each with
+// the path to it, how many items it has and the other lists it's in
+fn print_leaf_lists(lists: &[TodoList], root: &TodoList) -> String {
+    use std::fmt::Write;
+    let mut parents: HashMap<&str, Vec<&str>> = HashMap::new();
+    for list in lists.iter().filter(|list| list.name != TRASH) {
+        for entry in &list.items {
+            if let ListEntry::List(name) = entry {
+                if let Ok(sublist) = get_list_by_name(lists, name) {
+                    parents.entry(&sublist.name).or_default().push(&list.name);
+                }
+            }
+        }
+    }
+    let mut acc = String::new();
+    for (path, list) in root.subtree_with_paths(lists) {
+        let items = list
+            .items
+            .iter()
+            .filter(|entry| matches!(entry, ListEntry::Item(_)))
+            .count();
+        if items == 0 {
+            continue;
+        }
+        write!(
+            acc,
+            "{path} ({items} item{}",
+            if items == 1 { "" } else { "s" }
+        )
+        .unwrap();
+        // the one it's under in the path doesn't need saying again
+        let parent = path
+            .rsplit_once('/')
+            .map(|(parent, _)| parent.rsplit_once('/').map_or(parent, |(_, parent)| parent));
+        let others = parents
+            .get(list.name.as_str())
+            .into_iter()
+            .flatten()
+            .filter(|other| Some(**other) != parent)
+            .map(|other| format!("'{other}'"))
+            .collect::<Vec<String>>();
+        if !others.is_empty() {
+            write!(acc, ", also in {}", others.join(", ")).unwrap();
+        }
+        acc.push_str(")\n");
+    }
+    acc
+}
+
+// the names of all the lists that are a sublist of another one
+fn sublist_names(lists: &[TodoList]) -> HashSet<&str> {
+    lists
+        .iter()
+        .flat_map(|list| &list.items)
+        .filter_map(|entry| match entry {
+            ListEntry::List(name) => Some(name.as_str()),
+            ListEntry::Item(_) => None,
+        })
+        .collect()
+}
+
+// all the lists that aren't a sublist of another one, apart from the trash
+fn top_level_lists(lists: &[TodoList]) -> Vec<&TodoList> {
+    let sublists = sublist_names(lists);
+    lists
+        .iter()
+        .filter(|list| !sublists.contains(list.name.as_str()) && list.name != TRASH)
+        .collect()
+}
+
+// one line for each list that isn't a sublist of another, with how many of its items (and
+// its sublists' items) are done and the next deadline coming up
+fn lists_summary<'a>(
+    lists: &'a [TodoList],
+    shown: impl Iterator<Item = &'a TodoList>,
+    ascii: bool,
+) -> String {
+    use std::fmt::Write;
+    let sublists = sublist_names(lists);
+    let top_level = shown
+        .filter(|list| !sublists.contains(list.name.as_str()))
+        .collect::<Vec<&TodoList>>();
+    let name_width = top_level
+        .iter()
+        .map(|list| list.name.width())
+        .max()
+        .unwrap_or(0);
+    let today = today();
+    let mut acc = String::new();
+    for list in top_level {
+        let items = list.walk_items(lists);
+        let done = items.iter().filter(|item| item.done).count();
+        let next = list.next_deadline(lists, today).map_or_else(
+            || if ascii { "-" } else { "—" }.to_string(),
+            |item| format!("{} ({})", item.name, item.date.unwrap().format("%d/%m/%Y")),
+        );
+        let counts = format!("[{done}/{}]", items.len());
+        writeln!(
+            acc,
+            "{}{}  {counts:<9}  next: {next}",
+            list.name,
+            " ".repeat(name_width - list.name.width()),
+        )
+        .unwrap();
+    }
+    acc
+}
+
+// two lists with the same name can't be told apart, so names have to be unique
+fn check_list_name_free(lists: &[TodoList], name: &str) -> Result<(), String> {
+    if lists.iter().any(|list| list.name == name) {
+        return Err(format!("There is already a list called '{name}'"));
+    }
+    Ok(())
+}
+
+// move --create only makes <dest> if nothing else would be taken for it: a different spelling of
+// a list that's there, or a name that's the start of one (or that one starts with), is much more
+// likely a typo than a new list
+fn check_not_near_a_list(lists: &[TodoList], name: &str) -> Result<(), String> {
+    if let Some(Ok(idx)) = index_by_id("List", lists.iter().map(|list| list.id.as_deref()), name) {
+        return Err(format!(
+            "Not creating '{name}', it's the ID of '{}'",
+            lists[idx].name
+        ));
+    }
+    let nfc_name = nfc(name);
+    let near = lists
+        .iter()
+        .filter(|list| list.name != name)
+        .filter(|list| {
+            let other = nfc(&list.name);
+            other.starts_with(&nfc_name) || nfc_name.starts_with(&other)
+        })
+        .map(|list| format!("'{}'", list.name))
+        .collect::<Vec<String>>();
+    if near.is_empty() {
+        Ok(())
+    } else {
+        Err(format!(
+            "Not creating '{name}', it's too close to {}",
+            near.join(", ")
+        ))
+    }
+}
+
+fn new_list(lists: &mut Vec<TodoList>, name: String) -> CmdResult {
+    check_list_name_free(lists, &name)?;
+    lists.push(TodoList::new(name));
+    Ok((String::new(), true))
+}
+
+fn cmd_new(lists: &mut Vec<TodoList>, args: &[String]) -> CmdResult {
+    let mut args = args.to_vec();
+    if !take_flag(&mut args, "--from-stdin") {
+        return new_list(lists, args.join(" "));
+    }
+    if !args.is_empty() {
+        return Err(usage());
+    }
+    // one name per line. names that are already taken (including earlier in the input) are
+    // skipped rather than stopping the rest from being created
+    let (mut created, mut skipped) = (0, 0);
+    for name in read_input("-")?.lines().map(str::trim) {
+        if name.is_empty() {
+            continue;
+        }
+        if check_list_name_free(lists, name).is_ok() {
+            lists.push(TodoList::new(name.to_owned()));
+            created += 1;
+        } else {
+            skipped += 1;
+        }
+    }
+    Ok((
+        format!(
+            "Created {created} list{}, skipped {skipped} that already existed\n",
+            if created == 1 { "" } else { "s" }
+        ),
+        created > 0,
+    ))
+}
+
+fn cmd_rmlist(lists: &mut Vec<TodoList>, args: &[String], config: &Config) -> CmdResult {
+    let mut args = args.to_vec();
+    let keep_references = take_flag(&mut args, "--keep-references");
+    let name = get_list_by_name(lists, &args.join(" "))?.name.clone();
+    if config.use_trash {
+        return trash_list(lists, &name, keep_references);
+    }
+    lists.retain(|l| l.name != name);
+    if keep_references {
+        return Ok((String::new(), true));
+    }
+    // otherwise the lists it was in would be left pointing at nothing
+    let mut removed = 0;
+    for list in lists.iter_mut() {
+        let before = list.items.len();
+        list.items
+            .retain(|entry| !matches!(entry, ListEntry::List(sublist) if *sublist == name));
+        removed += before - list.items.len();
+    }
+    let out = match removed {
+        0 => String::new(),
+        1 => format!("Removed list '{name}' and 1 reference to it\n"),
+        n => format!("Removed list '{name}' and {n} references to it\n"),
+    };
+    Ok((out, true))
+}
+
+// rmlist with use_trash: the list stays as it is, just inside the trash, and the references to
+// it go in the trash too so restoring it puts it back where it was
+fn trash_list(lists: &mut Vec<TodoList>, name: &str, keep_references: bool) -> CmdResult {
+    if name == TRASH {
+        return Err("The trash can't go in the trash. Use 'trash empty' to empty it".to_string());
+    }
+    if trashed_lists(lists).contains(name) {
+        return Err(format!("'{name}' is already in the trash"));
+    }
+    let mut removed = 0;
+    if !keep_references {
+        for idx in 0..lists.len() {
+            let (references, kept) = std::mem::take(&mut lists[idx].items)
+                .into_iter()
+                .partition(|entry| matches!(entry, ListEntry::List(sublist) if sublist == name));
+            lists[idx].items = kept;
+            removed += references.len();
+            let from = lists[idx].name.clone();
+            trash_entries(lists, &from, references);
+        }
+    }
+    let trash = trash_index(lists);
+    lists[trash].items.push(ListEntry::List(name.to_owned()));
+    let out = match removed {
+        0 => format!("Moved list '{name}' to the trash\n"),
+        1 => format!("Moved list '{name}' and 1 reference to it to the trash\n"),
+        n => format!("Moved list '{name}' and {n} references to it to the trash\n"),
+    };
+    Ok((out, true))
+}
+
+// add from the command line, where --create-list makes the list first if it isn't there yet.
+// the ui and serve can't make lists, so they use cmd_add directly
+fn cmd_add_or_create(lists: &mut Vec<TodoList>, args: &[String], config: &Config) -> CmdResult {
+    let mut args = args.to_vec();
+    if take_flag(&mut args, "--create-list") {
+        let name = args.first().ok_or_else(usage)?;
+        if get_list_by_name(lists, name).is_err() {
+            // nothing's saved if adding the item fails, so the new list doesn't hang around
+            new_list(lists, name.clone())?;
+        }
+    }
+    cmd_add(lists, &args, config)
+}
+
+fn cmd_add(lists: &mut [TodoList], args: &[String], config: &Config) -> CmdResult {
+    let mut args = args.to_vec();
+    let every = take_option(&mut args, "--every")?;
+    let due_in = take_option(&mut args, "--in")?
+        .map(|duration| {
+            add_duration(today(), &duration).ok_or_else(|| {
+                format!("Invalid duration '{duration}', expected e.g. 3d, 2w, 1m or 1y")
+            })
+        })
+        .transpose()?;
+    let unique = take_flag(&mut args, "--unique") || config.reject_duplicate_items;
+    if args.len() < 2 {
+        return Err(usage());
+    }
+    let list = get_mut_list_by_name(lists, &args[0])?;
+    let last_arg = &args[args.len() - 1];
+
+    // a name in quotes is used as it is, without looking for a date in it. only a date can
+    // come after it
+    let (name, date) = if due_in.is_some() {
+        // the whole of the rest is the name, since the date's already been given
+        match parser::unquote(&args[1..].join(" ")) {
+            Some((_, rest)) if !rest.trim().is_empty() => {
+                return Err("Give either a date or --in, not both".to_string())
+            }
+            Some((name, _)) => (name, due_in),
+            None => (args[1..].join(" "), due_in),
+        }
+    } else if let Some((name, rest)) = parser::unquote(&args[1..].join(" ")) {
+        let rest = rest.trim();
+        let date = if rest.is_empty() {
+            None
+        } else {
+            Some(parse_date(rest).ok_or_else(|| format!("Invalid date '{rest}'"))?)
+        };
+        (name, date)
+    } else {
+        parse_date(last_arg).map_or_else(
+            || (args[1..].join(" "), None),
+            |timestamp| (args[1..(args.len() - 1)].join(" "), Some(timestamp)),
+        )
+    };
+    if unique {
+        let same_name = |other: &str| other.trim().to_lowercase() == name.trim().to_lowercase();
+        if let Some(existing) = list.items.iter().find(|entry| match entry {
+            ListEntry::Item(item) => same_name(&item.name),
+            ListEntry::List(_) => false,
+        }) {
+            return Err(format!(
+                "'{}' is already in '{}'",
+                existing.name(),
+                list.name
+            ));
+        }
+    }
+    let (repeat, date) = match every {
+        Some(period) => {
+            let repeat = RepeatKind::parse(&period)?;
+            (Some(repeat), Some(first_occurrence(repeat, date)?))
+        }
+        None => (
+            None,
+            date.or_else(|| {
+                list.default_due
+                    .map(|due| today() + chrono::Duration::days(due))
+            }),
+        ),
+    };
+
+    set_last_added(&list.name, &name);
+    list.items.push(ListEntry::Item(ListItem {
+        name,
+        date,
+        repeat,
+        priority: list.default_priority,
+        created: Some(today()),
+        ..Default::default()
+    }));
+    Ok((String::new(), true))
+}
+
+fn cmd_listconfig(lists: &mut [TodoList], args: &[String]) -> CmdResult {
+    let mut args = args.to_vec();
+    let due = take_option(&mut args, "--due")?;
+    let priority = take_option(&mut args, "--priority")?;
+    let list = get_mut_list_by_name(lists, &args.join(" "))?;
+    if due.is_none() && priority.is_none() {
+        let due = list.default_due.map_or_else(
+            || format!("'{}' has no default due date\n", list.name),
+            |due| format!("Items added to '{}' are due in {due} days\n", list.name),
+        );
+        let priority = match list.default_priority {
+            0 => format!("'{}' has no default priority\n", list.name),
+            priority => format!("Items added to '{}' get priority {priority}\n", list.name),
+        };
+        return Ok((due + &priority, false));
+    }
+    match due.as_deref() {
+        None => {}
+        Some("none") => list.default_due = None,
+        Some(due) => {
+            list.default_due = Some(parse_day_offset(due).ok_or_else(|| {
+                format!(
+                    "Invalid due offset '{due}', expected a number of days or weeks like +3d or 2w"
+                )
+            })?);
+        }
+    }
+    match priority.as_deref() {
+        None => {}
+        Some("none") => list.default_priority = 0,
+        Some(priority) => {
+            list.default_priority = priority
+                .parse()
+                .map_err(|_| format!("Invalid priority '{priority}', expected 0 to 255"))?;
+        }
+    }
+    Ok((String::new(), true))
+}
+
+// the name of the last item added to each list, so it can be picked out as @last. one
+// "<list>\t<item>" line per list
+fn last_added_file() -> PathBuf {
+    let mut path = config_dir();
+    path.push("last_added");
+    path
+}
+
+fn last_added(list_name: &str) -> Option<String> {
+    // one from earlier in a batch, that hasn't been written yet
+    let pending = DEFERRED.with(|deferred| {
+        deferred
+            .borrow()
+            .iter()
+            .rev()
+            .find_map(|action| match action {
+                Deferred::LastAdded(list, item) if list == list_name => Some(item.clone()),
+                _ => None,
+            })
+    });
+    if let Some(item) = pending {
+        return item;
+    }
+    let contents = std::fs::read_to_string(last_added_file()).ok()?;
+    contents.lines().find_map(|line| {
+        let (list, item) = line.split_once('\t')?;
+        (list == list_name).then(|| item.to_owned())
+    })
+}
+
+// with autorm --forget, so @last doesn't remember an item that's gone for good
+fn forget_last_added(list_name: &str, names: &HashSet<String>) {
+    if last_added(list_name).is_some_and(|item| names.contains(&item)) {
+        defer(Deferred::LastAdded(list_name.to_owned(), None));
+    }
+}
+
+fn set_last_added(list_name: &str, item_name: &str) {
+    defer(Deferred::LastAdded(
+        list_name.to_owned(),
+        Some(item_name.to_owned()),
+    ));
+}
+
+// replaces the line for `list_name`, or takes it out with None
+fn write_last_added(list_name: &str, item_name: Option<&str>) {
+    use std::fmt::Write;
+    // a list or item with a tab or newline in its name would make a mess of the file
+    if [list_name, item_name.unwrap_or_default()]
+        .iter()
+        .any(|name| name.contains(['\t', '\n', '\r']))
+    {
+        return;
+    }
+    let path = last_added_file();
+    let contents = std::fs::read_to_string(&path).unwrap_or_default();
+    let mut acc = String::new();
+    for line in contents.lines() {
+        if line
+            .split_once('\t')
+            .is_some_and(|(list, _)| list != list_name)
+        {
+            writeln!(acc, "{line}").unwrap();
+        }
+    }
+    if let Some(item_name) = item_name {
+        writeln!(acc, "{list_name}\t{item_name}").unwrap();
+    }
+    if let Err(e) = std::fs::write(&path, acc) {
+        eprintln!("Warning: unable to write {}: {e}", path.display());
+    }
+}
+
+fn templates_dir() -> Result<PathBuf, String> {
+    let mut path = config_dir();
+    path.push("templates");
+    std::fs::create_dir_all(&path)
+        .map_err(|e| format!("Unable to create {}: {e}", path.display()))?;
+    Ok(path)
+}
+
+fn template_path(name: &str) -> Result<PathBuf, String> {
+    if name.is_empty() || name.contains(['/', '\\']) {
+        return Err(format!("Invalid template name '{name}'"));
+    }
+    let mut path = templates_dir()?;
+    path.push(format!("{name}.txt"));
+    Ok(path)
+}
+
+fn cmd_template(lists: &mut [TodoList], args: &[String]) -> CmdResult {
+    match args.first().map(String::as_str) {
+        Some("save") if args.len() >= 3 => {
+            let name = args[2..].join(" ");
+            let mut template = get_list_by_name(lists, &args[1])?.clone();
+            template.name.clone_from(&name);
+            let path = template_path(&name)?;
+            save(&path, &[template])
+                .map_err(|e| format!("Unable to save template '{name}': {e}"))?;
+            Ok((String::new(), false))
+        }
+        Some("apply") if args.len() >= 3 => {
+            let path = template_path(&args[1])?;
+            let template = load(&path)
+                .map_err(|e| format!("Unable to load template '{}': {e}", args[1]))?
+                .into_iter()
+                .next()
+                .ok_or_else(|| format!("Template '{}' is empty", args[1]))?;
+            let list = get_mut_list_by_name(lists, &args[2..].join(" "))?;
+            // the copies start off fresh: not done and without dates
+            list.items.extend(template.items.into_iter().map(|entry| {
+                match entry {
+                    ListEntry::Item(item) => ListEntry::Item(ListItem {
+                        name: item.name,
+                        created: Some(today()),
+                        subitems: item
+                            .subitems
+                            .into_iter()
+                            .map(|(text, _)| (text, false))
+                            .collect(),
+                        ..Default::default()
+                    }),
+                    list @ ListEntry::List(_) => list,
+                }
+            }));
+            Ok((String::new(), true))
+        }
+        Some("list") if args.len() == 1 => {
+            let path = templates_dir()?;
+            let mut names = std::fs::read_dir(&path)
+                .map_err(|e| format!("Unable to read {}: {e}", path.display()))?
+                .filter_map(|entry| {
+                    let name = entry.ok()?.file_name().into_string().ok()?;
+                    name.strip_suffix(".txt").map(str::to_owned)
+                })
+                .collect::<Vec<String>>();
+            names.sort();
+            Ok((names.into_iter().map(|name| name + "\n").collect(), false))
+        }
+        _ => Err(usage()),
+    }
+}
+
+// adds the lists in another file to ours. lists with the same name as one we've already
+// got are merged into it
+fn cmd_import(lists: &mut Vec<TodoList>, source: &str) -> CmdResult {
+    let name = if source == "-" { "stdin" } else { source };
+    let imported = parser::parse_str(&read_input(source)?)
+        .map_err(|e| format!("Unable to import {name}: {}", e.0))?;
+    for list in imported {
+        match lists.iter_mut().find(|existing| existing.name == list.name) {
+            Some(existing) => existing.merge(&list, MergePolicy::MoreDone),
+            None => lists.push(list),
+        }
+    }
+    Ok((String::new(), true))
+}
+
+// what a (short) name picks out, found the same way every other command finds it
+fn cmd_which(lists: &[TodoList], args: &[String]) -> CmdResult {
+    use std::fmt::Write;
+    let how = |query: &str, name: &str| {
+        if query == "@last" {
+            "the last item added"
+        } else if nfc(query) == nfc(name) {
+            "its whole name"
+        } else if query.contains('/') {
+            "its path"
+        } else {
+            "the start of its name"
+        }
+    };
+    let (list_name, item) = args.split_first().ok_or_else(usage)?;
+    let list = &lists[get_list_index_by_path(lists, list_name)?];
+    let mut acc = String::new();
+    writeln!(
+        acc,
+        "'{list_name}' is the list '{}' (by {})",
+        list.name,
+        how(list_name, &list.name)
+    )
+    .unwrap();
+    if item.is_empty() {
+        return Ok((acc, false));
+    }
+    let item = item.join(" ");
+    let glob = is_glob(&item);
+    let matches = if glob {
+        glob_entries(list, &item)?
+    } else {
+        vec![get_index_by_name(list, &item)?]
+    };
+    for idx in matches {
+        let entry = &list.items[idx];
+        let what = match entry {
+            ListEntry::Item(_) => "item",
+            ListEntry::List(_) => "sublist",
+        };
+        let by = if glob {
+            "the pattern"
+        } else {
+            how(&item, entry.name())
+        };
+        writeln!(
+            acc,
+            "'{item}' is the {what} '{}' in it, entry {idx} (by {by})",
+            entry.name()
+        )
+        .unwrap();
+    }
+    Ok((acc, false))
+}
+
+fn cmd_diff(args: &[String]) -> CmdResult {
+    let mut args = args.to_vec();
+    let json = take_flag(&mut args, "--json");
+    let [old, new] = args.as_slice() else {
+        return Err(usage());
+    };
+    let parse = |file: &str| {
+        parser::parse_str(&read_input(file)?)
+            .map_err(|e| format!("Unable to parse {file}: {}", e.0))
+    };
+    let changes = diff::diff(&parse(old)?, &parse(new)?);
+    if json {
+        Ok((diff::to_json(&changes), false))
+    } else {
+        Ok((diff::render(&changes), false))
+    }
+}
+
+// `todo batch < commands.txt`: runs each line of stdin as if it was passed to todo, all on the
+// same lists, which are only saved once at the end. blank lines and lines starting with # are
+// skipped. a line that fails is reported with its line number and the rest carry on
+fn cmd_batch(lists: &mut Vec<TodoList>, list_file: &Path, config: &Config) -> CmdResult {
+    run_batch(lists, &read_input("-")?, list_file, config)
+}
+
+fn run_batch(
+    lists: &mut Vec<TodoList>,
+    input: &str,
+    list_file: &Path,
+    config: &Config,
+) -> CmdResult {
+    let mut modified = false;
+    for (line_no, line) in input.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        // a line that fails doesn't leave half of what it did behind, or anything that was
+        // waiting for the lists to be saved
+        let waiting = deferred_len();
+        let result = split_command_line(line).and_then(|mut args| {
+            let mut config = config.clone();
+            take_force(&mut config, &mut args);
+            let args = expand_aliases(&args, &config);
+            match args.first().map(String::as_str) {
+                None => Err(usage()),
+                Some(command @ ("batch" | "serve" | "ui")) => {
+                    Err(format!("'{command}' can't be run in a batch"))
+                }
+                Some(command) => {
+                    let before = (!READ_ONLY_COMMANDS.contains(&command)).then(|| lists.clone());
+                    let result = run_builtin(lists, &args, list_file, &config);
+                    match (&result, before) {
+                        (Ok((msg, true)), _) => {
+                            defer(Deferred::History(args.clone(), msg.clone()));
+                        }
+                        (Err(_), Some(before)) => *lists = before,
+                        _ => (),
+                    }
+                    result
+                }
+            }
+        });
+        // printed as it goes, so the output and errors come out in order
+        match result {
+            Ok((msg, changed)) => {
+                print!("{msg}");
+                modified |= changed;
+            }
+            Err(e) => {
+                undefer(waiting);
+                eprintln!("line {}: {e}", line_no + 1);
+                EXIT_CODE.store(1, Ordering::SeqCst);
+            }
+        }
+    }
+    Ok((String::new(), modified))
+}
+
+// splits a line up into arguments like a shell would (without any of the clever bits): on
+// whitespace, except inside "..." or '...', and \ takes the next character as it is
+fn split_command_line(line: &str) -> Result<Vec<String>, String> {
+    let mut args = Vec::new();
+    let mut current: Option<String> = None;
+    let mut quote = None;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (None, '"' | '\'') => {
+                quote = Some(c);
+                current.get_or_insert_with(String::new);
+            }
+            (Some('\''), c) => current.get_or_insert_with(String::new).push(c),
+            (_, '\\') => {
+                let escaped = chars
+                    .next()
+                    .ok_or("Nothing after \\ at the end of the line")?;
+                current.get_or_insert_with(String::new).push(escaped);
+            }
+            (None, c) if c.is_whitespace() => args.extend(current.take()),
+            (_, c) => current.get_or_insert_with(String::new).push(c),
+        }
+    }
+    if let Some(q) = quote {
+        return Err(format!("Missing closing {q}"));
+    }
+    args.extend(current);
+    Ok(args)
+}
+
+// writes out everything, re-emitted from what was loaded, so the backup is also checked
+// and tidied up. goes to <config>/backups/ with the date and time in its name by default
+fn cmd_backup(lists: &[TodoList], args: &[String]) -> CmdResult {
+    let mut args = args.to_vec();
+    let yaml = take_flag(&mut args, "--yaml");
+    let out = if yaml {
+        parser::emit_yaml(lists)
+    } else {
+        parser::emit_str(lists)
+    };
+    let path = match args.join(" ").as_str() {
+        "-" => return Ok((out, false)),
+        "" => {
+            let mut path = config_dir();
+            path.push("backups");
+            std::fs::create_dir_all(&path)
+                .map_err(|e| format!("Unable to create {}: {e}", path.display()))?;
+            path.push(format!(
+                "todo-{}.{}",
+                Local::now().format("%Y-%m-%d-%H%M%S"),
+                if yaml { "yaml" } else { "txt" }
+            ));
+            path
+        }
+        path => PathBuf::from(path),
+    };
+    std::fs::write(&path, out).map_err(|e| format!("Unable to write {}: {e}", path.display()))?;
+    Ok((format!("Backed up to {}\n", path.display()), false))
+}
+
+fn cmd_export(lists: &[TodoList], args: &[String]) -> CmdResult {
+    let mut args = args.to_vec();
+    // with --list, just that list and every list in it, so nothing it refers to is left out
+    let subtree: Vec<TodoList>;
+    let lists = match take_option(&mut args, "--list")? {
+        Some(name) => {
+            let list = get_list_by_name(lists, &name)?;
+            subtree = list.subtree(lists).into_iter().cloned().collect();
+            &subtree
+        }
+        None => lists,
+    };
+    let out = if take_flag(&mut args, "--txt") {
+        parser::emit_str(lists)
+    } else if take_flag(&mut args, "--csv") {
+        parser::emit_csv(lists)
+    } else if take_flag(&mut args, "--json") {
+        parser::emit_json(lists) + "\n"
+    } else if take_flag(&mut args, "--yaml") {
+        parser::emit_yaml(lists)
+    } else if take_flag(&mut args, "--ndjson") {
+        parser::emit_ndjson(&parser::all_items_with_paths(lists))
+    } else {
+        return Err("Expected one of --txt, --csv, --json, --yaml or --ndjson".to_string());
+    };
+    match args.join(" ").as_str() {
+        "" | "-" => Ok((out, false)),
+        path => {
+            std::fs::write(path, out).map_err(|e| format!("Unable to write {path}: {e}"))?;
+            Ok((String::new(), false))
+        }
+    }
+}
+
+fn cmd_addlist(lists: &mut Vec<TodoList>, args: &[String]) -> CmdResult {
+    let mut args = args.to_vec();
+    let copy = take_flag(&mut args, "--copy");
+    let [dest_list, src_list] = args.as_slice() else {
+        return Err(usage());
+    };
+    let src = &lists[get_list_index_by_path(lists, src_list)?];
+    let dest = get_list_index_by_path(lists, dest_list)?;
+    if copy {
+        let name = copy_list(lists, &src.name.clone());
+        lists[dest].items.push(ListEntry::List(name.clone()));
+        return Ok((
+            format!(
+                "Added a copy of '{src_list}' to '{}' as '{name}'\n",
+                lists[dest].name
+            ),
+            true,
+        ));
+    }
+    check_no_loop(lists, src, &lists[dest])?;
+    let lname = src.name.clone();
+    lists[dest].items.push(ListEntry::List(lname.clone()));
+    Ok((format!("Added '{lname}' to '{}'\n", lists[dest].name), true))
+}
+
+// makes a new list with the same entries as `name`, and new copies of all the lists in it too,
+// so changing one never changes the other. returns the name of the new list
+fn copy_list(lists: &mut Vec<TodoList>, name: &str) -> String {
+    let Ok(list) = get_list_by_name(lists, name) else {
+        return name.to_owned();
+    };
+    // each list is only copied once, even if it's in there more than once
+    let mut names = HashMap::new();
+    for list in list.subtree(lists) {
+        let mut copy_name = format!("{} (copy)", list.name);
+        let mut n = 2;
+        while lists.iter().any(|other| other.name == copy_name)
+            || names.values().any(|other| *other == copy_name)
+        {
+            copy_name = format!("{} (copy {n})", list.name);
+            n += 1;
+        }
+        names.insert(list.name.clone(), copy_name);
+    }
+    let copies = lists
+        .iter()
+        .filter_map(|list| {
+            let name = names.get(&list.name)?;
+            let mut copy = list.clone();
+            copy.name.clone_from(name);
+            // the copies are new things, with new IDs if they get any
+            copy.id = None;
+            for entry in &mut copy.items {
+                if let ListEntry::Item(item) = entry {
+                    item.id = None;
+                }
+                if let ListEntry::List(sublist) = entry {
+                    let sublist_name = get_list_by_name(lists, sublist)
+                        .map_or_else(|_| sublist.clone(), |list| list.name.clone());
+                    if let Some(copy_name) = names.get(&sublist_name) {
+                        sublist.clone_from(copy_name);
+                    }
+                }
+            }
+            Some(copy)
+        })
+        .collect::<Vec<TodoList>>();
+    lists.extend(copies);
+    names.remove(name).unwrap_or_else(|| name.to_owned())
+}
+
+// putting `sublist` inside `dest` mustn't make a list end up inside itself
+fn check_no_loop(lists: &[TodoList], sublist: &TodoList, dest: &TodoList) -> Result<(), String> {
+    if sublist.name == dest.name {
+        return Err(format!("'{}' can't go inside itself", dest.name));
+    }
+    if sublist
+        .subtree(lists)
+        .iter()
+        .any(|list| list.name == dest.name)
+    {
+        return Err(format!(
+            "'{}' can't go in '{}', because '{}' is already inside '{}'",
+            sublist.name, dest.name, dest.name, sublist.name
+        ));
+    }
+    Ok(())
+}
+
+// things a command does outside the list file wait until the lists have been saved, so a command
+// that fails, or a line of a batch that's undone, doesn't leave any of them behind
+enum Deferred {
+    // see write_last_added
+    LastAdded(String, Option<String>),
+    // the list and the item as it was when it was done
+    DoneHook(String, ListItem),
+    // a line of a batch, which is recorded by itself rather than as part of the batch
+    History(Vec<String>, String),
+}
+
+thread_local! {
+    // one list per thread, so the tests, which run side by side, don't get each other's
+    static DEFERRED: std::cell::RefCell<Vec<Deferred>> = const { std::cell::RefCell::new(Vec::new()) };
+}
+
+fn defer(action: Deferred) {
+    DEFERRED.with(|deferred| deferred.borrow_mut().push(action));
+}
+
+fn deferred_len() -> usize {
+    DEFERRED.with(|deferred| deferred.borrow().len())
+}
+
+// once the lists have been saved
+fn run_deferred(config: &Config) {
+    for action in DEFERRED.with(std::cell::RefCell::take) {
+        match action {
+            Deferred::LastAdded(list, item) => write_last_added(&list, item.as_deref()),
+            Deferred::DoneHook(list, item) => run_done_hook(config, &list, &item),
+            Deferred::History(args, msg) => record_history(config, &args, &msg),
+        }
+    }
+}
+
+// forgets everything deferred after the first `len`, when what did it is undone or isn't saved
+fn undefer(len: usize) {
+    DEFERRED.with(|deferred| deferred.borrow_mut().truncate(len));
+}
+
+// runs the on_done_hook from the config file, if there is one, after an item is done and the
+// lists have been saved. it can't make the done itself fail, so problems are only warned about
+fn run_done_hook(config: &Config, list: &str, item: &ListItem) {
+    let Some(hook) = &config.on_done_hook else {
+        return;
+    };
+    let mut command = if cfg!(windows) {
+        let mut command = std::process::Command::new("cmd");
+        command.arg("/C");
+        command
+    } else {
+        let mut command = std::process::Command::new("sh");
+        command.arg("-c");
+        command
+    };
+    let date = item
+        .date
+        .map_or_else(String::new, |date| date.format("%d/%m/%Y").to_string());
+    let status = command
+        .arg(hook)
+        .env("TODO_ITEM", &item.name)
+        .env("TODO_LIST", list)
+        .env("TODO_DATE", date)
+        .stdin(std::process::Stdio::null())
+        .status();
+    match status {
+        Ok(status) if status.success() => (),
+        Ok(status) => eprintln!("Warning: on_done_hook failed ({status})"),
+        Err(e) => eprintln!("Warning: unable to run on_done_hook: {e}"),
+    }
+}
+
+// the full name of a command, so hooks don't have to know all the short ones
+fn full_command_name(command: &str) -> &str {
+    match command {
+        "l" => "list",
+        "ls" => "lists",
+        "n" => "new",
+        "rl" => "rmlist",
+        "a" => "add",
+        "lc" => "listconfig",
+        "tp" => "template",
+        "im" => "import",
+        "al" => "addlist",
+        "d" => "done",
+        "dd" => "dedup",
+        "ar" => "autorm",
+        "tr" => "trash",
+        "lb" => "label",
+        "ln" => "link",
+        "pg" => "progress",
+        "pr" => "priority",
+        "rp" => "repeat",
+        "so" => "sort",
+        "rn" => "rename",
+        "rm" | "r" => "remove",
+        "mv" | "m" => "move",
+        "mg" => "merge",
+        "mvall" | "mva" | "ma" => "moveall",
+        "da" => "doneall",
+        "uda" => "undoneall",
+        command => command,
+    }
+}
+
+// runs post-<command> from hooks_dir, if it's set and there is one, once a command has changed
+// the lists and they've been saved. like on_done_hook, problems are only warned about
+fn run_post_hook(config: &Config, args: &[String], list_file: &Path) {
+    let (Some(dir), Some((command, args))) = (&config.hooks_dir, args.split_first()) else {
+        return;
+    };
+    let command = full_command_name(command);
+    let hook = dir.join(format!("post-{command}"));
+    if !hook.is_file() {
+        return;
+    }
+    let status = std::process::Command::new(&hook)
+        .args(args)
+        .env("TODO_COMMAND", command)
+        .env("TODO_FILE", list_file)
+        .stdin(std::process::Stdio::null())
+        .status();
+    match status {
+        Ok(status) if status.success() => (),
+        Ok(status) => eprintln!("Warning: {} failed ({status})", hook.display()),
+        Err(e) => eprintln!("Warning: unable to run {}: {e}", hook.display()),
+    }
+}
+
+fn done_entry(
+    lists: &mut [TodoList],
+    list_name: &str,
+    item_name: &str,
+    config: &Config,
+) -> CmdResult {
+    let list = get_mut_list_by_name(lists, list_name)?;
+    let idx = get_index_by_name(list, item_name)?;
+    mark_done(list, idx, config)
+}
+
+fn cmd_done(lists: &mut [TodoList], args: &[String], config: &Config) -> CmdResult {
+    let mut args = args.to_vec();
+    let quiet = take_flag(&mut args, "--quiet");
+    let index = take_option(&mut args, "--index")?;
+    if args.is_empty() || (index.is_some() && args.len() != 1) {
+        return Err(usage());
+    }
+    let name = args[1..].join(" ");
+    // an empty name is the start of every name, so it would pick out the only item there is
+    if index.is_none() && name.trim().is_empty() {
+        return Err(usage());
+    }
+    let list = get_mut_list_by_name(lists, &args[0])?;
+    if index.is_some() || !is_glob(&name) {
+        let idx = pick_entry(list, index.as_deref(), &name)?;
+        return quietly(quiet, mark_done(list, idx, config));
+    }
+    // only the items that aren't done yet, so none of them get undone
+    let matches = glob_entries(list, &name)?
+        .into_iter()
+        .filter(|&idx| matches!(&list.items[idx], ListEntry::Item(item) if !item.done))
+        .collect::<Vec<usize>>();
+    for &idx in &matches {
+        mark_done(list, idx, config)?;
+    }
+    quietly(
+        quiet,
+        Ok((
+            format!(
+                "Marked {} item{} as done\n",
+                matches.len(),
+                if matches.len() == 1 { "" } else { "s" }
+            ),
+            !matches.is_empty(),
+        )),
+    )
+}
+
+// --quiet leaves out the message saying what done, remove, rename or move did
+fn quietly(quiet: bool, result: CmdResult) -> CmdResult {
+    result.map(|(msg, modified)| (if quiet { String::new() } else { msg }, modified))
+}
+
+fn mark_done(list: &mut TodoList, idx: usize, config: &Config) -> CmdResult {
+    if let ListEntry::Item(i) = &mut list.items[idx] {
+        // the hook gets the item as it was when it was done, before a repeat moves its date on
+        let before = i.clone();
+        match (i.date, i.repeat) {
+            // repeating items move on to their next occurrence rather than being done
+            (Some(date), Some(repeat)) if !i.done => {
+                let today = today();
+                let mut next = repeat.next(date);
+                while next <= today {
+                    next = repeat.next(next);
+                }
+                // the next one keeps what it's about (its name, label, priority, link and when
+                // it was added) but starts again from nothing done
+                *i = ListItem {
+                    date: Some(next),
+                    done: false,
+                    progress: i.progress.map(|_| 0),
+                    completed: None,
+                    subitems: i
+                        .subitems
+                        .iter()
+                        .map(|(text, _)| (text.clone(), false))
+                        .collect(),
+                    ..i.clone()
+                };
+            }
+            _ => i.set_done(!i.done),
+        }
+        if !before.done && config.on_done_hook.is_some() {
+            defer(Deferred::DoneHook(list.name.clone(), before.clone()));
+        }
+        // with the whole name, so it's clear which item a short name picked out
+        let msg = match (before.done, i.done, i.date) {
+            (false, false, Some(date)) => format!(
+                "Marked '{}' as done, it's next due {}\n",
+                i.name,
+                date.format("%d/%m/%Y")
+            ),
+            (_, true, _) => format!("Marked '{}' as done\n", i.name),
+            (_, false, _) => format!("Marked '{}' as not done\n", i.name),
+        };
+        Ok((msg, true))
+    } else {
+        Err("You can't done a list silly (todo add this feature cos its cool)".to_string())
+    }
+}
+
+fn cmd_label(lists: &mut [TodoList], list_name: &str, args: &[String]) -> CmdResult {
+    let (colour, item_name) = args.split_last().ok_or_else(usage)?;
+    let label = if colour == "none" {
+        None
+    } else {
+        label_colour(colour)?;
+        Some(colour.clone())
+    };
+    let list = get_mut_list_by_name(lists, list_name)?;
+    let idx = get_index_by_name(list, &item_name.join(" "))?;
+    if let ListEntry::Item(i) = &mut list.items[idx] {
+        i.label = label;
+        Ok((String::new(), true))
+    } else {
+        Err("Only items can be labelled, not lists".to_string())
+    }
+}
+
+fn cmd_link(lists: &mut [TodoList], list_name: &str, args: &[String]) -> CmdResult {
+    let (url, item_name) = args.split_last().ok_or_else(usage)?;
+    let url = if url == "none" {
+        None
+    } else {
+        check_url(url)?;
+        Some(url.clone())
+    };
+    let list = get_mut_list_by_name(lists, list_name)?;
+    let idx = get_index_by_name(list, &item_name.join(" "))?;
+    if let ListEntry::Item(i) = &mut list.items[idx] {
+        i.url = url;
+        Ok((String::new(), true))
+    } else {
+        Err("Only items can have links, not lists".to_string())
+    }
+}
+
+// ] would end the attribute in the list file
+fn check_url(url: &str) -> Result<(), String> {
+    let scheme = url.split_once(':').map_or("", |(scheme, _)| scheme);
+    let valid = scheme.len() > 1
+        && scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+        && scheme
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+        && !url.contains(|c: char| c == ']' || c.is_whitespace() || c.is_control());
+    if valid {
+        Ok(())
+    } else {
+        Err(format!(
+            "Invalid URL '{url}', expected something like https://example.com. Use %5D for ]"
+        ))
+    }
+}
+
+fn cmd_open(lists: &[TodoList], list_name: &str, item_name: &str) -> CmdResult {
+    let list = get_list_by_name(lists, list_name)?;
+    let idx = get_index_by_name(list, item_name)?;
+    let ListEntry::Item(item) = &list.items[idx] else {
+        return Err("Only items can have links, not lists".to_string());
+    };
+    let url = item
+        .url
+        .as_ref()
+        .ok_or_else(|| format!("'{}' doesn't have a link", item.name))?;
+    // the opener will happily run programs and open local files, so only web and email
+    // links are passed to it
+    let scheme = url.split_once(':').map_or("", |(scheme, _)| scheme);
+    if !["http", "https", "mailto"].contains(&scheme.to_lowercase().as_str()) {
+        return Err(format!(
+            "Only http, https and mailto links can be opened, not '{url}'"
+        ));
+    }
+    let mut command = if cfg!(windows) {
+        let mut command = std::process::Command::new("rundll32");
+        command.arg("url.dll,FileProtocolHandler");
+        command
+    } else if cfg!(target_os = "macos") {
+        std::process::Command::new("open")
+    } else {
+        std::process::Command::new("xdg-open")
+    };
+    match command.arg(url).status() {
+        Ok(status) if status.success() => Ok((String::new(), false)),
+        Ok(status) => Err(format!("Unable to open {url} ({status})")),
+        Err(e) => Err(format!("Unable to open {url}: {e}")),
+    }
+}
+
+fn cmd_progress(lists: &mut [TodoList], list_name: &str, args: &[String]) -> CmdResult {
+    let (percent, item_name) = args.split_last().ok_or_else(usage)?;
+    let progress = percent
+        .trim_end_matches('%')
+        .parse::<u8>()
+        .ok()
+        .filter(|progress| *progress <= 100)
+        .ok_or_else(|| {
+            format!("Invalid progress '{percent}', expected a percentage from 0 to 100")
+        })?;
+    let list = get_mut_list_by_name(lists, list_name)?;
+    let idx = get_index_by_name(list, &item_name.join(" "))?;
+    if let ListEntry::Item(i) = &mut list.items[idx] {
+        i.progress = Some(progress);
+        i.set_done(progress == 100);
+        Ok((String::new(), true))
+    } else {
+        Err("Lists don't have progress, only items do".to_string())
+    }
+}
+
+fn cmd_priority(lists: &mut [TodoList], list_name: &str, args: &[String]) -> CmdResult {
+    let (priority, item_name) = args.split_last().ok_or_else(usage)?;
+    let priority = priority
+        .parse()
+        .map_err(|_| format!("Invalid priority '{priority}', expected 0 to 255"))?;
+    let list = get_mut_list_by_name(lists, list_name)?;
+    let idx = get_index_by_name(list, &item_name.join(" "))?;
+    if let ListEntry::Item(i) = &mut list.items[idx] {
+        i.priority = priority;
+        Ok((String::new(), true))
+    } else {
+        Err("Only items can have a priority, not lists".to_string())
+    }
+}
+
+fn cmd_repeat(lists: &mut [TodoList], list_name: &str, item_name: &str, period: &str) -> CmdResult {
+    let repeat = RepeatKind::parse(period)?;
+    let list = get_mut_list_by_name(lists, list_name)?;
+    let idx = get_index_by_name(list, item_name)?;
+    if let ListEntry::Item(i) = &mut list.items[idx] {
+        i.date = Some(first_occurrence(repeat, i.date)?);
+        i.repeat = Some(repeat);
+        Ok((String::new(), true))
+    } else {
+        Err("Lists can't repeat, only items can".to_string())
+    }
+}
+
+fn cmd_doneall(lists: &mut [TodoList], args: &[String], target_state: bool) -> CmdResult {
+    let mut args = args.to_vec();
+    let recursive = take_flag(&mut args, "--recursive");
+    let list = get_list_by_name(lists, &args.join(" "))?;
+    let names: Vec<String> = if recursive {
+        list.subtree(lists)
+            .iter()
+            .map(|list| list.name.clone())
+            .collect()
+    } else {
+        vec![list.name.clone()]
+    };
+    for list in lists.iter_mut().filter(|list| names.contains(&list.name)) {
+        for item in &mut list.items {
+            if let ListEntry::Item(item) = item {
+                item.set_done(target_state);
+            }
+        }
+    }
+    Ok((String::new(), true))
+}
+
+fn remove_entry(
+    lists: &mut Vec<TodoList>,
+    list_name: &str,
+    item_name: &str,
+    config: &Config,
+) -> CmdResult {
+    let idx = get_index_by_name(get_list_by_name(lists, list_name)?, item_name)?;
+    remove_at(lists, list_name, idx, config)
+}
+
+// removes the entry at `idx` in a list, for when it's already been picked out. like remove, it
+// goes in the trash with use_trash on
+fn remove_at(lists: &mut Vec<TodoList>, list_name: &str, idx: usize, config: &Config) -> CmdResult {
+    let list = get_mut_list_by_name(lists, list_name)?;
+    let entry = list.items.remove(idx);
+    let msg = format!("Removed '{}' from '{}'\n", entry.name(), list.name);
+    if config.use_trash && list.name != TRASH {
+        let from = list.name.clone();
+        trash_entries(lists, &from, vec![entry]);
+    }
+    Ok((msg, true))
+}
+
+fn cmd_remove(lists: &mut Vec<TodoList>, args: &[String], config: &Config) -> CmdResult {
+    let mut args = args.to_vec();
+    let quiet = take_flag(&mut args, "--quiet");
+    let index = take_option(&mut args, "--index")?;
+    if args.is_empty() || (index.is_some() && args.len() != 1) {
+        return Err(usage());
+    }
+    let name = args[1..].join(" ");
+    // an empty name is the start of every name, so it would pick out the only item there is
+    if index.is_none() && name.trim().is_empty() {
+        return Err(usage());
+    }
+    let list = get_mut_list_by_name(lists, &args[0])?;
+    let glob = index.is_none() && is_glob(&name);
+    let matches = if glob {
+        glob_entries(list, &name)?
+    } else {
+        vec![pick_entry(list, index.as_deref(), &name)?]
+    };
+    let mut removed = matches
+        .iter()
+        .rev()
+        .map(|&idx| list.items.remove(idx))
+        .collect::<Vec<ListEntry>>();
+    removed.reverse();
+    let msg = match removed.as_slice() {
+        [entry] if !glob => format!("Removed '{}' from '{}'\n", entry.name(), list.name),
+        _ => format!(
+            "Removed {} entr{}\n",
+            matches.len(),
+            if matches.len() == 1 { "y" } else { "ies" }
+        ),
+    };
+    // things taken out of the trash itself are gone for good
+    if config.use_trash && list.name != TRASH {
+        let from = list.name.clone();
+        trash_entries(lists, &from, removed);
+    }
+    quietly(quiet, Ok((msg, true)))
+}
+
+// reorders the entries in the file, unlike list --sort. sublists are sorted by their name, the
+// soonest deadline of anything not done in them, the highest priority of anything not done
+// in them, or the newest thing added or done in them, unless --lists-first or --lists-last
+// puts them all together
+fn cmd_sort(lists: &mut [TodoList], args: &[String]) -> CmdResult {
+    let mut args = args.to_vec();
+    let key = SortKey::parse(&take_option(&mut args, "--by")?.ok_or_else(usage)?)?;
+    let reverse = take_flag(&mut args, "--reverse");
+    let lists_first = take_flag(&mut args, "--lists-first");
+    let lists_last = take_flag(&mut args, "--lists-last");
+    if lists_first && lists_last {
+        return Err("Only one of --lists-first and --lists-last can be used".to_string());
+    }
+    let idx = get_list_index_by_name(lists, &args.join(" "))?;
+    let all = &*lists;
+    // the things in a sublist that aren't done yet
+    let not_done = |name: &str| -> Vec<&ListItem> {
+        get_list_by_name(all, name).map_or_else(
+            |_| Vec::new(),
+            |list| {
+                list.walk_items(all)
+                    .into_iter()
+                    .filter(|item| !item.done)
+                    .collect()
+            },
+        )
+    };
+    let date = |entry: &ListEntry| match entry {
+        ListEntry::Item(item) => item.date,
+        ListEntry::List(name) => not_done(name).iter().filter_map(|item| item.date).min(),
+    };
+    let priority = |entry: &ListEntry| match entry {
+        ListEntry::Item(item) => item.priority,
+        ListEntry::List(name) => not_done(name)
+            .iter()
+            .map(|item| item.priority)
+            .max()
+            .unwrap_or(0),
+    };
+    let stamp = |item: &ListItem| {
+        if key == SortKey::Created {
+            item.created
+        } else {
+            item.completed
+        }
+    };
+    let newest = |entry: &ListEntry| match entry {
+        ListEntry::Item(item) => stamp(item),
+        ListEntry::List(name) => get_list_by_name(all, name)
+            .ok()
+            .and_then(|list| list.walk_items(all).into_iter().filter_map(stamp).max()),
+    };
+    let compare = |a: &ListEntry, b: &ListEntry| {
+        let order = match key {
+            SortKey::Name => a.name().cmp(b.name()),
+            // undated things go at the end
+            SortKey::Date => {
+                let (a, b) = (date(a), date(b));
+                (a.is_none(), a).cmp(&(b.is_none(), b))
+            }
+            SortKey::Priority => priority(b).cmp(&priority(a)),
+            // newest first, and things without the date at the end
+            SortKey::Created | SortKey::Completed => {
+                let (a, b) = (newest(a), newest(b));
+                (a.is_none(), std::cmp::Reverse(a)).cmp(&(b.is_none(), std::cmp::Reverse(b)))
+            }
+        };
+        let group = |entry: &ListEntry| match entry {
+            ListEntry::List(_) if lists_first => 0,
+            ListEntry::List(_) if lists_last => 2,
+            _ => 1,
+        };
+        group(a)
+            .cmp(&group(b))
+            .then(if reverse { order.reverse() } else { order })
+    };
+    let mut items = lists[idx].items.clone();
+    items.sort_by(compare);
+    lists[idx].items = items;
+    Ok((String::new(), true))
+}
+
+// the item called `item_name` in `list_name`, for the commands that change its checklist
+fn get_mut_item<'a>(
+    lists: &'a mut [TodoList],
+    list_name: &str,
+    item_name: &str,
+) -> Result<&'a mut ListItem, String> {
+    let list = get_mut_list_by_name(lists, list_name)?;
+    let idx = get_index_by_name(list, item_name)?;
+    match &mut list.items[idx] {
+        ListEntry::Item(item) => Ok(item),
+        ListEntry::List(name) => Err(format!("'{name}' is a list, not an item")),
+    }
+}
+
+fn cmd_subadd(lists: &mut [TodoList], args: &[String]) -> CmdResult {
+    let [list, item, text @ ..] = args else {
+        return Err(usage());
+    };
+    if text.is_empty() {
+        return Err(usage());
+    }
+    let text = text.join(" ");
+    let item = get_mut_item(lists, list, item)?;
+    item.subitems.push((text.clone(), false));
+    Ok((format!("Added '{text}' to '{}'\n", item.name), true))
+}
+
+// marks a step in an item's checklist as done, or as not done if it already is
+fn cmd_subdone(lists: &mut [TodoList], args: &[String]) -> CmdResult {
+    let [list, item, index] = args else {
+        return Err(usage());
+    };
+    let item = get_mut_item(lists, list, item)?;
+    let (name, subitems) = (&item.name, &mut item.subitems);
+    let (text, done) = index
+        .parse::<usize>()
+        .ok()
+        .and_then(|idx| subitems.get_mut(idx))
+        .ok_or_else(|| format!("There is no step {index} in '{name}'"))?;
+    *done = !*done;
+    Ok((
+        format!(
+            "Marked '{text}' in '{name}' as {}\n",
+            if *done { "done" } else { "not done" }
+        ),
+        true,
+    ))
+}
+
+fn cmd_rename(lists: &mut [TodoList], args: &[String]) -> CmdResult {
+    let mut args = args.to_vec();
+    let allow_duplicate = take_flag(&mut args, "--allow-duplicate");
+    let quiet = take_flag(&mut args, "--quiet");
+    if args.len() < 3 {
+        return Err(usage());
+    }
+    let new = args[2..].join(" ");
+    let list = get_mut_list_by_name(lists, &args[0])?;
+    let idx = get_index_by_name(list, &args[1])?;
+    // another item with the same name (even spelt with different characters, see nfc) would
+    // make one of them impossible to pick out
+    let taken = list
+        .items
+        .iter()
+        .enumerate()
+        .any(|(i, entry)| i != idx && nfc(entry.name()) == nfc(&new));
+    if taken && !allow_duplicate {
+        return Err(format!(
+            "There is already an item called '{new}' in '{}'. Pass --allow-duplicate to rename it anyway",
+            list.name
+        ));
+    }
+    if let ListEntry::Item(i) = &mut list.items[idx] {
+        // so @last still finds it
+        if last_added(&list.name).as_ref() == Some(&i.name) {
+            set_last_added(&list.name, &new);
+        }
+        let msg = format!("Renamed '{}' to '{new}'\n", i.name);
+        new.clone_into(&mut i.name);
+        quietly(quiet, Ok((msg, true)))
+    } else {
+        Err("Renaming a list entry doesn't really make sense".to_string())
+    }
+}
+
+fn cmd_rnlist(lists: &mut [TodoList], old: &str, new: &str) -> CmdResult {
+    check_list_name_free(lists, new)?;
+    let list = get_mut_list_by_name(lists, old)?;
+    new.clone_into(&mut list.name);
+    Ok((String::new(), true))
+}
+
+fn cmd_move(lists: &mut Vec<TodoList>, args: &[String], config: &Config) -> CmdResult {
+    let mut args = args.to_vec();
+    let create = take_flag(&mut args, "--create");
+    let quiet = take_flag(&mut args, "--quiet");
+    if let Some(expr) = take_option(&mut args, "--where")? {
+        // it can empty a list just like moveall can
+        if config.append_only {
+            return Err(append_only_error("move --where"));
+        }
+        return quietly(quiet, move_where(lists, &args, &expr, create));
+    }
+    // with --index, there's no <item> between the lists
+    let index = take_option(&mut args, "--index")?;
+    let (item_name, dest_list_name) = match (index.is_some(), args.len()) {
+        (true, 2..) => ("", args[1..].join(" ")),
+        (false, 3..) => (args[1].as_str(), args[2..].join(" ")),
+        _ => return Err(usage()),
+    };
+    // nothing's saved if the move itself fails, so the new list doesn't hang around
+    if create {
+        check_not_near_a_list(lists, &dest_list_name)?;
+        new_list(lists, dest_list_name.clone())?;
+    }
+    // check that the dest list exists first
+    // otherwise, either the borrow checker will yell at me (lists is borrowed mutable twice in src_list and dest_list)
+    // or a nonexistant dest list will casue the item to be removed and not replaced
+    let dest_idx = get_list_index_by_path(lists, &dest_list_name)?;
+    let src_idx = get_list_index_by_path(lists, &args[0])?;
+    let glob = index.is_none() && is_glob(item_name);
+    let item_idxs = if glob {
+        glob_entries(&lists[src_idx], item_name)?
+    } else {
+        vec![pick_entry(&lists[src_idx], index.as_deref(), item_name)?]
+    };
+    // sublists can be moved around too, as long as they don't end up inside themselves
+    for &item_idx in &item_idxs {
+        if let ListEntry::List(name) = &lists[src_idx].items[item_idx] {
+            check_no_loop(lists, get_list_by_name(lists, name)?, &lists[dest_idx])?;
+        }
+    }
+    // removed from the back so the indices stay right, but kept in their order in <dest>
+    let mut moved = item_idxs
+        .iter()
+        .rev()
+        .map(|&item_idx| lists[src_idx].items.remove(item_idx))
+        .collect::<Vec<ListEntry>>();
+    moved.reverse();
+    let count = moved.len();
+    let msg = match moved.as_slice() {
+        [entry] if !glob => format!(
+            "Moved '{}' from '{}' to '{}'\n",
+            entry.name(),
+            lists[src_idx].name,
+            lists[dest_idx].name
+        ),
+        _ => format!(
+            "Moved {count} entr{}\n",
+            if count == 1 { "y" } else { "ies" }
+        ),
+    };
+    lists[dest_idx].items.extend(moved);
+    quietly(quiet, Ok((msg, true)))
+}
+
+// move <source> <dest> --where <filter>: every item in <source> that matches the filter, in the
+// same language as list --only. sublists stay where they are
+fn move_where(lists: &mut Vec<TodoList>, args: &[String], expr: &str, create: bool) -> CmdResult {
+    let filter = filter::Filter::parse(expr, today())?;
+    let (src_list_name, dest_list_name) = match args {
+        [src, dest @ ..] if !dest.is_empty() => (src, dest.join(" ")),
+        _ => return Err(usage()),
+    };
+    if create {
+        check_not_near_a_list(lists, &dest_list_name)?;
+        new_list(lists, dest_list_name.clone())?;
+    }
+    let dest_idx = get_list_index_by_path(lists, &dest_list_name)?;
+    let src_idx = get_list_index_by_path(lists, src_list_name)?;
+    if src_idx == dest_idx {
+        return Err(format!(
+            "Can't move from '{}' into itself",
+            lists[src_idx].name
+        ));
+    }
+    let (moved, kept): (Vec<ListEntry>, Vec<ListEntry>) = std::mem::take(&mut lists[src_idx].items)
+        .into_iter()
+        .partition(|entry| matches!(entry, ListEntry::Item(item) if filter.matches(item)));
+    lists[src_idx].items = kept;
+    let count = moved.len();
+    lists[dest_idx].items.extend(moved);
+    Ok((
+        format!("Moved {count} item{}\n", if count == 1 { "" } else { "s" }),
+        count > 0 || create,
+    ))
+}
+
+fn cmd_merge(lists: &mut [TodoList], args: &[String]) -> CmdResult {
+    let mut args = args.to_vec();
+    let policy = take_option(&mut args, "--prefer")?
+        .map(|policy| MergePolicy::parse(&policy))
+        .transpose()?
+        .unwrap_or(MergePolicy::MoreDone);
+    if args.len() < 2 {
+        return Err(usage());
+    }
+    let src = get_list_by_name(lists, &args[0])?;
+    let dest_idx = get_list_index_by_name(lists, &args[1..].join(" "))?;
+    // first, so merging a list into itself says so, rather than something about its sublists
+    if src.name == lists[dest_idx].name {
+        return Err(format!("Can't merge '{}' into itself", src.name));
+    }
+    for entry in &src.items {
+        if let ListEntry::List(name) = entry {
+            check_no_loop(lists, get_list_by_name(lists, name)?, &lists[dest_idx])?;
+        }
+    }
+    let src = src.clone();
+    lists[dest_idx].merge(&src, policy);
+    Ok((String::new(), true))
+}
+
+fn cmd_moveall(lists: &mut [TodoList], src_list_name: &str, dest_list_name: &str) -> CmdResult {
+    // check that the dest list exists first
+    // otherwise, either the borrow checker will yell at me (lists is borrowed mutable twice in src_list and dest_list)
+    // or a nonexistant dest list will casue the item to be removed and not replaced
+    let _ = get_list_by_name(lists, dest_list_name)?;
+    let src_list = get_mut_list_by_name(lists, src_list_name)?;
+    // Don't move a list into itself. Does not check recursively, so caution is still needed.
+    // let mut items = src_list
+    //     .items
+    //     .extract_if(|item| match item {
+    //         ListEntry::List(list) => list != dest_list_name,
+    //         _ => true,
+    //     })
+    //     .collect::<Vec<ListEntry>>();
+
+    // f***ing extract_if is nightly, so I guess I'll just implement it myself...
+    let mut items = Vec::new();
+    let mut i = 0;
+    while i < src_list.items.len() {
+        if matches!(&src_list.items[i], ListEntry::List(list) if list == dest_list_name) {
+            i += 1;
+        } else {
+            let val = src_list.items.remove(i);
+            items.push(val);
+        }
+    }
+
+    let dest_list = get_mut_list_by_name(lists, dest_list_name).unwrap(); // already checked
+    dest_list.items.append(&mut items);
+    Ok((String::new(), true))
+}
+
+// removes the later copies of items with the same name and of references to the same
+// sublist, keeping the first. if any copy of an item is done, the one that's kept is too
+fn dedup_list(list: &mut TodoList) -> usize {
+    let before = list.items.len();
+    let mut kept: Vec<ListEntry> = Vec::new();
+    for entry in std::mem::take(&mut list.items) {
+        let first = kept.iter_mut().find(|other| match (&**other, &entry) {
+            (ListEntry::Item(a), ListEntry::Item(b)) => a.name == b.name,
+            (ListEntry::List(a), ListEntry::List(b)) => a == b,
+            _ => false,
+        });
+        match (first, entry) {
+            (Some(ListEntry::Item(first)), ListEntry::Item(dup)) => {
+                if dup.done && !first.done {
+                    first.done = true;
+                    first.completed = dup.completed;
+                }
+            }
+            (Some(_), _) => (),
+            (None, entry) => kept.push(entry),
+        }
+    }
+    list.items = kept;
+    before - list.items.len()
+}
+
+fn cmd_dedup(lists: &mut [TodoList], args: &[String]) -> CmdResult {
+    let mut args = args.to_vec();
+    let recursive = take_flag(&mut args, "--recursive");
+    let names = if args.is_empty() {
+        lists.iter().map(|list| list.name.clone()).collect()
+    } else {
+        let list = get_list_by_name(lists, &args.join(" "))?;
+        if recursive {
+            list.subtree(lists)
+                .into_iter()
+                .map(|list| list.name.clone())
+                .collect()
+        } else {
+            vec![list.name.clone()]
+        }
+    };
+    let removed: usize = names
+        .iter()
+        .map(|name| dedup_list(get_mut_list_by_name(lists, name).unwrap()))
+        .sum();
+    Ok((
+        format!(
+            "Removed {removed} duplicate{}\n",
+            if removed == 1 { "" } else { "s" }
+        ),
+        removed > 0,
+    ))
+}
+
+// repairs what tends to go wrong in a list file, mostly from editing it by hand, and says
+// everything it changed. with --dry-run it only says what it would change
+fn cmd_fix(
+    lists: &mut Vec<TodoList>,
+    args: &[String],
+    list_file: &Path,
+    config: &Config,
+) -> CmdResult {
+    let dry_run = match args {
+        [] => false,
+        [flag] if flag == "--dry-run" => true,
+        _ => return Err(usage()),
+    };
+    if config.append_only && !dry_run {
+        return Err(append_only_error("fix"));
+    }
+    let mut fixed = if dry_run { lists.clone() } else { Vec::new() };
+    let target = if dry_run { &mut fixed } else { lists };
+    let mut changes = fix_names(target);
+    changes.extend(fix_references(target));
+    changes.extend(fix_cycles(target));
+    changes.extend(fix_duplicates(target));
+    // the dates are always written in the current format, so saving is all it takes
+    if let Ok(contents) = std::fs::read_to_string(list_file) {
+        changes.extend(
+            parser::strict_problems(&contents)
+                .into_iter()
+                .filter(|problem| problem.starts_with("Expected a space between the date"))
+                .map(|problem| problem.replacen("Expected", "Put", 1)),
+        );
+    }
+    if changes.is_empty() {
+        return Ok(("Nothing to fix\n".to_string(), false));
+    }
+    let mut out = String::new();
+    if dry_run {
+        out.push_str("Would have changed (nothing has been saved):\n");
+    }
+    for change in &changes {
+        out.push_str(change);
+        out.push('\n');
+    }
+    Ok((out, !dry_run))
+}
+
+// whitespace at the ends of names, which can't be seen and gets in the way of picking them out
+fn fix_names(lists: &mut [TodoList]) -> Vec<String> {
+    let mut changes = Vec::new();
+    let renamed = lists
+        .iter()
+        .filter(|list| list.name.trim() != list.name)
+        .map(|list| (list.name.clone(), list.name.trim().to_owned()))
+        .collect::<Vec<(String, String)>>();
+    for (old, new) in &renamed {
+        changes.push(format!("Renamed the list '{old}' to '{new}'"));
+    }
+    for list in lists.iter_mut() {
+        if let Some((_, new)) = renamed.iter().find(|(old, _)| *old == list.name) {
+            list.name.clone_from(new);
+        }
+        for entry in &mut list.items {
+            match entry {
+                ListEntry::List(name) => {
+                    if let Some((_, new)) = renamed.iter().find(|(old, _)| old == name) {
+                        name.clone_from(new);
+                    }
+                }
+                ListEntry::Item(item) if item.name.trim() != item.name => {
+                    changes.push(format!(
+                        "Renamed '{}' in '{}' to '{}'",
+                        item.name,
+                        list.name,
+                        item.name.trim()
+                    ));
+                    item.name = item.name.trim().to_owned();
+                }
+                ListEntry::Item(_) => (),
+            }
+        }
+    }
+    changes
+}
+
+// sublists that aren't the whole name of a list: ones that are the start of a list's name are
+// filled in, and ones that aren't a list at all are taken out
+fn fix_references(lists: &mut [TodoList]) -> Vec<String> {
+    let mut changes = Vec::new();
+    let names = lists
+        .iter()
+        .map(|list| list.name.clone())
+        .collect::<Vec<String>>();
+    for list in lists.iter_mut() {
+        let list_name = list.name.clone();
+        list.items.retain_mut(|entry| {
+            let ListEntry::List(name) = entry else {
+                return true;
+            };
+            if names.contains(name) {
+                return true;
+            }
+            let matches = names
+                .iter()
+                .filter(|other| other.starts_with(name.as_str()))
+                .collect::<Vec<&String>>();
+            if let [full] = matches.as_slice() {
+                changes.push(format!(
+                    "Changed the sublist '{name}' in '{list_name}' to '{full}'"
+                ));
+                name.clone_from(full);
+                true
+            } else {
+                changes.push(format!(
+                    "Took '{name}' out of '{list_name}', as it isn't a list"
+                ));
+                false
+            }
+        });
+    }
+    changes
+}
+
+// lists that end up inside themselves, which would make printing them go on forever. the
+// reference that closes each circle is taken out
+fn fix_cycles(lists: &mut [TodoList]) -> Vec<String> {
+    // 0 for not looked at yet, 1 for being looked through, 2 for done
+    fn find_back_references(
+        lists: &[TodoList],
+        idx: usize,
+        state: &mut [u8],
+        acc: &mut Vec<(usize, usize)>,
+    ) {
+        state[idx] = 1;
+        for (entry_idx, entry) in lists[idx].items.iter().enumerate() {
+            let ListEntry::List(name) = entry else {
+                continue;
+            };
+            let Some(sublist) = lists.iter().position(|list| list.name == *name) else {
+                continue;
+            };
+            match state[sublist] {
+                0 => find_back_references(lists, sublist, state, acc),
+                1 => acc.push((idx, entry_idx)),
+                _ => (),
+            }
+        }
+        state[idx] = 2;
+    }
+    let mut state = vec![0; lists.len()];
+    let mut back_references = Vec::new();
+    for idx in 0..lists.len() {
+        if state[idx] == 0 {
+            find_back_references(lists, idx, &mut state, &mut back_references);
+        }
+    }
+    // from the end, so the positions of the others don't move
+    back_references.sort_unstable();
+    let mut changes = Vec::new();
+    for (idx, entry_idx) in back_references.into_iter().rev() {
+        let entry = lists[idx].items.remove(entry_idx);
+        changes.push(format!(
+            "Took '{}' out of '{}', since '{}' is inside '{}'",
+            entry.name(),
+            lists[idx].name,
+            lists[idx].name,
+            entry.name()
+        ));
+    }
+    changes.reverse();
+    changes
+}
+
+// items that are the same in every way, and the same sublist more than once in a list. unlike
+// dedup, items with the same name but anything else different are left alone
+fn fix_duplicates(lists: &mut [TodoList]) -> Vec<String> {
+    let mut changes = Vec::new();
+    for list in lists.iter_mut() {
+        let mut kept: Vec<ListEntry> = Vec::new();
+        for entry in std::mem::take(&mut list.items) {
+            let duplicate = kept.iter().any(|other| match (other, &entry) {
+                (ListEntry::Item(a), ListEntry::Item(b)) => a == b,
+                (ListEntry::List(a), ListEntry::List(b)) => a == b,
+                _ => false,
+            });
+            if duplicate {
+                changes.push(format!(
+                    "Removed a copy of '{}' from '{}'",
+                    entry.name(),
+                    list.name
+                ));
+            } else {
+                kept.push(entry);
+            }
+        }
+        list.items = kept;
+    }
+    changes
+}
+
+fn cmd_autorm(
+    lists: &mut Vec<TodoList>,
+    args: &[String],
+    list_file: &Path,
+    config: &Config,
+) -> CmdResult {
+    let mut args = args.to_vec();
+    let cutoff = take_option(&mut args, "--completed-before")?
+        .map(|date| parse_date(&date).ok_or_else(|| format!("Invalid date '{date}'")))
+        .transpose()?;
+    let forget = take_flag(&mut args, "--forget");
+    let list = get_mut_list_by_name(lists, &args.join(" "))?;
+    // items done before completion dates were kept track of count as done long ago
+    let removable = |item: &ListItem| {
+        item.done && cutoff.is_none_or(|cutoff| item.completed.is_none_or(|c| c < cutoff))
+    };
+    let (kept, removed): (Vec<ListEntry>, Vec<ListEntry>) = std::mem::take(&mut list.items)
+        .into_iter()
+        .partition(|entry| !matches!(entry, ListEntry::Item(item) if removable(item)));
+    list.items = kept;
+    let from = list.name.clone();
+    if forget {
+        // nothing's kept anywhere they could come back from: not in the trash (including done
+        // items from this list that were put there before), not in the cached output, and their
+        // names aren't left in the history or as the last thing added to the list
+        let mut names = removed
+            .iter()
+            .map(|entry| entry.name().to_owned())
+            .collect::<HashSet<String>>();
+        let mut forgotten = removed.len();
+        if let Some(trash) = lists.iter_mut().find(|list| list.name == TRASH) {
+            let (kept, trashed): (Vec<ListEntry>, Vec<ListEntry>) =
+                std::mem::take(&mut trash.items)
+                    .into_iter()
+                    .partition(|entry| {
+                        !matches!(entry, ListEntry::Item(item)
+                            if !item.trashed_reference
+                                && item.trashed_from.as_deref() == Some(from.as_str())
+                                && removable(item))
+                    });
+            trash.items = kept;
+            forgotten += trashed.len();
+            names.extend(trashed.iter().map(|entry| entry.name().to_owned()));
+        }
+        cache::clear(list_file);
+        forget_last_added(&from, &names);
+        forget_history(lists, &from, &names);
+        return Ok((
+            format!(
+                "Removed {forgotten} done item{} from '{from}' for good\n",
+                if forgotten == 1 { "" } else { "s" }
+            ),
+            true,
+        ));
+    }
+    if config.use_trash && from != TRASH {
+        trash_entries(lists, &from, removed);
+    }
+    Ok((String::new(), true))
+}
+
+// where remove, rmlist and autorm put things with use_trash = true in the config file. it's an
+// ordinary list in the file, but lists, --all and so on leave it out
+const TRASH: &str = "__trash";
+
+// the index of the trash, which is made if there isn't one yet
+fn trash_index(lists: &mut Vec<TodoList>) -> usize {
+    lists
+        .iter()
+        .position(|list| list.name == TRASH)
+        .unwrap_or_else(|| {
+            lists.push(TodoList::new(TRASH.to_owned()));
+            lists.len() - 1
+        })
+}
+
+// the lists that have been put in the trash by rmlist, and the trash itself
+fn trashed_lists(lists: &[TodoList]) -> HashSet<&str> {
+    let mut acc = HashSet::from([TRASH]);
+    if let Some(trash) = lists.iter().find(|list| list.name == TRASH) {
+        acc.extend(trash.items.iter().filter_map(|entry| match entry {
+            ListEntry::List(name) => Some(name.as_str()),
+            ListEntry::Item(_) => None,
+        }));
+    }
+    acc
+}
+
+// puts entries taken out of the list `from` in the trash. a sublist goes in as an item with its
+// name, since list references can't remember where they came from
+fn trash_entries(lists: &mut Vec<TodoList>, from: &str, entries: Vec<ListEntry>) {
+    if entries.is_empty() {
+        return;
+    }
+    let trash = trash_index(lists);
+    for entry in entries {
+        let item = match entry {
+            ListEntry::Item(item) => ListItem {
+                trashed_from: Some(from.to_owned()),
+                ..item
+            },
+            ListEntry::List(name) => ListItem {
+                name,
+                trashed_from: Some(from.to_owned()),
+                trashed_reference: true,
+                ..Default::default()
+            },
+        };
+        lists[trash].items.push(ListEntry::Item(item));
+    }
+}
+
+fn cmd_trash(lists: &mut Vec<TodoList>, args: &[String], config: &Config) -> CmdResult {
+    use std::fmt::Write;
+    let trash = lists.iter().position(|list| list.name == TRASH);
+    match args.first().map(String::as_str) {
+        Some("list") if args.len() == 1 => {
+            let entries = trash.map_or(&[][..], |trash| &lists[trash].items);
+            if entries.is_empty() {
+                return Ok(("The trash is empty\n".to_string(), false));
+            }
+            let mut acc = String::new();
+            for entry in entries {
+                match entry {
+                    ListEntry::List(name) => writeln!(acc, "{name} (list)"),
+                    ListEntry::Item(item) => match (&item.trashed_from, item.trashed_reference) {
+                        (Some(from), true) => writeln!(acc, "{} (sublist of '{from}')", item.name),
+                        (Some(from), false) => writeln!(acc, "{} (from '{from}')", item.name),
+                        (None, _) => writeln!(acc, "{}", item.name),
+                    },
+                }
+                .unwrap();
+            }
+            Ok((acc, false))
+        }
+        Some("restore") if args.len() >= 2 => {
+            let trash = trash.ok_or("The trash is empty")?;
+            restore(lists, trash, &args[1..].join(" "))
+        }
+        Some("empty") if args.len() == 1 => {
+            if config.append_only {
+                return Err(append_only_error("trash empty"));
+            }
+            let Some(trash) = trash else {
+                return Ok((String::new(), false));
+            };
+            let trash = lists.remove(trash);
+            let gone = trash
+                .items
+                .iter()
+                .filter_map(|entry| match entry {
+                    ListEntry::List(name) => Some(name.as_str()),
+                    ListEntry::Item(_) => None,
+                })
+                .collect::<HashSet<&str>>();
+            lists.retain(|list| !gone.contains(list.name.as_str()));
+            for list in lists.iter_mut() {
+                list.items.retain(
+                    |entry| !matches!(entry, ListEntry::List(name) if gone.contains(name.as_str())),
+                );
+            }
+            let count = trash.items.len();
+            Ok((
+                format!(
+                    "Permanently removed {count} entr{} from the trash\n",
+                    if count == 1 { "y" } else { "ies" }
+                ),
+                true,
+            ))
+        }
+        _ => Err(usage()),
+    }
+}
+
+// puts the entry called `name` in the trash back where it came from. a list comes back along
+// with the references to it that rmlist took out
+fn restore(lists: &mut [TodoList], trash: usize, name: &str) -> CmdResult {
+    let idx = get_index_by_name(&lists[trash], name)?;
+    let name = lists[trash].items[idx].name().to_owned();
+    let entries = &lists[trash].items;
+    let restoring = match &entries[idx] {
+        ListEntry::List(_) => (0..entries.len())
+            .filter(|&i| match &entries[i] {
+                ListEntry::List(other) => *other == name,
+                ListEntry::Item(item) => item.trashed_reference && item.name == name,
+            })
+            .collect(),
+        ListEntry::Item(_) => vec![idx],
+    };
+    // everything has somewhere to go before anything is moved
+    for &i in &restoring {
+        let ListEntry::Item(item) = &entries[i] else {
+            continue;
+        };
+        let from = item
+            .trashed_from
+            .as_deref()
+            .ok_or_else(|| format!("'{name}' doesn't say where it came from"))?;
+        if !lists.iter().any(|list| list.name == from) {
+            return Err(format!(
+                "'{name}' came from '{from}', which doesn't exist any more"
+            ));
+        }
+        if item.trashed_reference && !lists.iter().any(|list| list.name == name) {
+            return Err(format!("The list '{name}' doesn't exist any more"));
+        }
+    }
+    let mut entries = restoring
+        .iter()
+        .rev()
+        .map(|&i| lists[trash].items.remove(i))
+        .collect::<Vec<ListEntry>>();
+    entries.reverse();
+    for entry in entries {
+        // a list is back on its own just by not being in the trash any more
+        let ListEntry::Item(mut item) = entry else {
+            continue;
+        };
+        let from = item.trashed_from.take().unwrap();
+        let entry = if std::mem::take(&mut item.trashed_reference) {
+            ListEntry::List(item.name)
+        } else {
+            ListEntry::Item(item)
+        };
+        get_mut_list_by_name(lists, &from)?.items.push(entry);
+    }
+    Ok((format!("Restored '{name}'\n"), true))
+}
+
+// the minimum and maximum allowed difference between the deadline date and today for each
+// time period, and how to describe it
+fn time_period(op: &str) -> (chrono::Duration, chrono::Duration, &'static str) {
+    use chrono::Duration;
+    match op {
+        "today" | "t" => (Duration::days(0), Duration::days(1), "today"),
+        "week" | "w" => (Duration::days(1), Duration::days(7), "this week"),
+        "overdue" | "od" => (
+            Duration::days(-365 * 1000), //1000 years ought to be enough
+            Duration::days(0),
+            "overdue",
+        ),
+        _ => unreachable!(),
+    }
+}
+
+// the items under each of `roots` that aren't under one of the roots before it, so a sublist
+// shared by two lists is only counted and shown once with --all, under the first one. they're
+// told apart by where they are, since two items can be the same in every other way
+fn items_per_root(all: &[TodoList], roots: &[&TodoList]) -> Vec<HashSet<*const ListItem>> {
+    let mut seen = HashSet::new();
+    roots
+        .iter()
+        .map(|root| {
+            root.walk_items(all)
+                .into_iter()
+                .map(std::ptr::from_ref)
+                .filter(|item| seen.insert(*item))
+                .collect()
+        })
+        .collect()
+}
+
+fn cmd_timeperiods(lists: &[TodoList], args: &[String], op: &str, config: &Config) -> CmdResult {
+    let (min_diff, max_diff, description) = time_period(op);
+
+    let mut args = args.to_vec();
+    let short = take_flag(&mut args, "--short");
+    let count = take_flag(&mut args, "--count");
+    // week --calendar-week is what's left of this calendar week, rather than the next 7 days
+    let max_diff = if take_flag(&mut args, "--calendar-week") && op.starts_with('w') {
+        calendar::week_start(today(), config.week_start) + chrono::Duration::days(7) - today()
+    } else {
+        max_diff
+    };
+    let opts = PrintOptions {
+        prune: take_flag(&mut args, "--prune"),
+        ascii: take_flag(&mut args, "--ascii") || config.ascii,
+        collapse_single: take_flag(&mut args, "--collapse") || config.collapse_single,
+        breadcrumbs: take_flag(&mut args, "--breadcrumb"),
+        // lists with nothing due are always left out here, so this is just so it can be given
+        // to every command that shows lists
+        no_empty: take_no_empty(&mut args),
+        ..PrintOptions::from_config(config)
+    };
+    let priorities = take_priority_range(&mut args)?;
+
+    // with --all, look through every list that isn't a sublist of another one
+    let roots = if take_flag(&mut args, "--all") {
+        if !args.is_empty() {
+            return Err(usage());
+        }
+        top_level_lists(lists)
+    } else {
+        vec![get_list_by_name(lists, &args.join(" "))?]
+    };
+    let today = today();
+    let filter = |item: &&ListItem| {
+        item.date.is_some()
+            && !item.done
+            && item.date.unwrap() - today < max_diff
+            && item.date.unwrap() - today >= min_diff
+            && priorities.contains(&item.priority)
+    };
+    let per_root = items_per_root(lists, &roots);
+    let num = if count || short {
+        roots
+            .iter()
+            .zip(&per_root)
+            .map(|(list, new)| {
+                list.walk_items(lists)
+                    .iter()
+                    .filter(|item| new.contains(&std::ptr::from_ref(**item)) && filter(item))
+                    .count()
+            })
+            .sum()
+    } else {
+        0
+    };
+    if count {
+        // also the exit code, capped so it doesn't run into the codes shells use themselves
+        EXIT_CODE.store(i32::try_from(num.min(125)).unwrap_or(125), Ordering::SeqCst);
+        Ok((format!("{num}\n"), false))
+    } else if short {
+        if num == 0 {
+            // don't bother printing if there's none. maybe should make this configurable.
+            return Ok((String::new(), false));
+        }
+        Ok((
+            format!(
+                "You have {} deadline{} {}\n",
+                num,
+                if num == 1 { "" } else { "s" },
+                description
+            ),
+            false,
+        ))
+    } else {
+        // lists without anything to show print nothing at all, so there are no empty headers
+        let out = roots
+            .iter()
+            .zip(&per_root)
+            .map(|(list, new)| {
+                list.print_with(
+                    lists,
+                    |item: &&ListItem| new.contains(&std::ptr::from_ref(*item)) && filter(item),
+                    opts,
+                )
+            })
+            .collect();
+        Ok((out, false))
+    }
+}
+
+// overdue and today together, overdue first
+fn cmd_agenda(lists: &[TodoList], args: &[String], config: &Config) -> CmdResult {
+    use std::fmt::Write;
+    let mut args = args.to_vec();
+    let opts = PrintOptions {
+        prune: take_flag(&mut args, "--prune"),
+        ascii: take_flag(&mut args, "--ascii") || config.ascii,
+        collapse_single: take_flag(&mut args, "--collapse") || config.collapse_single,
+        breadcrumbs: take_flag(&mut args, "--breadcrumb"),
+        ..PrintOptions::from_config(config)
+    };
+    let priorities = take_priority_range(&mut args)?;
+    // without a list, look through all of them
+    let roots = if take_flag(&mut args, "--all") || args.is_empty() {
+        if !args.is_empty() {
+            return Err(usage());
+        }
+        top_level_lists(lists)
+    } else {
+        vec![get_list_by_name(lists, &args.join(" "))?]
+    };
+
+    let today = today();
+    let per_root = items_per_root(lists, &roots);
+    let mut out = String::new();
+    for (op, header) in [("overdue", "Overdue"), ("today", "Today")] {
+        let (min_diff, max_diff, _) = time_period(op);
+        let filter = |item: &&ListItem| {
+            !item.done
+                && item
+                    .date
+                    .is_some_and(|date| date - today < max_diff && date - today >= min_diff)
+                && priorities.contains(&item.priority)
+        };
+        let section: String = roots
+            .iter()
+            .zip(&per_root)
+            .map(|(list, new)| {
+                list.print_with(
+                    lists,
+                    |item: &&ListItem| new.contains(&std::ptr::from_ref(*item)) && filter(item),
+                    opts,
+                )
+            })
+            .collect();
+        if !section.is_empty() {
+            let _ = write!(out, "{header}:\n{section}");
+        }
+    }
+    if out.is_empty() {
+        out = "Nothing due today!\n".to_string();
+    }
+    Ok((out, false))
+}
+
+// the one thing to do next: whatever's due soonest (today, or overdue), most important first,
+// or if nothing's due, the most important thing without a deadline
+fn cmd_next(lists: &[TodoList], args: &[String]) -> CmdResult {
+    let mut args = args.to_vec();
+    let roots = if let Some(name) = take_option(&mut args, "--list")? {
+        vec![get_list_by_name(lists, &name)?]
+    } else {
+        top_level_lists(lists)
+    };
+    if !args.is_empty() {
+        return Err(usage());
+    }
+    let today = today();
+    let items = roots
+        .into_iter()
+        .flat_map(|list| list.walk_items_with_path(lists))
+        .filter(|(_, item)| !item.done)
+        .collect::<Vec<(String, &ListItem)>>();
+    let due = items
+        .iter()
+        .filter(|(_, item)| item.date.is_some_and(|date| date <= today))
+        .min_by_key(|(_, item)| (item.date, std::cmp::Reverse(item.priority)));
+    let next = due.or_else(|| {
+        items
+            .iter()
+            .filter(|(_, item)| item.date.is_none())
+            .min_by_key(|(_, item)| std::cmp::Reverse(item.priority))
+    });
+    Ok((
+        next.map_or_else(
+            || "Nothing to do!\n".to_string(),
+            |(path, item)| {
+                let date = item.date.map_or_else(String::new, |date| {
+                    format!(" ({})", date.format("%d/%m/%Y"))
+                });
+                format!("{path}: {}{date}\n", item.name)
+            },
+        ),
+        false,
+    ))
+}
+
+// shows and changes the settings in the config file, so it doesn't have to be edited by hand
+fn cmd_config(config: &Config, args: &[String]) -> CmdResult {
+    use std::fmt::Write;
+    let settings = config.settings();
+    match args {
+        [cmd] if cmd == "list" => {
+            let width = settings.iter().map(|(key, _)| key.len()).max().unwrap_or(0);
+            let mut acc = String::new();
+            for (key, value) in &settings {
+                writeln!(acc, "{key:<width$} = {value}").unwrap();
+            }
+            Ok((acc, false))
+        }
+        [cmd, key] if cmd == "get" => settings
+            .iter()
+            .find(|(name, _)| name == key)
+            .map(|(_, value)| (format!("{value}\n"), false))
+            .ok_or_else(|| format!("Unknown setting '{key}'")),
+        [cmd, key, value @ ..] if cmd == "set" && !value.is_empty() => {
+            let mut path = config_dir();
+            path.push("config");
+            let contents = match std::fs::read_to_string(&path) {
+                Ok(contents) => contents,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+                Err(e) => return Err(format!("Unable to read {}: {e}", path.display())),
+            };
+            let value = value.join(" ");
+            let contents = Config::set(&contents, key, &value)?;
+            let mut tmp_name = path.as_os_str().to_owned();
+            tmp_name.push(".tmp");
+            write_and_replace(Path::new(&tmp_name), &path, &contents)
+                .map_err(|e| format!("Unable to write {}: {e}", path.display()))?;
+            Ok((format!("Set {key} to {value}\n"), false))
+        }
+        _ => Err(usage()),
+    }
+}
+
+// every repeating item, in <list> and its sublists or everywhere, soonest first
+fn cmd_recur(lists: &[TodoList], args: &[String]) -> CmdResult {
+    use std::fmt::Write;
+    let mut args = args.to_vec();
+    let json = take_flag(&mut args, "--json");
+    let scope = take_option(&mut args, "--list")?;
+    // the same as leaving --list out
+    take_flag(&mut args, "--all");
+    if !args.is_empty() {
+        return Err(usage());
+    }
+    let items = match scope {
+        Some(name) => get_list_by_name(lists, &name)?.walk_items_with_path(lists),
+        None => parser::all_items_with_paths(lists)
+            .into_iter()
+            .filter(|(path, _)| path.split('/').next() != Some(TRASH))
+            .collect(),
+    };
+    let mut repeating = items
+        .into_iter()
+        .filter_map(|(path, item)| Some((path, item, item.repeat?)))
+        .collect::<Vec<(String, &ListItem, RepeatKind)>>();
+    // ones without a date (which shouldn't happen) go at the end
+    repeating.sort_by_key(|(_, item, _)| (item.date.is_none(), item.date));
+    if json {
+        let objects = repeating
+            .iter()
+            .map(|(path, item, repeat)| {
+                format!(
+                    "{{\"list_path\":{},\"name\":{},\"repeat\":{},\"description\":{},\"next\":{}}}",
+                    parser::json_string(path),
+                    parser::json_string(&item.name),
+                    parser::json_string(&repeat.to_string()),
+                    parser::json_string(&repeat.describe()),
+                    item.date.map_or_else(
+                        || "null".to_string(),
+                        |date| parser::json_string(&date.format("%Y-%m-%d").to_string())
+                    )
+                )
+            })
+            .collect::<Vec<String>>();
+        return Ok((format!("[{}]\n", objects.join(",")), false));
+    }
+    if repeating.is_empty() {
+        return Ok(("Nothing repeats\n".to_string(), false));
+    }
+    let period_width = repeating
+        .iter()
+        .map(|(_, _, repeat)| repeat.describe().width())
+        .max()
+        .unwrap_or(0);
+    let mut acc = String::new();
+    for (path, item, repeat) in &repeating {
+        let date = item.date.map_or_else(
+            || format!("{:<10}", "-"),
+            |date| date.format("%d/%m/%Y").to_string(),
+        );
+        let period = repeat.describe();
+        writeln!(
+            acc,
+            "{date}  {period}{}  {path}: {}",
+            " ".repeat(period_width - period.width()),
+            item.name
+        )
+        .unwrap();
+    }
+    Ok((acc, false))
+}
+
+fn cmd_cal(lists: &[TodoList], args: &[String], week_start: chrono::Weekday) -> CmdResult {
+    let mut args = args.to_vec();
+    let scope = take_option(&mut args, "--list")?;
+    let today = today();
+    let (year, month) = if args.is_empty() {
+        (today.year(), today.month())
+    } else {
+        calendar::parse_month(&args.join(" "), today)
+            .ok_or_else(|| format!("Invalid month '{}', expected mm or mm/yyyy", args.join(" ")))?
+    };
+
+    let items = match scope {
+        Some(name) => get_list_by_name(lists, &name)?.walk_items(lists),
+        None => all_items(lists),
+    };
+    let mut counts = HashMap::new();
+    for item in items.iter().filter(|item| !item.done) {
+        if let Some(date) = item.date {
+            *counts.entry(date).or_insert(0) += 1;
+        }
+    }
+    Ok((
+        calendar::render_month(year, month, today, week_start, &counts),
+        false,
+    ))
+}
+
+// every item in every list, each one once, apart from the ones in the trash
+fn all_items(lists: &[TodoList]) -> Vec<&ListItem> {
+    let trashed = trashed_lists(lists);
+    lists
+        .iter()
+        .filter(|list| !trashed.contains(list.name.as_str()))
+        .flat_map(|list| &list.items)
+        .filter_map(|entry| match entry {
+            ListEntry::Item(item) => Some(item),
+            ListEntry::List(_) => None,
+        })
+        .collect()
+}
+
+// with audit_log = true, a line is added to this for each command that changes the lists
+fn history_file() -> PathBuf {
+    let mut path = config_dir();
+    path.push("history.log");
+    path
+}
+
+// the other way round from split_command_line, so the arguments in the log can be told apart
+fn quote_arg(arg: &str) -> Cow<'_, str> {
+    if !arg.is_empty() && !arg.contains(|c: char| c.is_whitespace() || "\"'\\".contains(c)) {
+        return Cow::from(arg);
+    }
+    Cow::from(format!(
+        "\"{}\"",
+        arg.replace('\\', "\\\\").replace('"', "\\\"")
+    ))
+}
+
+// when, who, the command and what it said it did (which has the names of the lists and items
+// it found, rather than the start of them that was typed)
+fn record_history(config: &Config, args: &[String], msg: &str) {
+    if !config.audit_log {
+        return;
+    }
+    let user = std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_else(|_| "unknown".to_string());
+    let command = args
+        .iter()
+        .enumerate()
+        .map(|(idx, arg)| match idx {
+            0 => Cow::from(full_command_name(arg)),
+            _ => quote_arg(arg),
+        })
+        .collect::<Vec<Cow<str>>>()
+        .join(" ");
+    let mut line = format!("{} {user}: {command}", now());
+    let said = msg.lines().collect::<Vec<&str>>().join("; ");
+    if !said.is_empty() {
+        line = format!("{line} ({said})");
+    }
+    line.push('\n');
+    let path = history_file();
+    let result = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .and_then(|mut file| file.write_all(line.as_bytes()));
+    if let Err(e) = result {
+        eprintln!("Warning: unable to write {}: {e}", path.display());
+    }
+}
+
+// takes the lines about any of `names` in `list_name` out of the history, for autorm --forget
+fn forget_history(lists: &[TodoList], list_name: &str, names: &HashSet<String>) {
+    let path = history_file();
+    let Ok(log) = std::fs::read_to_string(&path) else {
+        return;
+    };
+    let kept = log
+        .lines()
+        .filter(|line| !history_line_is_about(lists, line, list_name, names))
+        .fold(String::new(), |acc, line| acc + line + "\n");
+    if kept.len() == log.len() {
+        return;
+    }
+    let mut tmp_name = path.as_os_str().to_owned();
+    tmp_name.push(".tmp");
+    if let Err(e) = write_and_replace(Path::new(&tmp_name), &path, &kept) {
+        eprintln!("Warning: unable to write {}: {e}", path.display());
+    }
+}
+
+// a line is about an item if what the command said has the item's whole name in quotes (like
+// "Marked 'x' as done"), or it's the add that added it, and the command was run on that list.
+// a name that's only part of another one, or the same name in another list, doesn't count
+fn history_line_is_about(
+    lists: &[TodoList],
+    line: &str,
+    list_name: &str,
+    names: &HashSet<String>,
+) -> bool {
+    // after the time and who ran it
+    let Some((_, rest)) = line.split_once(": ") else {
+        return false;
+    };
+    let (command, said) = split_history_line(rest);
+    let Ok(mut args) = split_command_line(command) else {
+        return false;
+    };
+    for option in ["--index", "--every", "--in", "--where"] {
+        // a missing value doesn't matter here, the flag's still taken out
+        let _ = take_option(&mut args, option);
+    }
+    args.retain(|arg| !arg.starts_with("--"));
+    let Some((_, args)) = args.split_first() else {
+        return false;
+    };
+    let in_list = said.contains(&format!("'{list_name}'"))
+        || args.first().is_some_and(|list| {
+            list == list_name
+                || get_list_by_name(lists, list).is_ok_and(|list| list.name == list_name)
+        });
+    if !in_list {
+        return false;
+    }
+    if names.iter().any(|name| said.contains(&format!("'{name}'"))) {
+        return true;
+    }
+    // add (or anything with --quiet) doesn't say anything, so it's the whole name it was given,
+    // which for add may have a date after it
+    if args.len() < 2 {
+        return false;
+    }
+    let added = args[1..].join(" ");
+    let without_date = match args.split_last() {
+        Some((last, rest)) if rest.len() > 1 && parse_date(last).is_some() => rest[1..].join(" "),
+        _ => added.clone(),
+    };
+    [&added, &without_date].iter().any(|added| {
+        names.contains(*added)
+            || parser::unquote(added).is_some_and(|(name, _)| names.contains(&name))
+    })
+}
+
+// the command and what it said it did, which record_history puts after it in brackets. a " ("
+// inside an argument with spaces is in quotes, so it isn't mistaken for the start of it
+fn split_history_line(rest: &str) -> (&str, &str) {
+    let mut quoted = false;
+    let mut escaped = false;
+    for (idx, c) in rest.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '"' => quoted = !quoted,
+            ' ' if !quoted && rest[idx..].starts_with(" (") && rest.ends_with(')') => {
+                return (&rest[..idx], &rest[idx + 2..rest.len() - 1]);
+            }
+            _ => (),
+        }
+    }
+    (rest, "")
+}
+
+fn cmd_history(args: &[String], config: &Config) -> CmdResult {
+    let path = history_file();
+    match args {
+        [] => match std::fs::read_to_string(&path) {
+            Ok(log) if !log.is_empty() => Ok((log, false)),
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                Err(format!("Unable to read {}: {e}", path.display()))
+            }
+            _ if config.audit_log => Ok(("Nothing has been changed yet\n".to_string(), false)),
+            _ => Ok((
+                "Nothing has been recorded. Set audit_log = true in the config file to keep a history\n"
+                    .to_string(),
+                false,
+            )),
+        },
+        [flag] if flag == "--clear" => {
+            if config.append_only {
+                return Err(append_only_error("history --clear"));
+            }
+            match std::fs::remove_file(&path) {
+                Ok(()) => Ok(("Cleared the history\n".to_string(), false)),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                    Ok(("Cleared the history\n".to_string(), false))
+                }
+                Err(e) => Err(format!("Unable to remove {}: {e}", path.display())),
+            }
+        }
+        _ => Err(usage()),
+    }
+}
+
+// stats --record adds a row to this each time, so --history can show how things went. the
+// list column is empty for the stats of all the lists
+fn stats_file() -> PathBuf {
+    let mut path = config_dir();
+    path.push("stats.csv");
+    path
+}
+
+fn cmd_stats(lists: &[TodoList], args: &[String]) -> CmdResult {
+    use std::fmt::Write;
+    let mut args = args.to_vec();
+    let record = take_flag(&mut args, "--record");
+    let history = take_flag(&mut args, "--history");
+    let scope = args.join(" ");
+    let list = (!scope.is_empty())
+        .then(|| get_list_by_name(lists, &scope))
+        .transpose();
+    if history {
+        // by the list's whole name, like it's recorded, unless the list's gone since
+        let scope = match list {
+            Ok(Some(list)) => list.name.clone(),
+            _ => scope,
+        };
+        return Ok((stats_history(&scope)?, false));
+    }
+    let list = list?;
+    let items = list.map_or_else(|| all_items(lists), |list| list.walk_items(lists));
+    let today = today();
+    let total = items.len();
+    let done = items.iter().filter(|item| item.done).count();
+    let overdue = items
+        .iter()
+        .filter(|item| !item.done && item.date.is_some_and(|date| date < today))
+        .count();
+    let mut out = format!(
+        "{total} items, {done} done ({}%), {overdue} overdue\n",
+        percent(done, total)
+    );
+    if record {
+        let path = stats_file();
+        let mut row = String::new();
+        if !path.exists() {
+            row.push_str("date,list,total,done,overdue\n");
+        }
+        writeln!(
+            row,
+            "{},{},{total},{done},{overdue}",
+            today.format("%Y-%m-%d"),
+            parser::csv_field(list.map_or("", |list| &list.name))
+        )
+        .unwrap();
+        std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .and_then(|mut file| file.write_all(row.as_bytes()))
+            .map_err(|e| format!("Unable to write {}: {e}", path.display()))?;
+        writeln!(out, "Recorded in {}", path.display()).unwrap();
+    }
+    Ok((out, false))
+}
+
+fn percent(n: usize, total: usize) -> usize {
+    (n * 100).checked_div(total).unwrap_or(0)
+}
+
+// the rows recorded for `scope` (a list, or "" for all of them) as a table
+fn stats_history(scope: &str) -> Result<String, String> {
+    use std::fmt::Write;
+    let path = stats_file();
+    let contents = match std::fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Err("Nothing has been recorded yet. Run todo stats --record first".to_string())
+        }
+        Err(e) => return Err(format!("Unable to read {}: {e}", path.display())),
+    };
+    let mut out = String::from("date        total  done         overdue\n");
+    let mut found = false;
+    for line in contents.lines().skip(1) {
+        let fields = parser::split_csv_line(line);
+        let [date, list, total, done, overdue] = fields.as_slice() else {
+            return Err(format!("Unexpected line '{line}' in {}", path.display()));
+        };
+        if list != scope {
+            continue;
+        }
+        let (Ok(total), Ok(done)) = (total.parse(), done.parse()) else {
+            return Err(format!("Unexpected line '{line}' in {}", path.display()));
+        };
+        writeln!(
+            out,
+            "{date}  {total:>5}  {done:>5} ({:>3}%)  {overdue:>7}",
+            percent(done, total)
+        )
+        .unwrap();
+        found = true;
+    }
+    if !found {
+        return Err(if scope.is_empty() {
+            "Nothing has been recorded for all the lists yet".to_string()
+        } else {
+            format!("Nothing has been recorded for '{scope}' yet")
+        });
+    }
+    Ok(out)
+}
+
+#[cfg(feature = "serve")]
+fn cmd_serve(list_file: &Path, args: &[String], config: &Config) -> CmdResult {
+    let mut args = args.to_vec();
+    let port = take_option(&mut args, "--port")?.unwrap_or_else(|| "8080".to_string());
+    let host = take_option(&mut args, "--bind")?.unwrap_or_else(|| "127.0.0.1".to_string());
+    serve::serve(list_file, &format!("{host}:{port}"), config)
+}
+
+#[cfg(not(feature = "serve"))]
+fn cmd_serve(_list_file: &Path, _args: &[String], _config: &Config) -> CmdResult {
+    Err("todo was built without the 'serve' feature".to_string())
+}
+
+#[cfg(feature = "ui")]
+fn cmd_ui(lists: &mut Vec<TodoList>, args: &[String], config: &Config) -> CmdResult {
+    let root = args.join(" ");
+    ui::run(lists, (!root.is_empty()).then_some(root.as_str()), config)
+}
+
+#[cfg(not(feature = "ui"))]
+fn cmd_ui(_lists: &mut Vec<TodoList>, _args: &[String], _config: &Config) -> CmdResult {
+    Err("todo was built without the 'ui' feature".to_string())
+}
+
+// the commands that delete things, which append_only = true in the config file stops
+// unless --force is passed. everything else only adds things or marks them as done
+const DESTRUCTIVE_COMMANDS: [&str; 13] = [
+    "rmlist", "rl", "remove", "rm", "r", "autorm", "ar", "moveall", "mvall", "mva", "ma", "dedup",
+    "dd",
+];
+
+// the commands that only look at the lists, so a batch has nothing to undo if one of them fails
+const READ_ONLY_COMMANDS: [&str; 27] = [
+    "list", "l", "which", "wh", "diff", "backup", "bk", "export", "ex", "open", "o", "today", "t",
+    "week", "w", "overdue", "od", "agenda", "ag", "next", "nx", "config", "recur", "rc", "cal",
+    "history", "stats",
+];
+
+fn append_only_error(command: &str) -> String {
+    format!("'{command}' is blocked by append-only mode. Pass --force to run it anyway")
+}
+
+// prints what a command came up with, or writes it to the file given with --out
+fn show_output(out: Option<&Path>, output: &str) {
+    let Some(path) = out else {
+        print!("{output}");
+        return;
+    };
+    if let Err(e) = std::fs::write(path, output) {
+        eprintln!("Unable to write {}: {e}", path.display());
+        EXIT_CODE.store(1, Ordering::SeqCst);
+    }
+}
+
+// a random ID in the same form as a UUID (version 4), without needing a crate for it. each
+// RandomState has different keys, so the hashes are as good as random
+fn new_id() -> String {
+    use std::hash::{BuildHasher, Hasher};
+    let random = || {
+        let mut hasher = std::collections::hash_map::RandomState::new().build_hasher();
+        hasher.write_u128(
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map_or(0, |time| time.as_nanos()),
+        );
+        hasher.finish()
+    };
+    let (high, low) = (random(), random());
+    format!(
+        "{:08x}-{:04x}-4{:03x}-{:04x}-{:012x}",
+        high >> 32,
+        (high >> 16) & 0xffff,
+        high & 0xfff,
+        (low >> 48) & 0x3fff | 0x8000,
+        low & 0xffff_ffff_ffff
+    )
+}
+
+// gives everything without an ID one, returning whether anything needed one. an ID that's
+// already been used (from editing the file by hand, say) is replaced, so they stay unique
+fn assign_ids(lists: &mut [TodoList]) -> bool {
+    let mut seen = HashSet::new();
+    let mut changed = false;
+    let mut assign = |id: &mut Option<String>| {
+        if !id.as_ref().is_some_and(|id| seen.insert(id.clone())) {
+            let new = new_id();
+            seen.insert(new.clone());
+            *id = Some(new);
+            changed = true;
+        }
+    };
+    for list in lists {
+        assign(&mut list.id);
+        for entry in &mut list.items {
+            if let ListEntry::Item(item) = entry {
+                assign(&mut item.id);
+            }
+        }
+    }
+    changed
+}
+
+// --force lets the destructive commands run anyway, just this once
+fn take_force(config: &mut Config, args: &mut Vec<String>) {
+    if take_flag(args, "--force") {
+        config.append_only = false;
+    }
+}
+
+// the config file, and everything that depends on it
+fn load_config(args: &mut Vec<String>) -> Result<(PathBuf, Config), String> {
+    let mut config_file = config_dir();
+    config_file.push("config");
+    let mut config = Config::load(&config_file)?;
+    set_clock(&config)?;
+    PRIORITY_COLOURS
+        .set(config.priority_colours.clone())
+        .unwrap();
+    set_markers(&config);
+    VERIFY_SAVES.store(config.verify_saves, Ordering::SeqCst);
+    PRIORITY_SYMBOLS.store(
+        config.priority_symbols | take_flag(args, "--priority-symbols"),
+        Ordering::Relaxed,
+    );
+    config.stable_ids |= take_flag(args, "--stable-ids");
+    take_force(&mut config, args);
+    Ok((config_file, config))
+}
+
+// exit straight away on ctrl-c, unless we're in the middle of saving, in which case save()
+// exits once the file has been safely replaced
+fn set_ctrlc_handler() {
+    ctrlc::set_handler(|| {
+        if SAVING.load(Ordering::SeqCst) {
+            INTERRUPTED.store(true, Ordering::SeqCst);
+        } else {
+            std::process::exit(130);
+        }
+    })
+    .expect("Unable to set the ctrl-c handler");
+}
+
+// if the command is one of the aliases from the config file, what it stands for. an alias can
+// use another alias, but once one has been expanded, using its name again means the command
+// with that name, so `list = list --collapse` works and nothing goes round forever
+fn expand_aliases(args: &[String], config: &Config) -> Vec<String> {
+    let mut args = args.to_vec();
+    let mut expanded = HashSet::new();
+    while let Some(command) = args
+        .first()
+        .filter(|verb| expanded.insert((*verb).clone()))
+        .and_then(|verb| config.aliases.get(verb))
+    {
+        let params = &args[1..];
+        let mut used = vec![false; params.len()];
+        let mut acc = Vec::new();
+        for part in command {
+            let mut part = part.clone();
+            // from the highest down, so $1 doesn't replace the start of $10
+            for (idx, param) in params.iter().enumerate().rev() {
+                let placeholder = format!("${}", idx + 1);
+                if part.contains(&placeholder) {
+                    part = part.replace(&placeholder, param);
+                    used[idx] = true;
+                }
+            }
+            acc.push(part);
+        }
+        acc.extend(
+            params
+                .iter()
+                .zip(used)
+                .filter(|(_, used)| !used)
+                .map(|(param, _)| param.clone()),
+        );
+        args = acc;
+    }
+    args
+}
+
+// runs one command, given the arguments after `todo`
+fn run_command(
+    lists: &mut Vec<TodoList>,
+    args: &[String],
+    list_file: &Path,
+    config: &Config,
+) -> CmdResult {
+    run_builtin(lists, &expand_aliases(args, config), list_file, config)
+}
+
+#[rustfmt::skip] // ree it looks better all nicely indented
+fn run_builtin(
+    lists: &mut Vec<TodoList>,
+    args: &[String],
+    list_file: &Path,
+    config: &Config,
+) -> CmdResult {
+    if config.append_only && DESTRUCTIVE_COMMANDS.contains(&args[0].as_str()) {
+        return Err(append_only_error(&args[0]));
+    }
+    let nargs = args.len() - 1;
+    match args[0].as_str() {
+        "list"    | "l"       if nargs >= 1 => cmd_list(lists, &args[1..], config),
+        "lists"   | "ls"                    => cmd_lists(lists, &args[1..], config),
+        "new"     | "n"       if nargs > 0 => cmd_new(lists, &args[1..]),
+        "rmlist"  | "rl"      if nargs > 0 => cmd_rmlist(lists, &args[1..], config),
+        "add"     | "a"       if nargs >= 2 => cmd_add_or_create(lists, &args[1..], config),
+        "listconfig" | "lc"   if nargs >= 1 => cmd_listconfig(lists, &args[1..]),
+        "template" | "tp"     if nargs >= 1 => cmd_template(lists, &args[1..]),
+        "import"  | "im"      if nargs >= 1 => cmd_import(lists, &args[1..].join(" ")),
+        "backup"  | "bk"                    => cmd_backup(lists, &args[1..]),
+        "export"  | "ex"                    => cmd_export(lists, &args[1..]),
+        "diff"                              => cmd_diff(&args[1..]),
+        "which"   | "wh"      if nargs >= 1 => cmd_which(lists, &args[1..]),
+        "addlist" | "al"      if nargs >= 2 => cmd_addlist(lists, &args[1..]),
+        "done"    | "d"       if nargs >= 2 => cmd_done(lists, &args[1..], config),
+        "dedup"   | "dd"                    => cmd_dedup(lists, &args[1..]),
+        "fix"                               => cmd_fix(lists, &args[1..], list_file, config),
+        "autorm"  | "ar"      if nargs >= 1 => cmd_autorm(lists, &args[1..], list_file, config),
+        "trash"   | "tr"      if nargs >= 1 => cmd_trash(lists, &args[1..], config),
+        "label"   | "lb"      if nargs >= 3 => cmd_label(lists, &args[1], &args[2..]),
+        "link"    | "ln"      if nargs >= 3 => cmd_link(lists, &args[1], &args[2..]),
+        "open"    | "o"       if nargs >= 2 => cmd_open(lists, &args[1], &args[2..].join(" ")),
+        "progress" | "pg"     if nargs >= 3 => cmd_progress(lists, &args[1], &args[2..]),
+        "priority" | "pr"     if nargs >= 3 => cmd_priority(lists, &args[1], &args[2..]),
+        "repeat"  | "rp"      if nargs >= 3 => cmd_repeat(lists, &args[1], &args[2], &args[3..].join(" ")),
+        "sort"    | "so"      if nargs >= 3 => cmd_sort(lists, &args[1..]),
+        "rename"  | "rn"      if nargs >= 3 => cmd_rename(lists, &args[1..]),
+        "subadd"  | "sa"                    => cmd_subadd(lists, &args[1..]),
+        "subdone" | "sd"                    => cmd_subdone(lists, &args[1..]),
+        "renamelist" | "rl"   if nargs >= 2 => cmd_rnlist(lists, &args[1], &args[2..].join(" ")),
+        "rm" | "remove" | "r" if nargs >= 2 => cmd_remove(lists, &args[1..], config),
+        "move" | "mv" | "m"   if nargs >= 3 => cmd_move(lists, &args[1..], config),
+        "merge"   | "mg"      if nargs >= 2 => cmd_merge(lists, &args[1..]),
+        "moveall" | "mvall"
+        | "mva" | "ma"        if nargs >= 2 => cmd_moveall(lists, &args[1], &args[2..].join(" ")),
+        "today" | "t"
+        | "week" | "w"
+        | "overdue" | "od"    if nargs >= 1 => cmd_timeperiods(lists, &args[1..], &args[0], config),
+        "cal"                               => cmd_cal(lists, &args[1..], config.week_start),
+        "config"                            => cmd_config(config, &args[1..]),
+        "recur"   | "rc"                    => cmd_recur(lists, &args[1..]),
+        "agenda"  | "ag"                    => cmd_agenda(lists, &args[1..], config),
+        "next"    | "nx"                    => cmd_next(lists, &args[1..]),
+        "stats"   | "st"                    => cmd_stats(lists, &args[1..]),
+        "history"                           => cmd_history(&args[1..], config),
+        "serve"                             => cmd_serve(list_file, &args[1..], config),
+        "batch"                             => cmd_batch(lists, list_file, config),
+        "ui"                                => cmd_ui(lists, &args[1..], config),
+        "doneall" | "da" | "undoneall" | "uda" if nargs >= 1 => cmd_doneall(
+            lists,
+            &args[1..],
+            args[0] == "doneall" || args[0] == "da"
+        ),
+        _ => Err(usage()),
+    }
+}
+
+fn main() {
+    let mut args: Vec<String> = std::env::args().collect();
+    if args.len() < 2 {
+        println!("{}", usage());
+        return;
+    }
+
+    set_ctrlc_handler();
+
+    let (config_file, config) = match load_config(&mut args) {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+    };
+
+    let out = match take_option(&mut args, "--out") {
+        Ok(out) => out.map(PathBuf::from),
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+    };
+    TO_FILE.store(out.is_some(), Ordering::SeqCst);
+    let strict = take_flag(&mut args, "--strict");
+
+    let list_file = &list_file();
+    let cache = (config.cache_output && !strict)
+        .then(|| output_cache(list_file, &config_file, &args[1..]))
+        .flatten();
+    if let Some((output, code)) = cache.as_ref().and_then(cache::Cache::get) {
+        show_output(out.as_deref(), &output);
+        std::process::exit(code.max(EXIT_CODE.load(Ordering::SeqCst)));
+    }
+    let mut lists = match load_lists(list_file, strict) {
+        Ok(lists) => lists,
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+    };
+
+    // expanded here rather than in run_command, so the hook is for the command that was run
+    let args = expand_aliases(&args[1..], &config);
+    let result = run_builtin(&mut lists, &args, list_file, &config);
+    match result {
+        Ok((msg, modified)) => {
+            show_output(out.as_deref(), &msg);
+            // the first time stable_ids is used everything gets an ID, even if nothing changed
+            let modified = modified | (config.stable_ids && assign_ids(&mut lists));
+            if modified {
+                if let Err(e) = save(list_file, &lists) {
+                    eprintln!("Unable to save {}: {e}", list_file.display());
+                    std::process::exit(1);
+                }
+                // a batch's lines are recorded one by one as they were run, once it's saved
+                if args[0] != "batch" {
+                    record_history(&config, &args, &msg);
+                }
+                run_deferred(&config);
+                run_post_hook(&config, &args, list_file);
+            } else if let Some(cache) = &cache {
+                cache.put(&msg, EXIT_CODE.load(Ordering::SeqCst));
+            }
+        }
+        Err(e) => eprintln!("{e}"),
+    }
+    let code = EXIT_CODE.load(Ordering::SeqCst);
+    if code != 0 {
+        std::process::exit(code);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Weekday;
+
+    // every test that cares what day it is pretends it's this one, a wednesday
+    fn fixed_today() -> chrono::NaiveDate {
+        *FIXED_TODAY.get_or_init(|| chrono::NaiveDate::from_ymd_opt(2024, 5, 15).unwrap())
+    }
+
+    #[test]
+    fn periods() {
+        let parse = |s| RepeatKind::parse(s);
+        assert_eq!(parse("3d"), Ok(RepeatKind::EveryNDays(3)));
+        assert_eq!(parse("2w"), Ok(RepeatKind::EveryNDays(14)));
+        assert_eq!(parse("5"), Ok(RepeatKind::EveryNDays(5)));
+        assert_eq!(parse("daily"), Ok(RepeatKind::EveryNDays(1)));
+        assert_eq!(parse("weekly"), Ok(RepeatKind::EveryNDays(7)));
+        assert_eq!(parse("monday"), Ok(RepeatKind::Weekly(Weekday::Mon)));
+        assert_eq!(parse("Fri"), Ok(RepeatKind::Weekly(Weekday::Fri)));
+        assert_eq!(parse("1st"), Ok(RepeatKind::MonthlyOnDay(1)));
+        assert_eq!(parse("15th"), Ok(RepeatKind::MonthlyOnDay(15)));
+        assert_eq!(parse("31st"), Ok(RepeatKind::MonthlyOnDay(31)));
+        for bad in ["0d", "-2w", "fortnightly", "w", "", "0th", "32nd", "th"] {
+            assert!(parse(bad).is_err(), "{}", bad);
+        }
+        // written in the file the way they're read back
+        for period in [
+            "mon", "1st", "2nd", "3rd", "11th", "12th", "21st", "23rd", "14",
+        ] {
+            let repeat = parse(period).unwrap();
+            assert_eq!(repeat.to_string(), period);
+        }
+        assert_eq!(parse("sunday").unwrap().to_string(), "sun");
+    }
+
+    #[test]
+    fn first_occurrence_on_the_right_day() {
+        // 06/05/2024 was a Monday
+        let monday = chrono::NaiveDate::from_ymd_opt(2024, 5, 6).unwrap();
+        let weekly = |day| RepeatKind::Weekly(day);
+        assert_eq!(
+            first_occurrence(weekly(Weekday::Mon), Some(monday)),
+            Ok(monday)
+        );
+        assert!(first_occurrence(weekly(Weekday::Tue), Some(monday)).is_err());
+        assert_eq!(
+            first_occurrence(RepeatKind::EveryNDays(3), Some(monday)),
+            Ok(monday)
+        );
+        assert_eq!(
+            first_occurrence(RepeatKind::MonthlyOnDay(6), Some(monday)),
+            Ok(monday)
+        );
+        assert_eq!(
+            first_occurrence(RepeatKind::MonthlyOnDay(7), Some(monday)).unwrap_err(),
+            "06/05/2024 isn't on the 7th, but the item is meant to repeat on the 7th of every month"
+        );
+
+        let today = fixed_today();
+        assert_eq!(
+            first_occurrence(RepeatKind::EveryNDays(3), None),
+            Ok(today + chrono::Duration::days(3))
+        );
+        let next_friday = first_occurrence(weekly(Weekday::Fri), None).unwrap();
+        assert_eq!(next_friday.weekday(), Weekday::Fri);
+        assert!((0..7).contains(&(next_friday - today).num_days()));
+        // the 15th is today, and the 14th has been this month already
+        assert_eq!(
+            first_occurrence(RepeatKind::MonthlyOnDay(15), None),
+            Ok(today)
+        );
+        assert_eq!(
+            first_occurrence(RepeatKind::MonthlyOnDay(14), None).map(|date| date.to_string()),
+            Ok("2024-06-14".to_string())
+        );
+    }
+
+    #[test]
+    fn monthly_repeats_clamp_to_short_months() {
+        let date = |y, m, d| chrono::NaiveDate::from_ymd_opt(y, m, d).unwrap();
+        let the_31st = RepeatKind::MonthlyOnDay(31);
+        let steps: Vec<chrono::NaiveDate> =
+            std::iter::successors(Some(date(2023, 12, 31)), |&d| Some(the_31st.next(d)))
+                .take(6)
+                .collect();
+        // and back to the 31st once there is one
+        assert_eq!(
+            steps,
+            [
+                date(2023, 12, 31),
+                date(2024, 1, 31),
+                date(2024, 2, 29),
+                date(2024, 3, 31),
+                date(2024, 4, 30),
+                date(2024, 5, 31),
+            ]
+        );
+        assert_eq!(the_31st.next(date(2023, 1, 31)), date(2023, 2, 28));
+        assert_eq!(day_of_month(2100, 2, 29), date(2100, 2, 28));
+        assert_eq!(day_of_month(2000, 2, 31), date(2000, 2, 29));
+        assert_eq!(
+            RepeatKind::Weekly(Weekday::Mon).next(date(2024, 5, 6)),
+            date(2024, 5, 13)
+        );
+        assert_eq!(
+            RepeatKind::Weekly(Weekday::Mon).next(date(2024, 5, 9)),
+            date(2024, 5, 13)
+        );
+    }
+
+    #[test]
+    fn done_moves_a_repeating_item_on() {
+        let monday = chrono::NaiveDate::from_ymd_opt(2024, 5, 6).unwrap();
+        let mut chores = TodoList::new("chores".to_string());
+        for (name, repeat) in [("bins", Some(RepeatKind::EveryNDays(7))), ("once", None)] {
+            chores.items.push(ListEntry::Item(ListItem {
+                name: name.to_string(),
+                date: Some(monday),
+                repeat,
+                ..Default::default()
+            }));
+        }
+        let mut lists = vec![chores];
+        done_entry(&mut lists, "chores", "bins", &Config::default()).unwrap();
+        done_entry(&mut lists, "chores", "once", &Config::default()).unwrap();
+        let ListEntry::Item(bins) = &lists[0].items[0] else {
+            panic!("bins isn't an item");
+        };
+        let next = bins.date.unwrap();
+        assert!(!bins.done);
+        assert!(next > fixed_today());
+        assert_eq!(next.weekday(), Weekday::Mon);
+        assert!(matches!(&lists[0].items[1], ListEntry::Item(once) if once.done));
+    }
+
+    #[test]
+    fn list_sort_and_days_column() {
+        let today = fixed_today();
+        let mut trip = TodoList::new("trip".to_string());
+        for (name, days) in [("pack", Some(3)), ("book", Some(-2)), ("ask", None)] {
+            trip.items.push(ListEntry::Item(ListItem {
+                name: name.to_string(),
+                date: days.map(|days| today + chrono::Duration::days(days)),
+                ..Default::default()
+            }));
+        }
+        let lists = vec![trip];
+        let run = |flags: &[&str]| {
+            let mut args = flags.iter().map(ToString::to_string).collect::<Vec<_>>();
+            args.push("trip".to_string());
+            cmd_list(&lists, &args, &Config::default()).unwrap().0
+        };
+        // the names in the order they're shown
+        let order = |out: &str| {
+            out.lines()
+                .skip(1)
+                .filter_map(|line| {
+                    ["pack", "book", "ask"]
+                        .iter()
+                        .copied()
+                        .find(|n| line.contains(*n))
+                })
+                .collect::<Vec<&str>>()
+        };
+        assert_eq!(order(&run(&[])), ["pack", "book", "ask"]);
+        assert_eq!(order(&run(&["--sort", "name"])), ["ask", "book", "pack"]);
+        assert_eq!(order(&run(&["--sort", "date"])), ["book", "pack", "ask"]);
+        assert_eq!(order(&run(&["--sort", "days"])), ["book", "pack", "ask"]);
+        assert!(cmd_list(
+            &lists,
+            &["--sort".to_string(), "size".to_string(), "trip".to_string()],
+            &Config::default()
+        )
+        .is_err());
+
+        let out = run(&["--days"]);
+        let ends = out
+            .lines()
+            .skip(1)
+            .map(|line| line.rsplit(' ').next().unwrap())
+            .collect::<Vec<&str>>();
+        assert_eq!(ends, ["+3", "-2", "ask"]);
+        assert!(run(&[]).contains("(in 3 days)"));
+    }
+
+    #[test]
+    fn listconfig_due_applies_to_new_items() {
+        let mut lists = vec![TodoList::new("urgent".to_string())];
+        let to_args = |args: &[&str]| {
+            args.iter()
+                .map(ToString::to_string)
+                .collect::<Vec<String>>()
+        };
+
+        let (shown, modified) = cmd_listconfig(&mut lists, &to_args(&["urgent"])).unwrap();
+        assert_eq!(
+            shown,
+            "'urgent' has no default due date\n'urgent' has no default priority\n"
+        );
+        assert!(!modified);
+        cmd_listconfig(&mut lists, &to_args(&["--due", "+2w", "urgent"])).unwrap();
+        assert_eq!(lists[0].default_due, Some(14));
+        cmd_listconfig(&mut lists, &to_args(&["urgent", "--due", "1"])).unwrap();
+        assert_eq!(lists[0].default_due, Some(1));
+        assert!(cmd_listconfig(&mut lists, &to_args(&["urgent", "--due", "soon"])).is_err());
+
+        let today = fixed_today();
+        cmd_add(
+            &mut lists,
+            &to_args(&["urgent", "renew", "passport"]),
+            &Config::default(),
+        )
+        .unwrap();
+        cmd_add(
+            &mut lists,
+            &to_args(&["urgent", "tax", "return", "31/01/2030"]),
+            &Config::default(),
+        )
+        .unwrap();
+        let dates = lists[0]
+            .items
+            .iter()
+            .map(|entry| match entry {
+                ListEntry::Item(item) => item.date,
+                ListEntry::List(_) => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            dates,
+            [
+                Some(today + chrono::Duration::days(1)),
+                chrono::NaiveDate::from_ymd_opt(2030, 1, 31)
+            ]
+        );
+
+        cmd_listconfig(&mut lists, &to_args(&["urgent", "--due", "none"])).unwrap();
+        assert_eq!(lists[0].default_due, None);
+    }
+
+    #[test]
+    fn only_items_or_only_lists() {
+        let mut lists = parser::parse_str(
+            "home:\n\t- hoover\n\t= garden\ngarden:\n\t= shed\nshed:\n\t- oil the mower\n",
+        )
+        .unwrap();
+        let strings = |args: &[&str]| args.iter().map(|s| (*s).to_string()).collect::<Vec<_>>();
+
+        let items = cmd_list(
+            &lists,
+            &strings(&["--only-items", "home"]),
+            &Config::default(),
+        )
+        .unwrap()
+        .0;
+        assert!(items.contains("hoover"));
+        assert!(!items.contains("garden"));
+        let sublists = cmd_list(
+            &lists,
+            &strings(&["home", "--only-lists"]),
+            &Config::default(),
+        )
+        .unwrap()
+        .0;
+        assert!(sublists.contains("garden"));
+        assert!(!sublists.contains("hoover"));
+
+        assert_eq!(
+            cmd_lists(&mut lists, &strings(&["--only-items"]), &Config::default())
+                .unwrap()
+                .0,
+            "home\nshed\n"
+        );
+        assert_eq!(
+            cmd_lists(&mut lists, &strings(&["--only-lists"]), &Config::default())
+                .unwrap()
+                .0,
+            "home\ngarden\n"
+        );
+        assert_eq!(
+            cmd_lists(&mut lists, &[], &Config::default()).unwrap().0,
+            "home\ngarden\nshed\n"
+        );
+
+        let both = "Give either --only-items or --only-lists, not both";
+        let args = strings(&["--only-items", "--only-lists", "home"]);
+        assert_eq!(
+            cmd_list(&lists, &args, &Config::default()).unwrap_err(),
+            both
+        );
+        assert_eq!(
+            cmd_lists(&mut lists, &args[..2], &Config::default()).unwrap_err(),
+            both
+        );
+    }
+
+    #[test]
+    fn save_replaces_the_file_in_one_go() {
+        let dir = std::env::temp_dir().join(format!("todo-save-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("todo.txt");
+        std::fs::write(&file, "old:\n\t- this should go\n").unwrap();
+
+        let lists = vec![TodoList::new("new".to_string())];
+        save(&file, &lists).unwrap();
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "new:\n");
+        // nothing left lying around next to it
+        let names: Vec<_> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(names, ["todo.txt"]);
+        assert!(!SAVING.load(Ordering::SeqCst));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn truncate_to_a_width() {
+        assert_eq!(truncate("short", 10, false), "short");
+        assert_eq!(truncate("exactly", 7, false), "exactly");
+        assert_eq!(truncate("a bit too long", 8, false), "a bit t…");
+        // wide characters take up two columns each
+        assert_eq!(truncate("日本語のテキスト", 7, false), "日本語…");
+        assert_eq!(truncate("anything", 0, false), "");
+    }
+
+    #[test]
+    fn list_width_keeps_the_date_on_screen() {
+        let mut list = TodoList::new("reading".to_string());
+        list.items.push(ListEntry::Item(ListItem {
+            name: "the complete and unabridged history of everything".to_string(),
+            date: chrono::NaiveDate::from_ymd_opt(2031, 3, 1),
+            ..Default::default()
+        }));
+        let lists = vec![list];
+        let list_with = |width: &str| {
+            let args = ["reading", "--width", width].map(String::from);
+            cmd_list(&lists, &args, &Config::default()).unwrap().0
+        };
+
+        let narrow = list_with("50");
+        let line = narrow.lines().nth(1).unwrap();
+        assert!(line.contains('…'), "{}", line);
+        assert!(line.contains("01/03/2031"));
+        assert!(!line.contains("everything"));
+
+        assert!(list_with("0").contains("history of everything"));
+        assert!(cmd_list(
+            &lists,
+            &["reading", "--width", "wide"].map(String::from),
+            &Config::default()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn doneall_recursive_marks_sublists() {
+        // garden and shed refer to each other, so the walk has to stop somewhere
+        let text = "home:\n\t- dishes\n\t= garden\ngarden:\n\t- weeding\n\t= shed\nshed:\n\t- paint\n\t= garden\nwork:\n\t- email\n";
+        let home = vec!["home".to_string()];
+
+        let done_today = format!("+ [completed={}] ", fixed_today().format("%d/%m/%Y"));
+
+        let mut lists = parser::parse_str(text).unwrap();
+        cmd_doneall(&mut lists, &home, true).unwrap();
+        assert_eq!(
+            parser::emit_str(&lists),
+            text.replacen("- dishes", &format!("{done_today}dishes"), 1)
+        );
+
+        let recursive = vec!["--recursive".to_string(), "home".to_string()];
+        cmd_doneall(&mut lists, &recursive, true).unwrap();
+        let done = parser::emit_str(&lists);
+        for name in ["dishes", "weeding", "paint"] {
+            assert!(done.contains(&format!("{done_today}{name}")), "{}", done);
+        }
+        assert!(done.contains("- email"));
+
+        cmd_doneall(
+            &mut lists,
+            &["garden".to_string(), "--recursive".to_string()],
+            false,
+        )
+        .unwrap();
+        let undone = parser::emit_str(&lists);
+        assert!(undone.contains(&format!("{done_today}dishes")));
+        assert!(undone.contains("- weeding") && undone.contains("- paint"));
+    }
+
+    #[test]
+    fn templates_are_fresh_copies() {
+        let mut lists = parser::parse_str(
+            "packing:\n\t+ @01/07/2025 passport\n\t- charger\n\t= toiletries\ntoiletries:\nholiday:\n",
+        )
+        .unwrap();
+        let template = format!("packing-test-{}", std::process::id());
+        let run = |lists: &mut Vec<TodoList>, args: &[&str]| {
+            let args: Vec<String> = args.iter().map(|arg| (*arg).to_string()).collect();
+            cmd_template(lists, &args)
+        };
+
+        assert!(!run(&mut lists, &["save", "packing", &template]).unwrap().1);
+        assert!(run(&mut lists, &["list"]).unwrap().0.contains(&template));
+        assert!(run(&mut lists, &["apply", &template, "holiday"]).unwrap().1);
+        let copied: Vec<String> = lists[2]
+            .items
+            .iter()
+            .map(|entry| match entry {
+                ListEntry::Item(item) => {
+                    assert!(!item.done && item.date.is_none());
+                    item.name.trim().to_string()
+                }
+                ListEntry::List(name) => format!("= {name}"),
+            })
+            .collect();
+        assert_eq!(copied, ["passport", "charger", "= toiletries"]);
+        // the original is left alone
+        assert!(parser::emit_str(&lists[..1]).contains("+ @01/07/2025 passport"));
+
+        assert!(run(&mut lists, &["apply", "no-such-template", "holiday"]).is_err());
+        assert!(run(&mut lists, &["save", "packing", "../escape"]).is_err());
+        std::fs::remove_file(template_path(&template).unwrap()).unwrap();
+    }
+
+    #[test]
+    fn prune_merges_single_sublist_headers() {
+        let mut lists = parser::parse_str(
+            "work:\n\t= projects\nprojects:\n\t= website\nwebsite:\n\t- fix the footer\n",
+        )
+        .unwrap();
+        let pruned = PrintOptions {
+            prune: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            lists[0].print_with(&lists, |_| true, pruned),
+            " work/projects/website:\n     fix the footer\n"
+        );
+        assert_eq!(
+            lists[0].print(&lists, |_| true).lines().collect::<Vec<_>>(),
+            [
+                " work:",
+                "     projects:",
+                "         website:",
+                "             fix the footer"
+            ]
+        );
+
+        // once there are two sublists with something in them, projects keeps its header
+        lists.push(parser::parse_str("app:\n\t- ship it\n").unwrap().remove(0));
+        lists[1].items.push(ListEntry::List("app".to_string()));
+        let out = lists[0].print_with(&lists, |_| true, pruned);
+        assert!(
+            out.starts_with(" work/projects:\n     website:\n"),
+            "{}",
+            out
+        );
+
+        // and an empty sublist doesn't count
+        lists[3].items.clear();
+        let out = lists[0].print_with(&lists, |_| true, pruned);
+        assert!(out.starts_with(" work/projects/website:\n"), "{}", out);
+    }
+
+    #[test]
+    fn label_items_and_filter_on_them() {
+        let mut lists =
+            parser::parse_str("board:\n\t- rent\n\t- bins\n\t= other\nother:\n").unwrap();
+        let label = |lists: &mut Vec<TodoList>, item: &str, colour: &str| {
+            let args = vec![item.to_string(), colour.to_string()];
+            cmd_label(lists, "board", &args)
+        };
+
+        assert_eq!(label(&mut lists, "rent", "red"), Ok((String::new(), true)));
+        let err = label(&mut lists, "bins", "mauve").unwrap_err();
+        assert_eq!(
+            err,
+            "Unknown colour 'mauve', expected one of red, green, yellow, blue, magenta, cyan, white"
+        );
+        assert!(label(&mut lists, "other", "blue").is_err());
+
+        let only_red = ["board", "--label", "red", "--no-colour"].map(String::from);
+        let out = cmd_list(&lists, &only_red, &Config::default()).unwrap().0;
+        assert!(out.contains("rent") && !out.contains("bins"), "{}", out);
+        assert!(cmd_list(
+            &lists,
+            &["board", "--label", "mauve"].map(String::from),
+            &Config::default()
+        )
+        .is_err());
+
+        // in colour, the dot replaces the end of the indent
+        let opts = PrintOptions {
+            colour: true,
+            ..Default::default()
+        };
+        let out = lists[0].print_with(&lists, |_| true, opts);
+        assert!(out.contains("   \x1b[31m●\x1b[0m rent\n"), "{:?}", out);
+        assert!(out.contains("     bins\n"));
+
+        label(&mut lists, "rent", "none").unwrap();
+        assert!(parser::emit_str(&lists).starts_with("board:\n\t- rent\n"));
+    }
+
+    #[test]
+    fn import_merges_lists_by_name() {
+        let dir = std::env::temp_dir().join(format!("todo-import-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let backup = dir.join("backup.txt");
+        std::fs::write(&backup, "shopping:\n\t- eggs\nhobbies:\n\t- knit a scarf\n").unwrap();
+
+        let mut lists = parser::parse_str("shopping:\n\t- milk\n").unwrap();
+        let (_, modified) = cmd_import(&mut lists, backup.to_str().unwrap()).unwrap();
+        assert!(modified);
+        assert_eq!(
+            parser::emit_str(&lists),
+            "shopping:\n\t- milk\n\t- eggs\nhobbies:\n\t- knit a scarf\n"
+        );
+
+        std::fs::write(&backup, "shopping:\n\t* what's this\n").unwrap();
+        let err = cmd_import(&mut lists, backup.to_str().unwrap()).unwrap_err();
+        assert!(err.starts_with("Unable to import "), "{}", err);
+        assert!(err.ends_with("(line 2)"), "{}", err);
+        assert_eq!(lists.len(), 2);
+
+        let missing = dir.join("missing.txt");
+        assert!(cmd_import(&mut lists, missing.to_str().unwrap())
+            .unwrap_err()
+            .starts_with("Unable to read "));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn backup_is_tidied_up() {
+        let lists = parser::parse_str("\nchores:\n    - hoover\n\n").unwrap();
+        let stdout = vec!["-".to_string()];
+        assert_eq!(
+            cmd_backup(&lists, &stdout).unwrap(),
+            ("chores:\n\t- hoover\n".to_string(), false)
+        );
+
+        let file = std::env::temp_dir().join(format!("todo-backup-{}.yaml", std::process::id()));
+        let args = vec!["--yaml".to_string(), file.display().to_string()];
+        let (msg, modified) = cmd_backup(&lists, &args).unwrap();
+        assert!(!modified);
+        assert_eq!(msg, format!("Backed up to {}\n", file.display()));
+        assert!(std::fs::read_to_string(&file)
+            .unwrap()
+            .starts_with("- id: null\n  name: \"chores\"\n"));
+        std::fs::remove_file(&file).unwrap();
+
+        // by default it goes in its own timestamped file
+        let (msg, _) = cmd_backup(&lists, &[]).unwrap();
+        let path = Path::new(msg.trim().strip_prefix("Backed up to ").unwrap());
+        assert_eq!(path.parent().unwrap().file_name().unwrap(), "backups");
+        assert_eq!(path.extension().unwrap(), "txt");
+        assert_eq!(
+            std::fs::read_to_string(path).unwrap(),
+            "chores:\n\t- hoover\n"
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn ambiguous_names_list_what_they_match() {
+        let lists = parser::parse_str(
+            "fruit:\n\t- orange\n\t- organic apples\n\t- pear\nfriends:\nfridge:\n",
+        )
+        .unwrap();
+        assert_eq!(
+            get_list_by_name(&lists, "fri").unwrap_err(),
+            "List 'fri' is not specific enough to match a single item, it matches 'friends', 'fridge'"
+        );
+        assert_eq!(get_list_by_name(&lists, "fru").unwrap().name, "fruit");
+
+        let fruit = &lists[0];
+        assert_eq!(
+            get_index_by_name(fruit, "or").unwrap_err(),
+            "Item 'or' is not specific enough to match a single item, it matches 'orange', 'organic apples'"
+        );
+        assert_eq!(get_index_by_name(fruit, "orga"), Ok(1));
+        assert_eq!(
+            get_index_by_name(fruit, "kiwi").unwrap_err(),
+            "Item 'kiwi' does not exist"
+        );
+
+        let mut many = TodoList::new("many".to_string());
+        for n in 0..13 {
+            many.items.push(ListEntry::List(format!("sub{n}")));
+        }
+        let err = get_index_by_name(&many, "sub").unwrap_err();
+        assert!(err.ends_with("'sub8', 'sub9' (and 3 more)"), "{}", err);
+        assert!(!err.contains("sub10"));
+    }
+
+    #[test]
+    fn compact_overview() {
+        let today = fixed_today();
+        let due = |name: &str, days: i64, done: bool| {
+            ListEntry::Item(ListItem {
+                name: name.to_string(),
+                date: Some(today + chrono::Duration::days(days)),
+                done,
+                ..Default::default()
+            })
+        };
+        let mut work = TodoList::new("work".to_string());
+        work.items = vec![
+            due("report", 10, false),
+            due("old thing", -3, false),
+            due("call bob", 1, true),
+            ListEntry::List("meetings".to_string()),
+        ];
+        let mut meetings = TodoList::new("meetings".to_string());
+        meetings.items = vec![due("standup", 2, false)];
+        let mut ideas = TodoList::new("ideas".to_string());
+        ideas.items.push(ListEntry::Item(ListItem {
+            name: "a boat".to_string(),
+            ..Default::default()
+        }));
+        let mut lists = vec![work, meetings, ideas];
+
+        let (out, modified) =
+            cmd_lists(&mut lists, &["--compact".to_string()], &Config::default()).unwrap();
+        assert!(!modified);
+        let in_two_days = (today + chrono::Duration::days(2)).format("%d/%m/%Y");
+        assert_eq!(
+            out,
+            format!("work   [1/4]      next: standup ({in_two_days})\nideas  [0/1]      next: —\n")
+        );
+        // still only the lists with items in them
+        let out = cmd_lists(
+            &mut lists,
+            &["--compact".to_string(), "--only-lists".to_string()],
+            &Config::default(),
+        );
+        assert!(out.unwrap().0.starts_with("work "));
+    }
+
+    #[test]
+    fn progress_and_done_go_together() {
+        let mut lists = vec![TodoList::new("thesis".to_string())];
+        for name in ["intro", "method", "results"] {
+            lists[0].items.push(ListEntry::Item(ListItem {
+                name: name.to_string(),
+                ..Default::default()
+            }));
+        }
+        let progress = |lists: &mut Vec<TodoList>, item: &str, percent: &str| {
+            cmd_progress(lists, "thesis", &[item.to_string(), percent.to_string()])
+        };
+        let item = |lists: &[TodoList], idx: usize| match &lists[0].items[idx] {
+            ListEntry::Item(item) => (item.progress, item.done),
+            ListEntry::List(_) => unreachable!(),
+        };
+
+        progress(&mut lists, "intro", "45%").unwrap();
+        assert_eq!(item(&lists, 0), (Some(45), false));
+        let shown = lists[0].print(&lists, |_| true);
+        assert!(shown.contains("     intro [45%]\n"), "{}", shown);
+
+        progress(&mut lists, "method", "100").unwrap();
+        assert_eq!(item(&lists, 1), (Some(100), true));
+        assert!(!lists[0].print(&lists, |_| true).contains("[100%]"));
+
+        assert_eq!(
+            progress(&mut lists, "results", "120").unwrap_err(),
+            "Invalid progress '120', expected a percentage from 0 to 100"
+        );
+        assert!(progress(&mut lists, "results", "lots").is_err());
+
+        // doing an item finishes its progress, unless it never had any
+        done_entry(&mut lists, "thesis", "intro", &Config::default()).unwrap();
+        assert_eq!(item(&lists, 0), (Some(100), true));
+        cmd_doneall(&mut lists, &["thesis".to_string()], true).unwrap();
+        assert_eq!(item(&lists, 2), (None, true));
+    }
+
+    #[test]
+    fn rename_refuses_a_taken_name() {
+        let mut lists = parser::parse_str("renaming:\n\t- milk\n\t- eggs\n").unwrap();
+        let rename = |lists: &mut Vec<TodoList>, args: &str| {
+            let args = args.split(' ').map(str::to_owned).collect::<Vec<String>>();
+            cmd_rename(lists, &args)
+        };
+
+        let err = rename(&mut lists, "renaming milk eggs").unwrap_err();
+        assert_eq!(
+            err,
+            "There is already an item called 'eggs' in 'renaming'. Pass --allow-duplicate to rename it anyway"
+        );
+        assert_eq!(lists[0].items[0].name(), "milk");
+        // renaming something to its own name isn't a collision
+        rename(&mut lists, "renaming milk milk").unwrap();
+        rename(&mut lists, "renaming milk oat milk").unwrap();
+        assert_eq!(lists[0].items[0].name(), "oat milk");
+
+        rename(&mut lists, "--allow-duplicate renaming oat eggs").unwrap();
+        assert!(lists[0].items.iter().all(|entry| entry.name() == "eggs"));
+        assert!(rename(&mut lists, "renaming eggs").is_err());
+    }
+
+    #[test]
+    fn list_names_stay_unique() {
+        let mut lists = vec![TodoList::new("home".to_string())];
+        assert_eq!(
+            new_list(&mut lists, "home".to_string()).unwrap_err(),
+            "There is already a list called 'home'"
+        );
+        new_list(&mut lists, "homework".to_string()).unwrap();
+        assert!(cmd_rnlist(&mut lists, "homew", "home").is_err());
+        cmd_rnlist(&mut lists, "homew", "maths").unwrap();
+        let names: Vec<&str> = lists.iter().map(|list| list.name.as_str()).collect();
+        assert_eq!(names, ["home", "maths"]);
+    }
+
+    #[test]
+    fn ascii_output_is_ascii() {
+        let mut lists = parser::parse_str(concat!(
+            "ascii:\n\t- [label=red] @01/01/2024 overdue\n\t+ done\n",
+            "\t- [progress=50] a name that's much too long to fit\n\t= finished\n",
+            "finished:\n\t+ all of it\n",
+        ))
+        .unwrap();
+        let config = Config::default();
+        for flags in [&[][..], &["--width", "20"], &["--days", "--prune"]] {
+            let mut args = vec!["--ascii".to_string(), "ascii".to_string()];
+            args.extend(flags.iter().map(ToString::to_string));
+            let (out, _) = cmd_list(&lists, &args, &config).unwrap();
+            assert!(out.is_ascii(), "{:?} with {:?}", out, flags);
+            assert!(out.contains("[x]"));
+        }
+        // even with colours on, the dot is a *
+        let opts = PrintOptions {
+            ascii: true,
+            colour: true,
+            ..Default::default()
+        };
+        assert!(lists[0].print_with(&lists, |_| true, opts).is_ascii());
+
+        let compact = ["--compact".to_string()];
+        assert!(!cmd_lists(&mut lists, &compact, &config)
+            .unwrap()
+            .0
+            .is_ascii());
+        let ascii = Config {
+            ascii: true,
+            ..Config::default()
+        };
+        assert!(cmd_lists(&mut lists, &compact, &ascii)
+            .unwrap()
+            .0
+            .is_ascii());
+    }
+
+    #[test]
+    fn add_unique() {
+        let mut lists =
+            parser::parse_str("groceries:\n\t- Milk\n\t= milkshakes\nmilkshakes:\n").unwrap();
+        let add = |lists: &mut Vec<TodoList>, args: &[&str], config: &Config| {
+            let args: Vec<String> = args.iter().map(|arg| (*arg).to_string()).collect();
+            cmd_add(lists, &args, config)
+        };
+        let permissive = Config::default();
+        let strict = Config {
+            reject_duplicate_items: true,
+            ..Config::default()
+        };
+
+        assert_eq!(
+            add(&mut lists, &["--unique", "groceries", "milk"], &permissive).unwrap_err(),
+            "'Milk' is already in 'groceries'"
+        );
+        assert_eq!(
+            add(&mut lists, &["groceries", "  MILK "], &strict).unwrap_err(),
+            "'Milk' is already in 'groceries'"
+        );
+        // a sublist with the name doesn't count
+        add(&mut lists, &["groceries", "milkshakes"], &strict).unwrap();
+        add(&mut lists, &["groceries", "milk"], &permissive).unwrap();
+        assert_eq!(lists[0].items.len(), 4);
+    }
+
+    #[test]
+    fn lists_sort_and_persist() {
+        let today = fixed_today();
+        let in_days = |days: i64| (today + chrono::Duration::days(days)).format("%d/%m/%Y");
+        let text = format!(
+            "zoo:\n\t- @{} feed\nattic:\n\t- a\n\t- b\n\t- c\nmiddle:\n\t- @{} soon\n\t- x\n",
+            in_days(9),
+            in_days(2),
+        );
+        let mut lists = parser::parse_str(&text).unwrap();
+        let config = Config::default();
+        let mut lists_by = |args: &[&str]| {
+            let args: Vec<String> = args.iter().map(|arg| (*arg).to_string()).collect();
+            cmd_lists(&mut lists, &args, &config)
+        };
+
+        assert_eq!(
+            lists_by(&["--sort", "name"]).unwrap(),
+            ("attic\nmiddle\nzoo\n".to_string(), false)
+        );
+        assert_eq!(
+            lists_by(&["--sort", "count"]).unwrap().0,
+            "attic\nmiddle\nzoo\n"
+        );
+        assert_eq!(
+            lists_by(&["--sort", "due"]).unwrap().0,
+            "middle\nzoo\nattic\n"
+        );
+        assert_eq!(lists_by(&[]).unwrap().0, "zoo\nattic\nmiddle\n");
+        assert!(lists_by(&["--sort", "size"]).is_err());
+        assert_eq!(
+            lists_by(&["--persist"]).unwrap_err(),
+            "--persist only works with --sort"
+        );
+
+        assert!(lists_by(&["--sort", "due", "--persist"]).unwrap().1);
+        let names: Vec<&str> = lists.iter().map(|list| list.name.as_str()).collect();
+        assert_eq!(names, ["middle", "zoo", "attic"]);
+    }
+
+    #[test]
+    fn move_and_addlist_relocate_references_by_path() {
+        let mut lists = parser::parse_str(concat!(
+            "house:\n\t= upstairs\n\t= downstairs\n",
+            "upstairs:\n\t- hoover\n\t= bathroom\n",
+            "downstairs:\n\t- mop\n",
+            "bathroom:\n\t- scrub\n",
+            "shopping:\n\t- bleach\n",
+        ))
+        .unwrap();
+        let references = |lists: &[TodoList], idx: usize| {
+            lists[idx]
+                .items
+                .iter()
+                .filter_map(|entry| match entry {
+                    ListEntry::List(name) => Some(name.clone()),
+                    ListEntry::Item(_) => None,
+                })
+                .collect::<Vec<String>>()
+        };
+
+        cmd_move(
+            &mut lists,
+            &["house/upstairs", "bathroom", "house/downstairs"].map(String::from),
+            &Config::default(),
+        )
+        .unwrap();
+        assert!(references(&lists, 1).is_empty());
+        assert_eq!(references(&lists, 2), ["bathroom"]);
+        // the list itself is left alone
+        assert_eq!(parser::emit_str(&lists[3..4]), "bathroom:\n\t- scrub\n");
+
+        cmd_addlist(
+            &mut lists,
+            &["house/upstairs", "shopping"].map(String::from),
+        )
+        .unwrap();
+        assert_eq!(references(&lists, 1), ["shopping"]);
+        assert_eq!(get_list_index_by_path(&lists, "house/up/shop"), Ok(4));
+        assert_eq!(
+            get_list_index_by_path(&lists, "house/upstairs/hoover").unwrap_err(),
+            "'hoover' in 'upstairs' is an item, not a list"
+        );
+
+        // and neither can put a list inside itself
+        assert_eq!(
+            cmd_move(&mut lists, &["house", "downstairs", "house/downstairs/bathroom"].map(String::from), &Config::default()).unwrap_err(),
+            "'downstairs' can't go in 'bathroom', because 'bathroom' is already inside 'downstairs'"
+        );
+        assert_eq!(references(&lists, 0), ["upstairs", "downstairs"]);
+        assert!(cmd_addlist(
+            &mut lists,
+            &["house/upstairs/shopping", "house"].map(String::from)
+        )
+        .is_err());
+        assert!(cmd_addlist(&mut lists, &["shopping", "shopping"].map(String::from)).is_err());
+        assert!(references(&lists, 4).is_empty());
+    }
+
+    #[test]
+    fn timeperiod_count() {
+        let today = fixed_today();
+        let mut bills = TodoList::new("bills".to_string());
+        for days in [0, 0, 3, 5, -1, 8] {
+            bills.items.push(ListEntry::Item(ListItem {
+                name: format!("due in {days}"),
+                date: Some(today + chrono::Duration::days(days)),
+                ..Default::default()
+            }));
+        }
+        let lists = vec![bills];
+        let count = |op: &str| {
+            let args = vec!["bills".to_string(), "--count".to_string()];
+            cmd_timeperiods(&lists, &args, op, &Config::default())
+                .unwrap()
+                .0
+        };
+
+        assert_eq!(count("today"), "2\n");
+        assert_eq!(count("overdue"), "1\n");
+        assert_eq!(EXIT_CODE.load(Ordering::SeqCst), 1);
+        // the rest of the week, after today
+        assert_eq!(count("week"), "2\n");
+        assert_eq!(EXIT_CODE.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn collapse_single_item_sublists() {
+        let mut lists = parser::parse_str(
+            "garden:\n\t- weed\n\t= shed\n\t= pond\nshed:\n\t- oil the mower\npond:\n\t- fish\n\t- frogs\n",
+        )
+        .unwrap();
+        let list = |lists: &[TodoList], args: &[&str], config: &Config| {
+            let args: Vec<String> = args.iter().map(|arg| (*arg).to_string()).collect();
+            cmd_list(lists, &args, config).unwrap().0
+        };
+        let plain = Config::default();
+
+        let out = list(&lists, &["garden", "--collapse", "--width", "0"], &plain);
+        assert_eq!(
+            out,
+            " garden:\n     weed\n     shed > oil the mower\n     pond:\n         fish\n         frogs\n"
+        );
+        let collapsing = Config {
+            collapse_single: true,
+            ..Config::default()
+        };
+        assert_eq!(list(&lists, &["garden", "--width", "0"], &collapsing), out);
+        assert!(list(&lists, &["garden", "--width", "0"], &plain).contains("     shed:\n"));
+
+        // the dates still line up after the longer name
+        lists[1].items[0] = ListEntry::Item(ListItem {
+            name: "oil the mower".to_string(),
+            date: chrono::NaiveDate::from_ymd_opt(2030, 4, 1),
+            ..Default::default()
+        });
+        lists[0].items.push(ListEntry::Item(ListItem {
+            name: "mow".to_string(),
+            date: chrono::NaiveDate::from_ymd_opt(2030, 4, 2),
+            ..Default::default()
+        }));
+        let out = list(&lists, &["garden", "--width", "0"], &collapsing);
+        let columns: Vec<usize> = out.lines().filter_map(|line| line.find("/2030")).collect();
+        assert_eq!(columns.len(), 2);
+        assert_eq!(columns[0], columns[1]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn done_runs_the_hook() {
+        let dir = std::env::temp_dir().join(format!("todo-tests-hook-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let log = dir.join("done.log");
+        let config = Config {
+            on_done_hook: Some(format!(
+                "echo \"$TODO_LIST|$TODO_ITEM|$TODO_DATE\" >> '{}'",
+                log.display()
+            )),
+            ..Config::default()
+        };
+        let mut lists =
+            parser::parse_str("post:\n\t- letters; it's 5 o'clock $HOME\n\t- parcel\n").unwrap();
+        lists[0].items.push(ListEntry::Item(ListItem {
+            name: "stamps".to_string(),
+            date: chrono::NaiveDate::from_ymd_opt(2030, 1, 2),
+            ..Default::default()
+        }));
+
+        done_entry(&mut lists, "post", "letters; it's 5 o'clock $HOME", &config).unwrap();
+        done_entry(&mut lists, "post", "stamps", &config).unwrap();
+        // undoing it again isn't another completion
+        done_entry(&mut lists, "post", "stamps", &config).unwrap();
+        // nothing runs until the lists have been saved
+        assert!(!log.exists());
+        run_deferred(&config);
+        assert_eq!(
+            std::fs::read_to_string(&log).unwrap(),
+            "post|letters; it's 5 o'clock $HOME|\npost|stamps|02/01/2030\n"
+        );
+
+        // a broken hook is only a warning
+        let failing = Config {
+            on_done_hook: Some("exit 3".to_string()),
+            ..Config::default()
+        };
+        assert!(
+            done_entry(&mut lists, "post", "parcel", &failing)
+                .unwrap()
+                .1
+        );
+        run_deferred(&failing);
+        let ListEntry::Item(parcel) = &lists[0].items[1] else {
+            panic!("parcel isn't an item");
+        };
+        assert!(parcel.done);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn all_lists_and_no_empty() {
+        let mut lists = parser::parse_str(concat!(
+            "home:\n\t- @01/01/2020 boiler\n\t= garage\n",
+            "garage:\n\t- @02/01/2020 tyres\n\t- sweep\n",
+            "work:\n\t- @03/01/2020 report\n\t- @01/01/2099 retire\n",
+            "empty:\n",
+            "holder:\n\t= empty\n",
+        ))
+        .unwrap();
+        let run = |lists: &mut Vec<TodoList>, args: &str| {
+            let args: Vec<String> = args.split(' ').map(str::to_owned).collect();
+            match args[0].as_str() {
+                "lists" => cmd_lists(lists, &args[1..], &Config::default()),
+                "list" => cmd_list(lists, &args[1..], &Config::default()),
+                op => cmd_timeperiods(lists, &args[1..], op, &Config::default()),
+            }
+        };
+
+        // garage is only looked at once, through home
+        let out = run(&mut lists, "overdue --all --short").unwrap().0;
+        assert_eq!(out, "You have 3 deadlines overdue\n");
+        let out = run(&mut lists, "overdue --all --no-empty").unwrap().0;
+        for name in ["boiler", "tyres", "report"] {
+            assert_eq!(out.matches(name).count(), 1, "{out}");
+        }
+        assert!(!out.contains("empty") && !out.contains("holder"), "{}", out);
+        assert_eq!(run(&mut lists, "today --all").unwrap().0, "");
+        assert_eq!(run(&mut lists, "today --all work"), Err(usage()));
+
+        let shown = run(&mut lists, "lists --hide-empty").unwrap().0;
+        assert!(
+            shown.contains("garage") && !shown.contains("empty"),
+            "{}",
+            shown
+        );
+        assert!(run(&mut lists, "lists").unwrap().0.contains("holder"));
+
+        assert_eq!(
+            run(&mut lists, "list holder --no-empty --width 0"),
+            run(&mut lists, "list holder --width 0")
+        );
+    }
+
+    #[test]
+    fn autorm_completed_before() {
+        let mut lists = parser::parse_str(concat!(
+            "inbox:\n",
+            "\t+ [completed=01/03/2024] old\n",
+            "\t+ [completed=10/03/2024] recent\n",
+            "\t+ ancient\n",
+            "\t- open\n",
+        ))
+        .unwrap();
+        cmd_progress(
+            &mut lists,
+            "inbox",
+            &["open".to_string(), "100".to_string()],
+        )
+        .unwrap();
+        let ListEntry::Item(open) = &lists[0].items[3] else {
+            panic!("open isn't an item");
+        };
+        assert_eq!(open.completed, Some(fixed_today()));
+
+        let args = ["inbox", "--completed-before", "05/03/2024"].map(String::from);
+        cmd_autorm(&mut lists, &args, Path::new("todo.txt"), &Config::default()).unwrap();
+        let left: Vec<&str> = lists[0].items.iter().map(ListEntry::name).collect();
+        assert_eq!(left, ["recent", "open"]);
+
+        assert!(cmd_autorm(
+            &mut lists,
+            &["inbox", "--completed-before", "soon"].map(String::from),
+            Path::new("todo.txt"),
+            &Config::default()
+        )
+        .is_err());
+        cmd_doneall(&mut lists, &["inbox".to_string()], false).unwrap();
+        assert!(lists[0]
+            .items
+            .iter()
+            .all(|entry| matches!(entry, ListEntry::Item(item) if item.completed.is_none())));
+        done_entry(&mut lists, "inbox", "recent", &Config::default()).unwrap();
+        cmd_autorm(
+            &mut lists,
+            &["inbox".to_string()],
+            Path::new("todo.txt"),
+            &Config::default(),
+        )
+        .unwrap();
+        assert_eq!(lists[0].items.len(), 1);
+    }
+
+    #[test]
+    fn merge_same_name_different_state() {
+        let text = concat!(
+            "mine:\n\t- @01/03/2024 milk\n\t+ @01/01/2024 eggs\n\t- [progress=20] bread\n\t= shared\n",
+            "theirs:\n\t+ @01/01/2024 milk\n\t- @01/02/2024 eggs\n\t- [progress=60] bread\n",
+            "\t- cheese\n\t= shared\n\t= extra\n",
+            "shared:\nextra:\n",
+        );
+        let [january, february, march] =
+            [1, 2, 3].map(|month| chrono::NaiveDate::from_ymd_opt(2024, month, 1));
+        // (policy, then for milk and eggs whether they end up done and their date)
+        let cases = [
+            // the done one wins, date and all, whichever side it's on
+            ("done", [(true, january), (true, january)]),
+            // the later deadline wins, even over one that's done
+            ("later", [(false, march), (false, february)]),
+        ];
+        for (policy, expected) in cases {
+            let mut all = parser::parse_str(text).unwrap();
+            let args = ["--prefer", policy, "theirs", "mine"].map(String::from);
+            cmd_merge(&mut all, &args).unwrap();
+            // merging again changes nothing
+            cmd_merge(&mut all, &args).unwrap();
+
+            let merged = &all[0];
+            let names: Vec<&str> = merged.items.iter().map(ListEntry::name).collect();
+            // new things go at the end, and nothing turns up twice
+            assert_eq!(
+                names,
+                ["milk", "eggs", "bread", "shared", "cheese", "extra"]
+            );
+            for (idx, &(done, date)) in expected.iter().enumerate() {
+                let ListEntry::Item(item) = &merged.items[idx] else {
+                    panic!("{} isn't an item", names[idx]);
+                };
+                assert_eq!(
+                    (item.done, item.date),
+                    (done, date),
+                    "{policy} {}",
+                    names[idx]
+                );
+            }
+            // with the same deadline (none), the one further along wins either way
+            let ListEntry::Item(bread) = &merged.items[2] else {
+                panic!("bread isn't an item");
+            };
+            assert_eq!(bread.progress, Some(60));
+        }
+    }
+
+    #[test]
+    fn merge_into_itself() {
+        // garden is in home and home in garden, which the sublist check would trip over first
+        let mut all =
+            parser::parse_str("home:\n\t- dishes\n\t= garden\ngarden:\n\t- weeding\n\t= home\n")
+                .unwrap();
+        let err = cmd_merge(&mut all, &["home".to_string(), "home".to_string()]).unwrap_err();
+        assert_eq!(err, "Can't merge 'home' into itself");
+        assert_eq!(all[0].items.len(), 2);
+        assert!(cmd_merge(&mut all, &["home".to_string()]).is_err());
+    }
+
+    #[test]
+    fn priority_filters() {
+        let mut lists = parser::parse_str(concat!(
+            "jobs:\n\t- @01/01/2020 p0\n\t- [priority=1] @01/01/2020 p1\n",
+            "\t- [label=red] [priority=2] @01/01/2020 p2\n\t- @01/01/2020 p3\n",
+        ))
+        .unwrap();
+        cmd_priority(&mut lists, "jobs", &["p3".to_string(), "3".to_string()]).unwrap();
+        assert!(cmd_priority(&mut lists, "jobs", &["p3".to_string(), "256".to_string()]).is_err());
+
+        // which of p0..p3 list shows with these flags
+        let shown = |flags: &str| -> Vec<String> {
+            let mut args = vec!["jobs".to_string(), "--width".to_string(), "0".to_string()];
+            args.extend(flags.split_whitespace().map(str::to_owned));
+            let out = cmd_list(&lists, &args, &Config::default()).unwrap().0;
+            (0..4)
+                .map(|n| format!("p{n}"))
+                .filter(|name| out.contains(&format!(" {name} ")))
+                .collect()
+        };
+        assert_eq!(shown(""), ["p0", "p1", "p2", "p3"]);
+        // no priority is 0, which is below any minimum
+        assert_eq!(shown("--min-priority 1"), ["p1", "p2", "p3"]);
+        assert_eq!(shown("--min-priority 0").len(), 4);
+        assert_eq!(shown("--max-priority 1"), ["p0", "p1"]);
+        assert_eq!(shown("--min-priority 2 --max-priority 2"), ["p2"]);
+        assert!(shown("--min-priority 3 --max-priority 1").is_empty());
+        // and together with --label
+        assert_eq!(shown("--min-priority 1 --label red"), ["p2"]);
+        assert!(shown("--min-priority 3 --label red").is_empty());
+
+        for (flags, expected) in [
+            ("", "You have 4 deadlines overdue\n"),
+            ("--min-priority 1", "You have 3 deadlines overdue\n"),
+            (
+                "--min-priority 1 --max-priority 1",
+                "You have 1 deadline overdue\n",
+            ),
+            ("--min-priority 4", ""),
+        ] {
+            let mut args = vec!["--short".to_string(), "jobs".to_string()];
+            args.extend(flags.split_whitespace().map(str::to_owned));
+            let out = cmd_timeperiods(&lists, &args, "overdue", &Config::default()).unwrap();
+            assert_eq!(out.0, expected, "{flags}");
+        }
+
+        for bad in [
+            "--min-priority -1",
+            "--max-priority 256",
+            "--max-priority high",
+        ] {
+            let mut args = vec!["jobs".to_string()];
+            args.extend(bad.split(' ').map(str::to_owned));
+            assert!(
+                cmd_list(&lists, &args, &Config::default()).is_err(),
+                "{}",
+                bad
+            );
+        }
+    }
+
+    #[test]
+    fn priorities_are_shown_and_saved() {
+        let mut lists = parser::parse_str("jobs: [priority=2]\n\t- [priority=5] leak\n").unwrap();
+        cmd_add(
+            &mut lists,
+            &["jobs", "paint"].map(String::from),
+            &Config::default(),
+        )
+        .unwrap();
+        let text = parser::emit_str(&lists);
+        assert!(
+            text.starts_with("jobs: [priority=2]\n\t- [priority=5] leak\n\t- [priority=2] "),
+            "{}",
+            text
+        );
+        let out = cmd_list(
+            &lists,
+            &["jobs", "--width", "0"].map(String::from),
+            &Config::default(),
+        )
+        .unwrap()
+        .0;
+        assert!(
+            out.contains("leak [p5]") && out.contains("paint [p2]"),
+            "{}",
+            out
+        );
+
+        let listconfig = |lists: &mut Vec<TodoList>, args: &str| {
+            let args: Vec<String> = args.split(' ').map(str::to_owned).collect();
+            cmd_listconfig(lists, &args)
+        };
+        assert_eq!(
+            listconfig(&mut lists, "jobs").unwrap().0,
+            "'jobs' has no default due date\nItems added to 'jobs' get priority 2\n"
+        );
+        listconfig(&mut lists, "jobs --priority none").unwrap();
+        assert_eq!(lists[0].default_priority, 0);
+        assert!(listconfig(&mut lists, "jobs --priority -3").is_err());
+        listconfig(&mut lists, "jobs --priority 7 --due 1").unwrap();
+        assert_eq!(
+            (lists[0].default_priority, lists[0].default_due),
+            (7, Some(1))
+        );
+        assert!(parser::emit_json(&lists).contains("\"default_priority\":7"));
+        assert!(parser::parse_str("jobs: [priority=high]\n").is_err());
+    }
+
+    #[test]
+    fn next_picks_the_most_urgent() {
+        let mut lists = parser::parse_str(concat!(
+            "home:\n\t- [priority=9] @01/01/2099 someday\n\t- [priority=1] hoover\n\t= diy\n",
+            "diy:\n\t- @05/01/2020 shelf\n\t- [priority=2] @05/01/2020 fence\n\t+ @01/01/2020 gate\n",
+            "work:\n\t- [priority=3] expenses\n\t- timesheet\n",
+        ))
+        .unwrap();
+        let next = |lists: &[TodoList], args: &[&str]| {
+            let args: Vec<String> = args.iter().map(|arg| (*arg).to_owned()).collect();
+            cmd_next(lists, &args).map(|(out, _)| out)
+        };
+
+        // gate's been due the longest but it's done, and fence and shelf tie on the date
+        assert_eq!(next(&lists, &[]).unwrap(), "home/diy: fence (05/01/2020)\n");
+        assert_eq!(
+            next(&lists, &["--list", "diy"]).unwrap(),
+            "diy: fence (05/01/2020)\n"
+        );
+
+        // with nothing due, the most important undated item. someday isn't due yet
+        lists[1].items.clear();
+        assert_eq!(next(&lists, &[]).unwrap(), "work: expenses\n");
+        assert_eq!(next(&lists, &["--list", "home"]).unwrap(), "home: hoover\n");
+        assert_eq!(
+            next(&lists, &["--list", "diy"]).unwrap(),
+            "Nothing to do!\n"
+        );
+        assert!(next(&lists, &["--list", "nowhere"]).is_err());
+        assert_eq!(next(&lists, &["work"]), Err(usage()));
+    }
+
+    #[test]
+    fn agenda_has_overdue_then_today() {
+        let today = fixed_today();
+        let mut lists = parser::parse_str(
+            "home:\n\t- @01/01/2020 taxes\n\t+ @02/01/2020 filed\n\t- someday\nwork:\n",
+        )
+        .unwrap();
+        for (list, name, days) in [(0, "bins", 0), (1, "standup", 0), (1, "review", 2)] {
+            lists[list].items.push(ListEntry::Item(ListItem {
+                name: name.to_string(),
+                date: Some(today + chrono::Duration::days(days)),
+                ..Default::default()
+            }));
+        }
+        let agenda = |args: &[&str]| {
+            let args: Vec<String> = args.iter().map(|arg| (*arg).to_owned()).collect();
+            cmd_agenda(&lists, &args, &Config::default()).map(|(out, _)| out)
+        };
+
+        let out = agenda(&[]).unwrap();
+        assert_eq!(agenda(&["--all"]).unwrap(), out);
+        let overdue = out.find("Overdue:\n").unwrap();
+        let due_today = out.find("Today:\n").unwrap();
+        let position = |name: &str| out.find(name).unwrap();
+        assert!(overdue < position("taxes") && position("taxes") < due_today);
+        assert!(due_today < position("bins") && due_today < position("standup"));
+        for hidden in ["filed", "someday", "review"] {
+            assert!(!out.contains(hidden), "{}", out);
+        }
+
+        let work = agenda(&["work"]).unwrap();
+        assert!(
+            work.starts_with("Today:\n") && work.contains("standup"),
+            "{}",
+            work
+        );
+        assert_eq!(
+            agenda(&["work", "--min-priority", "1"]).unwrap(),
+            "Nothing due today!\n"
+        );
+        assert_eq!(agenda(&["--all", "work"]), Err(usage()));
+    }
+
+    #[test]
+    fn completed_and_created_since() {
+        let mut lists = parser::parse_str(concat!(
+            "log:\n",
+            "\t+ [completed=10/03/2024] [created=01/01/2024] shipped\n",
+            "\t+ [completed=01/03/2024] [created=20/02/2024] fixed\n",
+            "\t+ old\n",
+            "\t- [created=15/03/2024] planned\n",
+            "\t- untracked\n",
+        ))
+        .unwrap();
+        cmd_add(
+            &mut lists,
+            &["log", "new"].map(String::from),
+            &Config::default(),
+        )
+        .unwrap();
+        let ListEntry::Item(new) = &lists[0].items[5] else {
+            panic!("new isn't an item");
+        };
+        assert_eq!(new.created, Some(fixed_today()));
+
+        let shown = |flags: &[&str]| {
+            let mut args = vec!["log".to_string(), "--width".to_string(), "0".to_string()];
+            args.extend(flags.iter().map(|flag| (*flag).to_owned()));
+            let out = cmd_list(&lists, &args, &Config::default())?.0;
+            Ok::<_, String>(
+                ["shipped", "fixed", "old", "planned", "untracked", "new"]
+                    .iter()
+                    .copied()
+                    .filter(|name| out.contains(name))
+                    .collect::<Vec<_>>(),
+            )
+        };
+        assert_eq!(
+            shown(&["--completed-since", "05/03/2024"]),
+            Ok(vec!["shipped"])
+        );
+        // on the day counts, and items without the date are left out
+        assert_eq!(
+            shown(&["--completed-since", "01/03/2024"]),
+            Ok(vec!["shipped", "fixed"])
+        );
+        assert_eq!(
+            shown(&["--created-since", "20/02/2024"]),
+            Ok(vec!["fixed", "planned", "new"])
+        );
+        assert_eq!(
+            shown(&[
+                "--created-since",
+                "01/01/2024",
+                "--completed-since",
+                "02/03/2024"
+            ]),
+            Ok(vec!["shipped"])
+        );
+        assert_eq!(
+            shown(&["--created-since", "last week"]),
+            Err("Invalid date 'last week'".to_string())
+        );
+        assert!(parser::emit_str(&lists).contains("\t- [created=15/03/2024] planned\n"));
+    }
+
+    #[test]
+    fn add_with_a_quoted_name() {
+        let mut lists = vec![TodoList::new("adding".to_owned())];
+        for words in [
+            // without quotes, a date at the end is the deadline
+            "pay rent 01/02/2024",
+            // with them, the whole thing is the name
+            "\"=> literal 01/02/2024\"",
+            "\"@01/03/2024 not a date\"",
+            // and a date can still come after them
+            "\"- dash\" 01/04/2024",
+        ] {
+            let mut args = vec!["adding".to_owned()];
+            args.extend(words.split(' ').map(str::to_owned));
+            cmd_add(&mut lists, &args, &Config::default()).unwrap();
+        }
+        let bad = ["adding", "\"name\"", "soon"].map(String::from);
+        assert_eq!(
+            cmd_add(&mut lists, &bad, &Config::default()),
+            Err("Invalid date 'soon'".to_string())
+        );
+
+        // so the text doesn't depend on what day it is
+        for entry in &mut lists[0].items {
+            if let ListEntry::Item(item) = entry {
+                item.created = None;
+            }
+        }
+        let text = parser::emit_str(&lists);
+        assert_eq!(
+            text,
+            concat!(
+                "adding:\n",
+                "\t- @01/02/2024 pay rent\n",
+                "\t- => literal 01/02/2024\n",
+                "\t- \"@01/03/2024 not a date\"\n",
+                "\t- @01/04/2024 - dash\n",
+            )
+        );
+        assert_eq!(parser::emit_str(&parser::parse_str(&text).unwrap()), text);
+    }
+
+    #[test]
+    fn rmlist_leaves_no_dangling_references() {
+        let text = concat!(
+            "home:\n\t= chores\n\t- relax\n\t= chores\n",
+            "work:\n\t= chores\n",
+            "chores:\n\t- dishes\n\t= garden\n",
+            "garden:\n\t- weeding\n",
+        );
+        let sublists = |lists: &[TodoList]| -> Vec<String> {
+            lists
+                .iter()
+                .flat_map(|list| &list.items)
+                .filter_map(|entry| match entry {
+                    ListEntry::List(name) => Some(name.clone()),
+                    ListEntry::Item(_) => None,
+                })
+                .collect()
+        };
+
+        let mut lists = parser::parse_str(text).unwrap();
+        let (out, _) = cmd_rmlist(&mut lists, &["chores".to_string()], &Config::default()).unwrap();
+        assert_eq!(out, "Removed list 'chores' and 3 references to it\n");
+        // nothing points at it any more. the lists that were in it are still there, just not
+        // in anything
+        assert!(sublists(&lists).is_empty());
+        let names: Vec<&str> = lists.iter().map(|list| list.name.as_str()).collect();
+        assert_eq!(names, ["home", "work", "garden"]);
+        assert_eq!(lists[0].items.len(), 1);
+
+        let mut lists = parser::parse_str(text).unwrap();
+        let args = ["--keep-references", "chores"].map(String::from);
+        assert_eq!(
+            cmd_rmlist(&mut lists, &args, &Config::default()).unwrap().0,
+            ""
+        );
+        assert_eq!(sublists(&lists), ["chores", "chores", "chores"]);
+
+        let (out, _) = cmd_rmlist(&mut lists, &["garden".to_string()], &Config::default()).unwrap();
+        assert_eq!(out, "");
+        let mut lists = parser::parse_str("a:\n\t= b\nb:\n").unwrap();
+        let (out, _) = cmd_rmlist(&mut lists, &["b".to_string()], &Config::default()).unwrap();
+        assert_eq!(out, "Removed list 'b' and 1 reference to it\n");
+    }
+
+    #[test]
+    fn dedup_keeps_the_first_and_done_wins() {
+        let text = concat!(
+            "groceries:\n\t- milk\n\t- eggs\n\t+ [completed=02/03/2024] milk\n\t= pantry\n",
+            "\t- Milk\n\t= pantry\n\t- milk\n",
+            "pantry:\n\t- rice\n\t- rice\n",
+            "other:\n\t- eggs\n\t- eggs\n",
+        );
+        let mut lists = parser::parse_str(text).unwrap();
+        let (out, modified) = cmd_dedup(&mut lists, &["groceries".to_string()]).unwrap();
+        assert_eq!(out, "Removed 3 duplicates\n");
+        assert!(modified);
+        // names have to match exactly, so Milk stays
+        assert_eq!(
+            parser::emit_str(&lists[..1]),
+            "groceries:\n\t+ [completed=02/03/2024] milk\n\t- eggs\n\t= pantry\n\t- Milk\n"
+        );
+        // the sublist wasn't touched without --recursive
+        assert_eq!(lists[1].items.len(), 2);
+
+        let args = ["groceries", "--recursive"].map(String::from);
+        assert_eq!(
+            cmd_dedup(&mut lists, &args).unwrap().0,
+            "Removed 1 duplicate\n"
+        );
+        assert_eq!(lists[2].items.len(), 2);
+        assert_eq!(
+            cmd_dedup(&mut lists, &[]).unwrap(),
+            ("Removed 1 duplicate\n".to_string(), true)
+        );
+        assert_eq!(
+            cmd_dedup(&mut lists, &[]).unwrap(),
+            ("Removed 0 duplicates\n".to_string(), false)
+        );
+    }
+
+    #[test]
+    fn today_across_dst_changes() {
+        use chrono::TimeZone;
+        use chrono_tz::{America::New_York, Europe::London};
+        // (zone, the moment in UTC, and the date it is there then)
+        let cases = [
+            // the clocks go forward at 01:00 UTC on 31/03/2024, so that day ends at 23:00 UTC
+            (London, (2024, 3, 30, 23, 30), (2024, 3, 30)),
+            (London, (2024, 3, 31, 0, 30), (2024, 3, 31)),
+            (London, (2024, 3, 31, 1, 30), (2024, 3, 31)),
+            (London, (2024, 3, 31, 22, 59), (2024, 3, 31)),
+            (London, (2024, 3, 31, 23, 0), (2024, 4, 1)),
+            // and back at 01:00 UTC on 27/10/2024, when 01:00-02:00 happens twice there
+            (London, (2024, 10, 26, 23, 30), (2024, 10, 27)),
+            (London, (2024, 10, 27, 1, 30), (2024, 10, 27)),
+            (London, (2024, 10, 27, 23, 30), (2024, 10, 27)),
+            (London, (2024, 10, 28, 0, 0), (2024, 10, 28)),
+            // EDT (UTC-4) until 06:00 UTC on 03/11/2024, EST (UTC-5) after
+            (New_York, (2024, 11, 3, 3, 59), (2024, 11, 2)),
+            (New_York, (2024, 11, 3, 4, 0), (2024, 11, 3)),
+            (New_York, (2024, 11, 4, 4, 30), (2024, 11, 3)),
+            (New_York, (2024, 11, 4, 5, 0), (2024, 11, 4)),
+        ];
+        for (tz, (y, m, d, hour, min), (year, month, day)) in cases {
+            let now = chrono::Utc.with_ymd_and_hms(y, m, d, hour, min, 0).unwrap();
+            assert_eq!(
+                date_in(tz, now),
+                chrono::NaiveDate::from_ymd_opt(year, month, day).unwrap(),
+                "{now} in {tz}"
+            );
+        }
+
+        // days until a deadline are whole days, however long the days in between were
+        let now = chrono::Utc.with_ymd_and_hms(2024, 3, 30, 12, 0, 0).unwrap();
+        let deadline = chrono::NaiveDate::from_ymd_opt(2024, 4, 1).unwrap();
+        assert_eq!((deadline - date_in(London, now)).num_days(), 2);
+    }
+
+    #[test]
+    fn time_periods_with_a_fixed_clock() {
+        assert_eq!(fixed_today(), today());
+        let lists = parser::parse_str(concat!(
+            "clock:\n\t- @13/05/2024 two days ago\n\t+ @14/05/2024 done yesterday\n",
+            "\t- @14/05/2024 yesterday\n\t- @15/05/2024 today\n\t- @16/05/2024 tomorrow\n",
+            "\t- @18/05/2024 in three days\n\t- @21/05/2024 in six days\n",
+            "\t- @22/05/2024 in a week\n\t- no date\n",
+        ))
+        .unwrap();
+        // the lines of the output for each command, without the header
+        let output = |op: &str| -> Vec<String> {
+            let mut args = vec!["clock".to_string(), "--no-colour".to_string()];
+            let out = if op == "list" {
+                args.extend(["--width".to_string(), "0".to_string()]);
+                cmd_list(&lists, &args, &Config::default())
+            } else {
+                cmd_timeperiods(&lists, &args[..1], op, &Config::default())
+            };
+            out.unwrap()
+                .0
+                .lines()
+                .skip(1)
+                .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+                .collect()
+        };
+
+        assert_eq!(
+            output("overdue"),
+            [
+                "two days ago 13/05/2024 (2 days ago)",
+                "yesterday 14/05/2024 (1 days ago)",
+            ]
+        );
+        assert_eq!(output("today"), ["today 15/05/2024 (in 0 days)"]);
+        assert_eq!(
+            output("week"),
+            [
+                "tomorrow 16/05/2024 (in 1 day)",
+                "in three days 18/05/2024 (in 3 days)",
+                "in six days 21/05/2024 (in 6 days)",
+            ]
+        );
+        let list = output("list");
+        assert_eq!(list.len(), 9);
+        assert!(list.contains(&"in a week 22/05/2024 (in 7 days)".to_string()));
+        assert!(list.iter().any(|line| line.ends_with("no date")));
+    }
+
+    #[test]
+    fn collapse_done_counts_finished_sublists() {
+        let lists = parser::parse_str(concat!(
+            "move:\n\t+ book van\n\t- pack\n\t+ cancel internet\n\t= kitchen\n\t= garage\n",
+            "kitchen:\n\t+ plates\n\t+ glasses\n",
+            "garage:\n\t+ bikes\n\t- tools\n",
+        ))
+        .unwrap();
+        let show = |extra: &[&str]| {
+            let mut args = vec!["move".to_string(), "--width".to_string(), "0".to_string()];
+            args.extend(extra.iter().map(|arg| (*arg).to_string()));
+            cmd_list(&lists, &args, &Config::default()).unwrap().0
+        };
+
+        // kitchen is all done, so its two items go into move's count. garage still has
+        // something to do, so it keeps its header and gets a line of its own
+        assert_eq!(
+            show(&["--collapse-done"]),
+            concat!(
+                " move:\n",
+                "     pack\n",
+                "     garage:\n",
+                "         tools\n",
+                "✓        (1 completed item hidden)\n",
+                "✓    (4 completed items hidden)\n",
+            )
+        );
+        let everything = show(&[]);
+        assert!(everything.contains("kitchen:"));
+        assert!(!everything.contains("hidden"));
+    }
+
+    #[test]
+    fn export_needs_a_format() {
+        let lists = parser::parse_str("shed:\n\t- oil, grease\n").unwrap();
+        assert_eq!(
+            cmd_export(&lists, &[]).unwrap_err(),
+            "Expected one of --txt, --csv, --json, --yaml or --ndjson"
+        );
+
+        let file = std::env::temp_dir().join(format!("todo-export-{}.csv", std::process::id()));
+        let args = vec!["--csv".to_string(), file.display().to_string()];
+        assert_eq!(cmd_export(&lists, &args).unwrap(), (String::new(), false));
+        let written = std::fs::read_to_string(&file).unwrap();
+        std::fs::remove_file(&file).unwrap();
+        assert!(written.ends_with("\nshed,\"oil, grease\",false,0,,,,,\n"));
+
+        let (json, _) = cmd_export(&lists, &["--json".to_string(), "-".to_string()]).unwrap();
+        assert_eq!(json, parser::emit_json(&lists) + "\n");
+    }
+
+    #[test]
+    fn indent_width_and_guides() {
+        let lists = parser::parse_str(concat!(
+            "trip:\n\t- @20/05/2024 tickets\n\t= packing\n",
+            "packing:\n\t- @21/05/2024 passport\n\t= toiletries\n",
+            "toiletries:\n\t- @22/05/2024 toothbrush\n",
+        ))
+        .unwrap();
+        let today = fixed_today();
+        let narrow = PrintOptions {
+            ascii: true,
+            indent_width: 2,
+            today,
+            ..PrintOptions::default()
+        };
+        assert_eq!(
+            lists[0].print_with(&lists, |_| true, narrow),
+            concat!(
+                "[ ]trip:\n",
+                "[ ]  tickets       \t20/05/2024 (in 5 days)\n",
+                "[ ]  packing:\n",
+                "[ ]    passport    \t21/05/2024 (in 6 days)\n",
+                "[ ]    toiletries:\n",
+                "[ ]      toothbrush\t22/05/2024 (in 7 days)\n",
+            )
+        );
+
+        // the guides take the place of spaces, so the dates line up the same as without them
+        let guides = PrintOptions {
+            indent_width: 3,
+            indent_guides: true,
+            ..narrow
+        };
+        let out = lists[0].print_with(&lists, |_| true, guides);
+        assert!(out.contains("\n[ ]|  |  |  toothbrush\t"), "{}", out);
+        let tabs: Vec<usize> = out.lines().filter_map(|line| line.find('\t')).collect();
+        assert_eq!(tabs, [22, 22, 22]);
+        let fancy = lists[0].print_with(
+            &lists,
+            |_| true,
+            PrintOptions {
+                ascii: false,
+                ..guides
+            },
+        );
+        assert_eq!(fancy.matches('│').count(), 9);
+    }
+
+    #[test]
+    fn label_dot_with_a_one_column_indent() {
+        let lists = parser::parse_str(
+            "labels:\n\t- [label=red] @20/05/2024 red\n\t- @20/05/2024 plain one\n",
+        )
+        .unwrap();
+        let opts = PrintOptions {
+            colour: true,
+            ascii: true,
+            indent_width: 1,
+            today: fixed_today(),
+            ..PrintOptions::default()
+        };
+        // the dot doesn't fit in one column of indent, so it pushes the name along one, and
+        // the padding after it makes up for that
+        let out = lists[0].print_with(&lists, |_| true, opts);
+        let lines: Vec<&str> = out.lines().skip(1).collect();
+        assert_eq!(
+            lines,
+            [
+                "[ ]\x1b[31m*\x1b[0m red     \t20/05/2024 (in 5 days)",
+                "[ ] plain one\t20/05/2024 (in 5 days)",
+            ]
+        );
+    }
+
+    #[test]
+    fn new_with_and_without_stdin() {
+        let mut lists = parser::parse_str("work:\n").unwrap();
+        let words = |s: &str| s.split(' ').map(str::to_owned).collect::<Vec<String>>();
+        assert_eq!(
+            cmd_new(&mut lists, &words("day off")).unwrap(),
+            (String::new(), true)
+        );
+        assert_eq!(lists[1].name, "day off");
+        assert!(cmd_new(&mut lists, &words("work")).is_err());
+        // a name as well as --from-stdin is a mistake, not a list called that
+        assert_eq!(
+            cmd_new(&mut lists, &words("--from-stdin extra")).unwrap_err(),
+            usage()
+        );
+        assert_eq!(lists.len(), 2);
+    }
+
+    #[test]
+    fn list_only_filter() {
+        fixed_today();
+        let lists = parser::parse_str(concat!(
+            "work:\n",
+            "\t- [priority=3] @17/05/2024 slides\n",
+            "\t- [priority=3] @30/05/2024 budget\n",
+            "\t+ [priority=2] @16/05/2024 email\n",
+            "\t- [label=red] [priority=2] @16/05/2024 call\n",
+            "\t- notes\n",
+        ))
+        .unwrap();
+        let names = |args: &[&str]| -> Result<Vec<String>, String> {
+            let args: Vec<String> = args.iter().map(|arg| (*arg).to_string()).collect();
+            let (out, _) = cmd_list(&lists, &args, &Config::default())?;
+            Ok(out
+                .lines()
+                .skip(1)
+                .map(|line| line[3..].split('\t').next().unwrap().trim().to_owned())
+                .collect())
+        };
+
+        assert_eq!(
+            names(&[
+                "work",
+                "--ascii",
+                "--only",
+                "priority>=2 and not done and due<7d"
+            ])
+            .unwrap(),
+            ["slides [p3]", "call [p2]"]
+        );
+        // it goes along with the other filters rather than instead of them
+        assert_eq!(
+            names(&["work", "--ascii", "--label", "red", "--only", "not done"]).unwrap(),
+            ["call [p2]"]
+        );
+        assert_eq!(
+            names(&["work", "--only", "priority >= high"]).unwrap_err(),
+            "Invalid number 'high' in filter"
+        );
+    }
+
+    #[test]
+    fn numbered_entries_and_remove_by_index() {
+        let mut lists = parser::parse_str(concat!(
+            "shop:\n\t- milk\n\t- milk\n\t= hardware\n\t- bread\n\t- eggs\n\t- jam\n",
+            "\t- tea\n\t- rice\n\t- oats\n\t- @20/05/2024 flour\n\t- salt\n",
+            "hardware:\n\t- nails\n",
+        ))
+        .unwrap();
+        let numbered = PrintOptions {
+            numbered: true,
+            ascii: true,
+            today: fixed_today(),
+            ..PrintOptions::default()
+        };
+        let out = lists[0].print_with(&lists, |_| true, numbered);
+        let lines: Vec<&str> = out.lines().collect();
+        // the numbers are padded to the widest one in the list, and the sublist starts again
+        assert_eq!(lines[2], "[ ]    [1]  milk");
+        assert_eq!(lines[3], "[ ]    [2]  hardware:");
+        assert_eq!(lines[4], "[ ]        [0] nails");
+        assert_eq!(lines[11], "[ ]    [9]  flour    \t20/05/2024 (in 5 days)");
+        assert_eq!(lines[12], "[ ]    [10] salt");
+
+        let remove = |lists: &mut Vec<TodoList>, args: &str| {
+            let args: Vec<String> = args.split(' ').map(String::from).collect();
+            cmd_remove(lists, &args, &Config::default())
+        };
+        assert_eq!(
+            remove(&mut lists, "shop --index 10"),
+            Ok(("Removed 'salt' from 'shop'\n".to_string(), true))
+        );
+        assert_eq!(
+            remove(&mut lists, "shop --index 1"),
+            Ok(("Removed 'milk' from 'shop'\n".to_string(), true))
+        );
+        assert_eq!(lists[0].items.len(), 9);
+        assert_eq!(lists[0].items[0].name(), "milk");
+        assert_eq!(lists[0].items[1].name(), "hardware");
+
+        assert_eq!(
+            remove(&mut lists, "shop --index 9").unwrap_err(),
+            "There is no entry 9 in 'shop'"
+        );
+        assert_eq!(
+            remove(&mut lists, "shop --index -1").unwrap_err(),
+            "There is no entry -1 in 'shop'"
+        );
+        assert_eq!(
+            remove(&mut lists, "shop milk --index 0").unwrap_err(),
+            usage()
+        );
+        // without --index it still goes by name
+        assert!(remove(&mut lists, "shop hardware").is_ok());
+        assert!(get_index_by_name(&lists[0], "hardware").is_err());
+    }
+
+    #[test]
+    fn done_and_move_by_index() {
+        let mut lists = parser::parse_str(concat!(
+            "inbox:\n\t- call\n\t- call\n\t= later\n\t- file taxes\n",
+            "later:\n\t- paint\n",
+        ))
+        .unwrap();
+        let config = Config::default();
+        let run = |lists: &mut Vec<TodoList>, cmd: &str, args: &[&str]| {
+            let args: Vec<String> = args.iter().map(|arg| (*arg).to_string()).collect();
+            match cmd {
+                "done" => cmd_done(lists, &args, &config),
+                _ => cmd_move(lists, &args, &Config::default()),
+            }
+        };
+
+        // the first of the two, which a name can't pick out
+        run(&mut lists, "done", &["inbox", "--index", "0"]).unwrap();
+        let done: Vec<bool> = lists[0]
+            .items
+            .iter()
+            .map(|entry| matches!(entry, ListEntry::Item(item) if item.done))
+            .collect();
+        assert_eq!(done, [true, false, false, false]);
+
+        // with --index there's no item name between the two lists
+        run(&mut lists, "move", &["inbox", "--index", "3", "later"]).unwrap();
+        assert_eq!(lists[1].items[1].name(), "file taxes");
+        run(&mut lists, "move", &["--index", "2", "inbox", "later"]).unwrap_err();
+        assert_eq!(
+            run(&mut lists, "move", &["inbox", "--index", "7", "later"]).unwrap_err(),
+            "There is no entry 7 in 'inbox'"
+        );
+        assert_eq!(
+            run(&mut lists, "done", &["inbox", "call", "--index", "1"]).unwrap_err(),
+            usage()
+        );
+        assert_eq!(
+            run(&mut lists, "move", &["inbox", "later"]).unwrap_err(),
+            usage()
+        );
+
+        // a sublist picked out by number still can't go inside itself
+        assert_eq!(
+            run(&mut lists, "move", &["inbox", "--index", "2", "later"]).unwrap_err(),
+            "'later' can't go inside itself"
+        );
+    }
+
+    #[test]
+    fn numbered_sublist_headers_fit_the_column() {
+        let lists = parser::parse_str(concat!(
+            "home:\n\t- @20/05/2024 dishes\n\t= garden\n",
+            "garden:\n\t- @21/05/2024 water the tomatoes\n",
+        ))
+        .unwrap();
+        for (numbered, collapse) in [(true, true), (true, false), (false, true), (false, false)] {
+            let opts = PrintOptions {
+                numbered,
+                collapse_single: collapse,
+                ascii: true,
+                today: fixed_today(),
+                ..PrintOptions::default()
+            };
+            let out = lists[0].print_with(&lists, |_| true, opts);
+            let names: Vec<&str> = out
+                .lines()
+                .filter_map(|line| line.split_once('\t'))
+                .map(|(name, _)| name)
+                .collect();
+            assert_eq!(names.len(), 2);
+            assert_eq!(names[0].len(), names[1].len(), "{out:?}");
+            // the column is only as wide as the longest name needs
+            assert!(names.iter().any(|name| !name.ends_with(' ')), "{:?}", out);
+        }
+    }
+
+    #[test]
+    fn move_create() {
+        let text = "inbox:\n\t- call the bank\n\t- buy paint\nworkshop:\n\t- sand the table\n";
+        let attempts = [
+            // (dest, what comes back)
+            ("errands", Ok(())),
+            (
+                "work",
+                Err("Not creating 'work', it's too close to 'workshop'"),
+            ),
+            (
+                "workshop tools",
+                Err("Not creating 'workshop tools', it's too close to 'workshop'"),
+            ),
+            ("inbox", Err("There is already a list called 'inbox'")),
+        ];
+        for (dest, expected) in &attempts {
+            let mut lists = parser::parse_str(text).unwrap();
+            let args = ["--create", "inbox", "call the bank", dest].map(String::from);
+            let result = cmd_move(&mut lists, &args, &Config::default()).map(|_| ());
+            assert_eq!(result, expected.map_err(String::from), "{dest}");
+            if result.is_ok() {
+                assert_eq!(lists[2].name, *dest);
+                assert_eq!(lists[2].items[0].name(), "call the bank");
+                assert_eq!(lists[0].items.len(), 1);
+            } else {
+                assert_eq!(lists.len(), 2);
+                assert_eq!(lists[0].items.len(), 2);
+            }
+        }
+    }
+
+    #[test]
+    fn glob_patterns() {
+        let lists = parser::parse_str(
+            "globs:\n\t- buy milk\n\t- buy eggs\n\t= buy list\n\t- sell car\n\t- bug 1\n\t- bug 12\n",
+        )
+        .unwrap();
+        let glob = |pattern| glob_entries(&lists[0], pattern);
+        assert_eq!(glob("buy *"), Ok(vec![0, 1, 2]));
+        assert_eq!(glob("*e*"), Ok(vec![1, 3]));
+        assert_eq!(glob("bu? *"), Ok(vec![0, 1, 2, 4, 5]));
+        assert_eq!(glob("bug ?"), Ok(vec![4]));
+        assert_eq!(glob("bug ??"), Ok(vec![5]));
+        // the whole name has to match, not just the start of it
+        assert_eq!(
+            glob("bu?"),
+            Err("Nothing in 'globs' matches 'bu?'".to_owned())
+        );
+        assert!(glob("[").unwrap_err().starts_with("Invalid pattern '['"));
+        assert!(is_glob("buy *") && is_glob("bu?") && !is_glob("buy milk"));
+    }
+
+    #[test]
+    fn done_remove_and_move_with_patterns() {
+        let mut lists = parser::parse_str(concat!(
+            "work:\n\t- fix login\n\t+ fix typo\n\t- fix tests\n\t- write docs\n\t= fixtures\n",
+            "done:\n",
+            "fixtures:\n",
+        ))
+        .unwrap();
+        let config = Config::default();
+        let to_args = |s: &[&str]| s.iter().map(|arg| (*arg).to_string()).collect::<Vec<_>>();
+
+        // fix typo is already done, so it isn't counted (or undone)
+        let (msg, modified) = cmd_done(&mut lists, &to_args(&["work", "fix *"]), &config).unwrap();
+        assert_eq!((msg.as_str(), modified), ("Marked 2 items as done\n", true));
+        let (msg, modified) = cmd_done(&mut lists, &to_args(&["work", "fix *"]), &config).unwrap();
+        assert_eq!(
+            (msg.as_str(), modified),
+            ("Marked 0 items as done\n", false)
+        );
+
+        // sublists match too, and keep their order when they're moved
+        let (msg, _) = cmd_move(
+            &mut lists,
+            &to_args(&["work", "fix*", "done"]),
+            &Config::default(),
+        )
+        .unwrap();
+        assert_eq!(msg, "Moved 4 entries\n");
+        let names: Vec<&str> = lists[1].items.iter().map(ListEntry::name).collect();
+        assert_eq!(names, ["fix login", "fix typo", "fix tests", "fixtures"]);
+        assert_eq!(lists[0].items.len(), 1);
+
+        let (msg, _) = cmd_remove(
+            &mut lists,
+            &to_args(&["done", "fix t*"]),
+            &Config::default(),
+        )
+        .unwrap();
+        assert_eq!(msg, "Removed 2 entries\n");
+        let (msg, _) = cmd_remove(
+            &mut lists,
+            &to_args(&["done", "fix?login"]),
+            &Config::default(),
+        )
+        .unwrap();
+        assert_eq!(msg, "Removed 1 entry\n");
+        assert_eq!(lists[1].items[0].name(), "fixtures");
+
+        // without * or ? it's still the one name
+        assert_eq!(
+            cmd_remove(&mut lists, &to_args(&["work", "write"]), &Config::default()),
+            Ok(("Removed 'write docs' from 'work'\n".to_string(), true))
+        );
+        assert!(lists[0].items.is_empty());
+    }
+
+    #[test]
+    fn deadline_colours() {
+        // (days away, with soon_days = 3, with soon_days = 0)
+        let buckets = [
+            (-10, Some(31), Some(31)),
+            (-1, Some(31), Some(31)),
+            (0, Some(33), Some(33)),
+            (1, Some(36), None),
+            (3, Some(36), None),
+            (4, None, None),
+            (400, None, None),
+        ];
+        for &(days, soon, never_soon) in &buckets {
+            assert_eq!(due_colour(days, 3), soon, "{days} days");
+            assert_eq!(due_colour(days, 0), never_soon, "{days} days");
+        }
+
+        let lists = parser::parse_str(concat!(
+            "dates:\n\t- @14/05/2024 late\n\t- @15/05/2024 today\n\t- @17/05/2024 soon\n",
+            "\t- @30/05/2024 later\n\t+ @14/05/2024 finished\n",
+        ))
+        .unwrap();
+        let opts = PrintOptions {
+            colour: true,
+            ascii: true,
+            today: fixed_today(),
+            ..PrintOptions::default()
+        };
+        let out = lists[0].print_with(&lists, |_| true, opts);
+        let lines: Vec<&str> = out.lines().collect();
+        assert!(
+            lines[1].ends_with("14/05/2024 \x1b[31m(1 days ago)\x1b[0m"),
+            "{:?}",
+            lines[1]
+        );
+        assert!(
+            lines[2].ends_with("15/05/2024 \x1b[33m(in 0 days)\x1b[0m"),
+            "{:?}",
+            lines[2]
+        );
+        assert!(
+            lines[3].ends_with("17/05/2024 \x1b[36m(in 2 days)\x1b[0m"),
+            "{:?}",
+            lines[3]
+        );
+        assert!(
+            lines[4].ends_with("30/05/2024 (in 15 days)"),
+            "{:?}",
+            lines[4]
+        );
+        // done items aren't urgent, however late they were
+        assert!(
+            lines[5].ends_with("14/05/2024 (1 days ago)"),
+            "{:?}",
+            lines[5]
+        );
+        // and there's no colour at all without it
+        let plain = lists[0].print_with(
+            &lists,
+            |_| true,
+            PrintOptions {
+                colour: false,
+                ..opts
+            },
+        );
+        assert!(!plain.contains('\x1b'));
+    }
+
+    #[test]
+    fn stats_counts() {
+        let lists = parser::parse_str(concat!(
+            "work:\n\t- @01/01/2000 ancient\n\t+ @01/01/2000 dealt with\n\t- whenever\n\t= meetings\n",
+            "meetings:\n\t+ standup\n",
+            "home:\n\t- dishes\n",
+        ))
+        .unwrap();
+        let stats = |args: &[&str]| {
+            let args: Vec<String> = args.iter().map(|&arg| arg.to_string()).collect();
+            cmd_stats(&lists, &args).map(|(out, edited)| {
+                assert!(!edited);
+                out
+            })
+        };
+        assert_eq!(stats(&[]).unwrap(), "5 items, 2 done (40%), 1 overdue\n");
+        // a list's sublists count towards it
+        assert_eq!(
+            stats(&["wo"]).unwrap(),
+            "4 items, 2 done (50%), 1 overdue\n"
+        );
+        assert_eq!(
+            stats(&["home"]).unwrap(),
+            "1 items, 0 done (0%), 0 overdue\n"
+        );
+        assert!(stats(&["garden"]).is_err());
+        assert_eq!(percent(0, 0), 0);
+    }
+
+    #[test]
+    fn links() {
+        let mut lists =
+            parser::parse_str("bugs:\n\t- crash on start\n\t= triage\ntriage:\n").unwrap();
+        let link = |lists: &mut Vec<TodoList>, words: &str| {
+            let args: Vec<String> = words.split(' ').map(String::from).collect();
+            cmd_link(lists, "bugs", &args)
+        };
+
+        for bad in [
+            "example.com",
+            "h://x",
+            "https://a b",
+            "https://x/[1]",
+            "1http://x",
+        ] {
+            let err = link(&mut lists, &format!("crash on start {bad}")).unwrap_err();
+            assert!(err.starts_with("Invalid URL '"), "{}", err);
+        }
+        assert_eq!(
+            link(&mut lists, "triage https://x.org").unwrap_err(),
+            "Only items can have links, not lists"
+        );
+        assert_eq!(
+            cmd_open(&lists, "bugs", "crash on start").unwrap_err(),
+            "'crash on start' doesn't have a link"
+        );
+
+        link(
+            &mut lists,
+            "crash on start https://tracker.example/issues/12",
+        )
+        .unwrap();
+        let saved = parser::emit_str(&lists);
+        assert!(saved.contains("[url=https://tracker.example/issues/12] crash on start"));
+        let lists2 = parser::parse_str(&saved).unwrap();
+        let opts = PrintOptions {
+            ascii: true,
+            today: fixed_today(),
+            ..PrintOptions::default()
+        };
+        assert_eq!(
+            lists2[0].print_with(&lists2, |_| true, opts).lines().nth(1),
+            Some("[ ]    crash on start (https://tracker.example/issues/12)")
+        );
+        let linked = lists2[0].print_with(
+            &lists2,
+            |_| true,
+            PrintOptions {
+                hyperlinks: true,
+                ..opts
+            },
+        );
+        assert_eq!(
+            linked.lines().nth(1),
+            Some("[ ]    \x1b]8;;https://tracker.example/issues/12\x1b\\crash on start\x1b]8;;\x1b\\")
+        );
+
+        // only ever handed to the opener if it's a web or email link
+        link(&mut lists, "crash on start file:///etc/passwd").unwrap();
+        assert_eq!(
+            cmd_open(&lists, "bugs", "crash on start").unwrap_err(),
+            "Only http, https and mailto links can be opened, not 'file:///etc/passwd'"
+        );
+
+        link(&mut lists, "crash on start none").unwrap();
+        assert!(!parser::emit_str(&lists).contains("url="));
+    }
+
+    #[test]
+    fn dates_go_under_items_that_dont_fit() {
+        let lists = parser::parse_str(
+            "trip:\n\t- @20/05/2024 book the overnight sleeper train\n\t- @16/05/2024 pack\n",
+        )
+        .unwrap();
+        let opts = PrintOptions {
+            ascii: true,
+            today: fixed_today(),
+            width: Some(60),
+            dates: true,
+            ..PrintOptions::default()
+        };
+
+        // cut short, as with an explicit --width
+        let cut = lists[0].print_with(&lists, |_| true, opts);
+        assert_eq!(cut.lines().count(), 3);
+        assert!(
+            cut.contains("book the overnight") && !cut.contains("train"),
+            "{}",
+            cut
+        );
+
+        let below = lists[0].print_with(
+            &lists,
+            |_| true,
+            PrintOptions {
+                dates_below: true,
+                ..opts
+            },
+        );
+        assert_eq!(
+            below,
+            concat!(
+                "[ ]trip:\n",
+                "[ ]    book the overnight sleeper train\n",
+                "         20/05/2024 (in 5 days)\n",
+                "[ ]    pack\n",
+                "         16/05/2024 (in 1 day)\n",
+            )
+        );
+
+        // only when they don't fit
+        let wide = PrintOptions {
+            dates_below: true,
+            width: Some(100),
+            ..opts
+        };
+        assert_eq!(
+            lists[0].print_with(&lists, |_| true, wide).lines().count(),
+            3
+        );
+        let no_limit = PrintOptions {
+            dates_below: true,
+            width: None,
+            ..opts
+        };
+        assert_eq!(
+            lists[0]
+                .print_with(&lists, |_| true, no_limit)
+                .lines()
+                .count(),
+            3
+        );
+    }
+
+    #[test]
+    fn append_only_blocks_deleting() {
+        let run = |append_only: bool, line: &str| {
+            let mut all = parser::parse_str("work:\n\t- x\n\t- y\n").unwrap();
+            let mut config = Config {
+                append_only,
+                ..Config::default()
+            };
+            let mut args = split_command_line(line).unwrap();
+            take_force(&mut config, &mut args);
+            assert!(!args.contains(&"--force".to_string()));
+            let result = run_command(&mut all, &args, Path::new("/nonexistent/todo.txt"), &config);
+            (result.map(|_| ()), all[0].items.len())
+        };
+
+        for command in ["rmlist", "rm", "r", "autorm", "mva", "dedup"] {
+            let (result, left) = run(true, &format!("{command} work x"));
+            assert_eq!(
+                result.unwrap_err(),
+                format!(
+                    "'{command}' is blocked by append-only mode. Pass --force to run it anyway"
+                )
+            );
+            assert_eq!(left, 2, "{command}");
+        }
+        assert_eq!(run(false, "rm work x"), (Ok(()), 1));
+        assert_eq!(run(true, "add work z"), (Ok(()), 3));
+        assert_eq!(run(true, "done work y"), (Ok(()), 2));
+        // --force can go anywhere
+        assert_eq!(run(true, "rm --force work x"), (Ok(()), 1));
+        assert_eq!(run(true, "rm work y --force"), (Ok(()), 1));
+    }
+
+    #[test]
+    fn list_ndjson_keeps_the_filters() {
+        let lists = parser::parse_str(concat!(
+            "work:\n\t- [priority=3] ship it\n\t- tidy desk\n\t= admin\n",
+            "admin:\n\t- [priority=2] expenses\n",
+        ))
+        .unwrap();
+        let config = Config::default();
+        let list = |extra: &[&str]| {
+            let mut args = vec!["work".to_string(), "--ndjson".to_string()];
+            args.extend(extra.iter().map(|&arg| arg.to_string()));
+            cmd_list(&lists, &args, &config).unwrap().0
+        };
+
+        let paths = |out: String| {
+            out.lines()
+                .map(|line| line.split('"').nth(3).unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(paths(list(&[])), ["work", "work", "work/admin"]);
+        let urgent = list(&["--min-priority", "2"]);
+        assert_eq!(paths(urgent.clone()), ["work", "work/admin"]);
+        assert!(urgent.contains("\"name\":\"expenses\""));
+        assert_eq!(list(&["--only", "priority=0"]).lines().count(), 1);
+
+        let (all, _) = cmd_export(&lists, &["--ndjson".to_string()]).unwrap();
+        assert_eq!(all, list(&[]));
+    }
+
+    #[test]
+    fn add_create_list() {
+        let mut lists = parser::parse_str("inbox:\n\t- first\n").unwrap();
+        let config = Config::default();
+        let mut add = |words: &[&str]| {
+            let args: Vec<String> = words.iter().map(|&word| word.to_owned()).collect();
+            cmd_add_or_create(&mut lists, &args, &config)
+        };
+
+        // a list that's there (or a prefix of one) is just added to
+        assert_eq!(
+            add(&["--create-list", "inbox", "second"]),
+            Ok((String::new(), true))
+        );
+        add(&["in", "third", "--create-list"]).unwrap();
+        // without the flag a missing list is still an error, and nothing is made
+        assert!(add(&["groceries", "milk"]).is_err());
+        add(&["--create-list", "groceries", "milk"]).unwrap();
+        // the second time it's there already
+        add(&["groceries", "eggs"]).unwrap();
+        assert!(add(&["--create-list"]).is_err());
+
+        let names: Vec<Vec<&str>> = lists
+            .iter()
+            .map(|list| list.items.iter().map(ListEntry::name).collect())
+            .collect();
+        assert_eq!(
+            names,
+            [vec!["first", "second", "third"], vec!["milk", "eggs"]]
+        );
+        assert_eq!(lists[1].name, "groceries");
+        assert_eq!(top_level_lists(&lists).len(), 2);
+    }
+
+    #[test]
+    fn sort_saves_the_order() {
+        let text = concat!(
+            "sorting:\n\t- @03/01/2024 cheese\n\t- [priority=1] apples\n\t= errands\n",
+            "\t- [priority=3] @02/01/2024 bread\n\t+ [priority=5] @01/01/2024 done\n",
+            "errands:\n\t- [priority=2] @01/02/2024 post office\n\t+ @01/01/2020 bank\n",
+        );
+        let cases: [(&str, [&str; 5]); 7] = [
+            (
+                "--by name",
+                ["apples", "bread", "cheese", "done", "errands"],
+            ),
+            (
+                "--by name --reverse",
+                ["errands", "done", "cheese", "bread", "apples"],
+            ),
+            // a sublist goes by the soonest deadline in it that isn't done, and undated
+            // things last
+            (
+                "--by date",
+                ["done", "bread", "cheese", "errands", "apples"],
+            ),
+            // and by the highest priority in it that isn't done
+            (
+                "--by priority",
+                ["done", "bread", "errands", "apples", "cheese"],
+            ),
+            (
+                "--lists-first --by priority",
+                ["errands", "done", "bread", "apples", "cheese"],
+            ),
+            (
+                "--by date --lists-last",
+                ["done", "bread", "cheese", "apples", "errands"],
+            ),
+            (
+                "--by priority --reverse",
+                ["cheese", "apples", "errands", "bread", "done"],
+            ),
+        ];
+        for (flags, expected) in cases {
+            let mut lists = parser::parse_str(text).unwrap();
+            let args: Vec<String> = format!("sorting {flags}")
+                .split(' ')
+                .map(String::from)
+                .collect();
+            assert_eq!(cmd_sort(&mut lists, &args), Ok((String::new(), true)));
+            // it's the order in the file that changes
+            let saved = parser::parse_str(&parser::emit_str(&lists)).unwrap();
+            let names: Vec<&str> = saved[0].items.iter().map(ListEntry::name).collect();
+            assert_eq!(names, expected, "{flags}");
+        }
+
+        let mut lists = parser::parse_str(text).unwrap();
+        for bad in [
+            "sorting --by colour",
+            "sorting",
+            "sorting --by name --lists-first --lists-last",
+        ] {
+            let args: Vec<String> = bad.split(' ').map(String::from).collect();
+            assert!(cmd_sort(&mut lists, &args).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn list_sort_priority() {
+        let lists = parser::parse_str(
+            "chores:\n\t- sweep\n\t- [priority=2] bins\n\t= garden\n\t- [priority=9] boiler\ngarden:\n\t- [priority=1] weed\n\t- [priority=4] mow\n",
+        )
+        .unwrap();
+        let opts = PrintOptions {
+            sort: Some(SortKey::Priority),
+            ascii: true,
+            today: fixed_today(),
+            ..PrintOptions::default()
+        };
+        // highest first, with sublists after the items (sorted too), and the file isn't touched
+        assert_eq!(
+            lists[0].print_with(&lists, |_| true, opts),
+            concat!(
+                "[ ]chores:\n[ ]    boiler [p9]\n[ ]    bins [p2]\n[ ]    sweep\n",
+                "[ ]    garden:\n[ ]        mow [p4]\n[ ]        weed [p1]\n",
+            )
+        );
+        assert_eq!(lists[0].items[0].name(), "sweep");
+    }
+
+    #[test]
+    fn at_last_is_the_last_item_added() {
+        // a list name no other test uses, since where @last is kept is shared
+        let mut lists = parser::parse_str("last-added test:\n\t- older\n").unwrap();
+        let config = Config::default();
+        let list = |lists: &[TodoList]| get_list_by_name(lists, "last-added test").unwrap().clone();
+
+        assert_eq!(
+            get_index_by_name(&list(&lists), "@last").unwrap_err(),
+            "Nothing has been added to 'last-added test' yet"
+        );
+        for name in ["first new", "second new"] {
+            let args = ["last-added test".to_string(), name.to_string()];
+            cmd_add(&mut lists, &args, &config).unwrap();
+        }
+        assert_eq!(get_index_by_name(&list(&lists), "@last"), Ok(2));
+        // an item that's actually called @last still wins
+        let mut named = list(&lists);
+        named.items.insert(
+            0,
+            ListEntry::Item(ListItem {
+                name: "@last".into(),
+                ..Default::default()
+            }),
+        );
+        assert_eq!(get_index_by_name(&named, "@last"), Ok(0));
+
+        let args: Vec<String> = ["last-added test", "second new", "renamed"]
+            .map(String::from)
+            .to_vec();
+        cmd_rename(&mut lists, &args).unwrap();
+        assert_eq!(get_index_by_name(&list(&lists), "@last"), Ok(2));
+        lists[0].items.pop();
+        assert_eq!(
+            get_index_by_name(&list(&lists), "@last").unwrap_err(),
+            "'renamed', the last item added to 'last-added test', isn't in it any more"
+        );
+
+        // none of it is written until the lists are saved, and a failed command's is dropped
+        undefer(0);
+        assert!(last_added("last-added test").is_none());
+    }
+
+    #[test]
+    fn show_path() {
+        let lists = parser::parse_str(concat!(
+            "house:\n\t- [priority=1] hoover\n\t= kitchen\n",
+            "kitchen:\n\t- descale kettle\n\t= cupboards\n",
+            "cupboards:\n\t+ [priority=2] sort tins\n\t- [priority=2] @20/05/2024 fix hinge\n",
+        ))
+        .unwrap();
+        let args: Vec<String> = [
+            "house",
+            "--show-path",
+            "--ascii",
+            "--width",
+            "0",
+            "--no-colour",
+        ]
+        .iter()
+        .map(|&arg| arg.to_owned())
+        .collect();
+        let (out, _) = cmd_list(&lists, &args, &Config::default()).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines[0], "[ ]    house: hoover [p1]");
+        assert_eq!(lines[1], "[ ]    house/kitchen: descale kettle");
+        assert_eq!(lines[2], "[x]    house/kitchen/cupboards: sort tins");
+        // the dates still line up after the paths
+        assert!(
+            lines[3].starts_with("[ ]    house/kitchen/cupboards: fix hinge [p2] \t20/05/2024 ")
+        );
+        assert_eq!(lines.len(), 4);
+
+        // the filters still apply, and the headers are gone even for a sublist
+        let mut args = args;
+        args[0] = "kitchen".to_owned();
+        args.extend(["--min-priority".to_owned(), "2".to_owned()]);
+        let (out, _) = cmd_list(&lists, &args, &Config::default()).unwrap();
+        assert!(!out.contains("kitchen:\n"));
+        assert!(out.starts_with("[x]    kitchen/cupboards: sort tins\n"));
+        assert_eq!(out.lines().count(), 2);
+    }
+
+    #[test]
+    fn command_lines_split_like_a_shell() {
+        let cases: [(&str, &[&str]); 6] = [
+            ("add work tidy", &["add", "work", "tidy"]),
+            (
+                "  add   work\t\"fix it\"  12/12 ",
+                &["add", "work", "fix it", "12/12"],
+            ),
+            (
+                r#"rename work 'say "hi"' "it's""#,
+                &["rename", "work", "say \"hi\"", "it's"],
+            ),
+            (r#"add work a\ b \"c"#, &["add", "work", "a b", "\"c"]),
+            (r#"new "" x"#, &["new", "", "x"]),
+            ("", &[]),
+        ];
+        for (line, expected) in cases {
+            assert_eq!(split_command_line(line).unwrap(), expected, "{line}");
+        }
+        assert_eq!(
+            split_command_line("add work \"oops").unwrap_err(),
+            "Missing closing \""
+        );
+        assert!(split_command_line("add work oops\\").is_err());
+    }
+
+    #[test]
+    fn batch_undoes_a_line_that_fails() {
+        let mut lists = parser::parse_str("errands:\n\t- post the letter\n").unwrap();
+        let config = Config {
+            on_done_hook: Some("true".to_string()),
+            ..Config::default()
+        };
+        let input = concat!(
+            "add errands buy stamps\n",
+            "done errands @last\n",
+            "# makes 'later', then can't find the item\n",
+            "move --create errands \"pick up the parcel\" later\n",
+            "\n",
+            "list errands\n",
+            "done errands \"post the\n",
+        );
+        let (msg, modified) = run_batch(
+            &mut lists,
+            input,
+            Path::new("/nonexistent/todo.txt"),
+            &config,
+        )
+        .unwrap();
+        assert!(msg.is_empty());
+        assert!(modified);
+        assert_eq!(
+            lists.iter().map(|list| &list.name).collect::<Vec<_>>(),
+            ["errands"]
+        );
+        // @last is the one added a line before, even though that hasn't been saved yet
+        let done: Vec<(&str, bool)> = lists[0]
+            .items
+            .iter()
+            .filter_map(|entry| match entry {
+                ListEntry::Item(item) => Some((item.name.as_str(), item.done)),
+                ListEntry::List(_) => None,
+            })
+            .collect();
+        assert_eq!(done, [("post the letter", false), ("buy stamps", true)]);
+
+        // only what the lines that worked did is waiting to happen after saving
+        let waiting = DEFERRED.with(|deferred| {
+            deferred
+                .borrow()
+                .iter()
+                .map(|action| match action {
+                    Deferred::LastAdded(list, item) => format!("@last {list} {item:?}"),
+                    Deferred::DoneHook(list, item) => format!("hook {list} {}", item.name),
+                    Deferred::History(args, _) => args.join(" "),
+                })
+                .collect::<Vec<String>>()
+        });
+        undefer(0);
+        assert_eq!(
+            waiting,
+            [
+                "@last errands Some(\"buy stamps\")",
+                "add errands buy stamps",
+                "hook errands buy stamps",
+                "done errands @last"
+            ]
+        );
+    }
+
+    #[test]
+    fn composed_and_decomposed_names_match() {
+        let composed = "caf\u{e9}";
+        let decomposed = "cafe\u{301}";
+        assert_ne!(composed, decomposed);
+        assert_eq!(nfc(decomposed), composed);
+
+        for (list_name, item_name) in [(composed, decomposed), (decomposed, composed)] {
+            let text = format!("{list_name}:\n\t- r\u{e9}sum\u{e9}\n\t- {item_name} au lait\n");
+            let lists = parser::parse_str(&text).unwrap();
+            // whichever way it was typed, as the whole name or the start of one
+            for typed in [composed, decomposed] {
+                assert_eq!(get_list_index_by_name(&lists, typed), Ok(0));
+                let list = &lists[0];
+                assert_eq!(get_index_by_name(list, &format!("{typed} au lait")), Ok(1));
+                assert_eq!(get_index_by_name(list, typed), Ok(1));
+                assert_eq!(get_index_by_name(list, "re\u{301}sum"), Ok(0));
+            }
+        }
+
+        let mut lists = parser::parse_str("menu:\n\t- tea\n\t- caf\u{e9}\n").unwrap();
+        let err = cmd_rename(
+            &mut lists,
+            &[
+                "menu".to_string(),
+                "tea".to_string(),
+                decomposed.to_string(),
+            ],
+        )
+        .unwrap_err();
+        assert!(
+            err.starts_with("There is already an item called"),
+            "{}",
+            err
+        );
+        // and move --create won't make a second list that only looks the same as one
+        assert!(check_not_near_a_list(&lists, "me\u{301}nu").is_ok());
+        let lists = parser::parse_str("m\u{e9}nu:\n").unwrap();
+        assert!(check_not_near_a_list(&lists, "me\u{301}nu").is_err());
+    }
+
+    #[test]
+    fn names_coloured_by_priority() {
+        // shared by every test, so always the defaults
+        PRIORITY_COLOURS.get_or_init(|| Config::default().priority_colours);
+        assert_eq!(priority_colour(0), None);
+        assert_eq!(priority_colour(1), Some(34));
+        assert_eq!(priority_colour(3), Some(31));
+        assert_eq!(priority_colour(200), Some(31));
+
+        let lists = parser::parse_str(concat!(
+            "chores:\n\t- [priority=2] @14/05/2024 bins\n\t- laundry\n",
+            "\t- [priority=1] dishes\n\t+ [priority=3] hoover\n",
+        ))
+        .unwrap();
+        let opts = PrintOptions {
+            colour: true,
+            ascii: true,
+            today: fixed_today(),
+            ..PrintOptions::default()
+        };
+        let out = lists[0].print_with(&lists, |_| true, opts);
+        let lines: Vec<&str> = out.lines().skip(1).collect();
+        // the name takes the priority's colour, and the deadline still has its own
+        assert!(
+            lines[0].starts_with("[ ]    \x1b[33mbins\x1b[0m [p2]"),
+            "{:?}",
+            lines[0]
+        );
+        assert!(
+            lines[0].ends_with("\x1b[31m(1 days ago)\x1b[0m"),
+            "{:?}",
+            lines[0]
+        );
+        assert!(!lines[1].contains('\x1b'), "{:?}", lines[1]);
+        assert!(
+            lines[2].starts_with("[ ]    \x1b[34mdishes\x1b[0m [p1]"),
+            "{:?}",
+            lines[2]
+        );
+        // done items are left alone
+        assert!(!lines[3].contains('\x1b'), "{:?}", lines[3]);
+    }
+
+    #[test]
+    fn which_says_what_a_name_picks_out() {
+        let lists = parser::parse_str(concat!(
+            "groceries:\n\t- oranges\n\t- organic milk\n\t- or\n\t= baking\n",
+            "baking:\n\t- flour\n\t- sugar\n",
+            "garden:\n",
+        ))
+        .unwrap();
+        let which = |line: &str| {
+            let args = split_command_line(line).unwrap();
+            cmd_which(&lists, &args)
+        };
+
+        assert_eq!(
+            which("gro").unwrap(),
+            (
+                "'gro' is the list 'groceries' (by the start of its name)\n".to_string(),
+                false
+            )
+        );
+        let out = which("groceries ora").unwrap().0;
+        assert_eq!(
+            out.lines().collect::<Vec<_>>(),
+            [
+                "'groceries' is the list 'groceries' (by its whole name)",
+                "'ora' is the item 'oranges' in it, entry 0 (by the start of its name)",
+            ]
+        );
+        // an exact match wins over the start of a longer name
+        assert!(which("groceries or")
+            .unwrap()
+            .0
+            .ends_with("'or' is the item 'or' in it, entry 2 (by its whole name)\n"));
+        assert!(which("groceries bak")
+            .unwrap()
+            .0
+            .contains("'bak' is the sublist 'baking' in it, entry 3"));
+        assert_eq!(
+            which("groceries/baking").unwrap().0,
+            "'groceries/baking' is the list 'baking' (by its path)\n"
+        );
+        let out = which("groceries 'or*'").unwrap().0;
+        assert_eq!(out.lines().count(), 4, "{out}");
+        assert!(out.contains("'or*' is the item 'organic milk' in it, entry 1 (by the pattern)"));
+
+        // and why it doesn't pick out one, the same as every other command would say
+        assert_eq!(
+            which("g").unwrap_err(),
+            get_list_index_by_name(&lists, "g").unwrap_err()
+        );
+        assert!(which("groceries o").is_err());
+        assert!(which("garden weeds").is_err());
+        assert_eq!(which("").unwrap_err(), usage());
+    }
+
+    #[test]
+    fn newest_first_by_created_or_completed() {
+        let mut lists = parser::parse_str(concat!(
+            "reading:\n\t- no stamps\n\t+ [completed=10/01/2024] [created=01/01/2024] dune\n",
+            "\t- [created=05/01/2024] emma\n\t= shelf\n",
+            "\t+ [completed=20/01/2024] [created=02/01/2024] ulysses\n",
+            "\t- [created=09/01/2024] beloved\n",
+            "shelf:\n\t+ [completed=15/01/2024] [created=12/01/2024] ivanhoe\n",
+        ))
+        .unwrap();
+        let shown_order = |key: &str| {
+            let args = [
+                "reading",
+                "--sort",
+                key,
+                "--no-colour",
+                "--ascii",
+                "--only-items",
+            ]
+            .map(String::from);
+            let out = cmd_list(&lists, &args, &Config::default()).unwrap().0;
+            out.lines()
+                .skip(1)
+                .map(|line| line[4..].trim().to_string())
+                .collect::<Vec<String>>()
+        };
+        // anything without the date goes last, in the order it's in
+        assert_eq!(
+            shown_order("created"),
+            ["beloved", "emma", "ulysses", "dune", "no stamps"]
+        );
+        assert_eq!(
+            shown_order("completed"),
+            ["ulysses", "dune", "no stamps", "emma", "beloved"]
+        );
+
+        // sort goes by the newest item in a sublist
+        let args = ["reading", "--by", "completed"].map(String::from);
+        cmd_sort(&mut lists, &args).unwrap();
+        let names: Vec<&str> = lists[0].items.iter().map(ListEntry::name).collect();
+        assert_eq!(
+            names,
+            ["ulysses", "shelf", "dune", "no stamps", "emma", "beloved"]
+        );
+        assert!(SortKey::parse("modified").is_err());
+    }
+
+    #[test]
+    fn deep_and_wide_lists_print_quickly() {
+        use std::fmt::Write;
+        // a chain of sublists 200 deep with 50 items each, next to lots of lists that aren't
+        // in it at all
+        let mut text = String::new();
+        for depth in 0..200 {
+            writeln!(text, "step {depth}:").unwrap();
+            for n in 0..50 {
+                let mark = if n % 4 == 0 { '+' } else { '-' };
+                writeln!(text, "\t{mark} [priority={}] @01/01/2024 task {n}", n % 3).unwrap();
+            }
+            writeln!(text, "\t= step {}", depth + 1).unwrap();
+        }
+        text.push_str("step 200:\n\t- the end\n");
+        for n in 0..3000 {
+            writeln!(text, "unrelated {n}:\n\t- filler\n\t= step 150").unwrap();
+        }
+        let lists = parser::parse_str(&text).unwrap();
+        let opts = PrintOptions {
+            collapse_single: true,
+            today: fixed_today(),
+            ..PrintOptions::default()
+        };
+
+        let start = std::time::Instant::now();
+        let everything = lists[0].print_with(&lists, |_| true, opts);
+        let undone = lists[0].print_with(&lists, |item| !item.done && item.priority > 1, opts);
+        let took = start.elapsed();
+
+        assert!(
+            everything.ends_with("the end\n"),
+            "{}",
+            &everything[everything.len() - 200..]
+        );
+        assert!(!everything.contains("unrelated"));
+        assert_eq!(everything.matches("task 0 ").count(), 200);
+        // 12 of each 50 are high priority and not done
+        assert_eq!(undone.matches("task ").count(), 200 * 12);
+        assert!(!undone.contains("task 0 "));
+        assert!(took < std::time::Duration::from_secs(3), "took {:?}", took);
+    }
+
+    #[test]
+    fn a_copied_list_doesnt_follow_the_original() {
+        let mut lists = parser::parse_str(concat!(
+            "holiday:\n",
+            "packing:\n\t- passport\n\t= washbag\n",
+            "washbag:\n\t- toothbrush\n",
+            "washbag (copy):\n",
+        ))
+        .unwrap();
+        let (out, _) =
+            cmd_addlist(&mut lists, &["--copy", "holiday", "pack"].map(String::from)).unwrap();
+        assert_eq!(
+            out,
+            "Added a copy of 'pack' to 'holiday' as 'packing (copy)'\n"
+        );
+        // the lists in it are copied too, without clashing with one that's there already
+        assert_eq!(
+            parser::emit_str(&lists[4..]),
+            concat!(
+                "packing (copy):\n\t- passport\n\t= washbag (copy 2)\n",
+                "washbag (copy 2):\n\t- toothbrush\n",
+            )
+        );
+
+        let (_, modified) = run_batch(
+            &mut lists,
+            concat!(
+                "done packing passport\n",
+                "add washbag razor\n",
+                "rename washbag toothbrush electric toothbrush\n",
+                "remove \"packing (copy)\" passport\n",
+            ),
+            Path::new("/nonexistent/todo.txt"),
+            &Config::default(),
+        )
+        .unwrap();
+        assert!(modified);
+        let emitted = parser::emit_str(&lists);
+        assert!(emitted.contains("packing:\n\t+ "), "{}", emitted);
+        assert!(emitted.contains("] passport\n\t= washbag\n"), "{}", emitted);
+        assert!(
+            emitted.contains("washbag:\n\t- electric toothbrush\n\t- ["),
+            "{}",
+            emitted
+        );
+        assert!(emitted.ends_with(concat!(
+            "packing (copy):\n\t= washbag (copy 2)\n",
+            "washbag (copy 2):\n\t- toothbrush\n",
+        )));
+
+        // without --copy, it's the same list
+        cmd_addlist(&mut lists, &["holiday", "washbag"].map(String::from)).unwrap();
+        assert!(lists[0]
+            .walk_items(&lists)
+            .iter()
+            .any(|item| item.name == "razor"));
+    }
+
+    #[test]
+    fn a_repeating_item_starts_again_but_keeps_what_its_about() {
+        let mut lists = parser::parse_str(concat!(
+            "chores:\n\t- [every=monday] [label=green] [priority=2] [progress=60] ",
+            "[created=01/01/2024] [url=https://example.com/bins] [id=0b1e] @13/05/2024 put the bins out\n",
+            "\t\t+ wheel it out\n\t\t- bring it back in\n",
+            "\t- [priority=2] [progress=60] @13/05/2024 fix the shed\n",
+        ))
+        .unwrap();
+        let config = Config::default();
+        for item in ["put", "fix"] {
+            cmd_done(
+                &mut lists,
+                &["chores".to_string(), item.to_string()],
+                &config,
+            )
+            .unwrap();
+        }
+        let [ListEntry::Item(bins), ListEntry::Item(shed)] = lists[0].items.as_slice() else {
+            panic!("{:?}", lists[0].items);
+        };
+
+        let next = bins.date.unwrap();
+        assert_eq!(next.weekday(), chrono::Weekday::Mon);
+        assert!(next > today() && next <= today() + chrono::Duration::days(7));
+        let expected = ListItem {
+            name: "put the bins out".to_string(),
+            date: Some(next),
+            done: false,
+            repeat: Some(RepeatKind::Weekly(chrono::Weekday::Mon)),
+            label: Some("green".to_string()),
+            progress: Some(0),
+            completed: None,
+            created: chrono::NaiveDate::from_ymd_opt(2024, 1, 1),
+            priority: 2,
+            url: Some("https://example.com/bins".to_string()),
+            trashed_from: None,
+            trashed_reference: false,
+            id: Some("0b1e".to_string()),
+            subitems: vec![
+                ("wheel it out".to_string(), false),
+                ("bring it back in".to_string(), false),
+            ],
+        };
+        // every field, so one added later has to be thought about here too
+        assert_eq!(format!("{bins:?}"), format!("{expected:?}"));
+
+        // one that doesn't repeat is just done, and finished
+        assert!(shed.done);
+        assert_eq!(shed.progress, Some(100));
+        assert_eq!(shed.completed, Some(today()));
+        assert_eq!(shed.date, chrono::NaiveDate::from_ymd_opt(2024, 5, 13));
+    }
+
+    #[test]
+    fn breadcrumbs_instead_of_nested_headers() {
+        let lists = parser::parse_str(concat!(
+            "work:\n\t- [priority=2] expenses\n\t= clients\n\t- tidy desk\n",
+            "clients:\n\t= acme\n\t= globex\n",
+            "acme:\n\t- [priority=3] invoice\n\t+ [priority=2] kickoff\n",
+            "globex:\n\t- call back\n",
+        ))
+        .unwrap();
+        let list = |flags: &str| {
+            let mut args = vec!["work".to_string(), "--breadcrumb".to_string()];
+            args.extend(flags.split_whitespace().map(String::from));
+            cmd_list(&lists, &args, &Config::default()).unwrap().0
+        };
+
+        let out = list("--ascii --no-colour --min-priority 2");
+        assert_eq!(
+            out.lines().map(str::trim_end).collect::<Vec<_>>(),
+            [
+                "[ ]work:",
+                "[ ]    expenses [p2]",
+                "[ ]work > clients > acme:",
+                "[ ]    invoice [p3]",
+                "[x]    kickoff",
+            ]
+        );
+        // lists with nothing to show are left out, and they come in the order of the first
+        // item shown from each
+        let out = list("--ascii --no-colour --max-priority 0");
+        let headers: Vec<&str> = out.lines().filter(|line| line.ends_with(':')).collect();
+        assert_eq!(headers, ["[ ]work > clients > globex:", "[ ]work:"]);
+        assert!(list("--no-colour").contains("work › clients › acme:"));
+    }
+
+    #[test]
+    fn removing_from_the_ui_or_serve_uses_the_trash_too() {
+        let mut lists =
+            parser::parse_str("inbox:\n\t- [priority=2] reply to sam\n\t- spam\n").unwrap();
+        let config = Config {
+            use_trash: true,
+            ..Config::default()
+        };
+        remove_entry(&mut lists, "inbox", "reply", &config).unwrap();
+        remove_at(&mut lists, "inbox", 0, &config).unwrap();
+        assert!(lists[0].items.is_empty());
+        let text = parser::emit_str(&lists);
+        assert_eq!(
+            text,
+            "inbox:\n__trash:\n\t- [priority=2] [trashed_from=inbox] reply to sam\n\t- [trashed_from=inbox] spam\n"
+        );
+        assert_eq!(parser::emit_str(&parser::parse_str(&text).unwrap()), text);
+
+        // taking something out of the trash gets rid of it
+        remove_at(&mut lists, TRASH, 1, &config).unwrap();
+        assert_eq!(lists[1].items.len(), 1);
+        // and with it off, nothing's kept
+        let mut lists = parser::parse_str("inbox:\n\t- spam\n").unwrap();
+        remove_at(&mut lists, "inbox", 0, &Config::default()).unwrap();
+        assert_eq!(parser::emit_str(&lists), "inbox:\n");
+    }
+
+    #[test]
+    fn aliases_fill_in_and_chain() {
+        let mut config = Config::default();
+        for (name, command) in [
+            ("tw", "list work --collapse"),
+            ("move", "add $2 $1"),
+            ("wk", "tw --sort due"),
+            ("today", "today --collapse"),
+            ("a", "b"),
+            ("b", "a"),
+        ] {
+            config
+                .aliases
+                .insert(name.to_owned(), split_command_line(command).unwrap());
+        }
+        let expand =
+            |line: &str| expand_aliases(&split_command_line(line).unwrap(), &config).join(" ");
+        assert_eq!(expand("tw"), "list work --collapse");
+        assert_eq!(expand("tw --ascii"), "list work --collapse --ascii");
+        assert_eq!(expand("move milk home extra"), "add home milk extra");
+        assert_eq!(expand("wk"), "list work --collapse --sort due");
+        // an alias named after a command uses the command, not itself again
+        assert_eq!(expand("today work"), "today --collapse work");
+        assert_eq!(expand("a x"), "a x");
+        assert_eq!(expand("done work 1"), "done work 1");
+    }
+
+    #[test]
+    fn ages_in_their_own_column() {
+        assert_eq!(
+            [0, 13, 14, 59, 60, 364, 365, 800, -3].map(format_age),
+            ["0d", "13d", "2w", "8w", "2mo", "12mo", "1y", "2y", "0d"]
+        );
+
+        let created = |days: i64| (today() - chrono::Duration::days(days)).format("%d/%m/%Y");
+        let file = format!(
+            "home:\n\t- [created={}] hoover\n\t- [created={}] fix the long fence\n\t- old habit\n",
+            created(3),
+            created(100)
+        );
+        let lists = parser::parse_str(&file).unwrap();
+        let args = ["home", "--age", "--ascii", "--no-colour"].map(String::from);
+        let (out, _) = cmd_list(&lists, &args, &Config::default()).unwrap();
+        assert_eq!(
+            out.lines().skip(1).collect::<Vec<_>>(),
+            [
+                "[ ]    hoover                3d",
+                "[ ]    fix the long fence   3mo",
+                "[ ]    old habit              -",
+            ],
+            "{out}"
+        );
+        let args = ["home", "--age", "--no-colour"].map(String::from);
+        let (out, _) = cmd_list(&lists, &args, &Config::default()).unwrap();
+        assert!(out.contains("old habit              —"), "{}", out);
+    }
+
+    #[test]
+    fn move_just_what_matches() {
+        let mut lists = parser::parse_str(
+            "inbox:\n\t+ paid rent\n\t- [priority=3] call bank\n\t= errands\n\t- buy milk\narchive:\nerrands:\n",
+        )
+        .unwrap();
+        let mut run = |line: &str, config: &Config| {
+            cmd_move(&mut lists, &split_command_line(line).unwrap(), config)
+        };
+
+        assert_eq!(
+            run("inbox archive --where done", &Config::default()).unwrap(),
+            ("Moved 1 item\n".to_string(), true)
+        );
+        // the sublist isn't an item, so it stays put whatever the filter says
+        assert_eq!(
+            run(
+                "inbox work/later --where \"not done\" --create",
+                &Config::default()
+            )
+            .unwrap(),
+            ("Moved 2 items\n".to_string(), true)
+        );
+        assert_eq!(
+            run("inbox archive --where done", &Config::default()).unwrap(),
+            ("Moved 0 items\n".to_string(), false)
+        );
+        assert_eq!(
+            run("archive archive --where done", &Config::default()).unwrap_err(),
+            "Can't move from 'archive' into itself"
+        );
+        assert_eq!(
+            run("inbox arch --where done --create", &Config::default()).unwrap_err(),
+            "Not creating 'arch', it's too close to 'archive'"
+        );
+        let append_only = Config {
+            append_only: true,
+            ..Config::default()
+        };
+        assert!(run("archive inbox --where done", &append_only)
+            .unwrap_err()
+            .contains("append-only"));
+
+        let file = parser::emit_str(&lists);
+        assert!(
+            file.starts_with("inbox:\n\t= errands\narchive:\n\t+ "),
+            "{}",
+            file
+        );
+        assert!(
+            file.contains("later:\n\t- [priority=3] call bank\n\t- buy milk\n"),
+            "{}",
+            file
+        );
+    }
+
+    #[test]
+    fn list_without_dates() {
+        fixed_today();
+        let all = parser::parse_str(concat!(
+            "checklist:\n\t- @16/05/2024 passport\n\t+ @01/05/2024 tickets\n\t- towel\n\t= bag\n",
+            "bag:\n\t- @20/05/2024 sun cream\n",
+        ))
+        .unwrap();
+        let list = |extra: &str| {
+            let line = format!("--no-colour --ascii --width 0 checklist {extra}");
+            cmd_list(
+                &all,
+                &split_command_line(&line).unwrap(),
+                &Config::default(),
+            )
+            .unwrap()
+            .0
+        };
+        let with_dates = list("");
+        assert!(
+            with_dates.contains("16/05/2024 (in 1 day)"),
+            "{}",
+            with_dates
+        );
+        // everything's still there, just with nothing after the names
+        assert_eq!(
+            list("--no-dates"),
+            "[ ]checklist:\n[ ]    passport\n[x]    tickets\n[ ]    towel\n[ ]    bag:\n[ ]        sun cream\n"
+        );
+        assert_eq!(list("--no-date"), list("--no-dates"));
+    }
+
+    #[test]
+    fn done_remove_rename_and_move_say_what_they_did() {
+        fixed_today();
+        let mut lists = parser::parse_str(
+            "chores:\n\t- hoover the stairs\n\t- [every=weekly] @13/05/2024 bins\n\t- dust\nlater:\n",
+        )
+        .unwrap();
+        let config = Config::default();
+        let steps = [
+            ("done chores hoov", "Marked 'hoover the stairs' as done\n"),
+            (
+                "done chores hoov",
+                "Marked 'hoover the stairs' as not done\n",
+            ),
+            (
+                "done chores bins",
+                "Marked 'bins' as done, it's next due 20/05/2024\n",
+            ),
+            (
+                "rename chores du dust the shelves",
+                "Renamed 'dust' to 'dust the shelves'\n",
+            ),
+            (
+                "move chores dust later",
+                "Moved 'dust the shelves' from 'chores' to 'later'\n",
+            ),
+            (
+                "remove later --index 0",
+                "Removed 'dust the shelves' from 'later'\n",
+            ),
+            ("done chores hoov --quiet", ""),
+        ];
+        for (line, said) in steps {
+            let args = split_command_line(line).unwrap();
+            assert_eq!(
+                run_command(&mut lists, &args, Path::new("todo.txt"), &config),
+                Ok((said.to_string(), true)),
+                "{line}"
+            );
+        }
+
+        // with no name, it isn't the start of every name
+        for line in [
+            "done chores",
+            "remove chores ' '",
+            "done --quiet chores",
+            "remove",
+        ] {
+            let args = split_command_line(line).unwrap();
+            assert!(
+                run_command(&mut lists, &args, Path::new("todo.txt"), &config).is_err(),
+                "{}",
+                line
+            );
+        }
+        assert_eq!(lists[0].items.len(), 2);
+        assert!(lists[1].items.is_empty());
+    }
+
+    #[test]
+    fn where_the_items_are() {
+        let lists = parser::parse_str(concat!(
+            "home:\n\t= rooms\n\t= garden\n\t- pay rent\n",
+            "rooms:\n\t= kitchen\n\t= bathroom\n",
+            "kitchen:\n\t- descale kettle\n\t- defrost\n",
+            "bathroom:\n",
+            "garden:\n\t= kitchen\n\t- weed\n",
+            "work:\n\t= kitchen\n",
+        ))
+        .unwrap();
+        let args: Vec<String> = vec!["home".into(), "--only-leaf-lists".into()];
+        let (out, modified) = cmd_list(&lists, &args, &Config::default()).unwrap();
+        assert!(!modified);
+        // rooms and bathroom have no items of their own, and kitchen is only shown once
+        assert_eq!(
+            out,
+            "home (1 item)\n\
+             home/rooms/kitchen (2 items, also in 'garden', 'work')\n\
+             home/garden (1 item)\n"
+        );
+    }
+
+    #[test]
+    fn invalid_utf8_says_where() {
+        let error = |bytes: &[u8]| decode_utf8(bytes.to_vec()).unwrap_err().to_string();
+        assert_eq!(
+            error(b"list:\n\t- caf\xe9\n"),
+            "invalid UTF-8 at byte 12 (line 2, column 7)"
+        );
+        assert_eq!(error(b"\xff"), "invalid UTF-8 at byte 0 (line 1, column 1)");
+        // a character cut off at the end of the file
+        assert_eq!(
+            error("list:\n\t- \u{2713}".as_bytes().split_last().unwrap().1),
+            "invalid UTF-8 at byte 9 (line 2, column 4)"
+        );
+        // the valid multi-byte characters before it are counted in bytes
+        assert_eq!(
+            error(b"\xc3\xa9\xc3\xa9\x80"),
+            "invalid UTF-8 at byte 4 (line 1, column 5)"
+        );
+        assert_eq!(
+            decode_utf8(b"list:\n\t- caf\xc3\xa9\n".to_vec()).unwrap(),
+            "list:\n\t- caf\u{e9}\n"
+        );
+
+        // and it gets as far as the error from loading the file
+        let path = config_dir().join("invalid-utf8.txt");
+        std::fs::write(&path, b"list:\n\t- ok\n\t- not \x80k\n").unwrap();
+        let err = load_lists(&path, false).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        assert!(
+            err.contains("invalid UTF-8 at byte 19 (line 3, column 8)"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn sublists_inline_as_links_or_not_at_all() {
+        let lists = parser::parse_str(concat!(
+            "trip:\n\t- book flights\n\t= packing\n\t= visas\n",
+            "packing:\n\t- socks\n\t+ charger\n\t= toiletries\n",
+            "toiletries:\n\t- toothbrush\n",
+            "visas:\n\t+ apply\n",
+        ))
+        .unwrap();
+        let show = |refs: &str| {
+            let args = format!("trip --ascii --no-colour --width 0 --resolve-refs {refs}");
+            let args: Vec<String> = args.split(' ').map(str::to_owned).collect();
+            cmd_list(&lists, &args, &Config::default()).map(|(out, _)| out)
+        };
+
+        let inline = show("inline").unwrap();
+        assert!(
+            inline.contains("[ ]        toiletries:\n[ ]            toothbrush\n"),
+            "{}",
+            inline
+        );
+        let link = show("link").unwrap();
+        // counting what's in the lists under it too
+        assert_eq!(
+            link,
+            "[ ]trip:\n[ ]    book flights\n[ ]    -> packing (3 items)\n[x]    -> visas (1 item)\n"
+        );
+        assert_eq!(show("skip").unwrap(), "[ ]trip:\n[ ]    book flights\n");
+        assert!(show("sideways").is_err());
+
+        // links to lists with nothing to show go too, with --no-empty
+        let args: Vec<String> = ["trip", "--resolve-refs", "link", "--ascii", "--hide-empty"]
+            .iter()
+            .map(|&arg| arg.to_string())
+            .chain(["--only".to_string(), "not done".to_string()])
+            .collect();
+        let (out, _) = cmd_list(&lists, &args, &Config::default()).unwrap();
+        assert!(out.contains("packing"), "{}", out);
+        assert!(!out.contains("visas"), "{}", out);
+    }
+
+    #[test]
+    fn recur_lists_whatever_repeats() {
+        let lists = parser::parse_str(concat!(
+            "home:\n\t- [every=14d] @03/06/2024 water plants\n\t- mop\n\t= bills\n",
+            "bills:\n\t- [every=1st] @01/06/2024 rent\n",
+            "gym:\n\t- [every=thursday] @16/05/2024 swim\n\t- [every=1d] @16/05/2024 stretch\n",
+        ))
+        .unwrap();
+        let recur = |line: &str| {
+            let args: Vec<String> = line.split_whitespace().map(String::from).collect();
+            cmd_recur(&lists, &args).map(|(out, _)| out)
+        };
+        let everything = recur("").unwrap();
+        let mut lines = everything.lines();
+        // the two on the same day stay in the order they're in
+        assert_eq!(
+            lines.next(),
+            Some("16/05/2024  every thursday             gym: swim")
+        );
+        assert_eq!(
+            lines.next(),
+            Some("16/05/2024  every day                  gym: stretch")
+        );
+        assert_eq!(
+            lines.next(),
+            Some("01/06/2024  on the 1st of every month  home/bills: rent")
+        );
+        assert_eq!(
+            lines.next(),
+            Some("03/06/2024  every 2 weeks              home: water plants")
+        );
+        assert_eq!(lines.next(), None);
+        assert_eq!(recur("--all").unwrap(), everything);
+
+        assert_eq!(
+            recur("--list bills --json").unwrap(),
+            "[{\"list_path\":\"bills\",\"name\":\"rent\",\"repeat\":\"1st\",\
+             \"description\":\"on the 1st of every month\",\"next\":\"2024-06-01\"}]\n"
+        );
+        let lists = parser::parse_str("home:\n\t- mop\n").unwrap();
+        assert_eq!(cmd_recur(&lists, &[]).unwrap().0, "Nothing repeats\n");
+        assert_eq!(
+            cmd_recur(&lists, &["--json".to_string()]).unwrap().0,
+            "[]\n"
+        );
+    }
+
+    #[test]
+    fn markers_padded_to_the_widest() {
+        let config = Config {
+            done_marker: "DONE".to_owned(),
+            undone_marker: "-".to_owned(),
+            list_done_marker: Some("\u{2705}".to_owned()),
+            ..Config::default()
+        };
+        // the emoji takes two columns
+        assert_eq!(padded_markers(&config), ["DONE", "-   ", "\u{2705}  "]);
+        assert_eq!(padded_markers(&Config::default()), ["✓", " ", "✓"]);
+    }
+
+    #[test]
+    fn durations_from_a_date() {
+        let jan_31 = chrono::NaiveDate::from_ymd_opt(2024, 1, 31).unwrap();
+        let after = |duration: &str| {
+            add_duration(jan_31, duration).map(|date| date.format("%d/%m/%Y").to_string())
+        };
+        for (duration, expected) in [
+            ("3d", "03/02/2024"),
+            ("3 days", "03/02/2024"),
+            ("2w", "14/02/2024"),
+            ("1 week", "07/02/2024"),
+            // a month on from the 31st is the last day of february, in a leap year too
+            ("1m", "29/02/2024"),
+            ("13 months", "28/02/2025"),
+            ("1y", "31/01/2025"),
+            ("0d", "31/01/2024"),
+        ] {
+            assert_eq!(after(duration).as_deref(), Some(expected), "{duration}");
+        }
+        for bad in ["", "d", "3", "-1d", "3x", "1.5w", "in 3 days"] {
+            assert_eq!(after(bad), None, "{bad}");
+        }
+    }
+
+    #[test]
+    fn add_in_a_while() {
+        fixed_today();
+        let mut lists = parser::parse_str("taxes:\n").unwrap();
+        let config = Config::default();
+        let mut add = |line: &str| cmd_add(&mut lists, &split_command_line(line).unwrap(), &config);
+        add("--in 2w taxes file the return").unwrap();
+        // with --in, what looks like a date at the end is part of the name
+        add("taxes --in 3d chase 01/01/2025").unwrap();
+        add("taxes \"ask about 2023\" --in 1m").unwrap();
+        assert_eq!(
+            add("--in soon taxes panic").unwrap_err(),
+            "Invalid duration 'soon', expected e.g. 3d, 2w, 1m or 1y"
+        );
+        assert_eq!(
+            add("--in 1y taxes '\"next year\" 01/06/2025'").unwrap_err(),
+            "Give either a date or --in, not both"
+        );
+        assert_eq!(
+            parser::emit_str(&lists),
+            concat!(
+                "taxes:\n",
+                "\t- [created=15/05/2024] @29/05/2024 file the return\n",
+                "\t- [created=15/05/2024] @18/05/2024 chase 01/01/2025\n",
+                "\t- [created=15/05/2024] @15/06/2024 ask about 2023\n",
+            )
+        );
+    }
+
+    #[test]
+    fn ids_pick_things_out_whatever_theyre_called() {
+        let mut lists = parser::parse_str(concat!(
+            "home: [id=a1]\n\t- [id=b2] hoover\n\t- [id=b3] dust\n\t- [id=a1] clash\n",
+            "work: [id=c9]\n\t- [id=b2] copy\n",
+        ))
+        .unwrap();
+        // the IDs already there are kept, and only the second of a pair gets a new one
+        assert!(assign_ids(&mut lists));
+        assert_eq!(lists[0].id.as_deref(), Some("a1"));
+        let ids: Vec<String> = parser::all_items_with_paths(&lists)
+            .into_iter()
+            .map(|(_, item)| item.id.clone().unwrap())
+            .collect();
+        assert_eq!(ids[..2], ["b2", "b3"]);
+        assert!(ids[2] != "a1" && ids[3] != "b2", "{:?}", ids);
+        assert_eq!(ids[3].len(), 36);
+        assert!(!assign_ids(&mut lists));
+
+        let config = Config::default();
+        let run = |lists: &mut Vec<TodoList>, line: &str| {
+            let args = split_command_line(line).unwrap();
+            run_command(lists, &args, Path::new("todo.txt"), &config)
+        };
+        assert!(run(&mut lists, "done home #b").unwrap_err().starts_with(
+            "Item '#b' is not specific enough to match a single item, it matches 'b2', 'b3'"
+        ));
+        run(&mut lists, "rename #a1 #b2 vacuum").unwrap();
+        run(&mut lists, "move home #b2 work").unwrap();
+        // not an ID, so it's a name after all
+        run(&mut lists, "add home #c1 tidy up").unwrap();
+        assert!(run(&mut lists, "done home #c1").is_ok());
+        assert_eq!(
+            run(&mut lists, "move home #b3 #a --create").unwrap_err(),
+            "Not creating '#a', it's the ID of 'home'"
+        );
+        let work = get_list_by_name(&lists, "work").unwrap();
+        assert_eq!(work.items[1].name(), "vacuum");
+    }
+
+    #[test]
+    fn a_shared_sublist_only_turns_up_once_across_all_the_lists() {
+        fixed_today();
+        let lists = parser::parse_str(concat!(
+            "home:\n\t- @15/05/2024 dishes\n\t= shared\n",
+            "work:\n\t= shared\n\t- @15/05/2024 email\n",
+            "shared:\n\t- @15/05/2024 call the bank\n\t- @14/05/2024 renew insurance\n",
+        ))
+        .unwrap();
+        let config = Config::default();
+        let period = |op: &str, line: &str| {
+            let args: Vec<String> = line.split(' ').map(String::from).collect();
+            cmd_timeperiods(&lists, &args, op, &config).unwrap().0
+        };
+
+        assert_eq!(period("today", "--all --count"), "3\n");
+        assert_eq!(period("overdue", "--all --count"), "1\n");
+        // under the first list it's in, and not the other
+        let today = period("today", "--all --ascii");
+        assert_eq!(today.matches("call the bank").count(), 1, "{today}");
+        assert!(
+            today.find("call the bank") < today.find("work:"),
+            "{}",
+            today
+        );
+        assert_eq!(today.matches("shared:").count(), 1, "{today}");
+        let (agenda, _) = cmd_agenda(&lists, &[], &config).unwrap();
+        assert_eq!(agenda.matches("renew insurance").count(), 1, "{agenda}");
+        assert_eq!(agenda.matches("call the bank").count(), 1, "{agenda}");
+
+        // on its own, a list still has all of it
+        assert_eq!(period("today", "work --count"), "2\n");
+    }
+
+    #[test]
+    fn timeline_headings() {
+        fixed_today();
+        let lists = parser::parse_str(concat!(
+            "work:\n\t- @30/05/2024 review\n\t- [priority=2] @15/05/2024 standup\n",
+            "\t- @15/05/2024 lunch\n\t- @13/05/2024 report\n\t= home\n",
+            "home:\n\t- @18/05/2024 bins\n\t- @16/05/2024 dentist\n\t- paint the fence\n",
+            "\t+ @13/05/2024 old news\n",
+        ))
+        .unwrap();
+        let args = split_command_line("work --timeline --ascii --no-colour").unwrap();
+        let (out, _) = cmd_list(&lists, &args, &Config::default()).unwrap();
+        let lines: Vec<&str> = out.lines().map(str::trim_end).collect();
+        let headings: Vec<&str> = lines
+            .iter()
+            .copied()
+            .filter(|line| line.ends_with(':') && !line.starts_with(' '))
+            .collect();
+        assert_eq!(
+            headings,
+            [
+                "Overdue:",
+                "Today:",
+                "Tomorrow:",
+                "Saturday:",
+                "Later:",
+                "No date:"
+            ]
+        );
+        // the higher priority one goes first, whatever order they're in the file
+        let standup = lines.iter().position(|line| line.contains("work: standup"));
+        let lunch = lines.iter().position(|line| line.contains("work: lunch"));
+        assert!(standup.is_some() && standup < lunch, "{}", out);
+        assert!(out.contains("work/home: dentist"), "{}", out);
+        assert!(!out.contains("old news"), "{}", out);
+
+        // without a list it's got the lot, but still just the once
+        let everything = cmd_list(
+            &lists,
+            &split_command_line("--timeline").unwrap(),
+            &Config::default(),
+        )
+        .unwrap()
+        .0;
+        assert_eq!(everything.matches("dentist").count(), 1, "{everything}");
+        assert!(everything.contains("work: report"), "{}", everything);
+    }
+
+    #[test]
+    fn done_items_sink_to_the_bottom() {
+        let lists = parser::parse_str(
+            "chores:\n\t+ hoover\n\t- dishes\n\t= garden\n\t+ bins\n\t- laundry\ngarden:\n\t- weeds\n",
+        )
+        .unwrap();
+        let names = |extra: &str| -> Vec<String> {
+            let args =
+                split_command_line(&format!("chores --ascii --expand-done-last {extra}")).unwrap();
+            let (out, _) = cmd_list(&lists, &args, &Config::default()).unwrap();
+            out.lines()
+                .skip(1)
+                .map(|line| line.trim_end().to_string())
+                .collect()
+        };
+
+        // garden stays where it was while the items move round it
+        assert_eq!(
+            names(""),
+            [
+                "[ ]    dishes",
+                "[ ]    laundry",
+                "[ ]    garden:",
+                "[ ]        weeds",
+                "[x]    hoover",
+                "[x]    bins",
+            ]
+        );
+        assert_eq!(
+            names("--lists-last --sort name"),
+            [
+                "[ ]    dishes",
+                "[ ]    laundry",
+                "[x]    bins",
+                "[x]    hoover",
+                "[ ]    garden:",
+                "[ ]        weeds",
+            ]
+        );
+        // still numbered by where they are in the file
+        assert_eq!(names("--numbered")[4], "[x]    [0] hoover");
+    }
+
+    #[test]
+    fn append_only_keeps_the_history() {
+        let config = Config {
+            append_only: true,
+            audit_log: true,
+            ..Config::default()
+        };
+        assert_eq!(
+            cmd_history(&["--clear".to_string()], &config).unwrap_err(),
+            "'history --clear' is blocked by append-only mode. Pass --force to run it anyway"
+        );
+        assert!(READ_ONLY_COMMANDS.contains(&"history"));
+    }
+
+    #[test]
+    fn ticking_off_steps() {
+        let mut lists = parser::parse_str("trip:\n\t- pack\n\t- book the train\n").unwrap();
+        for step in ["passport", "charger", "socks"] {
+            let args = split_command_line(&format!("trip pack {step}")).unwrap();
+            assert!(cmd_subadd(&mut lists, &args).unwrap().1);
+        }
+        let tick = |lists: &mut Vec<TodoList>, n: &str| {
+            cmd_subdone(lists, &["trip".into(), "pa".into(), n.into()])
+        };
+        tick(&mut lists, "0").unwrap();
+        tick(&mut lists, "2").unwrap();
+        // and back again
+        tick(&mut lists, "2").unwrap();
+        assert!(tick(&mut lists, "3").is_err());
+
+        let args = split_command_line("trip --ascii --numbered").unwrap();
+        let out = cmd_list(&lists, &args, &Config::default()).unwrap().0;
+        let lines: Vec<&str> = out.lines().map(str::trim_end).collect();
+        assert_eq!(
+            lines,
+            [
+                "[ ]trip:",
+                "[ ]    [0] pack [1/3]",
+                "[x]        [0] passport",
+                "[ ]        [1] charger",
+                "[ ]        [2] socks",
+                "[ ]    [1] book the train",
+            ]
+        );
+    }
+
+    #[test]
+    fn lists_as_a_tree() {
+        let mut lists = parser::parse_str(concat!(
+            "home:\n\t= garden\n\t- hoover\n\t= bills\n",
+            "garden:\n\t= shed\n",
+            "shed:\n",
+            "work:\n\t= bills\n",
+            "bills:\n",
+            "ping:\n\t= pong\n",
+            "pong:\n\t= ping\n",
+        ))
+        .unwrap();
+        let (tree, _) = cmd_lists(&mut lists, &["--tree".to_string()], &Config::default()).unwrap();
+        // bills is only gone into the first time, and ping and pong are only in each other
+        assert_eq!(
+            tree,
+            concat!(
+                "home\n",
+                "├── garden\n",
+                "│   └── shed\n",
+                "└── bills\n",
+                "work\n",
+                "└── bills ↻\n",
+                "ping\n",
+                "└── pong\n",
+                "    └── ping ↻\n",
+            )
+        );
+        let config = Config {
+            ascii: true,
+            ..Config::default()
+        };
+        let (tree, _) = cmd_lists(&mut lists, &["--tree".to_string()], &config).unwrap();
+        assert_eq!(tree.lines().nth(5), Some("`-- bills (again)"));
+    }
+
+    #[test]
+    fn fix_tidies_up_a_messy_file() {
+        let messy = concat!(
+            "home :\n\t- \"hoover \"\n\t- \"hoover \"\n\t+ \"hoover \"\n\t= gard\n\t= garden\n\t= nowhere\n",
+            "garden:\n\t- weeds\n\t= home\n",
+            "work:\n\t= home \n",
+        );
+        let mut lists = parser::parse_str(messy).unwrap();
+        let nowhere = Path::new("/nonexistent/todo.txt");
+        let config = Config::default();
+
+        let (preview, modified) =
+            cmd_fix(&mut lists, &["--dry-run".to_string()], nowhere, &config).unwrap();
+        assert!(!modified);
+        assert_eq!(parser::emit_str(&lists), messy);
+
+        let (report, modified) = cmd_fix(&mut lists, &[], nowhere, &config).unwrap();
+        assert!(modified);
+        assert_eq!(
+            report,
+            concat!(
+                "Renamed the list 'home ' to 'home'\n",
+                "Renamed 'hoover ' in 'home' to 'hoover'\n",
+                "Renamed 'hoover ' in 'home' to 'hoover'\n",
+                "Renamed 'hoover ' in 'home' to 'hoover'\n",
+                "Changed the sublist 'gard' in 'home' to 'garden'\n",
+                "Took 'nowhere' out of 'home', as it isn't a list\n",
+                "Took 'home' out of 'garden', since 'garden' is inside 'home'\n",
+                "Removed a copy of 'hoover' from 'home'\n",
+                "Removed a copy of 'garden' from 'home'\n",
+            )
+        );
+        assert_eq!(
+            preview,
+            format!("Would have changed (nothing has been saved):\n{report}")
+        );
+        // the done hoover isn't a copy, and work's reference followed home's new name
+        assert_eq!(
+            parser::emit_str(&lists),
+            "home:\n\t- hoover\n\t+ hoover\n\t= garden\ngarden:\n\t- weeds\nwork:\n\t= home\n"
+        );
+        assert_eq!(
+            cmd_fix(&mut lists, &[], nowhere, &config).unwrap(),
+            ("Nothing to fix\n".to_string(), false)
+        );
+    }
+
+    #[test]
+    fn priorities_as_exclamation_marks() {
+        let lists = parser::parse_str(concat!(
+            "symbols:\n\t- none\n\t- [priority=1] low\n\t- [priority=3] urgent\n",
+            "\t- [priority=7] very urgent\n\t+ [priority=3] finished\n",
+            "\t- [priority=2] [progress=50] halfway\n\t\t+ step\n",
+        ))
+        .unwrap();
+        let expected = [
+            ("", ""),
+            (" !", " [p1]"),
+            (" !!!", " [p3]"),
+            (" !7", " [p7]"),
+            // done items don't need it
+            ("", ""),
+            (" !! [50%] [1/1]", " [p2] [50%] [1/1]"),
+        ];
+        for (entry, (symbols, badge)) in lists[0].items.iter().zip(expected) {
+            let ListEntry::Item(item) = entry else {
+                unreachable!()
+            };
+            assert_eq!(item.name_suffix_with(true), symbols, "{}", item.name);
+            assert_eq!(item.name_suffix_with(false), badge, "{}", item.name);
+        }
+    }
+
+    #[test]
+    fn which_history_lines_are_about_a_forgotten_item() {
+        let lists =
+            parser::parse_str("chores:\n\t- fix the tap\n\t- a\nfixes:\n\t- fix\n").unwrap();
+        let names: HashSet<String> = ["fix", "a"].map(String::from).into();
+        let about = |line: &str| {
+            history_line_is_about(
+                &lists,
+                &format!("2024-05-15 10:00:00 sam: {line}"),
+                "chores",
+                &names,
+            )
+        };
+
+        assert!(about("done chores fix (Marked 'fix' as done)"));
+        assert!(about("add chores fix"));
+        assert!(about("add ch \"a\" 20/05/2024"));
+        assert!(about("remove chores --quiet a"));
+        // only part of a name, or another list
+        assert!(!about("done chores tap (Marked 'fix the tap' as done)"));
+        assert!(!about("add chores fix the tap"));
+        assert!(!about("done fixes fix (Marked 'fix' as done)"));
+        assert!(!about("add fixes fix"));
+        // a bracket inside the quotes isn't where what it said starts
+        assert!(!about("add chores \"a (b)\""));
+        assert_eq!(
+            split_history_line("add chores \"a (b)\" (Added 'x')"),
+            ("add chores \"a (b)\"", "Added 'x'")
+        );
+    }
+
+    #[test]
+    fn weeks_start_where_theyre_set_to() {
+        fixed_today();
+        let lists = parser::parse_str(concat!(
+            "plans:\n\t- @16/05/2024 thursday\n\t- @18/05/2024 saturday\n",
+            "\t- @19/05/2024 sunday\n\t- @20/05/2024 monday\n\t- @27/05/2024 the monday after\n",
+        ))
+        .unwrap();
+        let mondays = Config::default();
+        let sundays = Config {
+            week_start: chrono::Weekday::Sun,
+            ..Config::default()
+        };
+
+        let count = |line: &str, config: &Config| {
+            let args = split_command_line(line).unwrap();
+            cmd_timeperiods(&lists, &args, "week", config).unwrap().0
+        };
+        assert_eq!(count("plans --count", &mondays), "4\n");
+        assert_eq!(count("plans --count --calendar-week", &mondays), "3\n");
+        assert_eq!(count("plans --count --calendar-week", &sundays), "2\n");
+
+        let headings = |config: &Config| {
+            let args = split_command_line("plans --timeline").unwrap();
+            let out = cmd_list(&lists, &args, config).unwrap().0;
+            out.lines()
+                .filter(|line| !line.starts_with(' ') && line.ends_with(':'))
+                .map(str::to_owned)
+                .collect::<Vec<String>>()
+        };
+        assert_eq!(
+            headings(&mondays),
+            ["Tomorrow:", "Saturday:", "Sunday:", "Next week:", "Later:"]
+        );
+        // the sunday's in next week along with the monday
+        assert_eq!(
+            headings(&sundays),
+            ["Tomorrow:", "Saturday:", "Next week:", "Later:"]
+        );
+    }
+
+    #[test]
+    fn export_one_list_and_what_it_refers_to() {
+        let all = parser::parse_str(concat!(
+            "work:\n\t- email\n\t= project\n",
+            "project:\n\t- [priority=2] write it\n\t= reviews\n",
+            "reviews:\n\t+ first draft\n",
+            "home:\n\t- dishes\n\t= reviews\n",
+        ))
+        .unwrap();
+        let export = |line: &str| cmd_export(&all, &split_command_line(line).unwrap());
+
+        let (txt, _) = export("--txt --list work").unwrap();
+        // reviews comes along through project, and home's left behind
+        assert_eq!(
+            txt,
+            concat!(
+                "work:\n\t- email\n\t= project\n",
+                "project:\n\t- [priority=2] write it\n\t= reviews\n",
+                "reviews:\n\t+ first draft\n",
+            )
+        );
+        let (json, _) = export("--json --list work").unwrap();
+        assert!(
+            json.contains("\"reviews\"") && !json.contains("dishes"),
+            "{}",
+            json
+        );
+        let (ndjson, _) = export("--ndjson --list project").unwrap();
+        assert_eq!(ndjson.lines().count(), 2, "{ndjson}");
+        assert!(export("--txt --list nowhere").is_err());
+
+        // and it goes back in somewhere else without any loose ends
+        let path = std::env::temp_dir().join(format!("todo-export-{}.txt", std::process::id()));
+        std::fs::write(&path, &txt).unwrap();
+        let mut elsewhere = parser::parse_str("work:\n\t- timesheet\n").unwrap();
+        let imported = cmd_import(&mut elsewhere, path.to_str().unwrap());
+        std::fs::remove_file(&path).unwrap();
+        imported.unwrap();
+        assert_eq!(
+            parser::emit_str(&elsewhere[..1]),
+            "work:\n\t- timesheet\n\t- email\n\t= project\n"
+        );
+        let names: Vec<&str> = elsewhere.iter().map(|list| list.name.as_str()).collect();
+        assert_eq!(names, ["work", "project", "reviews"]);
     }
 }
@@ -1,4 +1,6 @@
-use crate::{ListEntry, ListItem, TodoList};
+use crate::{top_level_lists, ListEntry, ListItem, RepeatKind, TodoList};
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
 
 // fn parse_one_list(s: &str) -> TodoList {
 //     s.lines().map()
@@ -7,25 +9,125 @@ use crate::{ListEntry, ListItem, TodoList};
 #[derive(Debug)]
 pub struct ParseError(pub String);
 
+fn split_attribute(attr: &str, line_num: usize) -> Result<(&str, &str), ParseError> {
+    attr.split_once('=')
+        .ok_or_else(|| ParseError(format!("Expected '=' in attribute (line {line_num})")))
+}
+
+fn parse_attribute_date(value: &str, line_num: usize) -> Result<chrono::NaiveDate, ParseError> {
+    chrono::NaiveDate::parse_from_str(value, "%d/%m/%Y")
+        .map_err(|_| ParseError(format!("Invalid date '{value}' (line {line_num})")))
+}
+
 fn parse_text_item(line: &str, done: bool, line_num: usize) -> Result<ListEntry, ParseError> {
-    let (date, rest_of_line) = if line.starts_with('@') {
+    let mut item = ListItem {
+        done,
+        ..Default::default()
+    };
+    // optional [key=value] attributes come before the date
+    let mut line = line;
+    while let Some(rest) = line.strip_prefix('[') {
+        let (attr, rest) = rest.split_once(']').ok_or_else(|| {
+            ParseError(format!(
+                "Expected ']' after item attribute (line {line_num})"
+            ))
+        })?;
+        line = rest.trim_start();
+        let (key, value) = split_attribute(attr, line_num)?;
+        match key {
+            "every" => {
+                item.repeat = Some(RepeatKind::parse(value).map_err(|_| {
+                    ParseError(format!("Invalid repeat period '{value}' (line {line_num})"))
+                })?);
+            }
+            "id" => item.id = Some(value.to_owned()),
+            "label" => item.label = Some(value.to_owned()),
+            "url" => item.url = Some(value.to_owned()),
+            "trashed_from" => item.trashed_from = Some(unescape_attribute(value)),
+            "trashed_ref" => {
+                item.trashed_from = Some(unescape_attribute(value));
+                item.trashed_reference = true;
+            }
+            "completed" => item.completed = Some(parse_attribute_date(value, line_num)?),
+            "created" => item.created = Some(parse_attribute_date(value, line_num)?),
+            "priority" => {
+                item.priority = value.parse().map_err(|_| {
+                    ParseError(format!("Invalid priority '{value}' (line {line_num})"))
+                })?;
+            }
+            "progress" => {
+                item.progress =
+                    Some(value.parse().ok().filter(|p| *p <= 100).ok_or_else(|| {
+                        ParseError(format!("Invalid progress '{value}' (line {line_num})"))
+                    })?);
+            }
+            _ => {
+                return Err(ParseError(format!(
+                    "Unknown item attribute '{key}' (line {line_num})"
+                )))
+            }
+        }
+    }
+
+    let (date, rest_of_line) = if let Some(rest) = line.strip_prefix('@') {
         // parse the date
-        let date_str = &line[1..11]; // TODO this might cause problems
-        (
-            Some(
-                chrono::NaiveDate::parse_from_str(date_str, "%d/%m/%Y")
-                    .map_err(|_| ParseError(format!("Invalid date literal (line {line_num})")))?,
-            ),
-            &line[11..],
-        )
+        let parse = |s: &str| chrono::NaiveDate::parse_from_str(s, "%d/%m/%Y").ok();
+        let (date_str, name) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+        let (date, name) = match parse(date_str) {
+            Some(date) => (date, name.trim_start()),
+            // older versions didn't put a space between the date and the name
+            None => rest
+                .get(..10)
+                .and_then(parse)
+                .map(|date| (date, &rest[10..]))
+                .ok_or_else(|| ParseError(format!("Invalid date literal (line {line_num})")))?,
+        };
+        (Some(date), name)
     } else {
         (None, line)
     };
-    Ok(ListEntry::Item(ListItem {
-        name: rest_of_line.to_owned(),
-        date,
-        done,
-    }))
+    // a name in quotes is taken as it is, even if it looks like an attribute or a date
+    let name = unquote(rest_of_line)
+        .filter(|(_, rest)| rest.trim().is_empty())
+        .map_or_else(|| rest_of_line.to_owned(), |(name, _)| name);
+    Ok(ListEntry::Item(ListItem { name, date, ..item }))
+}
+
+// if `s` starts with a quoted string, returns what's in the quotes (with \" and \\ unescaped)
+// and whatever comes after them
+pub fn unquote(s: &str) -> Option<(String, &str)> {
+    let mut chars = s.strip_prefix('"')?.chars();
+    let mut acc = String::new();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => acc.push(chars.next()?),
+            '"' => return Some((acc, chars.as_str())),
+            c => acc.push(c),
+        }
+    }
+    None
+}
+
+// list names can have anything in them, but ] would end the attribute
+fn escape_attribute(value: &str) -> String {
+    value.replace('%', "%25").replace(']', "%5D")
+}
+
+fn unescape_attribute(value: &str) -> String {
+    value.replace("%5D", "]").replace("%25", "%")
+}
+
+// item names that would be read back as something else (or lose their whitespace) are
+// written in quotes
+fn quote_name(name: &str) -> Cow<'_, str> {
+    if name.starts_with(['[', '@', '"']) || name.trim() != name {
+        Cow::from(format!(
+            "\"{}\"",
+            name.replace('\\', "\\\\").replace('"', "\\\"")
+        ))
+    } else {
+        Cow::from(name)
+    }
 }
 
 fn parse_list_header(line: &str, line_num: usize) -> Result<TodoList, ParseError> {
@@ -37,13 +139,128 @@ fn parse_list_header(line: &str, line_num: usize) -> Result<TodoList, ParseError
         )));
     }
 
-    let item_name = line.trim_end();
+    // optional [key=value] attributes come after the ':'
+    let mut item_name = line.trim_end();
+    let mut attrs = Vec::new();
+    while let Some(rest) = item_name.strip_suffix(']') {
+        let (rest, attr) = rest.rsplit_once('[').ok_or_else(|| {
+            ParseError(format!(
+                "Expected '[' before list attribute (line {line_num})"
+            ))
+        })?;
+        attrs.push(attr);
+        item_name = rest.trim_end();
+    }
+
     if !item_name.ends_with(':') {
         return Err(ParseError(format!(
             "Expected ':' at end of list definition (line {line_num})",
         )));
     }
-    Ok(TodoList::new(item_name.trim_end_matches(':').to_owned()))
+    let mut list = TodoList::new(item_name.trim_end_matches(':').to_owned());
+    for attr in attrs {
+        let (key, value) = split_attribute(attr, line_num)?;
+        match key {
+            "due" => {
+                list.default_due = Some(value.parse().map_err(|_| {
+                    ParseError(format!(
+                        "Invalid default due offset '{value}' (line {line_num})"
+                    ))
+                })?);
+            }
+            "priority" => {
+                list.default_priority = value.parse().map_err(|_| {
+                    ParseError(format!(
+                        "Invalid default priority '{value}' (line {line_num})"
+                    ))
+                })?;
+            }
+            "id" => list.id = Some(value.to_owned()),
+            _ => {
+                return Err(ParseError(format!(
+                    "Unknown list attribute '{key}' (line {line_num})"
+                )))
+            }
+        }
+    }
+    Ok(list)
+}
+
+// things that parse_str lets through, because older versions wrote them or because they don't
+// stop anything working, but which --strict doesn't. all of them, rather than just the first.
+// `s` has to have parsed already
+pub fn strict_problems(s: &str) -> Vec<String> {
+    let mut problems: Vec<(usize, String)> = Vec::new();
+    let mut headers: HashMap<String, usize> = HashMap::new();
+    let mut references = Vec::new();
+    for (line_num, line) in s.lines().enumerate() {
+        let line_num = line_num + 1;
+        let mut problem = |problem: String| problems.push((line_num, problem));
+        if line.trim().is_empty() {
+            continue;
+        }
+        if !line.starts_with(char::is_whitespace) {
+            let Ok(list) = parse_list_header(line, line_num) else {
+                continue;
+            };
+            if let Some(first) = headers.insert(list.name.clone(), line_num) {
+                problem(format!(
+                    "List '{}' was already defined on line {first}",
+                    list.name
+                ));
+            }
+            continue;
+        }
+        let line = line.trim_start();
+        let (init, rest) = line.split_at(1);
+        let rest = rest.trim_start();
+        if init == "=" {
+            references.push((rest, line_num));
+            continue;
+        }
+        let Ok(ListEntry::Item(item)) = parse_text_item(rest, init == "+", line_num) else {
+            continue;
+        };
+        let mut after_attributes = rest;
+        while after_attributes.starts_with('[') {
+            after_attributes = after_attributes
+                .split_once(']')
+                .map_or("", |(_, rest)| rest.trim_start());
+        }
+        if let Some(date) = after_attributes.strip_prefix('@') {
+            let date = date.split(char::is_whitespace).next().unwrap_or_default();
+            if chrono::NaiveDate::parse_from_str(date, "%d/%m/%Y").is_err() {
+                problem("Expected a space between the date and the name".to_string());
+            }
+        }
+        if item.name.is_empty() {
+            problem("Item with no name".to_string());
+        }
+        if let Some(Err(e)) = item.label.as_deref().map(crate::label_colour) {
+            problem(e);
+        }
+        if let Some(Err(e)) = item.url.as_deref().map(crate::check_url) {
+            problem(e);
+        }
+        if item.repeat.is_some() && item.date.is_none() {
+            problem("Repeating item with no date".to_string());
+        }
+        if item.completed.is_some() && !item.done {
+            problem("Item that isn't done has a completed date".to_string());
+        }
+    }
+    // sublists are normally found by the start of their name like everything else, but
+    // strictly they should be the whole thing
+    for (name, line_num) in references {
+        if !headers.contains_key(name) {
+            problems.push((line_num, format!("'{name}' isn't the name of a list")));
+        }
+    }
+    problems.sort_by_key(|(line_num, _)| *line_num);
+    problems
+        .into_iter()
+        .map(|(line_num, problem)| format!("{problem} (line {line_num})"))
+        .collect()
 }
 
 pub fn parse_str(s: &str) -> Result<Vec<TodoList>, ParseError> {
@@ -57,10 +274,26 @@ pub fn parse_str(s: &str) -> Result<Vec<TodoList>, ParseError> {
             continue;
         }
         if line.chars().next().is_some_and(char::is_whitespace) {
+            let raw = line;
             let line = line.trim_start();
             let (init, rest) = line.split_at(1);
             let rest = rest.trim_start();
 
+            // a step in the checklist of the item before it is indented one more level
+            if raw.starts_with("\t\t") && matches!(init, "-" | "+") {
+                let Some(ListEntry::Item(item)) =
+                    res.last_mut().and_then(|list| list.items.last_mut())
+                else {
+                    return Err(ParseError(format!(
+                        "Expected an item before the step in its checklist (line {line_num})"
+                    )));
+                };
+                let text = unquote(rest)
+                    .filter(|(_, after)| after.trim().is_empty())
+                    .map_or_else(|| rest.to_owned(), |(text, _)| text);
+                item.subitems.push((text, init == "+"));
+                continue;
+            }
             let item = match init {
                 "-" => parse_text_item(rest, false, line_num),
                 "+" => parse_text_item(rest, true, line_num),
@@ -70,36 +303,96 @@ pub fn parse_str(s: &str) -> Result<Vec<TodoList>, ParseError> {
                     )))
             }?;
             res.last_mut()
-                .ok_or(ParseError(format!(
-                    "Expected list header before item (line {line_num})"
-                )))?
+                .ok_or_else(|| {
+                    ParseError(format!(
+                        "Expected list header before item (line {line_num})"
+                    ))
+                })?
                 .items
                 .push(item);
         } else {
-            res.push(parse_list_header(line, line_num + 1)?);
+            res.push(parse_list_header(line, line_num)?);
         }
     }
     Ok(res)
 }
 
 fn serialise_list(list: &TodoList) -> String {
-    list.items
-        .iter()
-        .fold(list.name.clone() + ":\n", |mut acc, item| {
-            acc += "\t";
-            acc += &match item {
-                ListEntry::List(name) => format!("= {name}"),
-                ListEntry::Item(item) => format!(
-                    "{} {}{}",
-                    if item.done { "+" } else { "-" },
-                    item.date
-                        .map_or_else(String::new, |date| format!("@{}", date.format("%d/%m/%Y"))),
-                    &item.name
-                ),
-            };
-            acc += "\n";
-            acc
-        })
+    use std::fmt::Write;
+    let mut header = list.name.clone() + ":";
+    if let Some(due) = list.default_due {
+        write!(header, " [due={due}]").unwrap();
+    }
+    if list.default_priority != 0 {
+        write!(header, " [priority={}]", list.default_priority).unwrap();
+    }
+    if let Some(id) = &list.id {
+        write!(header, " [id={id}]").unwrap();
+    }
+    list.items.iter().fold(header + "\n", |mut acc, item| {
+        match item {
+            ListEntry::List(name) => writeln!(acc, "\t= {name}"),
+            ListEntry::Item(item) => writeln!(
+                acc,
+                "\t{} {}{}{}",
+                if item.done { "+" } else { "-" },
+                item_attributes(item),
+                item.date
+                    .map_or_else(String::new, |date| format!("@{} ", date.format("%d/%m/%Y"))),
+                quote_name(&item.name)
+            )
+            .and_then(|()| {
+                item.subitems.iter().try_for_each(|(text, done)| {
+                    writeln!(
+                        acc,
+                        "\t\t{} {}",
+                        if *done { "+" } else { "-" },
+                        quote_name(text)
+                    )
+                })
+            }),
+        }
+        .unwrap();
+        acc
+    })
+}
+
+fn item_attributes(item: &ListItem) -> String {
+    use std::fmt::Write;
+    let mut acc = String::new();
+    if let Some(id) = &item.id {
+        write!(acc, "[id={id}] ").unwrap();
+    }
+    if let Some(repeat) = item.repeat {
+        write!(acc, "[every={repeat}] ").unwrap();
+    }
+    if let Some(label) = &item.label {
+        write!(acc, "[label={label}] ").unwrap();
+    }
+    if item.priority > 0 {
+        write!(acc, "[priority={}] ", item.priority).unwrap();
+    }
+    if let Some(progress) = item.progress {
+        write!(acc, "[progress={progress}] ").unwrap();
+    }
+    if let Some(completed) = item.completed {
+        write!(acc, "[completed={}] ", completed.format("%d/%m/%Y")).unwrap();
+    }
+    if let Some(created) = item.created {
+        write!(acc, "[created={}] ", created.format("%d/%m/%Y")).unwrap();
+    }
+    if let Some(url) = &item.url {
+        write!(acc, "[url={url}] ").unwrap();
+    }
+    if let Some(from) = &item.trashed_from {
+        let key = if item.trashed_reference {
+            "trashed_ref"
+        } else {
+            "trashed_from"
+        };
+        write!(acc, "[{key}={}] ", escape_attribute(from)).unwrap();
+    }
+    acc
 }
 
 pub fn emit_str(ls: &[TodoList]) -> String {
@@ -108,3 +401,613 @@ pub fn emit_str(ls: &[TodoList]) -> String {
         acc
     })
 }
+
+pub fn json_string(s: &str) -> String {
+    use std::fmt::Write;
+    let mut acc = String::from('"');
+    for c in s.chars() {
+        match c {
+            '"' => acc += "\\\"",
+            '\\' => acc += "\\\\",
+            '\n' => acc += "\\n",
+            '\t' => acc += "\\t",
+            c if c.is_control() => write!(acc, "\\u{:04x}", c as u32).unwrap(),
+            c => acc.push(c),
+        }
+    }
+    acc.push('"');
+    acc
+}
+
+fn item_to_json(item: &ListItem) -> String {
+    format!(
+        "{{\"type\":\"item\",\"id\":{},\"name\":{},\"done\":{},\"date\":{},\"repeat_every\":{},\"repeat\":{},\"label\":{},\"progress\":{},\"completed\":{},\"created\":{},\"priority\":{},\"url\":{},\"subitems\":[{}]}}",
+        item.id
+            .as_deref()
+            .map_or_else(|| "null".to_string(), json_string),
+        json_string(&item.name),
+        item.done,
+        item.date.map_or_else(
+            || "null".to_string(),
+            |date| json_string(&date.format("%Y-%m-%d").to_string())
+        ),
+        // repeat_every is kept for anything that already reads it
+        item.repeat.map_or(0, RepeatKind::days),
+        item.repeat
+            .map_or_else(|| "null".to_string(), |repeat| json_string(&repeat.to_string())),
+        item.label
+            .as_deref()
+            .map_or_else(|| "null".to_string(), json_string),
+        item.progress
+            .map_or_else(|| "null".to_string(), |progress| progress.to_string()),
+        item.completed.map_or_else(
+            || "null".to_string(),
+            |date| json_string(&date.format("%Y-%m-%d").to_string())
+        ),
+        item.created.map_or_else(
+            || "null".to_string(),
+            |date| json_string(&date.format("%Y-%m-%d").to_string())
+        ),
+        item.priority,
+        item.url
+            .as_deref()
+            .map_or_else(|| "null".to_string(), json_string),
+        item.subitems
+            .iter()
+            .map(|(text, done)| format!("{{\"name\":{},\"done\":{done}}}", json_string(text)))
+            .collect::<Vec<String>>()
+            .join(",")
+    )
+}
+
+pub fn list_to_json(list: &TodoList) -> String {
+    let items = list
+        .items
+        .iter()
+        .map(|item| match item {
+            ListEntry::List(name) => {
+                format!("{{\"type\":\"list\",\"name\":{}}}", json_string(name))
+            }
+            ListEntry::Item(item) => item_to_json(item),
+        })
+        .collect::<Vec<String>>();
+    format!(
+        "{{\"id\":{},\"name\":{},\"default_due\":{},\"default_priority\":{},\"items\":[{}]}}",
+        list.id
+            .as_deref()
+            .map_or_else(|| "null".to_string(), json_string),
+        json_string(&list.name),
+        list.default_due
+            .map_or_else(|| "null".to_string(), |due| due.to_string()),
+        list.default_priority,
+        items.join(",")
+    )
+}
+
+// strings are always quoted, using the JSON escapes, which YAML understands too
+fn item_to_yaml(item: &ListItem) -> String {
+    use std::fmt::Write;
+    format!(
+        "    - type: item\n      id: {}\n      name: {}\n      done: {}\n      date: {}\n      repeat_every: {}\n      repeat: {}\n      label: {}\n      progress: {}\n      completed: {}\n      created: {}\n      priority: {}\n      url: {}\n      subitems:{}\n",
+        item.id
+            .as_deref()
+            .map_or_else(|| "null".to_string(), json_string),
+        json_string(&item.name),
+        item.done,
+        item.date
+            .map_or_else(|| "null".to_string(), |date| date.format("%Y-%m-%d").to_string()),
+        // repeat_every is kept for anything that already reads it
+        item.repeat.map_or(0, RepeatKind::days),
+        item.repeat
+            .map_or_else(|| "null".to_string(), |repeat| json_string(&repeat.to_string())),
+        item.label
+            .as_deref()
+            .map_or_else(|| "null".to_string(), json_string),
+        item.progress
+            .map_or_else(|| "null".to_string(), |progress| progress.to_string()),
+        item.completed
+            .map_or_else(|| "null".to_string(), |date| date.format("%Y-%m-%d").to_string()),
+        item.created
+            .map_or_else(|| "null".to_string(), |date| date.format("%Y-%m-%d").to_string()),
+        item.priority,
+        item.url
+            .as_deref()
+            .map_or_else(|| "null".to_string(), json_string),
+        if item.subitems.is_empty() {
+            " []".to_string()
+        } else {
+            item.subitems
+                .iter()
+                .fold(String::new(), |mut acc, (text, done)| {
+                    let _ = write!(
+                        acc,
+                        "\n        - name: {}\n          done: {done}",
+                        json_string(text)
+                    );
+                    acc
+                })
+        }
+    )
+}
+
+fn list_to_yaml(list: &TodoList) -> String {
+    use std::fmt::Write;
+    let mut acc = format!(
+        "- id: {}\n  name: {}\n  default_due: {}\n  default_priority: {}\n  items:",
+        list.id
+            .as_deref()
+            .map_or_else(|| "null".to_string(), json_string),
+        json_string(&list.name),
+        list.default_due
+            .map_or_else(|| "null".to_string(), |due| due.to_string()),
+        list.default_priority,
+    );
+    if list.items.is_empty() {
+        acc += " []";
+    }
+    acc.push('\n');
+    for item in &list.items {
+        match item {
+            ListEntry::List(name) => {
+                writeln!(acc, "    - type: list\n      name: {}", json_string(name)).unwrap();
+            }
+            ListEntry::Item(item) => acc += &item_to_yaml(item),
+        }
+    }
+    acc
+}
+
+pub fn emit_yaml(ls: &[TodoList]) -> String {
+    if ls.is_empty() {
+        return "[]\n".to_string();
+    }
+    ls.iter().map(list_to_yaml).collect()
+}
+
+pub fn emit_json(ls: &[TodoList]) -> String {
+    format!(
+        "[{}]",
+        ls.iter()
+            .map(list_to_json)
+            .collect::<Vec<String>>()
+            .join(",")
+    )
+}
+
+pub fn csv_field(s: &str) -> Cow<'_, str> {
+    if s.contains([',', '"', '\n', '\r']) {
+        Cow::from(format!("\"{}\"", s.replace('"', "\"\"")))
+    } else {
+        Cow::from(s)
+    }
+}
+
+// the fields in a line written with csv_field
+pub fn split_csv_line(line: &str) -> Vec<String> {
+    let mut fields = vec![String::new()];
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        let field = fields.last_mut().unwrap();
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(String::new()),
+            c => field.push(c),
+        }
+    }
+    fields
+}
+
+// every item, with the path to the list it's in. each list is only gone through once,
+// starting from the ones that aren't in another list
+pub fn all_items_with_paths(ls: &[TodoList]) -> Vec<(String, &ListItem)> {
+    let mut visited = HashSet::new();
+    let mut items = Vec::new();
+    for list in top_level_lists(ls).into_iter().chain(ls) {
+        list.walk_items_with_path_inner(ls, &list.name, &mut visited, &mut items);
+    }
+    items
+}
+
+// one JSON object per line for each item, with the path to the list it's in, for jq, log
+// processors and the like
+pub fn emit_ndjson(items: &[(String, &ListItem)]) -> String {
+    use std::fmt::Write;
+    let mut acc = String::new();
+    for (path, item) in items {
+        // item_to_json never has a newline in it, since json_string escapes them
+        let json = item_to_json(item);
+        writeln!(acc, "{{\"list_path\":{},{}", json_string(path), &json[1..]).unwrap();
+    }
+    acc
+}
+
+// one row for each item, with the path to the list it's in
+pub fn emit_csv(ls: &[TodoList]) -> String {
+    use std::fmt::Write;
+    let items = all_items_with_paths(ls);
+    let date = |date: Option<chrono::NaiveDate>| {
+        date.map_or_else(String::new, |date| date.format("%Y-%m-%d").to_string())
+    };
+    let mut acc = String::from("list_path,name,done,priority,date,created,completed,label,url\n");
+    for (path, item) in items {
+        writeln!(
+            acc,
+            "{},{},{},{},{},{},{},{},{}",
+            csv_field(&path),
+            csv_field(&item.name),
+            item.done,
+            item.priority,
+            date(item.date),
+            date(item.created),
+            date(item.completed),
+            item.label.as_deref().unwrap_or(""),
+            csv_field(item.url.as_deref().unwrap_or(""))
+        )
+        .unwrap();
+    }
+    acc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repeat_period_round_trips() {
+        let text = "chores:\n\t- [every=7] @06/05/2024 take out the bins\n\t+ water plants\n";
+        let lists = parse_str(text).unwrap();
+        let ListEntry::Item(bins) = &lists[0].items[0] else {
+            panic!("expected an item");
+        };
+        assert_eq!(bins.repeat, Some(crate::RepeatKind::EveryNDays(7)));
+        assert_eq!(bins.name.trim(), "take out the bins");
+        assert_eq!(emit_str(&lists), text);
+    }
+
+    #[test]
+    fn bad_attributes() {
+        for line in [
+            "[every=often] soon",
+            "[every=3 nothing",
+            "[when=3] x",
+            "[every] x",
+        ] {
+            let text = format!("chores:\n\t- {line}\n");
+            assert!(parse_str(&text).is_err(), "{}", line);
+        }
+    }
+
+    #[test]
+    fn default_due_on_the_header() {
+        let text = "urgent: [due=1]\n\t- passport\nlater:\n";
+        let lists = parse_str(text).unwrap();
+        assert_eq!(lists[0].name, "urgent");
+        assert_eq!(lists[0].default_due, Some(1));
+        assert_eq!(lists[1].default_due, None);
+        assert_eq!(emit_str(&lists), text);
+        assert!(parse_str("urgent: [due=soon]\n").is_err());
+    }
+
+    #[test]
+    fn labels_in_text_and_json() {
+        let text = "board:\n\t- [every=2] [label=green] water the cactus\n\t- [label=red] @01/02/2030 rent\n";
+        let lists = parse_str(text).unwrap();
+        assert_eq!(emit_str(&lists), text);
+        let json = emit_json(&lists);
+        assert!(
+            json.contains("\"repeat_every\":2,\"repeat\":\"2\",\"label\":\"green\""),
+            "{}",
+            json
+        );
+        assert!(json.contains("\"label\":\"red\""));
+
+        let unlabelled = parse_str("board:\n\t- nothing\n").unwrap();
+        assert!(emit_json(&unlabelled).contains("\"label\":null"));
+    }
+
+    #[test]
+    fn header_errors_have_the_right_line() {
+        let err = parse_str("fine:\n\t- ok\nbroken: [colour=blue]\n").unwrap_err();
+        assert!(err.0.ends_with("(line 3)"), "{}", err.0);
+        let err = parse_str("\n\tnot a list\n").unwrap_err();
+        assert!(err.0.ends_with("(line 2)"), "{}", err.0);
+    }
+
+    #[test]
+    fn yaml() {
+        assert_eq!(emit_yaml(&[]), "[]\n");
+        let lists =
+            parse_str("a \"quoted\" list:\n\t+ [label=cyan] @29/02/2024 leap\n\t= empty\nempty:\n")
+                .unwrap();
+        let yaml = emit_yaml(&lists);
+        assert!(yaml.starts_with(
+            "- id: null\n  name: \"a \\\"quoted\\\" list\"\n  default_due: null\n  default_priority: 0\n  items:\n    - type: item\n"
+        ));
+        for field in [
+            "      name: \"leap\"\n",
+            "      done: true\n",
+            "      date: 2024-02-29\n",
+            "      label: \"cyan\"\n",
+        ] {
+            assert!(yaml.contains(field), "{}", field);
+        }
+        assert!(yaml.ends_with(concat!(
+            "    - type: list\n",
+            "      name: \"empty\"\n",
+            "- id: null\n",
+            "  name: \"empty\"\n",
+            "  default_due: null\n",
+            "  default_priority: 0\n",
+            "  items: []\n",
+        )));
+    }
+
+    #[test]
+    fn progress_attribute() {
+        let text = "thesis:\n\t- [progress=45] chapter two\n\t+ [progress=100] chapter one\n";
+        let lists = parse_str(text).unwrap();
+        assert_eq!(emit_str(&lists), text);
+        assert!(emit_json(&lists).contains("\"progress\":45,"));
+        for bad in ["101", "-1", "half", ""] {
+            let text = format!("thesis:\n\t- [progress={bad}] chapter three\n");
+            let err = parse_str(&text).unwrap_err();
+            assert_eq!(err.0, format!("Invalid progress '{bad}' (line 2)"));
+        }
+    }
+
+    #[test]
+    fn date_is_separate_from_the_name() {
+        let lists = parse_str("shopping:\n\t-@01/01/2024 buy milk\n").unwrap();
+        let [ListEntry::Item(item)] = lists[0].items.as_slice() else {
+            panic!("expected one item, got {:?}", lists[0].items);
+        };
+        assert_eq!(item.name, "buy milk");
+        assert_eq!(item.date, chrono::NaiveDate::from_ymd_opt(2024, 1, 1));
+
+        let text = emit_str(&lists);
+        assert_eq!(text, "shopping:\n\t- @01/01/2024 buy milk\n");
+        assert_eq!(emit_str(&parse_str(&text).unwrap()), text);
+        // files from before there was a space still load
+        assert_eq!(
+            emit_str(&parse_str("shopping:\n\t- @01/01/2024buy milk\n").unwrap()),
+            text
+        );
+    }
+
+    #[test]
+    fn odd_dates_are_errors_not_panics() {
+        for line in ["- @01/01", "- @", "- @01/01/20ü4 x", "- @ü1/01/2024milk"] {
+            let err = parse_str(&format!("list:\n\t{line}\n")).unwrap_err();
+            assert_eq!(err.0, "Invalid date literal (line 2)");
+        }
+        let lists = parse_str("list:\n\t- @01/01/2024 café crème\n").unwrap();
+        assert_eq!(lists[0].items[0].name(), "café crème");
+    }
+
+    #[test]
+    fn completed_dates() {
+        let text = "done:\n\t+ [completed=03/02/2024] @01/02/2024 taxes\n";
+        let lists = parse_str(text).unwrap();
+        assert_eq!(emit_str(&lists), text);
+        let json = emit_json(&lists);
+        assert!(json.contains("\"completed\":\"2024-02-03\""), "{}", json);
+        assert!(emit_yaml(&lists).contains("      completed: 2024-02-03\n"));
+
+        let err = parse_str("done:\n\t+ [completed=yesterday] taxes\n").unwrap_err();
+        assert_eq!(err.0, "Invalid date 'yesterday' (line 2)");
+    }
+
+    #[test]
+    fn names_that_look_like_something_else_round_trip() {
+        let names = [
+            "- starts with a dash",
+            "+ starts with a plus",
+            "= starts with an equals",
+            "=> literal name",
+            "@01/01/2024 looks like a date",
+            "[priority=3] looks like an attribute",
+            "\"quoted\" already",
+            "back\\slash \"and quotes\"",
+            "  spaces around  ",
+        ];
+        let mut list = TodoList::new("quoting".to_owned());
+        list.items.extend(names.iter().map(|name| {
+            ListEntry::Item(ListItem {
+                name: (*name).to_owned(),
+                ..Default::default()
+            })
+        }));
+        let text = emit_str(&[list]);
+        assert!(
+            text.contains("\t- \"@01/01/2024 looks like a date\"\n"),
+            "{}",
+            text
+        );
+        assert!(text.contains("\t- - starts with a dash\n"), "{}", text);
+
+        let lists = parse_str(&text).unwrap();
+        for (entry, name) in lists[0].items.iter().zip(names) {
+            let ListEntry::Item(item) = entry else {
+                panic!("'{}' came back as a list", name);
+            };
+            assert_eq!(
+                (item.name.as_str(), item.date, item.priority),
+                (name, None, 0)
+            );
+        }
+        assert_eq!(lists[0].items.len(), names.len());
+
+        let lists = parse_str("quoting:\n\t+ [priority=2] @01/01/2024 \"= not a list\"\n").unwrap();
+        let ListEntry::Item(item) = &lists[0].items[0] else {
+            panic!("not an item");
+        };
+        assert_eq!(item.name, "= not a list");
+        assert!(item.done && item.priority == 2);
+        assert_eq!(item.date, chrono::NaiveDate::from_ymd_opt(2024, 1, 1));
+        // an unfinished quote is just part of the name
+        assert_eq!(
+            parse_str("quoting:\n\t- \"half open\n").unwrap()[0].items[0].name(),
+            "\"half open"
+        );
+    }
+
+    #[test]
+    fn csv_rows_have_paths_and_quoting() {
+        let mut lists = parse_str(concat!(
+            "work:\n\t- [priority=2] [url=https://example.com/?q=a,b] @03/04/2024 report\n\t= home\n",
+            "errands:\n\t= home\n",
+            "home:\n\t+ [completed=01/04/2024] [label=blue] fix tap\n\t- placeholder\n",
+        ))
+        .unwrap();
+        lists[2].items[1] = ListEntry::Item(ListItem {
+            name: "say \"hi\", then\nleave".to_owned(),
+            ..Default::default()
+        });
+
+        let csv = emit_csv(&lists);
+        let mut rows = csv.split_inclusive('\n');
+        assert_eq!(
+            rows.next(),
+            Some("list_path,name,done,priority,date,created,completed,label,url\n")
+        );
+        assert_eq!(
+            rows.collect::<String>(),
+            concat!(
+                "work,report,false,2,2024-04-03,,,,\"https://example.com/?q=a,b\"\n",
+                "work/home,fix tap,true,0,,,2024-04-01,blue,\n",
+                "work/home,\"say \"\"hi\"\", then\nleave\",false,0,,,,,\n",
+            )
+        );
+        // home's items only turn up under the first list it's reached through
+        assert!(!csv.contains("errands"));
+        assert_eq!(emit_csv(&[]).lines().count(), 1);
+    }
+
+    #[test]
+    fn csv_lines_split_back_into_fields() {
+        let fields = ["plain", "a, b", "say \"hi\"", "", "\"", ",,"];
+        let line = fields.map(csv_field).join(",");
+        assert_eq!(line, "plain,\"a, b\",\"say \"\"hi\"\"\",,\"\"\"\",\",,\"");
+        assert_eq!(split_csv_line(&line), fields);
+        assert_eq!(split_csv_line(""), [""]);
+    }
+
+    #[test]
+    fn ndjson_has_a_line_per_item() {
+        let mut lists = parse_str(concat!(
+            "garden:\n\t- [priority=1] mow\n\t= shed\n",
+            "shed:\n\t+ @02/03/2024 oil the hinges\n\t- placeholder\n",
+        ))
+        .unwrap();
+        lists[1].items[1] = ListEntry::Item(ListItem {
+            name: "two\nlines".to_owned(),
+            ..Default::default()
+        });
+
+        let out = emit_ndjson(&all_items_with_paths(&lists));
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with(
+            "{\"list_path\":\"garden\",\"type\":\"item\",\"id\":null,\"name\":\"mow\","
+        ));
+        assert!(lines[0].contains("\"priority\":1"));
+        assert!(lines[1].starts_with("{\"list_path\":\"garden/shed\",\"type\":\"item\",\"id\":null,\"name\":\"oil the hinges\",\"done\":true,\"date\":\"2024-03-02\""));
+        assert!(lines[2].contains("\"name\":\"two\\nlines\""));
+        for line in lines {
+            assert!(line.starts_with('{') && line.ends_with('}'), "{}", line);
+        }
+        assert_eq!(emit_ndjson(&[]), "");
+    }
+
+    #[test]
+    fn anchored_repeats_in_the_file_and_exports() {
+        let lists =
+            parse_str("bills:\n\t- [every=1st] rent\n\t- [every=friday] payslip\n").unwrap();
+        assert_eq!(
+            emit_str(&lists),
+            "bills:\n\t- [every=1st] rent\n\t- [every=fri] payslip\n"
+        );
+        let json = emit_json(&lists);
+        // repeat_every is the number of days, if there is one
+        assert!(
+            json.contains("\"repeat_every\":0,\"repeat\":\"1st\""),
+            "{}",
+            json
+        );
+        assert!(
+            json.contains("\"repeat_every\":7,\"repeat\":\"fri\""),
+            "{}",
+            json
+        );
+        assert!(emit_yaml(&lists).contains("      repeat_every: 0\n      repeat: \"1st\"\n"));
+        assert!(parse_str("bills:\n\t- [every=32nd] rent\n").is_err());
+    }
+
+    #[test]
+    fn strict_finds_everything_lenient_lets_through() {
+        let text = concat!(
+            "home:\n",
+            "\t- @01/01/2024buy milk\n",
+            "\t- [label=mauve] paint\n",
+            "\t- [every=weekly] water plants\n",
+            "\t- [completed=02/01/2024] not really done\n",
+            "\t= gard\n",
+            "\t- [url=example.com] look it up\n",
+            "garden:\n",
+            "home:\n",
+            "\t- fine\n",
+        );
+        // it all loads normally
+        assert!(parse_str(text).is_ok());
+        assert_eq!(
+            strict_problems(text),
+            [
+                "Expected a space between the date and the name (line 2)",
+                "Unknown colour 'mauve', expected one of red, green, yellow, blue, magenta, cyan, white (line 3)",
+                "Repeating item with no date (line 4)",
+                "Item that isn't done has a completed date (line 5)",
+                "'gard' isn't the name of a list (line 6)",
+                "Invalid URL 'example.com', expected something like https://example.com. Use %5D for ] (line 7)",
+                "List 'home' was already defined on line 1 (line 9)",
+            ]
+        );
+        let tidy = "home:\n\t- @01/01/2024 buy milk\n\t+ [completed=02/01/2024] done\n\t= garden\ngarden:\n";
+        assert!(strict_problems(tidy).is_empty());
+    }
+
+    #[test]
+    fn steps_under_their_item() {
+        let text = concat!(
+            "trip:\n\t- @01/06/2024 pack\n\t\t+ passport\n\t\t- \"@ the station\"\n",
+            "\t+ book the train\n",
+        );
+        let lists = parse_str(text).unwrap();
+        let ListEntry::Item(pack) = &lists[0].items[0] else {
+            panic!("{:?}", lists[0].items);
+        };
+        assert_eq!(
+            pack.subitems,
+            [
+                ("passport".to_owned(), true),
+                ("@ the station".to_owned(), false)
+            ]
+        );
+        assert_eq!(emit_str(&lists), text);
+
+        let json = list_to_json(&lists[0]);
+        assert!(
+            json.contains("\"subitems\":[{\"name\":\"passport\",\"done\":true},{\"name\":\"@ the station\",\"done\":false}]"),
+            "{}",
+            json
+        );
+        assert!(json.contains("\"name\":\"book the train\",\"done\":true,"));
+
+        // a step has to have an item to go under
+        assert!(parse_str("trip:\n\t\t- passport\n").is_err());
+    }
+}
@@ -0,0 +1,279 @@
+// A tiny HTTP API over the same commands the CLI uses. There is NO authentication, so
+// anyone who can reach the port can read and change your lists. It only listens on
+// localhost unless told otherwise with --bind.
+//
+// Requests have to be for the address it's listening on (the Host header), so a web page
+// can't get at it by pointing its own domain at 127.0.0.1, and POSTs need an `X-Todo: 1`
+// header, which a web page can't send to another site without the browser asking first.
+//
+//   GET  /lists              all lists as JSON
+//   GET  /list/<name>        a single list as JSON
+//   POST /lists              create a list, the body is its name
+//   POST /list/<name>/add    add an item, the body is the same as the arguments to `todo add`
+//   POST /list/<name>/done   toggle an item as done, the body is the item name
+//   POST /list/<name>/remove remove an item, the body is the item name
+
+use crate::config::Config;
+use crate::{
+    append_only_error, cmd_add, done_entry, get_list_by_name, load, new_list, parser, remove_entry,
+    run_deferred, save, undefer, CmdResult,
+};
+use std::path::Path;
+use tiny_http::{Header, Method, Response, Server};
+
+// the names localhost goes by. listening on one of them means it can be reached as any of them
+const LOCALHOST: [&str; 3] = ["localhost", "127.0.0.1", "[::1]"];
+
+pub fn serve(list_file: &Path, addr: &str, config: &Config) -> CmdResult {
+    let server = Server::http(addr).map_err(|e| format!("Unable to listen on {addr}: {e}"))?;
+    eprintln!("Listening on http://{addr}");
+    let hosts = allowed_hosts(addr);
+    for mut request in server.incoming_requests() {
+        let mut body = String::new();
+        let checked = check_request(&hosts, request.method(), request.headers());
+        let (status, json) = if let Err(e) = checked {
+            e
+        } else if request.as_reader().read_to_string(&mut body).is_err() {
+            (400, error_json("Request body is not valid UTF-8"))
+        } else {
+            handle(
+                list_file,
+                config,
+                request.method(),
+                request.url(),
+                body.trim(),
+            )
+        };
+        let response = Response::from_string(json)
+            .with_status_code(status)
+            .with_header(Header::from_bytes("Content-Type", "application/json").unwrap());
+        if let Err(e) = request.respond(response) {
+            eprintln!("Failed to send response: {e}");
+        }
+    }
+    Ok((String::new(), false))
+}
+
+// what the Host header can be: the address it was told to listen on, or any other name for it
+// if that's localhost
+fn allowed_hosts(addr: &str) -> Vec<String> {
+    let mut hosts = vec![addr.to_owned()];
+    if let Some((host, port)) = addr.rsplit_once(':') {
+        if LOCALHOST.contains(&host) {
+            hosts.extend(LOCALHOST.iter().map(|host| format!("{host}:{port}")));
+        }
+    }
+    hosts
+}
+
+fn check_request(
+    hosts: &[String],
+    method: &Method,
+    headers: &[Header],
+) -> Result<(), (u16, String)> {
+    let header = |name: &'static str| {
+        headers
+            .iter()
+            .find(|header| header.field.equiv(name))
+            .map(|header| header.value.as_str())
+    };
+    if !header("Host").is_some_and(|host| hosts.iter().any(|allowed| allowed == host)) {
+        return Err((421, error_json("Unexpected Host header")));
+    }
+    if *method != Method::Get && header("X-Todo") != Some("1") {
+        return Err((
+            403,
+            error_json("Requests that change things need an X-Todo: 1 header"),
+        ));
+    }
+    Ok(())
+}
+
+fn handle(
+    list_file: &Path,
+    config: &Config,
+    method: &Method,
+    url: &str,
+    body: &str,
+) -> (u16, String) {
+    // reload every time so changes made with the CLI while the server is running aren't lost
+    let mut lists = match load(list_file) {
+        Ok(lists) => lists,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => return (500, error_json(&e.to_string())),
+    };
+    let path = url
+        .split('?')
+        .next()
+        .unwrap_or_default()
+        .split('/')
+        .filter(|part| !part.is_empty())
+        .map(percent_decode)
+        .collect::<Vec<String>>();
+    let path = path.iter().map(String::as_str).collect::<Vec<&str>>();
+
+    // anything left over from a request that failed
+    undefer(0);
+    let result = match (method, path.as_slice()) {
+        (Method::Get, ["lists"]) => return (200, parser::emit_json(&lists)),
+        (Method::Get, ["list", name]) => {
+            return match get_list_by_name(&lists, name) {
+                Ok(list) => (200, parser::list_to_json(list)),
+                Err(e) => (404, error_json(&e)),
+            }
+        }
+        (Method::Post, ["lists"]) => new_list(&mut lists, body.to_owned()),
+        (Method::Post, ["list", name, "add"]) => {
+            let mut args = vec![(*name).to_string()];
+            args.extend(body.split_whitespace().map(str::to_owned));
+            cmd_add(&mut lists, &args, config)
+        }
+        (Method::Post, ["list", name, "done"]) => done_entry(&mut lists, name, body, config),
+        (Method::Post, ["list", _, "remove"]) if config.append_only => {
+            Err(append_only_error("remove"))
+        }
+        (Method::Post, ["list", name, "remove"]) => remove_entry(&mut lists, name, body, config),
+        _ => return (404, error_json("Not found")),
+    };
+
+    match result {
+        Ok((msg, modified)) => {
+            if modified {
+                if let Err(e) = save(list_file, &lists) {
+                    return (500, error_json(&e.to_string()));
+                }
+                run_deferred(config);
+            }
+            (
+                200,
+                format!("{{\"ok\":true,\"message\":{}}}", parser::json_string(&msg)),
+            )
+        }
+        Err(e) => (400, error_json(&e)),
+    }
+}
+
+fn error_json(msg: &str) -> String {
+    format!("{{\"ok\":false,\"error\":{}}}", parser::json_string(msg))
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                out.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                out.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(headers: &[(&str, &str)]) -> Vec<Header> {
+        headers
+            .iter()
+            .map(|(field, value)| Header::from_bytes(*field, *value).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn only_the_bound_host() {
+        let hosts = allowed_hosts("127.0.0.1:8080");
+        let get = |host_headers: &[(&str, &str)]| {
+            check_request(&hosts, &Method::Get, &headers(host_headers))
+                .map_err(|(status, _)| status)
+        };
+        assert_eq!(get(&[("Host", "127.0.0.1:8080")]), Ok(()));
+        assert_eq!(get(&[("Host", "localhost:8080")]), Ok(()));
+        assert_eq!(get(&[("host", "[::1]:8080")]), Ok(()));
+        // a page on another domain that's been pointed at 127.0.0.1
+        assert_eq!(get(&[("Host", "evil.example:8080")]), Err(421));
+        assert_eq!(get(&[("Host", "localhost:9090")]), Err(421));
+        assert_eq!(get(&[]), Err(421));
+
+        // somewhere else only answers to its own address
+        let hosts = allowed_hosts("192.168.1.5:8080");
+        assert_eq!(hosts, ["192.168.1.5:8080"]);
+    }
+
+    #[test]
+    fn posts_need_the_header() {
+        let hosts = allowed_hosts("localhost:8080");
+        let post = |extra: &[(&str, &str)]| {
+            let mut all = vec![("Host", "localhost:8080")];
+            all.extend_from_slice(extra);
+            check_request(&hosts, &Method::Post, &headers(&all)).map_err(|(status, _)| status)
+        };
+        assert_eq!(post(&[("X-Todo", "1")]), Ok(()));
+        // what a form or a fetch() with no special headers on another site would send
+        assert_eq!(
+            post(&[
+                ("Content-Type", "text/plain"),
+                ("Origin", "https://evil.example")
+            ]),
+            Err(403)
+        );
+        assert_eq!(post(&[("X-Todo", "yes")]), Err(403));
+    }
+
+    #[test]
+    fn endpoints() {
+        let file = std::env::temp_dir().join(format!("todo-serve-{}.txt", std::process::id()));
+        let _ = std::fs::remove_file(&file);
+        let request = |method, url, body| handle(&file, &Config::default(), &method, url, body);
+
+        assert_eq!(request(Method::Get, "/lists", "").1, "[]");
+        assert_eq!(request(Method::Post, "/lists", "c++ stuff").0, 200);
+        assert_eq!(
+            request(Method::Post, "/list/c++%20stuff/add", "read the book").0,
+            200
+        );
+        let (status, json) = request(Method::Get, "/list/c++ stuff", "");
+        assert_eq!(status, 200);
+        assert!(json.contains("\"name\":\"read the book\""), "{}", json);
+        assert!(json.contains("\"done\":false"), "{}", json);
+
+        assert_eq!(request(Method::Post, "/list/c++ stuff/done", "read").0, 200);
+        assert!(request(Method::Get, "/lists", "")
+            .1
+            .contains("\"done\":true"));
+        assert_eq!(
+            request(Method::Post, "/list/c++ stuff/remove", "read").0,
+            200
+        );
+        assert!(!request(Method::Get, "/lists", "")
+            .1
+            .contains("read the book"));
+
+        let (status, json) = request(Method::Post, "/list/nothing/add", "x");
+        assert_eq!(status, 400);
+        assert!(json.starts_with("{\"ok\":false"), "{}", json);
+        assert_eq!(request(Method::Get, "/list/nothing", "").0, 404);
+        assert_eq!(request(Method::Delete, "/lists", "").0, 404);
+        std::fs::remove_file(&file).unwrap();
+    }
+
+    #[test]
+    fn plus_in_a_path_is_a_plus() {
+        assert_eq!(percent_decode("c++"), "c++");
+        assert_eq!(percent_decode("shopping%20list"), "shopping list");
+        assert_eq!(percent_decode("caf%C3%A9"), "caf\u{e9}");
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%zz"), "%zz");
+    }
+}
@@ -0,0 +1,331 @@
+// `todo ui`, a full-screen interface for browsing lists and ticking things off. changes go
+// through the same cmd_* functions as the command line, and are saved on quit
+//
+//   up/down (or k/j)  move the cursor
+//   space             mark the item under the cursor as done (or not done)
+//   a                 add an item to the list the cursor is in, same arguments as `todo add`
+//   d                 remove the item or sublist under the cursor
+//   q/esc             save and quit
+
+use crate::config::Config;
+use crate::{
+    append_only_error, cmd_add, done_marker, get_list_by_name, get_mut_list_by_name, list_marker,
+    mark_done, remove_at, top_level_lists, truncate, CmdResult, ListEntry, TodoList,
+};
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::style::{Attribute, Print, SetAttribute};
+use crossterm::{cursor, execute, queue, terminal};
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::io::Write;
+
+const HELP: &str = "space: done  a: add  d: remove  q: save and quit";
+
+// a line on the screen: either a list header or an item
+struct Row {
+    // the list this row is an entry of, and its index in that list's items. None for the
+    // headers of the lists at the top
+    parent: Option<(String, usize)>,
+    // the list new items go in when this row is selected
+    list: String,
+    is_item: bool,
+    text: String,
+}
+
+// puts the terminal back how it was when it's dropped, even if something panics
+struct RawTerminal;
+
+impl RawTerminal {
+    fn enter() -> std::io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        execute!(
+            std::io::stdout(),
+            terminal::EnterAlternateScreen,
+            cursor::Hide
+        )?;
+        Ok(Self)
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        let _ = execute!(
+            std::io::stdout(),
+            cursor::Show,
+            terminal::LeaveAlternateScreen
+        );
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+pub fn run(lists: &mut Vec<TodoList>, root: Option<&str>, config: &Config) -> CmdResult {
+    if let Some(root) = root {
+        get_list_by_name(lists, root)?;
+    }
+    let _terminal = RawTerminal::enter().map_err(|e| format!("Unable to start the UI: {e}"))?;
+    let mut selected = 0;
+    let mut scroll = 0;
+    let mut modified = false;
+    let mut status = String::new();
+    // what's been typed so far after pressing 'a'
+    let mut input: Option<String> = None;
+    loop {
+        let rows = rows(lists, root, config.ascii);
+        selected = selected.min(rows.len().saturating_sub(1));
+        let bottom_line = input.as_ref().map_or_else(
+            || {
+                if status.is_empty() {
+                    HELP.to_string()
+                } else {
+                    status.clone()
+                }
+            },
+            |input| format!("Add to '{}': {input}", rows[selected].list),
+        );
+        draw(&rows, selected, &mut scroll, &bottom_line, config.ascii)
+            .map_err(|e| format!("Unable to draw the UI: {e}"))?;
+
+        // anything else, like the terminal being resized, just redraws
+        let Event::Key(key) = event::read().map_err(|e| format!("Unable to read input: {e}"))?
+        else {
+            continue;
+        };
+        if key.kind == KeyEventKind::Release {
+            continue;
+        }
+        status.clear();
+        let row = rows.get(selected);
+        let result = match (input.as_mut(), key.code) {
+            (Some(_), KeyCode::Esc) => {
+                input = None;
+                None
+            }
+            (Some(text), KeyCode::Enter) => {
+                let mut args = vec![rows[selected].list.clone()];
+                args.extend(text.split_whitespace().map(str::to_owned));
+                input = None;
+                Some(cmd_add(lists, &args, config))
+            }
+            (Some(text), KeyCode::Backspace) => {
+                text.pop();
+                None
+            }
+            (Some(text), KeyCode::Char(c)) => {
+                text.push(c);
+                None
+            }
+            (None, KeyCode::Char('q') | KeyCode::Esc) => break,
+            (None, KeyCode::Char('c')) if key.modifiers.contains(KeyModifiers::CONTROL) => break,
+            (None, KeyCode::Up | KeyCode::Char('k')) => {
+                selected = selected.saturating_sub(1);
+                None
+            }
+            (None, KeyCode::Down | KeyCode::Char('j')) => {
+                selected += 1;
+                None
+            }
+            (None, KeyCode::Char('a')) if row.is_some() => {
+                input = Some(String::new());
+                None
+            }
+            (None, KeyCode::Char(' ')) => row
+                .filter(|row| row.is_item)
+                .and_then(|row| row.parent.as_ref())
+                .map(|(list, idx)| mark_done(get_mut_list_by_name(lists, list)?, *idx, config)),
+            (None, KeyCode::Char('d')) if config.append_only => {
+                Some(Err(append_only_error("remove")))
+            }
+            (None, KeyCode::Char('d')) => row
+                .and_then(|row| row.parent.as_ref())
+                .map(|(list, idx)| remove_at(lists, list, *idx, config)),
+            _ => None,
+        };
+        match result {
+            Some(Ok((_, changed))) => modified |= changed,
+            Some(Err(e)) => status = e,
+            None => (),
+        }
+    }
+    Ok((String::new(), modified))
+}
+
+fn rows(lists: &[TodoList], root: Option<&str>, ascii: bool) -> Vec<Row> {
+    let roots = match root.map(|root| get_list_by_name(lists, root)) {
+        Some(Ok(list)) => vec![list],
+        Some(Err(_)) => Vec::new(),
+        None => top_level_lists(lists),
+    };
+    let mut acc = Vec::new();
+    let mut visited = HashSet::new();
+    for list in roots {
+        add_rows(lists, list, None, 0, ascii, &mut visited, &mut acc);
+    }
+    acc
+}
+
+fn add_rows<'a>(
+    lists: &'a [TodoList],
+    list: &'a TodoList,
+    parent: Option<(String, usize)>,
+    depth: usize,
+    ascii: bool,
+    visited: &mut HashSet<&'a str>,
+    acc: &mut Vec<Row>,
+) {
+    let indent = " ".repeat(depth * 4);
+    acc.push(Row {
+        parent,
+        list: list.name.clone(),
+        is_item: false,
+        // marked as done when everything in it is, like in `todo list`
+        text: format!(
+            "{}{indent}{}:",
+            list_marker(list.walk_items(lists).iter().all(|item| item.done), ascii),
+            list.name
+        ),
+    });
+    // a list that's already been shown is just a header, so circular references stop
+    if !visited.insert(&list.name) {
+        return;
+    }
+    for (idx, entry) in list.items.iter().enumerate() {
+        let parent = Some((list.name.clone(), idx));
+        match entry {
+            ListEntry::List(name) => {
+                if let Ok(sublist) = get_list_by_name(lists, name) {
+                    add_rows(lists, sublist, parent, depth + 1, ascii, visited, acc);
+                }
+            }
+            ListEntry::Item(item) => acc.push(Row {
+                parent,
+                list: list.name.clone(),
+                is_item: true,
+                text: format!(
+                    "{}{indent}    {}{}",
+                    done_marker(item.done, ascii),
+                    item.name,
+                    item.date
+                        .map_or_else(String::new, |date| format!("  {}", date.format("%d/%m/%Y")))
+                ),
+            }),
+        }
+    }
+}
+
+fn draw(
+    rows: &[Row],
+    selected: usize,
+    scroll: &mut usize,
+    bottom_line: &str,
+    ascii: bool,
+) -> std::io::Result<()> {
+    let mut out = std::io::stdout();
+    let (width, height) = terminal::size()?;
+    let width = usize::from(width);
+    let visible = usize::from(height).saturating_sub(1).max(1);
+    // keep the cursor on the screen
+    if selected < *scroll {
+        *scroll = selected;
+    } else if selected >= *scroll + visible {
+        *scroll = selected + 1 - visible;
+    }
+
+    queue!(out, terminal::Clear(terminal::ClearType::All))?;
+    for (line, row) in rows.iter().enumerate().skip(*scroll).take(visible) {
+        let y = u16::try_from(line - *scroll).unwrap_or(u16::MAX);
+        queue!(out, cursor::MoveTo(0, y))?;
+        let text = truncate(&row.text, width, ascii);
+        if line == selected {
+            queue!(
+                out,
+                SetAttribute(Attribute::Reverse),
+                Print(text),
+                SetAttribute(Attribute::Reset)
+            )?;
+        } else {
+            queue!(out, Print(text))?;
+        }
+    }
+    queue!(
+        out,
+        cursor::MoveTo(0, height.saturating_sub(1)),
+        Print(truncate(bottom_line, width, ascii))
+    )?;
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_str;
+
+    #[test]
+    fn rows_follow_the_tree() {
+        let lists = parse_str("home:\n\t- tidy\n\t= garden\ngarden:\n\t+ mow\nwork:\n").unwrap();
+        let texts = |rows: Vec<Row>| rows.into_iter().map(|row| row.text).collect::<Vec<_>>();
+        // garden is a sublist, so only home and work are at the top
+        assert_eq!(
+            texts(rows(&lists, None, false)),
+            [
+                " home:",
+                "     tidy",
+                // headers get a marker too, once everything in the list is done
+                "✓    garden:",
+                "✓        mow",
+                "✓work:"
+            ]
+        );
+
+        // a list that turns up again inside itself is only a header the second time
+        let mut looped = lists.clone();
+        looped[1].items.push(ListEntry::List("home".to_string()));
+        assert_eq!(
+            texts(rows(&looped, Some("home"), false)),
+            [
+                " home:",
+                "     tidy",
+                "     garden:",
+                "✓        mow",
+                "         home:"
+            ]
+        );
+
+        let garden = rows(&lists, Some("gar"), false);
+        assert_eq!(garden[0].text, "✓garden:");
+        assert_eq!(garden[1].parent, Some(("garden".to_string(), 0)));
+        assert!(garden[1].is_item && !garden[0].is_item);
+        assert!(rows(&lists, Some("nowhere"), false).is_empty());
+    }
+
+    #[test]
+    fn rows_know_which_entry_they_are() {
+        // two items with the same name: the second row has to pick out the second one
+        let mut lists = parse_str("chores:\n\t- bins\n\t- bins\n").unwrap();
+        let row = &rows(&lists, None, false)[2];
+        let (list, idx) = row.parent.clone().unwrap();
+        mark_done(
+            get_mut_list_by_name(&mut lists, &list).unwrap(),
+            idx,
+            &Config::default(),
+        )
+        .unwrap();
+        let done: Vec<bool> = lists[0]
+            .items
+            .iter()
+            .map(|entry| matches!(entry, ListEntry::Item(item) if item.done))
+            .collect();
+        assert_eq!(done, [false, true]);
+        remove_at(&mut lists, &list, idx, &Config::default()).unwrap();
+        assert_eq!(crate::parser::emit_str(&lists), "chores:\n\t- bins\n");
+    }
+
+    #[test]
+    fn ascii_rows() {
+        let lists = parse_str("done:\n\t+ this\n\t- not this\n").unwrap();
+        let texts = rows(&lists, None, true)
+            .into_iter()
+            .map(|row| row.text)
+            .collect::<Vec<String>>();
+        assert_eq!(texts, ["[ ]done:", "[x]    this", "[ ]    not this"]);
+    }
+}
@@ -0,0 +1,859 @@
+// runs the todo binary itself, for the things that can't be got at from the unit tests, like
+// reading from stdin. each test gets a directory of its own with a todo.txt in it (which is
+// used instead of the one in the config directory), and its own config directory
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("todo-cli-{}-{}", name, std::process::id()));
+    std::fs::create_dir_all(dir.join("config")).unwrap();
+    dir
+}
+
+fn run(dir: &Path, args: &[&str], stdin: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_todo"))
+        .args(args)
+        .current_dir(dir)
+        .env("XDG_CONFIG_HOME", dir.join("config"))
+        .env("HOME", dir)
+        .env_remove("COLUMNS")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+// the output of a command that's expected to work
+fn todo(dir: &Path, args: &[&str], stdin: &str) -> String {
+    let out = run(dir, args, stdin);
+    assert!(out.status.success(), "{:?}", out);
+    String::from_utf8(out.stdout).unwrap()
+}
+
+#[test]
+fn new_from_stdin() {
+    let dir = scratch_dir("new");
+    std::fs::write(dir.join("todo.txt"), "work:\n").unwrap();
+
+    let out = todo(
+        &dir,
+        &["new", "--from-stdin"],
+        "  home  \n\nwork\nshopping\nhome\n",
+    );
+    assert_eq!(out, "Created 2 lists, skipped 2 that already existed\n");
+    assert_eq!(
+        std::fs::read_to_string(dir.join("todo.txt")).unwrap(),
+        "work:\nhome:\nshopping:\n"
+    );
+
+    let out = todo(&dir, &["new", "--from-stdin"], "home\n");
+    assert_eq!(out, "Created 0 lists, skipped 1 that already existed\n");
+    let out = todo(&dir, &["new", "--from-stdin"], "\n  \nhobbies\n");
+    assert_eq!(out, "Created 1 list, skipped 0 that already existed\n");
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn a_broken_list_file_is_left_alone() {
+    let dir = scratch_dir("broken");
+    let broken = "work:\n\t- fine\n\tnot an item\n";
+    std::fs::write(dir.join("todo.txt"), broken).unwrap();
+
+    let out = run(&dir, &["add", "work", "more"], "");
+    assert_eq!(out.status.code(), Some(1));
+    let err = String::from_utf8(out.stderr).unwrap();
+    assert!(err.starts_with("Unable to load todo.txt: "), "{}", err);
+    assert!(err.trim_end().ends_with("(line 3)"), "{}", err);
+    assert_eq!(
+        std::fs::read_to_string(dir.join("todo.txt")).unwrap(),
+        broken
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+// XDG_CONFIG_HOME is only where the config directory comes from on linux
+#[cfg(target_os = "linux")]
+#[test]
+fn no_list_file_is_a_fresh_start() {
+    // with no todo.txt here or in the config directory, it starts from nothing and saves in
+    // the config directory
+    let dir = scratch_dir("fresh");
+    todo(&dir, &["new", "fresh"], "");
+    let global = dir.join("config").join("todo").join("todo.txt");
+    assert_eq!(std::fs::read_to_string(global).unwrap(), "fresh:\n");
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn cached_output_until_the_file_changes() {
+    let dir = scratch_dir("cache");
+    std::fs::create_dir_all(dir.join("config").join("todo")).unwrap();
+    std::fs::write(
+        dir.join("config").join("todo").join("config"),
+        "cache_output = true\nascii = true\n",
+    )
+    .unwrap();
+    let list_file = dir.join("todo.txt");
+    let an_hour_ago = std::time::SystemTime::now() - std::time::Duration::from_secs(3600);
+    let write_settled = |contents: &str| {
+        std::fs::write(&list_file, contents).unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(&list_file)
+            .unwrap()
+            .set_modified(an_hour_ago)
+            .unwrap();
+    };
+
+    write_settled("work:\n\t- aaa\n");
+    let first = todo(&dir, &["list", "work", "--width", "0"], "");
+    assert_eq!(first, "[ ]work:\n[ ]    aaa\n");
+    assert!(dir.join("todo.txt.cache").is_dir());
+    // the same size and modification time looks like the same file, so the saved output is
+    // shown without reading it
+    write_settled("work:\n\t- bbb\n");
+    assert_eq!(todo(&dir, &["list", "work", "--width", "0"], ""), first);
+
+    // any edit through todo changes the modification time
+    todo(&dir, &["add", "work", "ccc"], "");
+    let after = todo(&dir, &["list", "work", "--width", "0"], "");
+    assert_eq!(after, "[ ]work:\n[ ]    bbb\n[ ]    ccc\n");
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn stats_history_is_kept_per_list() {
+    let dir = scratch_dir("stats");
+    std::fs::write(dir.join("todo.txt"), "work:\n\t- a\n\t+ b\nhome:\n\t- c\n").unwrap();
+    let stats_csv = dir.join("config").join("todo").join("stats.csv");
+
+    // errors go to stderr, with nothing on stdout
+    let err = run(&dir, &["stats", "--history"], "");
+    assert!(err.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&err.stderr).contains("Nothing has been recorded yet"));
+
+    let out = todo(&dir, &["stats", "wo", "--record"], "");
+    assert!(out.starts_with("2 items, 1 done (50%), 0 overdue\nRecorded in "));
+    todo(&dir, &["stats", "--record"], "");
+    todo(&dir, &["done", "home", "c"], "");
+    todo(&dir, &["stats", "--record"], "");
+
+    let csv = std::fs::read_to_string(&stats_csv).unwrap();
+    let rows: Vec<&str> = csv.lines().collect();
+    assert_eq!(rows.len(), 4);
+    assert_eq!(rows[0], "date,list,total,done,overdue");
+    // recorded under the list's whole name, not what was typed
+    assert!(rows[1].ends_with(",work,2,1,0"));
+    assert!(rows[2].ends_with(",,3,1,0"));
+    assert!(rows[3].ends_with(",,3,2,0"));
+
+    let history = todo(&dir, &["stats", "--history"], "");
+    let lines: Vec<&str> = history.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert!(lines[1].ends_with("      3      1 ( 33%)        0"));
+    assert!(lines[2].ends_with("      3      2 ( 66%)        0"));
+    let work = todo(&dir, &["stats", "work", "--history"], "");
+    assert_eq!(work.lines().count(), 2);
+    let home = run(&dir, &["stats", "home", "--history"], "");
+    assert!(
+        String::from_utf8_lossy(&home.stderr).contains("Nothing has been recorded for 'home' yet")
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn columns_sets_the_width_in_a_pipe() {
+    let dir = scratch_dir("columns");
+    std::fs::write(
+        dir.join("todo.txt"),
+        "work:\n\t- @01/06/2030 a rather long item name indeed\n",
+    )
+    .unwrap();
+    let list = |columns: &str| {
+        let out = Command::new(env!("CARGO_BIN_EXE_todo"))
+            .args(["list", "work", "--ascii", "--days"])
+            .current_dir(&dir)
+            .env("XDG_CONFIG_HOME", dir.join("config"))
+            .env("COLUMNS", columns)
+            .output()
+            .unwrap();
+        String::from_utf8(out.stdout).unwrap()
+    };
+
+    let narrow = list("30");
+    let lines: Vec<&str> = narrow.lines().collect();
+    assert_eq!(lines.len(), 3, "{}", narrow);
+    assert_eq!(lines[1], "[ ]    a rather long item name indeed");
+    assert!(lines[2].starts_with("         01/06/2030 +"), "{}", narrow);
+    // wide enough, or not a number, and the date stays on the item's line
+    for columns in ["200", "lots"] {
+        let out = list(columns);
+        assert_eq!(out.lines().count(), 2, "{}", out);
+        assert!(out.lines().nth(1).unwrap().contains("indeed\t"), "{}", out);
+    }
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn config_problems_stop_it_before_anything_happens() {
+    let dir = scratch_dir("config");
+    let config_dir = dir.join("config").join("todo");
+    std::fs::create_dir_all(&config_dir).unwrap();
+    std::fs::write(dir.join("todo.txt"), "work:\n\t- a\n").unwrap();
+
+    std::fs::write(config_dir.join("config"), "ascii = sometimes\n").unwrap();
+    let out = run(&dir, &["add", "work", "b"], "");
+    assert_eq!(out.status.code(), Some(1));
+    let err = String::from_utf8(out.stderr).unwrap();
+    assert!(err.starts_with("Error in "), "{}", err);
+    assert!(err.ends_with("config: Expected true or false for 'ascii', not 'sometimes' (line 1)\n"));
+    assert_eq!(
+        std::fs::read_to_string(dir.join("todo.txt")).unwrap(),
+        "work:\n\t- a\n"
+    );
+
+    std::fs::write(config_dir.join("config"), "append_only = true\n").unwrap();
+    let out = run(&dir, &["rm", "work", "a"], "");
+    assert!(String::from_utf8_lossy(&out.stderr).contains("blocked by append-only mode"));
+    assert_eq!(
+        std::fs::read_to_string(dir.join("todo.txt")).unwrap(),
+        "work:\n\t- a\n"
+    );
+    todo(&dir, &["rm", "--force", "work", "a"], "");
+    assert_eq!(
+        std::fs::read_to_string(dir.join("todo.txt")).unwrap(),
+        "work:\n"
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn at_last_across_runs() {
+    let dir = scratch_dir("last");
+    std::fs::write(dir.join("todo.txt"), "inbox:\n\t- old thing\nwork:\n").unwrap();
+
+    todo(&dir, &["add", "inbox", "call the plumber"], "");
+    todo(&dir, &["add", "work", "write the report"], "");
+    // adding a duplicate fails, so it isn't remembered
+    std::fs::write(
+        dir.join("config").join("todo").join("config"),
+        "reject_duplicate_items = true\n",
+    )
+    .unwrap();
+    let out = run(&dir, &["add", "inbox", "old thing"], "");
+    assert!(!out.stderr.is_empty());
+
+    todo(&dir, &["done", "inbox", "@last"], "");
+    todo(&dir, &["rm", "work", "@last"], "");
+    let list = std::fs::read_to_string(dir.join("todo.txt")).unwrap();
+    let lines: Vec<&str> = list.lines().collect();
+    assert_eq!(lines.len(), 4, "{}", list);
+    assert_eq!(lines[1], "\t- old thing");
+    assert!(lines[2].starts_with("\t+ ") && lines[2].ends_with("] call the plumber"));
+    assert_eq!(lines[3], "work:");
+    let last = std::fs::read_to_string(dir.join("config").join("todo").join("last_added")).unwrap();
+    assert_eq!(last, "inbox\tcall the plumber\nwork\twrite the report\n");
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn diff_two_files() {
+    let dir = scratch_dir("diff");
+    std::fs::write(dir.join("todo.txt"), "unrelated:\n").unwrap();
+    std::fs::write(dir.join("before.txt"), "work:\n\t- a\n").unwrap();
+    std::fs::write(dir.join("after.txt"), "work:\n\t+ a\n\t- b\n").unwrap();
+    std::fs::write(dir.join("broken.txt"), "work:\n\tnope\n").unwrap();
+
+    assert_eq!(
+        todo(&dir, &["diff", "before.txt", "after.txt"], ""),
+        "work: done 'a'\nwork: + 'b'\n"
+    );
+    // - is stdin, like import
+    let json = todo(
+        &dir,
+        &["diff", "-", "after.txt", "--json"],
+        "work:\n\t+ a\n",
+    );
+    assert_eq!(
+        json,
+        "[{\"change\":\"item_added\",\"list\":\"work\",\"name\":\"b\"}]\n"
+    );
+    let out = run(&dir, &["diff", "before.txt", "broken.txt"], "");
+    assert!(String::from_utf8_lossy(&out.stderr).starts_with("Unable to parse broken.txt: "));
+    // neither file is the list file, which isn't touched
+    assert_eq!(
+        std::fs::read_to_string(dir.join("todo.txt")).unwrap(),
+        "unrelated:\n"
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn batch_saves_once_then_runs_the_hooks() {
+    let dir = scratch_dir("batch");
+    let config_dir = dir.join("config").join("todo");
+    std::fs::create_dir_all(&config_dir).unwrap();
+    // the hook copies the list file, to show it's only run once that's been saved
+    std::fs::write(
+        config_dir.join("config"),
+        "on_done_hook = cp todo.txt \"seen by $TODO_ITEM.txt\"\n",
+    )
+    .unwrap();
+    std::fs::write(dir.join("todo.txt"), "home:\n\t- hoover\n").unwrap();
+
+    let out = run(
+        &dir,
+        &["batch"],
+        concat!(
+            "# the weekly tidy\n",
+            "add home 'dust the shelves'\n",
+            "\n",
+            "done home hoover\n",
+            "done home \"mop the floor\"\n",
+            "serve\n",
+            "add home 'unfinished\n",
+        ),
+    );
+    let (stdout, stderr) = (
+        String::from_utf8(out.stdout).unwrap(),
+        String::from_utf8(out.stderr).unwrap(),
+    );
+    assert_eq!(out.status.code(), Some(1));
+    assert_eq!(stdout, "Marked 'hoover' as done\n");
+    let errors: Vec<&str> = stderr.lines().collect();
+    assert_eq!(errors.len(), 3, "{}", stderr);
+    assert!(errors[0].starts_with("line 5: "), "{}", stderr);
+    assert_eq!(errors[1], "line 6: 'serve' can't be run in a batch");
+    assert_eq!(errors[2], "line 7: Missing closing '");
+
+    let saved = std::fs::read_to_string(dir.join("todo.txt")).unwrap();
+    let lines: Vec<&str> = saved.lines().collect();
+    assert_eq!(lines.len(), 3, "{}", saved);
+    assert!(lines[1].starts_with("\t+ [completed=") && lines[1].ends_with("] hoover"));
+    assert!(lines[2].ends_with("] dust the shelves"), "{}", saved);
+    assert_eq!(
+        std::fs::read_to_string(dir.join("seen by hoover.txt")).unwrap(),
+        saved
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn out_writes_the_output_to_a_file() {
+    let dir = scratch_dir("out");
+    std::fs::write(dir.join("todo.txt"), "reading:\n\t- dune\n").unwrap();
+
+    let shown = todo(&dir, &["list", "reading"], "");
+    assert_eq!(
+        todo(&dir, &["list", "reading", "--out", "report.txt"], ""),
+        ""
+    );
+    assert_eq!(
+        std::fs::read_to_string(dir.join("report.txt")).unwrap(),
+        shown
+    );
+    // anywhere in the arguments, and a second run replaces the file
+    todo(&dir, &["--out", "report.txt", "add", "reading", "emma"], "");
+    assert_eq!(std::fs::read_to_string(dir.join("report.txt")).unwrap(), "");
+
+    let out = run(
+        &dir,
+        &["list", "reading", "--out", "no such dir/report.txt"],
+        "",
+    );
+    assert_eq!(out.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&out.stderr)
+        .starts_with("Unable to write no such dir/report.txt: "));
+    let out = run(&dir, &["list", "--out"], "");
+    assert_eq!(out.status.code(), Some(1));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn strict_refuses_a_sloppy_file() {
+    let dir = scratch_dir("strict");
+    let sloppy = "work:\n\t- [every=3d] standup\n\t= meetngs\nmeetings:\n";
+    std::fs::write(dir.join("todo.txt"), sloppy).unwrap();
+
+    assert!(todo(&dir, &["lists"], "").contains("work"));
+    let out = run(&dir, &["add", "--strict", "work", "retro"], "");
+    assert_eq!(out.status.code(), Some(1));
+    assert_eq!(String::from_utf8(out.stdout).unwrap(), "");
+    let err = String::from_utf8(out.stderr).unwrap();
+    let lines: Vec<&str> = err.lines().collect();
+    assert!(lines[0].starts_with("2 problems in "), "{}", err);
+    assert_eq!(
+        lines[1..],
+        [
+            "Repeating item with no date (line 2)",
+            "'meetngs' isn't the name of a list (line 3)"
+        ]
+    );
+    // and nothing was added
+    assert_eq!(
+        std::fs::read_to_string(dir.join("todo.txt")).unwrap(),
+        sloppy
+    );
+
+    std::fs::write(dir.join("todo.txt"), "work:\n\t= meetings\nmeetings:\n").unwrap();
+    todo(&dir, &["lists", "--strict"], "");
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn the_trash_keeps_what_was_removed() {
+    let dir = scratch_dir("trash");
+    let config = dir.join("config").join("todo").join("config");
+    std::fs::create_dir_all(config.parent().unwrap()).unwrap();
+    std::fs::write(&config, "use_trash = true\n").unwrap();
+    let file = dir.join("todo.txt");
+    std::fs::write(
+        &file,
+        "home:\n\t- hoover\n\t- dust\n\t= garden\ngarden:\n\t- weed\nwork:\n\t= garden\n",
+    )
+    .unwrap();
+
+    todo(&dir, &["rm", "home", "hoover"], "");
+    assert_eq!(
+        todo(&dir, &["rmlist", "garden"], ""),
+        "Moved list 'garden' and 2 references to it to the trash\n"
+    );
+    // it's hidden everywhere apart from the trash command
+    assert_eq!(todo(&dir, &["lists"], ""), "home\nwork\n");
+    assert_eq!(
+        todo(&dir, &["trash", "list"], ""),
+        "hoover (from 'home')\ngarden (sublist of 'home')\ngarden (sublist of 'work')\ngarden (list)\n"
+    );
+
+    // a list comes back with the references to it
+    assert_eq!(
+        todo(&dir, &["trash", "restore", "garden"], ""),
+        "Restored 'garden'\n"
+    );
+    assert_eq!(todo(&dir, &["trash", "list"], ""), "hoover (from 'home')\n");
+    let saved = std::fs::read_to_string(&file).unwrap();
+    assert!(
+        saved.starts_with("home:\n\t- dust\n\t= garden\ngarden:\n\t- weed\nwork:\n\t= garden\n"),
+        "{}",
+        saved
+    );
+
+    // emptying it is the one thing that deletes for good, so append-only mode stops it
+    std::fs::write(&config, "use_trash = true\nappend_only = true\n").unwrap();
+    let out = run(&dir, &["trash", "empty"], "");
+    assert!(String::from_utf8_lossy(&out.stderr).contains("blocked by append-only mode"));
+    assert_eq!(
+        todo(&dir, &["trash", "empty", "--force"], ""),
+        "Permanently removed 1 entry from the trash\n"
+    );
+    assert_eq!(todo(&dir, &["trash", "list"], ""), "The trash is empty\n");
+    assert!(!std::fs::read_to_string(&file).unwrap().contains("hoover"));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn aliases_from_the_config_file() {
+    let dir = scratch_dir("aliases");
+    let config_dir = dir.join("config").join("todo");
+    std::fs::create_dir_all(&config_dir).unwrap();
+    std::fs::write(
+        config_dir.join("config"),
+        "[aliases]\nbuy = add shopping\nshop = list shopping\n",
+    )
+    .unwrap();
+    let file = dir.join("todo.txt");
+    std::fs::write(&file, "shopping:\n").unwrap();
+
+    todo(&dir, &["buy", "bread"], "");
+    // a batch goes through the same aliases
+    todo(&dir, &["batch"], "buy milk\nbuy \"oat flakes\"\n");
+    let saved = std::fs::read_to_string(&file).unwrap();
+    let names: Vec<_> = saved
+        .lines()
+        .filter_map(|line| line.split("] ").nth(1))
+        .collect();
+    assert_eq!(names, ["bread", "milk", "oat flakes"], "{saved}");
+    assert!(todo(&dir, &["shop"], "").contains("oat flakes"));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn hooks_run_after_commands_that_change_something() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = scratch_dir("hooks");
+    let hooks = dir.join("hooks");
+    std::fs::create_dir_all(&hooks).unwrap();
+    let config_dir = dir.join("config").join("todo");
+    std::fs::create_dir_all(&config_dir).unwrap();
+    std::fs::write(
+        config_dir.join("config"),
+        format!("hooks_dir = {}\n", hooks.display()),
+    )
+    .unwrap();
+    let script = |name: &str, body: &str| {
+        let path = hooks.join(name);
+        std::fs::write(&path, format!("#!/bin/sh\n{body}\n")).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    };
+    // it sees the file as it was saved
+    script(
+        "post-add",
+        r#"echo "$TODO_COMMAND $# $1 $2 $(grep -c - "$TODO_FILE")" >> "$(dirname "$TODO_FILE")/ran""#,
+    );
+    script("post-list", r#"touch "$(dirname "$TODO_FILE")/listed""#);
+    script("post-done", "exit 3");
+    std::fs::write(dir.join("todo.txt"), "home:\n").unwrap();
+
+    todo(&dir, &["a", "home", "sweep"], "");
+    todo(&dir, &["add", "home", "mop the floor"], "");
+    todo(&dir, &["list", "home"], "");
+    let out = run(&dir, &["done", "home", "sweep"], "");
+    assert_eq!(out.status.code(), Some(0));
+    let stderr = String::from_utf8(out.stderr).unwrap();
+    assert!(
+        stderr.starts_with("Warning: ") && stderr.contains("post-done failed"),
+        "{}",
+        stderr
+    );
+
+    assert_eq!(
+        std::fs::read_to_string(dir.join("ran")).unwrap(),
+        "add 2 home sweep 1\nadd 2 home mop the floor 2\n"
+    );
+    // list doesn't change anything, so it has nothing to say to a hook
+    assert!(!dir.join("listed").exists());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn wide_markers_keep_the_names_lined_up() {
+    let dir = scratch_dir("markers");
+    let config_dir = dir.join("config").join("todo");
+    std::fs::create_dir_all(&config_dir).unwrap();
+    std::fs::write(
+        config_dir.join("config"),
+        "done_marker = DONE\nundone_marker = -\nlist_done_marker = \u{2705}\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("todo.txt"),
+        "markers:\n\t+ done\n\t- not done\n\t= finished\nfinished:\n\t+ all of it\n",
+    )
+    .unwrap();
+
+    let out = todo(&dir, &["list", "markers", "--no-colour"], "");
+    assert_eq!(
+        out.lines().map(str::trim_end).collect::<Vec<_>>(),
+        [
+            "-   markers:",
+            "DONE    done",
+            "-       not done",
+            "\u{2705}      finished:",
+            "DONE        all of it",
+        ]
+    );
+    // --ascii doesn't use them
+    assert!(todo(&dir, &["list", "markers", "--ascii"], "").starts_with("[ ]markers:\n[x]    done"));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn stable_ids_are_saved_the_first_time() {
+    let dir = scratch_dir("stable-ids");
+    let file = dir.join("todo.txt");
+    std::fs::write(&file, "home:\n\t- hoover\n").unwrap();
+
+    // list doesn't change anything, but the IDs still have to be kept
+    todo(&dir, &["--stable-ids", "list", "home"], "");
+    let saved = std::fs::read_to_string(&file).unwrap();
+    let ids: Vec<&str> = saved
+        .split("[id=")
+        .skip(1)
+        .map(|rest| &rest[..rest.find(']').unwrap()])
+        .collect();
+    assert_eq!(ids.len(), 2, "{saved}");
+    assert_ne!(ids[0], ids[1]);
+
+    todo(
+        &dir,
+        &["rename", "home", &format!("#{}", &ids[1][..8]), "vacuum"],
+        "",
+    );
+    todo(&dir, &["--stable-ids", "list", "home"], "");
+    assert_eq!(
+        std::fs::read_to_string(&file).unwrap(),
+        saved.replace("hoover", "vacuum")
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn config_command_changes_the_file() {
+    let dir = scratch_dir("config-command");
+    let config = dir.join("config").join("todo").join("config");
+    std::fs::create_dir_all(config.parent().unwrap()).unwrap();
+    std::fs::write(&config, "# how many days count as soon\nsoon_days = 3\n").unwrap();
+
+    assert_eq!(todo(&dir, &["config", "get", "soon_days"], ""), "3\n");
+    assert_eq!(
+        todo(&dir, &["config", "set", "soon_days", "7"], ""),
+        "Set soon_days to 7\n"
+    );
+    assert_eq!(
+        todo(&dir, &["config", "set", "done_marker", "[x]"], ""),
+        "Set done_marker to [x]\n"
+    );
+    assert_eq!(
+        std::fs::read_to_string(&config).unwrap(),
+        "# how many days count as soon\nsoon_days = 7\ndone_marker = [x]\n"
+    );
+    // lined up, with every setting there is, whether it's in the file or not
+    let list = todo(&dir, &["config", "list"], "");
+    let settings: Vec<(&str, &str)> = list
+        .lines()
+        .map(|line| line.split_once(" = ").unwrap())
+        .map(|(key, value)| (key.trim_end(), value))
+        .collect();
+    assert!(settings.contains(&("soon_days", "7")), "{}", list);
+    assert!(settings.contains(&("done_marker", "[x]")), "{}", list);
+    assert!(settings.contains(&("ascii", "false")), "{}", list);
+    let column = list.find(" = ").unwrap();
+    assert!(
+        list.lines().all(|line| line.find(" = ") == Some(column)),
+        "{}",
+        list
+    );
+
+    let out = run(&dir, &["config", "set", "ascii", "maybe"], "");
+    assert_eq!(
+        String::from_utf8(out.stderr).unwrap(),
+        "Expected true or false for 'ascii', not 'maybe'\n"
+    );
+    assert!(!std::fs::read_to_string(&config).unwrap().contains("maybe"));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn a_save_that_wouldnt_load_back_is_refused() {
+    let dir = scratch_dir("verify");
+    let before = "errands:\n\t- @20/05/2024 post office\n";
+    std::fs::write(dir.join("todo.txt"), before).unwrap();
+
+    // a newline in the name would start a line of its own in the file
+    let out = run(&dir, &["add", "errands", "milk\nbread"], "");
+    assert!(!out.status.success());
+    let err = String::from_utf8(out.stderr).unwrap();
+    assert!(err.contains("it doesn't load"), "{}", err);
+    assert_eq!(
+        std::fs::read_to_string(dir.join("todo.txt")).unwrap(),
+        before
+    );
+    assert!(!dir.join("todo.txt.tmp").exists());
+
+    // nothing wrong with it, so it goes through
+    todo(&dir, &["add", "errands", "milk"], "");
+    assert!(std::fs::read_to_string(dir.join("todo.txt"))
+        .unwrap()
+        .contains("\t- [created="));
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn history_of_what_changed() {
+    let dir = scratch_dir("history");
+    let config = dir.join("config").join("todo");
+    std::fs::create_dir_all(&config).unwrap();
+    std::fs::write(config.join("config"), "audit_log = true\n").unwrap();
+    std::fs::write(dir.join("todo.txt"), "errands:\n\t- post the letter\n").unwrap();
+
+    assert_eq!(
+        todo(&dir, &["history"], ""),
+        "Nothing has been changed yet\n"
+    );
+    todo(&dir, &["d", "err", "post"], "");
+    todo(&dir, &["list", "errands"], "");
+    // the line that fails isn't kept, and the rest go in one by one
+    let out = run(
+        &dir,
+        &["batch"],
+        "a errands \"buy stamps\"\ndone errands nothing\nrn errands buy \"buy 2 stamps\"\n",
+    );
+    assert!(!out.status.success());
+
+    // without the time and who did it. the command is written out in full, and the message
+    // says what the names it was given picked out
+    let log = todo(&dir, &["history"], "");
+    let commands: Vec<&str> = log
+        .lines()
+        .map(|line| line.split_once(": ").unwrap().1)
+        .collect();
+    assert_eq!(
+        commands,
+        [
+            "done err post (Marked 'post the letter' as done)",
+            "add errands \"buy stamps\"",
+            "rename errands buy \"buy 2 stamps\" (Renamed 'buy stamps' to 'buy 2 stamps')",
+        ]
+    );
+    assert!(
+        log.lines().all(|line| line.as_bytes()[4] == b'-'),
+        "{}",
+        log
+    );
+
+    assert_eq!(
+        todo(&dir, &["history", "--clear"], ""),
+        "Cleared the history\n"
+    );
+    assert!(!config.join("history.log").exists());
+}
+
+#[test]
+fn fix_rewrites_dates_stuck_to_the_name() {
+    let dir = scratch_dir("fix");
+    let list_file = dir.join("todo.txt");
+    std::fs::write(
+        &list_file,
+        "home:\n\t- @01/06/2024buy milk\n\t- @02/06/2024 bread\n",
+    )
+    .unwrap();
+
+    let said = todo(&dir, &["fix"], "");
+    assert_eq!(said, "Put a space between the date and the name (line 2)\n");
+    assert_eq!(
+        std::fs::read_to_string(&list_file).unwrap(),
+        "home:\n\t- @01/06/2024 buy milk\n\t- @02/06/2024 bread\n"
+    );
+    assert_eq!(todo(&dir, &["fix", "--dry-run"], ""), "Nothing to fix\n");
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn priority_symbols_arent_mixed_up_with_cached_badges() {
+    let dir = scratch_dir("symbols");
+    std::fs::create_dir_all(dir.join("config").join("todo")).unwrap();
+    std::fs::write(
+        dir.join("config").join("todo").join("config"),
+        "cache_output = true\nascii = true\n",
+    )
+    .unwrap();
+    let list_file = dir.join("todo.txt");
+    std::fs::write(&list_file, "work:\n\t- [priority=3] deploy\n").unwrap();
+    // only files that haven't changed for a while are cached
+    std::fs::File::options()
+        .write(true)
+        .open(&list_file)
+        .unwrap()
+        .set_modified(std::time::SystemTime::now() - std::time::Duration::from_secs(600))
+        .unwrap();
+
+    let list = |extra: &[&str]| {
+        let mut args = vec!["list", "work", "--width", "0"];
+        args.extend(extra);
+        todo(&dir, &args, "")
+    };
+    assert_eq!(list(&[]), "[ ]work:\n[ ]    deploy [p3]\n");
+    assert_eq!(
+        list(&["--priority-symbols"]),
+        "[ ]work:\n[ ]    deploy !!!\n"
+    );
+    assert_eq!(list(&[]), "[ ]work:\n[ ]    deploy [p3]\n");
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn forgotten_items_leave_nothing_behind() {
+    let dir = scratch_dir("forget");
+    let config = dir.join("config").join("todo");
+    std::fs::create_dir_all(&config).unwrap();
+    std::fs::write(
+        config.join("config"),
+        "use_trash = true\naudit_log = true\n",
+    )
+    .unwrap();
+    std::fs::write(dir.join("todo.txt"), "chores:\n\t- bins\n").unwrap();
+
+    for line in [
+        "add chores bread",
+        "add chores secret",
+        "done chores secret",
+        "done chores bins",
+        "remove chores bins",
+    ] {
+        let args: Vec<&str> = line.split(' ').collect();
+        todo(&dir, &args, "");
+    }
+    assert_eq!(todo(&dir, &["trash", "list"], ""), "bins (from 'chores')\n");
+
+    assert_eq!(
+        todo(&dir, &["autorm", "chores", "--forget"], ""),
+        "Removed 2 done items from 'chores' for good\n"
+    );
+    assert_eq!(todo(&dir, &["trash", "list"], ""), "The trash is empty\n");
+    let history = std::fs::read_to_string(config.join("history.log")).unwrap();
+    let commands: Vec<&str> = history
+        .lines()
+        .map(|line| line.split_once(": ").unwrap().1)
+        .collect();
+    assert_eq!(
+        commands,
+        [
+            "add chores bread",
+            "autorm chores --forget (Removed 2 done items from 'chores' for good)",
+        ]
+    );
+    // @last was the secret, so there isn't one now
+    assert_eq!(
+        std::fs::read_to_string(config.join("last_added")).unwrap(),
+        ""
+    );
+    let out = run(&dir, &["done", "chores", "@last"], "");
+    assert_eq!(
+        String::from_utf8(out.stderr).unwrap(),
+        "Nothing has been added to 'chores' yet\n"
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}